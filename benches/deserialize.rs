@@ -0,0 +1,60 @@
+//! Per-node overhead on a large flat document.
+//!
+//! 5000 children nodes with an argument and a few properties each: the run is
+//! dominated by node routing and property bookkeeping (seen-key tracking,
+//! flatten paths), not value conversion, so this is the benchmark that moves
+//! when those buffers regress to per-node heap allocation.
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use facet::Facet;
+use std::hint::black_box;
+
+#[derive(Debug, Facet)]
+struct Telemetry {
+    #[facet(children)]
+    samples: Vec<Sample>,
+}
+
+#[derive(Debug, Facet)]
+struct Sample {
+    #[facet(argument)]
+    name: String,
+    #[facet(property)]
+    value: f64,
+    #[facet(property)]
+    unit: String,
+    #[facet(property, default)]
+    scale: f64,
+}
+
+fn document(nodes: usize) -> String {
+    use std::fmt::Write;
+    let mut kdl = String::with_capacity(nodes * 48);
+    for index in 0..nodes {
+        writeln!(kdl, "sample \"probe-{index}\" value={index}.5 unit=\"ms\"").unwrap();
+    }
+    kdl
+}
+
+fn flat_document(c: &mut Criterion) {
+    let kdl = document(5000);
+    c.bench_function("deserialize/flat/5000-nodes", |b| {
+        b.iter(|| facet_kdl::from_str::<Telemetry>(black_box(&kdl)).unwrap())
+    });
+}
+
+/// The children-container growth path: 100k nodes into one `Vec`, where the
+/// up-front node count should turn ~17 doubling reallocations (plus the
+/// copies they imply) into a single sized allocation.
+fn huge_children(c: &mut Criterion) {
+    let kdl = document(100_000);
+    let mut group = c.benchmark_group("deserialize/children");
+    group.sample_size(10);
+    group.bench_function("100k-nodes", |b| {
+        b.iter(|| facet_kdl::from_str::<Telemetry>(black_box(&kdl)).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, flat_document, huge_children);
+criterion_main!(benches);
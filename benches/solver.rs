@@ -0,0 +1,102 @@
+//! Solver cost on heavily ambiguous schemas.
+//!
+//! Three flattened enums multiply into `3 * 3 * 3 = 27` resolutions per node;
+//! this exercises the candidate filtering fast path (memoized fits probes, no
+//! value conversion until the winning resolution is replayed).
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use facet::Facet;
+use std::hint::black_box;
+
+#[derive(Debug, Facet)]
+struct Doc {
+    #[facet(children)]
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug, Facet)]
+struct Rule {
+    #[facet(property)]
+    priority: u8,
+    #[facet(flatten)]
+    action: Action,
+    #[facet(flatten)]
+    target: Target,
+    #[facet(flatten)]
+    schedule: Schedule,
+}
+
+#[derive(Debug, Facet)]
+#[repr(u8)]
+#[allow(dead_code)] // variants are built through reflection only
+enum Action {
+    Allow {
+        #[facet(property)]
+        scope: String,
+    },
+    Deny {
+        #[facet(property)]
+        reason: String,
+    },
+    Log {
+        #[facet(property)]
+        level: String,
+    },
+}
+
+#[derive(Debug, Facet)]
+#[repr(u8)]
+#[allow(dead_code)] // variants are built through reflection only
+enum Target {
+    Host {
+        #[facet(property)]
+        host: String,
+    },
+    Network {
+        #[facet(property)]
+        cidr: String,
+    },
+    Group {
+        #[facet(property)]
+        group: String,
+    },
+}
+
+#[derive(Debug, Facet)]
+#[repr(u8)]
+#[allow(dead_code)] // variants are built through reflection only
+enum Schedule {
+    Always {
+        #[facet(property)]
+        note: String,
+    },
+    Window {
+        #[facet(property)]
+        from: String,
+    },
+    Cron {
+        #[facet(property)]
+        cron: String,
+    },
+}
+
+fn document(nodes: usize) -> String {
+    (0..nodes)
+        .map(|index| {
+            format!(
+                "rule priority={} scope=\"admin\" cidr=\"10.0.0.0/8\" cron=\"* * * * *\"\n",
+                index % 100
+            )
+        })
+        .collect()
+}
+
+fn ambiguous_solver(c: &mut Criterion) {
+    let kdl = document(100);
+    c.bench_function("solver/27-resolutions/100-nodes", |b| {
+        b.iter(|| facet_kdl::from_str::<Doc>(black_box(&kdl)).unwrap())
+    });
+}
+
+criterion_group!(benches, ambiguous_solver);
+criterion_main!(benches);
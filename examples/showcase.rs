@@ -0,0 +1,44 @@
+//! A small end-to-end tour: parse a config, print it, serialize it back.
+//!
+//! Run with `cargo run --example showcase`.
+
+use facet::Facet;
+
+#[derive(Debug, Facet)]
+struct Config {
+    #[facet(child)]
+    server: Server,
+    #[facet(children)]
+    plugins: Vec<Plugin>,
+}
+
+#[derive(Debug, Facet)]
+struct Server {
+    #[facet(argument)]
+    name: String,
+    #[facet(property)]
+    port: u16,
+}
+
+#[derive(Debug, Facet)]
+struct Plugin {
+    #[facet(argument)]
+    path: String,
+    #[facet(property)]
+    enabled: Option<bool>,
+}
+
+fn main() -> miette::Result<()> {
+    let input = r#"
+server "main" port=8080
+plugin "/usr/lib/a.so" enabled=#true
+plugin "/usr/lib/b.so"
+"#;
+
+    let config: Config = facet_kdl::from_str(input)?;
+    println!("parsed: {config:#?}");
+
+    let output = facet_kdl::to_string(&config)?;
+    println!("serialized back:\n{output}");
+    Ok(())
+}
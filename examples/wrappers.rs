@@ -0,0 +1,40 @@
+//! The idiom wrapper types: document shapes with no attributes at all.
+//!
+//! Run with `cargo run --example wrappers`.
+
+use facet::Facet;
+use facet_kdl::{Args, NodeName, Props};
+
+#[derive(Debug, Facet)]
+struct Pipeline {
+    #[facet(children)]
+    steps: Vec<Step>,
+}
+
+/// One pipeline step: `build "cargo" "build" RUST_LOG=debug`.
+///
+/// The wrappers carry their roles in the types — the node's own name, its
+/// positional arguments, and whatever properties are left over — so the
+/// struct needs no facet attributes.
+#[derive(Debug, Facet)]
+struct Step {
+    name: NodeName,
+    command: Args<String>,
+    env: Props,
+}
+
+fn main() -> miette::Result<()> {
+    let input = r#"
+step "cargo" "build" RUST_LOG=debug
+step "cargo" "test" RUST_BACKTRACE=1 CI=#true
+"#;
+
+    let pipeline: Pipeline = facet_kdl::from_str(input)?;
+    for step in &pipeline.steps {
+        println!("{}: {:?} with env {:?}", step.name, step.command.0, step.env.0);
+    }
+
+    let output = facet_kdl::to_string(&pipeline)?;
+    println!("serialized back:\n{output}");
+    Ok(())
+}
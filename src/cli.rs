@@ -0,0 +1,158 @@
+//! Deriving a CLI argument specification from a document type.
+//!
+//! Small config-driven tools often want to accept either a KDL file or the
+//! equivalent command-line flags. [`command_spec`] maps a document-shaped
+//! type onto a clap-like spec — child nodes become subcommands, properties
+//! become flags, arguments become positionals — so both frontends can be
+//! generated from a single type definition. This is deliberately a mapping
+//! layer only: wiring the spec into an argument parser is left to the
+//! application.
+
+use facet_core::{Def, Facet, Field, Shape, Type, UserType};
+
+use crate::fields::{FieldRole, field_role};
+
+/// The kind of value a flag or positional accepts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueKind {
+    /// A string value.
+    String,
+    /// An integer value.
+    Integer,
+    /// A floating-point value.
+    Float,
+    /// A boolean; as a flag this means presence/absence.
+    Bool,
+    /// Anything else (parsed from a string by the target type).
+    Other,
+}
+
+impl ValueKind {
+    fn of(shape: &'static Shape) -> ValueKind {
+        let shape = match shape.def {
+            Def::Option(option_def) => option_def.t(),
+            _ => shape,
+        };
+        match shape.type_identifier {
+            "String" | "str" | "char" => ValueKind::String,
+            "bool" => ValueKind::Bool,
+            "f32" | "f64" => ValueKind::Float,
+            "u8" | "u16" | "u32" | "u64" | "u128" | "usize" | "i8" | "i16" | "i32" | "i64"
+            | "i128" | "isize" => ValueKind::Integer,
+            _ => ValueKind::Other,
+        }
+    }
+}
+
+/// One flag (`--name value`) derived from a property field.
+#[derive(Debug, Clone)]
+pub struct FlagSpec {
+    /// The flag name, taken from the field name.
+    pub name: &'static str,
+    /// The kind of value the flag accepts.
+    pub value_kind: ValueKind,
+    /// Whether the flag must be provided.
+    pub required: bool,
+    /// The field's doc comment lines, for `--help` output.
+    pub doc: &'static [&'static str],
+}
+
+/// One positional argument derived from an `argument`/`arguments` field.
+#[derive(Debug, Clone)]
+pub struct PositionalSpec {
+    /// The positional's name, taken from the field name.
+    pub name: &'static str,
+    /// The kind of value it accepts.
+    pub value_kind: ValueKind,
+    /// Whether it collects all remaining positionals.
+    pub repeated: bool,
+}
+
+/// A command or subcommand derived from a node shape.
+#[derive(Debug, Clone)]
+pub struct CommandSpec {
+    /// The command name: the binary name at the root, the node name below.
+    pub name: String,
+    /// Whether the command may be given more than once (children containers).
+    pub repeated: bool,
+    /// Flags derived from property fields.
+    pub flags: Vec<FlagSpec>,
+    /// Positionals derived from argument fields.
+    pub positionals: Vec<PositionalSpec>,
+    /// Subcommands derived from child/children fields.
+    pub subcommands: Vec<CommandSpec>,
+}
+
+/// Derives the CLI spec for a document-shaped type.
+///
+/// `name` is used as the root command name. Returns `None` when `T` is not a
+/// struct.
+pub fn command_spec<'facet, T: Facet<'facet>>(name: &str) -> Option<CommandSpec> {
+    shape_spec(T::SHAPE, name.to_string(), false)
+}
+
+fn shape_spec(shape: &'static Shape, name: String, repeated: bool) -> Option<CommandSpec> {
+    let Type::User(UserType::Struct(struct_type)) = &shape.ty else {
+        return None;
+    };
+    let mut spec = CommandSpec {
+        name,
+        repeated,
+        flags: Vec::new(),
+        positionals: Vec::new(),
+        subcommands: Vec::new(),
+    };
+    collect_fields(struct_type.fields, &mut spec);
+    Some(spec)
+}
+
+fn collect_fields(fields: &'static [Field], spec: &mut CommandSpec) {
+    for field in fields {
+        match field_role(field) {
+            Some(FieldRole::Property) => spec.flags.push(FlagSpec {
+                name: field.name,
+                value_kind: ValueKind::of(field.shape()),
+                required: !matches!(field.shape().def, Def::Option(_)),
+                doc: field.doc,
+            }),
+            Some(FieldRole::Argument) => spec.positionals.push(PositionalSpec {
+                name: field.name,
+                value_kind: ValueKind::of(field.shape()),
+                repeated: false,
+            }),
+            Some(FieldRole::Arguments) => spec.positionals.push(PositionalSpec {
+                name: field.name,
+                value_kind: match field.shape().def {
+                    Def::List(list_def) => ValueKind::of(list_def.t()),
+                    _ => ValueKind::Other,
+                },
+                repeated: true,
+            }),
+            Some(FieldRole::Child) => {
+                if let Some(sub) = shape_spec(field.shape(), field.name.to_string(), false) {
+                    spec.subcommands.push(sub);
+                }
+            }
+            Some(FieldRole::Children) => {
+                let element = match field.shape().def {
+                    Def::List(list_def) => Some(list_def.t()),
+                    Def::Set(set_def) => Some(set_def.t()),
+                    Def::Map(map_def) => Some(map_def.v()),
+                    _ => None,
+                };
+                if let Some(element) = element {
+                    let name = element.type_identifier.to_lowercase();
+                    if let Some(sub) = shape_spec(element, name, true) {
+                        spec.subcommands.push(sub);
+                    }
+                }
+            }
+            Some(FieldRole::Flatten) => {
+                if let Type::User(UserType::Struct(struct_type)) = &field.shape().ty {
+                    collect_fields(struct_type.fields, spec);
+                }
+            }
+            Some(FieldRole::Skip) | None => {}
+        }
+    }
+}
@@ -0,0 +1,240 @@
+//! Completion data export for editors and language servers.
+//!
+//! [`completion_model`] walks a document type the same way the deserializer
+//! does and flattens the result into a plain data structure: which node names
+//! are valid at each path, which properties each node accepts, and — for
+//! enum-typed properties — which values are allowed. The model derives
+//! [`Facet`], so it can be serialized with any facet serializer and shipped
+//! to a language server or the kdl-lsp ecosystem as-is.
+
+use facet::Facet;
+use facet_core::{Def, Field, Shape, Type, UserType};
+
+use crate::fields::{FieldRole, field_role, unwrap_option};
+
+/// The full completion model for a document type.
+#[derive(Debug, Clone, Facet)]
+pub struct CompletionModel {
+    /// One entry per node path reachable from the document root.
+    #[facet(children)]
+    pub scopes: Vec<ScopeCompletions>,
+}
+
+/// The nodes valid at one position in the document tree.
+#[derive(Debug, Clone, Facet)]
+pub struct ScopeCompletions {
+    /// The node names leading here; empty at the document root.
+    #[facet(arguments)]
+    pub path: Vec<String>,
+    /// The nodes that may appear at this position.
+    #[facet(children)]
+    pub nodes: Vec<NodeCompletion>,
+}
+
+/// One node name valid in a scope, with its entry completions.
+#[derive(Debug, Clone, Facet)]
+pub struct NodeCompletion {
+    /// The node name.
+    #[facet(argument)]
+    pub name: String,
+    /// Whether the node may appear more than once.
+    #[facet(property)]
+    pub repeated: bool,
+    /// The properties the node accepts.
+    #[facet(children)]
+    pub properties: Vec<PropertyCompletion>,
+}
+
+/// One property a node accepts.
+#[derive(Debug, Clone, Facet)]
+pub struct PropertyCompletion {
+    /// The property name.
+    #[facet(argument)]
+    pub name: String,
+    /// A rough value type: `"string"`, `"integer"`, `"float"`, `"bool"`, or
+    /// `"other"`.
+    #[facet(property)]
+    pub value_type: String,
+    /// Whether the property must be provided.
+    #[facet(property)]
+    pub required: bool,
+    /// Allowed values, when the target type is a unit-variant enum.
+    #[facet(arguments)]
+    pub values: Vec<String>,
+}
+
+/// Builds the completion model for a document-shaped type.
+pub fn completion_model<'facet, T: Facet<'facet>>() -> CompletionModel {
+    let mut model = CompletionModel { scopes: Vec::new() };
+    let mut stack = Vec::new();
+    collect_scope(T::SHAPE, Vec::new(), &mut model, &mut stack);
+    model
+}
+
+fn collect_scope(
+    shape: &'static Shape,
+    path: Vec<String>,
+    model: &mut CompletionModel,
+    stack: &mut Vec<*const Shape>,
+) {
+    if stack.contains(&(shape as *const Shape)) {
+        // Recursive document types would otherwise produce infinite scopes.
+        return;
+    }
+    stack.push(shape);
+    let mut scope = ScopeCompletions {
+        path: path.clone(),
+        nodes: Vec::new(),
+    };
+    let mut recurse = Vec::new();
+    collect_nodes(shape, &mut scope.nodes, &mut recurse);
+    if !scope.nodes.is_empty() {
+        model.scopes.push(scope);
+    }
+    for (name, child_shape) in recurse {
+        let mut child_path = path.clone();
+        child_path.push(name);
+        collect_scope(child_shape, child_path, model, stack);
+    }
+    stack.pop();
+}
+
+fn collect_nodes(
+    shape: &'static Shape,
+    nodes: &mut Vec<NodeCompletion>,
+    recurse: &mut Vec<(String, &'static Shape)>,
+) {
+    let Type::User(UserType::Struct(struct_type)) = &shape.ty else {
+        return;
+    };
+    for field in struct_type.fields {
+        match field_role(field) {
+            Some(FieldRole::Child) => {
+                let target = unwrap_option(field.shape());
+                match &target.ty {
+                    Type::User(UserType::Enum(enum_type)) => {
+                        // Enum children match on variant names. Variant
+                        // payloads don't have a shape of their own, so
+                        // completion stops at the variant's properties.
+                        for variant in enum_type.variants {
+                            nodes.push(node_completion(
+                                variant.name.to_string(),
+                                variant_fields(variant),
+                                false,
+                            ));
+                        }
+                    }
+                    _ => {
+                        nodes.push(node_completion(
+                            field.name.to_string(),
+                            struct_fields(target),
+                            false,
+                        ));
+                        recurse.push((field.name.to_string(), target));
+                    }
+                }
+            }
+            Some(FieldRole::Children) => {
+                if let Some(element) = container_element(field.shape()) {
+                    let name = element.type_identifier.to_lowercase();
+                    nodes.push(node_completion(name.clone(), struct_fields(element), true));
+                    recurse.push((name, element));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn node_completion(name: String, fields: &'static [Field], repeated: bool) -> NodeCompletion {
+    let mut node = NodeCompletion {
+        name,
+        repeated,
+        properties: Vec::new(),
+    };
+    collect_properties(fields, &mut node.properties);
+    node
+}
+
+fn collect_properties(fields: &'static [Field], properties: &mut Vec<PropertyCompletion>) {
+    for field in fields {
+        match field_role(field) {
+            Some(FieldRole::Property) => properties.push(PropertyCompletion {
+                name: field.name.to_string(),
+                value_type: value_type(field.shape()).to_string(),
+                required: !matches!(field.shape().def, Def::Option(_)),
+                values: allowed_values(unwrap_option(field.shape())),
+            }),
+            Some(FieldRole::Flatten) => match &field.shape().ty {
+                Type::User(UserType::Struct(inner)) => {
+                    collect_properties(inner.fields, properties);
+                }
+                Type::User(UserType::Enum(enum_type)) => {
+                    // Variant-specific properties: all optional from the
+                    // editor's point of view, since picking any one variant
+                    // makes the others invalid.
+                    for variant in enum_type.variants {
+                        let mut variant_properties = Vec::new();
+                        collect_properties(variant_fields(variant), &mut variant_properties);
+                        for mut property in variant_properties {
+                            property.required = false;
+                            properties.push(property);
+                        }
+                    }
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+}
+
+fn value_type(shape: &'static Shape) -> &'static str {
+    match unwrap_option(shape).type_identifier {
+        "String" | "str" | "char" => "string",
+        "bool" => "bool",
+        "f32" | "f64" => "float",
+        "u8" | "u16" | "u32" | "u64" | "u128" | "usize" | "i8" | "i16" | "i32" | "i64" | "i128"
+        | "isize" => "integer",
+        _ => "other",
+    }
+}
+
+/// For unit-variant enums, the variant names are the allowed values.
+fn allowed_values(shape: &'static Shape) -> Vec<String> {
+    let Type::User(UserType::Enum(enum_type)) = &shape.ty else {
+        return Vec::new();
+    };
+    if enum_type
+        .variants
+        .iter()
+        .any(|variant| !variant.data.fields.is_empty())
+    {
+        return Vec::new();
+    }
+    enum_type
+        .variants
+        .iter()
+        .map(|variant| variant.name.to_string())
+        .collect()
+}
+
+fn struct_fields(shape: &'static Shape) -> &'static [Field] {
+    match &shape.ty {
+        Type::User(UserType::Struct(struct_type)) => struct_type.fields,
+        _ => &[],
+    }
+}
+
+fn variant_fields(variant: &'static facet_core::Variant) -> &'static [Field] {
+    variant.data.fields
+}
+
+fn container_element(shape: &'static Shape) -> Option<&'static Shape> {
+    match shape.def {
+        Def::List(list_def) => Some(list_def.t()),
+        Def::Set(set_def) => Some(set_def.t()),
+        Def::Map(map_def) => Some(map_def.v()),
+        _ => None,
+    }
+}
@@ -0,0 +1,3911 @@
+//! Deserialization of KDL documents into `Facet` types.
+
+use facet_core::{Def, Facet, Field, Shape, Type, UserType, Variant};
+use facet_reflect::Partial;
+use kdl::{KdlDocument, KdlEntry, KdlNode, KdlValue};
+use miette::SourceSpan;
+use smallvec::SmallVec;
+use std::borrow::Cow;
+
+use crate::error::{ExpectedProperty, KdlError, KdlErrorKind, KdlErrors};
+use crate::fields::{
+    FieldRole, args_inner, denies_unknown_fields, field_role, has_default, has_kdl_attr,
+    is_node_name, is_props, is_sensitive,
+    is_unit_like, kdl_aliases, kdl_group, kdl_node_or_property, kdl_validator, newtype_inner,
+    pointee,
+    spanned_inner, top_level_offenders, transparent_inner, unit_only_variants, unwrap_option,
+    variant_denies_unknown_fields, variant_list_payload,
+};
+#[cfg(feature = "bitflags")]
+use crate::fields::kdl_flags_with;
+use crate::naming::Naming;
+#[cfg(feature = "solver")]
+use crate::solver::Schema;
+use crate::spanned::Span;
+use crate::version::{KdlVersion, VersionPolicy};
+
+/// How numeric KDL literals may be coerced into differently-kinded Rust
+/// number types.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NumberCoercion {
+    /// Integer literals only fill integer fields, float literals only float
+    /// fields. Out-of-range integers are errors.
+    Strict,
+    /// Conversions that can't lose information are allowed: integer literals
+    /// fill float fields when exactly representable, and whole-number float
+    /// literals fill integer fields. This is the default.
+    #[default]
+    AllowLossless,
+    /// Any numeric conversion is allowed; lossy ones log a warning and
+    /// truncate.
+    AllowLossyWithWarning,
+}
+
+/// What happens when a `child` field's node appears more than once in one
+/// document (or children block), whether under its canonical name or an
+/// alias.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DuplicateNodePolicy {
+    /// Report an error pointing at both occurrences. This is the default.
+    #[default]
+    Error,
+    /// Keep the last occurrence, silently dropping earlier ones.
+    LastWins,
+}
+
+/// What happens when a node selects an enum variant with no child fields but
+/// carries a children block anyway — `Stdout { something }` where `Stdout`
+/// has nowhere to put the block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnexpectedChildrenPolicy {
+    /// Report an error pointing at the block and listing the stray child
+    /// nodes. This is the default.
+    #[default]
+    Error,
+    /// Log a warning listing the stray child nodes and drop them.
+    Warn,
+}
+
+/// How `#null` values aimed at non-`Option` fields are handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NullPolicy {
+    /// `#null` only fills `Option` fields; anywhere else it's an error.
+    /// This is the default.
+    #[default]
+    Error,
+    /// `#null` on a `#[facet(default)]` field means "use the default", the
+    /// convention several KDL tools follow. Fields without the flag still
+    /// error.
+    UseDefault,
+}
+
+/// A value validation hook, run after a field's value has been set.
+///
+/// Receives the raw KDL value and the value's type annotation, if it carries
+/// one (`(semver)"1.2.3"` passes `Some("semver")`), so a single generic hook
+/// can branch on the annotation. Returning `Err` fails deserialization with a
+/// [`KdlErrorKind::ValidationFailed`] pointing at the value's span. Unlike a
+/// `deserialize_with`-style hook, the conversion itself is unchanged — this
+/// is for bounds and invariants on top of it (e.g. a `Percentage(u8)` newtype
+/// requiring `0..=100`).
+pub type Validator = fn(&KdlValue, Option<&str>) -> Result<(), String>;
+
+/// A value validation hook with access to the caller's context object.
+///
+/// Registered under [`DeserializeOptions::context_validators`] and referenced
+/// by the same `#[facet(kdl(validate_with = name))]` attribute as a plain
+/// [`Validator`], but receives the context passed to
+/// [`from_str_with_context`] as its first argument. Hooks downcast it back to
+/// the concrete type they were written against:
+/// `context.downcast_ref::<Paths>()`. A field naming a context validator is a
+/// schema error under the context-less entry points.
+pub type ContextValidator =
+    fn(&dyn std::any::Any, &KdlValue, Option<&str>) -> Result<(), String>;
+
+/// A snapshot handed to a [`Progress`] callback.
+#[derive(Debug)]
+pub struct ProgressReport<'a> {
+    /// Nodes visited so far, across the whole document.
+    pub nodes_visited: usize,
+    /// Node names from the document root down to the node just entered.
+    pub path: &'a [String],
+}
+
+/// A periodic progress hook for very large documents.
+///
+/// The callback runs every `every` visited nodes, so GUIs can surface load
+/// progress on huge datasets (scene graphs, telemetry dumps). Returning
+/// [`ControlFlow::Break`] aborts the run with [`KdlErrorKind::Cancelled`].
+///
+/// [`ControlFlow::Break`]: std::ops::ControlFlow::Break
+#[derive(Clone)]
+pub struct Progress {
+    every: usize,
+    callback: std::sync::Arc<ProgressCallback>,
+}
+
+/// The callback type behind [`Progress::every`].
+type ProgressCallback =
+    dyn Fn(&ProgressReport<'_>) -> std::ops::ControlFlow<()> + Send + Sync;
+
+impl Progress {
+    /// Calls `callback` once every `nodes` visited nodes.
+    ///
+    /// A `nodes` of zero is treated as one; "never" is spelled by leaving
+    /// [`DeserializeOptions::progress`] unset.
+    pub fn every<F>(nodes: usize, callback: F) -> Self
+    where
+        F: Fn(&ProgressReport<'_>) -> std::ops::ControlFlow<()> + Send + Sync + 'static,
+    {
+        Self {
+            every: nodes.max(1),
+            callback: std::sync::Arc::new(callback),
+        }
+    }
+}
+
+impl std::fmt::Debug for Progress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Progress")
+            .field("every", &self.every)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A handle for aborting a running deserialization from another thread.
+///
+/// Clone the token, hand one clone to [`DeserializeOptions::cancellation`]
+/// and keep the other; calling [`cancel`] makes the run stop at the next
+/// node boundary with [`KdlErrorKind::Cancelled`], which carries how many
+/// nodes had been visited.
+///
+/// [`cancel`]: CancellationToken::cancel
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(std::sync::Arc<std::sync::atomic::AtomicBool>);
+
+impl CancellationToken {
+    /// Creates a token in the "not cancelled" state.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requests cancellation. Idempotent; there is no way to un-cancel.
+    pub fn cancel(&self) {
+        self.0.store(true, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    /// Whether [`cancel`] has been called on this token or any clone of it.
+    ///
+    /// [`cancel`]: CancellationToken::cancel
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(std::sync::atomic::Ordering::Relaxed)
+    }
+}
+
+/// Where a field's final value came from during deserialization.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldOrigin {
+    /// The document supplied the value (an entry, a node, or an explicit
+    /// `#null` on an `Option` field).
+    Document,
+    /// The field fell back to its default: it was absent and carried
+    /// `#[facet(default)]` (or is an `Option`), or a `#null` hit it under
+    /// [`NullPolicy::UseDefault`].
+    Default,
+}
+
+/// Which fields a deserialization run filled from the document and which fell
+/// back to defaults, as recorded by [`from_str_with_origins`].
+///
+/// Paths are dotted Rust field names from the root (`server.port`), with
+/// children container elements indexed in document order (`mount[0].path`).
+/// Entries appear in the order the run resolved them; support tooling
+/// rendering an "effective config" view can dim everything marked
+/// [`FieldOrigin::Default`].
+#[derive(Debug, Clone, Default)]
+pub struct FieldOriginMap {
+    entries: Vec<(String, FieldOrigin)>,
+}
+
+impl FieldOriginMap {
+    /// The recorded origin of the field at `path`, if the run touched it.
+    pub fn origin(&self, path: &str) -> Option<FieldOrigin> {
+        self.entries
+            .iter()
+            .find(|(seen, _)| seen == path)
+            .map(|(_, origin)| *origin)
+    }
+
+    /// All recorded `(path, origin)` pairs, in resolution order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, FieldOrigin)> {
+        self.entries
+            .iter()
+            .map(|(path, origin)| (path.as_str(), *origin))
+    }
+
+    /// The number of fields the run recorded an origin for.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether no origins were recorded.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// Options controlling deserialization behavior.
+#[derive(Debug, Clone, Default)]
+pub struct DeserializeOptions {
+    /// How numeric literals are coerced across integer/float kinds.
+    pub number_coercion: NumberCoercion,
+    /// How duplicate `child` nodes are handled.
+    pub duplicate_children: DuplicateNodePolicy,
+    /// How property conflicts between the occurrences of a
+    /// `#[facet(kdl(singleton))]` child are resolved when the nodes merge.
+    /// Independent of [`Self::duplicate_children`], which singleton fields
+    /// bypass entirely.
+    pub singleton_conflicts: DuplicateNodePolicy,
+    /// How `#null` values on non-`Option` fields are handled.
+    pub null_policy: NullPolicy,
+    /// How a children block on a variant node with no child fields is
+    /// handled.
+    pub unexpected_children: UnexpectedChildrenPolicy,
+    /// Named validators referenced by `#[facet(kdl(validate_with = name))]`
+    /// field attributes.
+    pub validators: Vec<(&'static str, Validator)>,
+    /// Named context-aware validators, also referenced by
+    /// `#[facet(kdl(validate_with = name))]`; they only run under
+    /// [`from_str_with_context`], which supplies the context they receive.
+    pub context_validators: Vec<(&'static str, ContextValidator)>,
+    /// Named flag parsers referenced by `#[facet(kdl(flags_with = name))]`
+    /// field attributes; see the [`crate::parse_flags`] helper.
+    #[cfg(feature = "bitflags")]
+    pub flag_parsers: Vec<(&'static str, crate::flags::FlagParser)>,
+    /// The convention mapping Rust field/variant names to document names.
+    pub naming: Naming,
+    /// A periodic callback reporting traversal progress, for interactive
+    /// loads of very large documents.
+    pub progress: Option<Progress>,
+    /// A token checked between nodes, so another thread can abort the run.
+    pub cancellation: Option<CancellationToken>,
+    /// The property key carrying an explicit variant discriminant on
+    /// enum-valued nodes (`kind="VariantName"`), as written by the matching
+    /// `SerializeOptions::variant_property` setting.
+    ///
+    /// When set, the property is accepted on enum nodes (it is not an
+    /// unknown property) and verified against the variant the node name
+    /// selects; a mismatch is an error.
+    pub variant_property: Option<String>,
+    /// Which KDL dialects the parse accepts — KDL 2 only, KDL 1 only, or
+    /// auto-detection trying both. [`from_str_with_version`] reports which
+    /// dialect an auto-detected parse matched.
+    pub version: VersionPolicy,
+    /// A cap on how many variant-combination resolutions the flatten solver
+    /// may enumerate for one node shape; deeply nested multi-flatten schemas
+    /// multiply combinatorially. Exceeding the cap is an error naming the
+    /// flatten fields responsible, raised before the resolutions are built.
+    /// `None` applies the built-in limit of 4096.
+    #[cfg(feature = "solver")]
+    pub max_resolutions: Option<usize>,
+    /// How the flatten solver breaks a tie when several variant combinations
+    /// all accept a node. The default refuses to guess and reports the
+    /// ambiguity as an error.
+    #[cfg(feature = "solver")]
+    pub tie_break: crate::solver::TieBreakPolicy,
+    /// Silently skip properties no field claims, instead of reporting them.
+    ///
+    /// Shapes marked `#[facet(deny_unknown_fields)]` — and enum variants
+    /// marked `#[facet(kdl(deny_unknown_fields))]` — stay strict even when
+    /// this is set, so a flattened enum can mix lenient and strict variants.
+    pub allow_unknown_properties: bool,
+}
+
+/// Size and shape statistics for a KDL document, as computed by [`inspect`].
+///
+/// Everything is counted in one pass over the parsed document, so tooling
+/// can decide cheaply — stream or load whole, accept or reject against a
+/// size limit — before committing to a full typed deserialization.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DocumentStats {
+    /// Total node count, children at every depth included.
+    pub nodes: usize,
+    /// The deepest nesting level; `1` for a flat document, `0` for an empty
+    /// one.
+    pub max_depth: usize,
+    /// Total `key=value` property entries across all nodes.
+    pub properties: usize,
+    /// Total positional argument entries across all nodes.
+    pub arguments: usize,
+    /// String values, across properties and arguments.
+    pub strings: usize,
+    /// Integer values.
+    pub integers: usize,
+    /// Float values.
+    pub floats: usize,
+    /// Boolean values.
+    pub booleans: usize,
+    /// `#null` values.
+    pub nulls: usize,
+}
+
+/// Parses `kdl` and tallies its [`DocumentStats`] in one pass.
+pub fn inspect(kdl: &str) -> Result<DocumentStats, KdlError> {
+    let document = parse(kdl)?;
+    let mut stats = DocumentStats::default();
+    tally_nodes(document.nodes(), 1, &mut stats);
+    Ok(stats)
+}
+
+fn tally_nodes(nodes: &[KdlNode], depth: usize, stats: &mut DocumentStats) {
+    for node in nodes {
+        stats.nodes += 1;
+        stats.max_depth = stats.max_depth.max(depth);
+        for entry in node.entries() {
+            if entry.name().is_some() {
+                stats.properties += 1;
+            } else {
+                stats.arguments += 1;
+            }
+            match entry.value() {
+                KdlValue::String(_) => stats.strings += 1,
+                KdlValue::Integer(_) => stats.integers += 1,
+                KdlValue::Float(_) => stats.floats += 1,
+                KdlValue::Bool(_) => stats.booleans += 1,
+                KdlValue::Null => stats.nulls += 1,
+            }
+        }
+        if let Some(children) = node.children() {
+            tally_nodes(children.nodes(), depth + 1, stats);
+        }
+    }
+}
+
+/// Parses `kdl` into a [`kdl::KdlDocument`] without deserializing anything.
+///
+/// Tooling that inspects the raw document gets facet-kdl's error type (and
+/// its span conventions) instead of depending on a possibly different version
+/// of the `kdl` crate for the parse step alone.
+pub fn parse(kdl: &str) -> Result<KdlDocument, KdlError> {
+    kdl.parse()
+        .map_err(|error| KdlError::new(KdlErrorKind::Parse(error), None, kdl))
+}
+
+/// Parses `kdl` under `policy`, reporting which dialect matched.
+///
+/// Under [`VersionPolicy::Auto`] a document failing both dialects reports
+/// the KDL 2 error, as that is the spec people write today.
+fn parse_versioned(
+    kdl: &str,
+    policy: VersionPolicy,
+) -> Result<(KdlDocument, KdlVersion), kdl::KdlError> {
+    match policy {
+        VersionPolicy::Auto => match KdlDocument::parse_v2(kdl) {
+            Ok(document) => Ok((document, KdlVersion::V2)),
+            Err(v2_error) => match KdlDocument::parse_v1(kdl) {
+                Ok(document) => Ok((document, KdlVersion::V1)),
+                Err(_) => Err(v2_error),
+            },
+        },
+        VersionPolicy::V2Only => {
+            KdlDocument::parse_v2(kdl).map(|document| (document, KdlVersion::V2))
+        }
+        VersionPolicy::V1Only => {
+            KdlDocument::parse_v1(kdl).map(|document| (document, KdlVersion::V1))
+        }
+    }
+}
+
+/// Deserializes a value of type `T` from a KDL document.
+///
+/// The top-level type must be a struct whose fields are all marked
+/// `#[facet(child)]` or `#[facet(children)]`, or a map such as
+/// `HashMap<String, T>` where every top-level node becomes one entry — node
+/// name as key, node contents as value; see the crate documentation for the
+/// full attribute vocabulary.
+///
+/// # Numbers
+///
+/// KDL integer literals are stored as `i128` by the parser. Literals that
+/// don't fit the target field's width are an error (under the default
+/// [`NumberCoercion::AllowLossless`] policy), never a silent truncation.
+/// Values beyond what the literal syntax can carry — e.g.
+/// `"18446744073709551615"` for a `u64` field, or any `u128` — may be written
+/// as strings and are parsed into the target type, with parse failures
+/// reported at the value's span.
+///
+/// # Children order
+///
+/// Elements of a `#[facet(children)]` container appear in document order.
+/// This holds even when the matching nodes are interleaved with other nodes:
+/// each one is appended to its field's container as it is reached, so the
+/// relative order within one field always mirrors the document. Map
+/// containers are filled the same way — keys are inserted in document order,
+/// which sorted maps like `BTreeMap` then re-order by key but
+/// insertion-order-preserving maps observe as-is.
+pub fn from_str<'input, 'facet, T: Facet<'facet>>(kdl: &'input str) -> Result<T, KdlError> {
+    from_str_impl(kdl, false).map_err(|mut errors| errors.errors.remove(0))
+}
+
+/// Like [`from_str`], with explicit [`DeserializeOptions`].
+pub fn from_str_with_options<'input, 'facet, T: Facet<'facet>>(
+    kdl: &'input str,
+    options: &DeserializeOptions,
+) -> Result<T, KdlError> {
+    from_str_opts(kdl, false, false, options.clone(), None)
+        .map(|(value, _, _)| value)
+        .map_err(|mut errors| errors.errors.remove(0))
+}
+
+/// Deserializes a concatenation of KDL documents, one value per document.
+///
+/// Records are separated by lines consisting solely of `---`; when the input
+/// contains no such line, it splits on blank lines instead — the lighter
+/// convention for log-like streams of small documents. Blank-line splitting
+/// means a record can't contain blank lines of its own; inputs that need
+/// them must carry the explicit separator. Empty records (leading or
+/// trailing separators, runs of blank lines) are skipped, so a trailing
+/// separator doesn't produce a phantom value.
+///
+/// Error spans point into the offending record's own text, not the combined
+/// input.
+pub fn from_str_multi<'input, 'facet, T: Facet<'facet>>(
+    kdl: &'input str,
+) -> Result<Vec<T>, KdlError> {
+    from_str_multi_with_options(kdl, &DeserializeOptions::default())
+}
+
+/// Like [`from_str_multi`], with explicit [`DeserializeOptions`].
+pub fn from_str_multi_with_options<'input, 'facet, T: Facet<'facet>>(
+    kdl: &'input str,
+    options: &DeserializeOptions,
+) -> Result<Vec<T>, KdlError> {
+    split_documents(kdl)
+        .into_iter()
+        .map(|record| from_str_with_options(record, options))
+        .collect()
+}
+
+/// Splits a multi-document string into its records, dropping empty ones.
+///
+/// One `---` separator line anywhere switches the whole input to explicit
+/// mode; mixing both conventions would make a blank line mean different
+/// things in different records.
+fn split_documents(kdl: &str) -> Vec<&str> {
+    let explicit = kdl.lines().any(|line| line.trim() == "---");
+    let mut records = Vec::new();
+    let mut start = 0;
+    let mut offset = 0;
+    for line in kdl.split_inclusive('\n') {
+        let boundary = if explicit {
+            line.trim() == "---"
+        } else {
+            line.trim().is_empty()
+        };
+        if boundary {
+            records.push(&kdl[start..offset]);
+            start = offset + line.len();
+        }
+        offset += line.len();
+    }
+    records.push(&kdl[start..]);
+    records.retain(|record| !record.trim().is_empty());
+    records
+}
+
+/// Deserializes a value of type `T` from an already-parsed
+/// [`kdl::KdlDocument`].
+///
+/// Tools that parse once for linting or formatting skip the
+/// render-to-string-and-reparse round trip this way. Error spans are the
+/// document's own, so for a document parsed from text they point into that
+/// text; the source attached to errors is the document rendered back to a
+/// string, which for an unmodified document reproduces the original input
+/// byte for byte. A programmatically edited document may carry stale spans —
+/// kdl-rs does not recompute them — making diagnostics point at the wrong
+/// place, though deserialization itself is unaffected.
+pub fn from_document<'facet, T: Facet<'facet>>(document: &KdlDocument) -> Result<T, KdlError> {
+    from_document_with_options(document, &DeserializeOptions::default())
+}
+
+/// Like [`from_document`], with explicit [`DeserializeOptions`].
+pub fn from_document_with_options<'facet, T: Facet<'facet>>(
+    document: &KdlDocument,
+    options: &DeserializeOptions,
+) -> Result<T, KdlError> {
+    let source = document.to_string();
+    let mut partial = Partial::alloc::<T>()
+        .map_err(|error| KdlError::new(KdlErrorKind::Reflect(error), None, &source))?;
+    let mut deserializer = KdlDeserializer::new(&source);
+    deserializer.options = options.clone();
+    deserializer.deserialize_document(partial.inner_mut(), document, T::SHAPE)?;
+    partial
+        .build()
+        .map(|boxed| *boxed)
+        .map_err(|error| KdlError::new(KdlErrorKind::Reflect(error), None, &source))
+}
+
+/// Deserializes a value of type `T` from a slice of nodes treated as the
+/// document body.
+///
+/// For tools that splice documents together programmatically — a generated
+/// section plus a user-maintained one — without re-stringifying and
+/// re-parsing the combined result. Spans are taken from the nodes verbatim,
+/// so nodes parsed from separate sources keep the offsets of their own
+/// source; callers that want to map a diagnostic back should use
+/// [`KdlError::span`](crate::KdlError) against their own bookkeeping rather
+/// than the error's attached source text, which is the spliced assembly
+/// rendered back to a string.
+pub fn from_nodes<'facet, T: Facet<'facet>>(nodes: &[KdlNode]) -> Result<T, KdlError> {
+    from_nodes_with_options(nodes, &DeserializeOptions::default())
+}
+
+/// Like [`from_nodes`], with explicit [`DeserializeOptions`].
+pub fn from_nodes_with_options<'facet, T: Facet<'facet>>(
+    nodes: &[KdlNode],
+    options: &DeserializeOptions,
+) -> Result<T, KdlError> {
+    let mut document = KdlDocument::new();
+    document.nodes_mut().extend(nodes.iter().cloned());
+    from_document_with_options(&document, options)
+}
+
+/// Like [`from_str_with_options`], passing a caller-owned context object to
+/// context-aware validators.
+///
+/// This is dependency injection for validation hooks: a secrets resolver, a
+/// base directory to resolve relative paths against, a registry of known
+/// names. The context reaches every [`ContextValidator`] as `&dyn Any`;
+/// plain [`Validator`]s never see it and run unchanged.
+pub fn from_str_with_context<'input, 'facet, T: Facet<'facet>, C: 'static>(
+    kdl: &'input str,
+    options: &DeserializeOptions,
+    context: &'input C,
+) -> Result<T, KdlError> {
+    from_str_opts(kdl, false, false, options.clone(), Some(context))
+        .map(|(value, _, _)| value)
+        .map_err(|mut errors| errors.errors.remove(0))
+}
+
+/// Like [`from_str_with_options`], additionally reporting which fields were
+/// filled from the document and which fell back to defaults.
+///
+/// The returned [`FieldOriginMap`] is what support tooling needs to render an
+/// "effective config" view that distinguishes user-set values from defaults.
+/// Fields a flattened-enum (solver) node leaves to `#[facet(default)]` are
+/// not recorded; everything routed through the plain paths is.
+pub fn from_str_with_origins<'input, 'facet, T: Facet<'facet>>(
+    kdl: &'input str,
+    options: &DeserializeOptions,
+) -> Result<(T, FieldOriginMap), KdlError> {
+    from_str_opts(kdl, false, true, options.clone(), None)
+        .map(|(value, origins, _)| (value, origins))
+        .map_err(|mut errors| errors.errors.remove(0))
+}
+
+/// Like [`from_str_with_options`], additionally reporting which KDL dialect
+/// the parse matched.
+///
+/// Under the default [`VersionPolicy::Auto`] this is how a rewriting tool
+/// learns whether it read a legacy KDL 1 file, so it can set
+/// `SerializeOptions::dialect` accordingly and hand the file back in the
+/// dialect it arrived in. Under the strict policies the answer is fixed by
+/// the policy itself.
+pub fn from_str_with_version<'input, 'facet, T: Facet<'facet>>(
+    kdl: &'input str,
+    options: &DeserializeOptions,
+) -> Result<(T, KdlVersion), KdlError> {
+    from_str_opts(kdl, false, false, options.clone(), None)
+        .map(|(value, _, version)| (value, version))
+        .map_err(|mut errors| errors.errors.remove(0))
+}
+
+/// Deserializes `kdl` as `T` and returns a copy of the input annotated with
+/// inline comments describing how each piece was mapped.
+///
+/// Nodes gain a comment naming the field path they routed into, properties
+/// the field they filled, enum nodes the variant chosen; fields that fell
+/// back to defaults are listed in a trailing comment. The notes come from
+/// the same traversal as real deserialization, so the output reflects what
+/// [`from_str_with_options`] would actually do — far easier to read than
+/// `log::trace` output when debugging a mapping. The built value is
+/// discarded; errors abort annotation just like deserialization.
+pub fn annotate<'input, 'facet, T: Facet<'facet>>(
+    kdl: &'input str,
+    options: &DeserializeOptions,
+) -> Result<String, KdlError> {
+    let (document, _) = parse_versioned(kdl, options.version)
+        .map_err(|error| KdlError::new(KdlErrorKind::Parse(error), None, kdl))?;
+    let mut partial = Partial::alloc::<T>()
+        .map_err(|error| KdlError::new(KdlErrorKind::Reflect(error), None, kdl))?;
+    let mut deserializer = KdlDeserializer::new(kdl);
+    deserializer.options = options.clone();
+    deserializer.origins = Some(FieldOriginMap::default());
+    deserializer.trace = Some(Vec::new());
+    deserializer.deserialize_document(partial.inner_mut(), &document, T::SHAPE)?;
+    let events = deserializer.trace.take().unwrap_or_default();
+    let origins = deserializer.origins.take().unwrap_or_default();
+    Ok(render_annotated(kdl, &events, &origins))
+}
+
+/// Lays [`annotate`]'s collected notes over the original input.
+fn render_annotated(input: &str, events: &[(usize, String)], origins: &FieldOriginMap) -> String {
+    let mut output = String::with_capacity(input.len() + events.len() * 32);
+    let mut cursor = 0;
+    for line in input.split_inclusive('\n') {
+        let start = cursor;
+        cursor += line.len();
+        let (content, newline) = match line.strip_suffix('\n') {
+            Some(stripped) => (stripped, "\n"),
+            None => (line, ""),
+        };
+        output.push_str(content);
+        let mut notes = events
+            .iter()
+            .filter(|(offset, _)| (start..cursor).contains(offset))
+            .map(|(_, note)| note.as_str());
+        if let Some(first) = notes.next() {
+            output.push_str("  // ");
+            output.push_str(first);
+            for note in notes {
+                output.push_str("; ");
+                output.push_str(note);
+            }
+        }
+        output.push_str(newline);
+    }
+    let defaulted: Vec<&str> = origins
+        .iter()
+        .filter(|(_, origin)| *origin == FieldOrigin::Default)
+        .map(|(path, _)| path)
+        .collect();
+    if !defaulted.is_empty() {
+        if !output.is_empty() && !output.ends_with('\n') {
+            output.push('\n');
+        }
+        output.push_str("// defaulted: ");
+        output.push_str(&defaulted.join(", "));
+        output.push('\n');
+    }
+    output
+}
+
+/// One scalar visited by [`walk`]: where it would land in the target type,
+/// what the document says, and where it says it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WalkEntry {
+    /// Dotted field path from the root (`server.port`), with children
+    /// container elements indexed in document order (`mount[0].path`).
+    pub path: String,
+    /// The raw KDL value, before any conversion into the field's type.
+    pub value: KdlValue,
+    /// The value's span in the input text.
+    pub span: Span,
+}
+
+/// Lists every property and argument of `kdl` with its resolved field path
+/// under `T`'s matching rules, in document order.
+///
+/// This is the building block for audit tooling — "list every place TLS is
+/// disabled across 200 config files" is a `walk` per file plus a filter on
+/// [`WalkEntry::path`] and [`WalkEntry::value`]. The triples come from the
+/// same traversal as real deserialization, so paths reflect renames,
+/// aliases, flattening and variant selection exactly as
+/// [`from_str`] resolves them. The built value is discarded; errors abort
+/// the walk just like deserialization. Values are reported verbatim —
+/// `#[facet(sensitive)]` redaction applies to diagnostics, not to a caller
+/// who already holds the input the spans point into.
+pub fn walk<'input, 'facet, T: Facet<'facet>>(kdl: &'input str) -> Result<Vec<WalkEntry>, KdlError> {
+    walk_with_options::<T>(kdl, &DeserializeOptions::default())
+}
+
+/// Like [`walk`], with explicit [`DeserializeOptions`].
+pub fn walk_with_options<'input, 'facet, T: Facet<'facet>>(
+    kdl: &'input str,
+    options: &DeserializeOptions,
+) -> Result<Vec<WalkEntry>, KdlError> {
+    let (document, _) = parse_versioned(kdl, options.version)
+        .map_err(|error| KdlError::new(KdlErrorKind::Parse(error), None, kdl))?;
+    let mut partial = Partial::alloc::<T>()
+        .map_err(|error| KdlError::new(KdlErrorKind::Reflect(error), None, kdl))?;
+    let mut deserializer = KdlDeserializer::new(kdl);
+    deserializer.options = options.clone();
+    deserializer.walk = Some(Vec::new());
+    deserializer.deserialize_document(partial.inner_mut(), &document, T::SHAPE)?;
+    Ok(deserializer.walk.take().unwrap_or_default())
+}
+
+/// Like [`from_str`], but keeps going after recoverable errors (unknown
+/// properties, unknown nodes) and reports everything it found in one
+/// [`KdlErrors`] diagnostic.
+///
+/// Errors that leave the partially-built value in an unusable state (parse
+/// errors, reflection errors) still abort immediately.
+pub fn from_str_collect_errors<'input, 'facet, T: Facet<'facet>>(
+    kdl: &'input str,
+) -> Result<T, KdlErrors> {
+    from_str_impl(kdl, true)
+}
+
+/// Like [`from_str_collect_errors`], with explicit [`DeserializeOptions`].
+///
+/// Strict unknown checking combined with multi-error collection is the shape
+/// CI validation of config files wants: one run lists every unknown property
+/// and node, each with its span and its expected-names suggestion.
+pub fn from_str_collect_errors_with_options<'input, 'facet, T: Facet<'facet>>(
+    kdl: &'input str,
+    options: &DeserializeOptions,
+) -> Result<T, KdlErrors> {
+    from_str_opts(kdl, true, false, options.clone(), None).map(|(value, _, _)| value)
+}
+
+fn from_str_impl<'input, 'facet, T: Facet<'facet>>(
+    kdl: &'input str,
+    collect_all: bool,
+) -> Result<T, KdlErrors> {
+    from_str_opts(kdl, collect_all, false, DeserializeOptions::default(), None)
+        .map(|(value, _, _)| value)
+}
+
+fn from_str_opts<'input, 'facet, T: Facet<'facet>>(
+    kdl: &'input str,
+    collect_all: bool,
+    track_origins: bool,
+    options: DeserializeOptions,
+    context: Option<&'input dyn std::any::Any>,
+) -> Result<(T, FieldOriginMap, KdlVersion), KdlErrors> {
+    let (document, version) = parse_versioned(kdl, options.version).map_err(|error| {
+        KdlErrors::new(vec![KdlError::new(KdlErrorKind::Parse(error), None, kdl)])
+    })?;
+    let mut partial = Partial::alloc::<T>().map_err(|error| {
+        KdlErrors::new(vec![KdlError::new(KdlErrorKind::Reflect(error), None, kdl)])
+    })?;
+    let mut deserializer = KdlDeserializer::new(kdl);
+    deserializer.collect_all = collect_all;
+    deserializer.options = options;
+    deserializer.context = context;
+    if track_origins {
+        deserializer.origins = Some(FieldOriginMap::default());
+    }
+    if let Err(fatal) = deserializer.deserialize_document(partial.inner_mut(), &document, T::SHAPE) {
+        deserializer.errors.push(fatal);
+        return Err(KdlErrors::new(deserializer.errors));
+    }
+    if !deserializer.errors.is_empty() {
+        return Err(KdlErrors::new(deserializer.errors));
+    }
+    let origins = deserializer.origins.take().unwrap_or_default();
+    let value = partial
+        .build()
+        .map(|boxed| *boxed)
+        .map_err(|error| {
+            KdlErrors::new(vec![KdlError::new(KdlErrorKind::Reflect(error), None, kdl)])
+        })?;
+    Ok((value, origins, version))
+}
+
+/// The order in which a node's entries are processed.
+///
+/// Normally this is document order. With the test-only `shuffle-entries`
+/// feature, named entries are deterministically permuted (seeded from the
+/// `FACET_KDL_SHUFFLE_SEED` environment variable) while positional entries
+/// keep their slots, so hidden order dependencies in the flatten/solver
+/// bookkeeping show up as test failures instead of field reports.
+#[cfg(not(feature = "shuffle-entries"))]
+fn entry_order(node: &KdlNode) -> Vec<&KdlEntry> {
+    node.entries().iter().collect()
+}
+
+#[cfg(feature = "shuffle-entries")]
+fn entry_order(node: &KdlNode) -> Vec<&KdlEntry> {
+    let mut entries: Vec<&KdlEntry> = node.entries().iter().collect();
+    let named_slots: Vec<usize> = entries
+        .iter()
+        .enumerate()
+        .filter_map(|(index, entry)| entry.name().is_some().then_some(index))
+        .collect();
+    let mut seed: u64 = std::env::var("FACET_KDL_SHUFFLE_SEED")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(0x5eed);
+    // Fisher-Yates over the named slots only, with a tiny xorshift PRNG so
+    // runs are reproducible from the seed alone.
+    for i in (1..named_slots.len()).rev() {
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        let j = (seed % (i as u64 + 1)) as usize;
+        entries.swap(named_slots[i], named_slots[j]);
+    }
+    entries
+}
+
+/// Whether a KDL value could fill the given shape, without committing to it.
+///
+/// This is the cheap compatibility check the solver uses to filter candidate
+/// resolutions; [`KdlDeserializer::deserialize_value`] performs the actual
+/// conversion. It honors the same [`NumberCoercion`] policy as the real
+/// conversion so that disambiguation and assignment can't disagree.
+#[cfg(feature = "solver")]
+pub(crate) fn kdl_value_fits_shape(
+    value: &KdlValue,
+    shape: &'static Shape,
+    coercion: NumberCoercion,
+) -> bool {
+    let shape = match shape.def {
+        Def::Option(option_def) => option_def.t(),
+        _ => shape,
+    };
+    match value {
+        KdlValue::String(_) => matches!(shape.type_identifier, "String" | "str" | "char"),
+        KdlValue::Integer(integer) => {
+            if is_integer_identifier(shape.type_identifier) {
+                return true;
+            }
+            if is_float_identifier(shape.type_identifier) {
+                return match coercion {
+                    NumberCoercion::Strict => false,
+                    NumberCoercion::AllowLossless => integer_is_exact_in_f64(*integer),
+                    NumberCoercion::AllowLossyWithWarning => true,
+                };
+            }
+            false
+        }
+        KdlValue::Float(float) => {
+            if is_float_identifier(shape.type_identifier) {
+                return true;
+            }
+            if is_integer_identifier(shape.type_identifier) {
+                return match coercion {
+                    NumberCoercion::Strict => false,
+                    NumberCoercion::AllowLossless => float.fract() == 0.0 && float.is_finite(),
+                    // `#inf`/`#-inf`/`#nan` have no integer to truncate to.
+                    NumberCoercion::AllowLossyWithWarning => float.is_finite(),
+                };
+            }
+            false
+        }
+        KdlValue::Bool(_) => shape.type_identifier == "bool",
+        KdlValue::Null => matches!(shape.def, Def::Option(_)),
+    }
+}
+
+fn is_integer_identifier(identifier: &str) -> bool {
+    matches!(
+        identifier,
+        "u8" | "u16"
+            | "u32"
+            | "u64"
+            | "u128"
+            | "usize"
+            | "i8"
+            | "i16"
+            | "i32"
+            | "i64"
+            | "i128"
+            | "isize"
+    )
+}
+
+fn is_float_identifier(identifier: &str) -> bool {
+    matches!(identifier, "f32" | "f64")
+}
+
+/// Whether an i128 survives a round trip through f64 unchanged.
+fn integer_is_exact_in_f64(integer: i128) -> bool {
+    (integer as f64) as i128 == integer
+}
+
+/// Renders a float the way KDL spells it, so diagnostics echo the keyword
+/// forms (`#inf`, `#-inf`, `#nan`) rather than Rust's `inf`/`NaN`.
+fn render_float(float: f64) -> String {
+    if float.is_nan() {
+        "#nan".to_string()
+    } else if float == f64::INFINITY {
+        "#inf".to_string()
+    } else if float == f64::NEG_INFINITY {
+        "#-inf".to_string()
+    } else {
+        float.to_string()
+    }
+}
+
+/// Tracks progress through the positional arguments of a node.
+enum ArgumentsState {
+    /// No `arguments` list has been opened yet.
+    NotStarted,
+    /// The `arguments` list is currently open in the `Partial`.
+    Open,
+    /// The `arguments` list has been closed; positional entries are no longer
+    /// accepted.
+    Completed,
+}
+
+pub(crate) struct KdlDeserializer<'input> {
+    /// The raw source, for error construction.
+    input: &'input str,
+    /// The chain of flattened fields currently open in the `Partial`.
+    ///
+    /// Inline storage: flatten nesting deeper than a handful of levels is
+    /// unheard of, so the common case never heap-allocates.
+    open_paths: SmallVec<[&'static str; 8]>,
+    /// Property keys already consumed on the current node.
+    ///
+    /// Keys matching a field name exactly are interned as that field's
+    /// `&'static str`; only renamed or unknown keys allocate.
+    seen_keys: SmallVec<[Cow<'static, str>; 16]>,
+    /// Property names the current shape accepts, for diagnostics. A plain
+    /// `Vec` is fine: it's reused across nodes (cleared, capacity kept) and
+    /// only filled on the error path.
+    property_names: Vec<ExpectedProperty>,
+    /// When set, recoverable errors are collected instead of aborting.
+    collect_all: bool,
+    /// Options this run was started with.
+    options: DeserializeOptions,
+    /// Recoverable errors collected so far.
+    errors: Vec<KdlError>,
+    /// Set while converting a value for a `#[facet(sensitive)]` field, so
+    /// diagnostics and logs never echo the value itself.
+    redacting: bool,
+    /// Whether the node currently being processed rejects unknown properties.
+    ///
+    /// Refreshed per node from `allow_unknown_properties` and any
+    /// `deny_unknown_fields` mark on the node's shape or selected variant.
+    strict_properties: bool,
+    /// Nodes visited so far, for progress reporting.
+    nodes_visited: usize,
+    /// Node names from the document root down to the node being processed,
+    /// maintained only to hand to progress callbacks.
+    node_path: Vec<String>,
+    /// Field origins recorded so far; `Some` only under
+    /// [`from_str_with_origins`].
+    origins: Option<FieldOriginMap>,
+    /// Field-path segments from the root to the field currently being filled,
+    /// for origin recording.
+    origin_path: Vec<String>,
+    /// The caller's context object, handed to context-aware validators;
+    /// `Some` only under [`from_str_with_context`].
+    context: Option<&'input dyn std::any::Any>,
+    /// Mapping notes recorded as `(input offset, note)` pairs; `Some` only
+    /// under [`annotate`].
+    trace: Option<Vec<(usize, String)>>,
+    /// Collected `(path, value, span)` triples when running under [`walk`].
+    walk: Option<Vec<WalkEntry>>,
+    /// Whether the node currently being processed maps to an enum variant,
+    /// where a configured discriminant property is expected and consumed.
+    ///
+    /// Refreshed per node, like `strict_properties`.
+    variant_node: bool,
+}
+
+impl<'input> KdlDeserializer<'input> {
+    pub(crate) fn new(input: &'input str) -> Self {
+        Self {
+            input,
+            open_paths: SmallVec::new(),
+            seen_keys: SmallVec::new(),
+            property_names: Vec::new(),
+            collect_all: false,
+            options: DeserializeOptions::default(),
+            errors: Vec::new(),
+            redacting: false,
+            strict_properties: true,
+            nodes_visited: 0,
+            node_path: Vec::new(),
+            origins: None,
+            origin_path: Vec::new(),
+            context: None,
+            trace: None,
+            walk: None,
+            variant_node: false,
+        }
+    }
+
+    /// Checks a discriminant property (`kind="Variant"`) against the selected
+    /// variant, when [`DeserializeOptions::variant_property`] names one.
+    fn verify_variant_property(
+        &self,
+        node: &KdlNode,
+        variant: &'static Variant,
+    ) -> Result<(), KdlError> {
+        let Some(key) = self.options.variant_property.as_deref() else {
+            return Ok(());
+        };
+        for entry in node.entries() {
+            let matches_key = entry.name().is_some_and(|name| name.value() == key);
+            if !matches_key {
+                continue;
+            }
+            let found = match entry.value() {
+                KdlValue::String(text) => text.clone(),
+                other => other.to_string(),
+            };
+            if found != variant.name {
+                return Err(self.error(
+                    KdlErrorKind::VariantPropertyMismatch {
+                        key: key.to_string(),
+                        expected: variant.name,
+                        found,
+                    },
+                    entry.span(),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Records a mapping note for [`annotate`] at the given span's offset.
+    ///
+    /// Call sites guard with `self.trace.is_some()` so the note is only
+    /// formatted when a trace is actually being collected.
+    fn trace_note(&mut self, span: SourceSpan, note: String) {
+        if let Some(events) = &mut self.trace {
+            events.push((span.offset(), note));
+        }
+    }
+
+    /// The dotted path of the field named `leaf` under the current field
+    /// path.
+    fn field_path(&self, leaf: &str) -> String {
+        if self.origin_path.is_empty() {
+            leaf.to_string()
+        } else {
+            format!("{}.{leaf}", self.origin_path.join("."))
+        }
+    }
+
+    /// Whether field-path segments need to be maintained at all — only
+    /// origin recording, annotate traces and walk runs ever read them.
+    fn tracks_field_paths(&self) -> bool {
+        self.origins.is_some() || self.trace.is_some() || self.walk.is_some()
+    }
+
+    /// Pushes a field-path segment, building the string only when something
+    /// will read it; the plain deserialization path skips the allocation
+    /// entirely. Balanced by a plain `origin_path.pop()`, which is a no-op
+    /// on the empty vector.
+    fn push_field_path(&mut self, segment: impl FnOnce() -> String) {
+        if self.tracks_field_paths() {
+            self.origin_path.push(segment());
+        }
+    }
+
+    /// Pushes the flatten-path segments in `prefix` onto the field path;
+    /// see [`Self::push_field_path`].
+    fn push_field_path_prefix(&mut self, prefix: &[&'static str]) {
+        if self.tracks_field_paths() {
+            for segment in prefix {
+                self.origin_path.push((*segment).to_string());
+            }
+        }
+    }
+
+    /// Drops the segments a matching [`Self::push_field_path_prefix`] added.
+    fn pop_field_path_prefix(&mut self, prefix: &[&'static str]) {
+        if self.tracks_field_paths() {
+            self.origin_path
+                .truncate(self.origin_path.len() - prefix.len());
+        }
+    }
+
+    /// Records the origin of the field named `leaf` under the current field
+    /// path, overwriting an earlier record for the same path.
+    ///
+    /// Last-wins matters: unit-like marker fields are defaulted eagerly and
+    /// re-recorded as document-supplied when their presence node turns up.
+    fn record_origin(&mut self, leaf: &str, origin: FieldOrigin) {
+        let Some(map) = &mut self.origins else {
+            return;
+        };
+        let path = if self.origin_path.is_empty() {
+            leaf.to_string()
+        } else {
+            format!("{}.{leaf}", self.origin_path.join("."))
+        };
+        if let Some((_, recorded)) = map.entries.iter_mut().find(|(seen, _)| *seen == path) {
+            *recorded = origin;
+        } else {
+            map.entries.push((path, origin));
+        }
+    }
+
+    /// Renders a value for a diagnostic or log line, honoring redaction.
+    fn render_value(&self, value: impl core::fmt::Display) -> String {
+        if self.redacting {
+            "<redacted>".to_string()
+        } else {
+            value.to_string()
+        }
+    }
+
+    /// Records a recoverable error in collect-all mode, or returns it.
+    fn recover(&mut self, error: KdlError) -> Result<(), KdlError> {
+        if self.collect_all {
+            self.errors.push(error);
+            Ok(())
+        } else {
+            Err(error)
+        }
+    }
+
+    fn error(&self, kind: KdlErrorKind, span: impl Into<Option<SourceSpan>>) -> KdlError {
+        KdlError::new(kind, span.into(), self.input)
+    }
+
+    fn reflect(&self, error: facet_reflect::ReflectError, span: SourceSpan) -> KdlError {
+        self.error(KdlErrorKind::Reflect(error), span)
+    }
+
+    /// Deserializes the top level of a document.
+    pub(crate) fn deserialize_document(
+        &mut self,
+        partial: &mut Partial,
+        document: &KdlDocument,
+        shape: &'static Shape,
+    ) -> Result<(), KdlError> {
+        // A newtype document delegates to its inner type: `struct Doc(Inner)`
+        // deserializes as `Inner`, wrapped on the way out.
+        if let Some(inner) = newtype_inner(shape) {
+            partial
+                .begin_field(inner.name)
+                .map_err(|error| self.error(KdlErrorKind::Reflect(error), None))?;
+            self.deserialize_document(partial, document, inner.shape())?;
+            partial
+                .end()
+                .map_err(|error| self.error(KdlErrorKind::Reflect(error), None))?;
+            return Ok(());
+        }
+        // A map document skips the wrapper struct: every top-level node
+        // becomes one entry, node name as key, node contents as value. The
+        // children-container path already does exactly this for a field; the
+        // root only lacks a field frame to open around the map.
+        if let Def::Map(map_def) = shape.def {
+            let span = document.nodes().first().map(|node| node.span());
+            partial
+                .begin_map()
+                .map_err(|error| self.error(KdlErrorKind::Reflect(error), span))?;
+            for (index, node) in document.nodes().iter().enumerate() {
+                if self.trace.is_some() {
+                    let note = format!("node -> `{}`", self.field_path(&format!("[{index}]")));
+                    self.trace_note(node.span(), note);
+                }
+                self.push_field_path(|| format!("[{index}]"));
+                let result =
+                    self.deserialize_map_children_entry(partial, node, map_def.k(), map_def.v());
+                self.origin_path.pop();
+                result?;
+            }
+            return Ok(());
+        }
+        let Type::User(UserType::Struct(struct_type)) = &shape.ty else {
+            return Err(self.error(KdlErrorKind::InvalidDocumentShape(shape), None));
+        };
+        let offenders = top_level_offenders(struct_type.fields);
+        if !offenders.is_empty() {
+            return Err(self.error(
+                KdlErrorKind::IllegalAttributesOnTopLevelShape { shape, offenders },
+                None,
+            ));
+        }
+        self.deserialize_document_with_fields(partial, document.nodes(), struct_type.fields)
+    }
+
+    /// Routes a run of nodes into `child`/`children` fields.
+    ///
+    /// Used for the top level of a document, for the children block of any
+    /// node, and by the incremental reparser with a pruned node list.
+    pub(crate) fn deserialize_document_with_fields(
+        &mut self,
+        partial: &mut Partial,
+        nodes: &[KdlNode],
+        fields: &'static [Field],
+    ) -> Result<(), KdlError> {
+        // Catch `#[facet(children)]` on a non-container up front: letting a
+        // node route into it would surface as an opaque reflection error.
+        for field in fields {
+            if field_role(field) != Some(FieldRole::Children) {
+                continue;
+            }
+            let mut shape = field.shape();
+            loop {
+                if let Def::Option(option_def) = shape.def {
+                    shape = option_def.t();
+                    continue;
+                }
+                if let Some(inner) = pointee(shape) {
+                    shape = inner;
+                    continue;
+                }
+                break;
+            }
+            let element = match shape.def {
+                Def::List(list_def) => list_def.t(),
+                Def::Set(set_def) => set_def.t(),
+                Def::Map(map_def) => map_def.v(),
+                _ => {
+                    return Err(self.error(
+                        KdlErrorKind::SchemaError(format!(
+                            "children field `{}` has non-container type `{}`; wrap it in \
+                             `Vec<{shape}>`, or mark it `#[facet(child)]` if one node is meant",
+                            field.name,
+                            field.shape()
+                        )),
+                        None,
+                    ));
+                }
+            };
+            // Catch unsupported element types here too: letting a node route
+            // into one would surface as a begin_list_item or select_variant
+            // reflection error long after the real mistake.
+            let element = spanned_inner(element).unwrap_or(element);
+            if !matches!(
+                &element.ty,
+                Type::User(UserType::Struct(_) | UserType::Enum(_))
+            ) {
+                return Err(self.error(
+                    KdlErrorKind::SchemaError(format!(
+                        "children field `{}` has element type `{element}`, which can't \
+                         fill a node; elements must be structs or enums (scalar \
+                         elements aren't supported yet)",
+                        field.name
+                    )),
+                    None,
+                ));
+            }
+        }
+        // Under `LastWins`, remember where each child field's final
+        // occurrence sits so routing can skip the superseded ones outright:
+        // re-entering an already-built child frame is not something the
+        // reflection backend supports.
+        let mut last_child_spans: Vec<(&'static str, SourceSpan)> = Vec::new();
+        if self.options.duplicate_children == DuplicateNodePolicy::LastWins {
+            for node in nodes {
+                let name = node.name().value();
+                let ty = node.ty().map(|ty| ty.value());
+                if let Some(field) = fields.iter().find(|field| {
+                    field_role(field) == Some(FieldRole::Child)
+                        && child_field_matches(field, name, ty, &self.options.naming)
+                }) {
+                    match last_child_spans
+                        .iter_mut()
+                        .find(|(seen, _)| *seen == field.name)
+                    {
+                        Some((_, span)) => *span = node.span(),
+                        None => last_child_spans.push((field.name, node.span())),
+                    }
+                }
+            }
+        }
+        let merged_singletons = self.merge_singleton_children(nodes, fields)?;
+        let mut seen_children: Vec<(&'static str, SourceSpan)> = Vec::new();
+        let mut children_counts: Vec<(&'static str, usize)> = Vec::new();
+        for node in nodes {
+            // The path vector exists for progress callbacks alone; without
+            // one, skip the per-node allocation.
+            if self.options.progress.is_some() {
+                self.node_path.push(node.name().value().to_string());
+            }
+            let routed = self.tick_progress(node.span()).and_then(|()| {
+                self.route_node(
+                    partial,
+                    node,
+                    nodes,
+                    fields,
+                    &mut seen_children,
+                    &mut children_counts,
+                    &last_child_spans,
+                    &merged_singletons,
+                )
+            });
+            self.node_path.pop();
+            routed?;
+        }
+        self.fill_unit_like_fields(partial, fields)?;
+        self.finish_children_containers(partial, fields)?;
+        self.fill_missing_child_fields(partial, nodes, fields)?;
+        Ok(())
+    }
+
+    /// Counts a visited node, checks the cancellation token, and runs the
+    /// progress callback when one is due.
+    fn tick_progress(&mut self, span: SourceSpan) -> Result<(), KdlError> {
+        self.nodes_visited += 1;
+        if let Some(token) = &self.options.cancellation {
+            if token.is_cancelled() {
+                return Err(self.error(
+                    KdlErrorKind::Cancelled {
+                        nodes_visited: self.nodes_visited,
+                    },
+                    span,
+                ));
+            }
+        }
+        let Some(progress) = &self.options.progress else {
+            return Ok(());
+        };
+        if !self.nodes_visited.is_multiple_of(progress.every) {
+            return Ok(());
+        }
+        let report = ProgressReport {
+            nodes_visited: self.nodes_visited,
+            path: &self.node_path,
+        };
+        if (progress.callback)(&report).is_break() {
+            return Err(self.error(
+                KdlErrorKind::Cancelled {
+                    nodes_visited: self.nodes_visited,
+                },
+                span,
+            ));
+        }
+        Ok(())
+    }
+
+    /// Merges the occurrences of each repeated `#[facet(kdl(singleton))]`
+    /// child into one synthetic node, as `(field name, first span, merged)`.
+    ///
+    /// Later occurrences contribute their properties and children; the
+    /// entries keep their original spans, so value diagnostics still point
+    /// at the right occurrence. Positional arguments only make sense once,
+    /// on the first occurrence.
+    fn merge_singleton_children(
+        &mut self,
+        nodes: &[KdlNode],
+        fields: &'static [Field],
+    ) -> Result<Vec<(&'static str, SourceSpan, KdlNode)>, KdlError> {
+        let mut merged: Vec<(&'static str, SourceSpan, KdlNode)> = Vec::new();
+        for node in nodes {
+            let name = node.name().value();
+            let ty = node.ty().map(|ty| ty.value());
+            let Some(field) = fields.iter().find(|field| {
+                field_role(field) == Some(FieldRole::Child)
+                    && has_kdl_attr(field, "singleton")
+                    && child_field_matches(field, name, ty, &self.options.naming)
+            }) else {
+                continue;
+            };
+            let Some((_, _, target)) = merged
+                .iter_mut()
+                .find(|(seen, _, _)| *seen == field.name)
+            else {
+                merged.push((field.name, node.span(), node.clone()));
+                continue;
+            };
+            for entry in node.entries() {
+                let Some(entry_name) = entry.name() else {
+                    return Err(self.error(
+                        KdlErrorKind::UnsupportedShape(format!(
+                            "repeated singleton node `{name}` carries a positional \
+                             argument; only the first occurrence may"
+                        )),
+                        entry.span(),
+                    ));
+                };
+                let existing = target.entries().iter().position(|seen| {
+                    seen.name()
+                        .is_some_and(|seen_name| seen_name.value() == entry_name.value())
+                });
+                match existing {
+                    Some(index) => {
+                        if self.options.singleton_conflicts == DuplicateNodePolicy::Error {
+                            let error = self.error(
+                                KdlErrorKind::SingletonConflict {
+                                    field: field.name,
+                                    key: entry_name.value().to_string(),
+                                    first: target.entries()[index].span(),
+                                    offending: entry.span(),
+                                },
+                                entry.span(),
+                            );
+                            self.recover(error)?;
+                        }
+                        target.entries_mut()[index] = entry.clone();
+                    }
+                    None => target.entries_mut().push(entry.clone()),
+                }
+            }
+            if let Some(children) = node.children() {
+                target
+                    .ensure_children()
+                    .nodes_mut()
+                    .extend(children.nodes().iter().cloned());
+            }
+        }
+        Ok(merged)
+    }
+
+    /// Routes one node into the `child`/`children` field that claims it.
+    #[allow(clippy::too_many_arguments)]
+    fn route_node(
+        &mut self,
+        partial: &mut Partial,
+        node: &KdlNode,
+        nodes: &[KdlNode],
+        fields: &'static [Field],
+        seen_children: &mut Vec<(&'static str, SourceSpan)>,
+        children_counts: &mut Vec<(&'static str, usize)>,
+        last_child_spans: &[(&'static str, SourceSpan)],
+        merged_singletons: &[(&'static str, SourceSpan, KdlNode)],
+    ) -> Result<(), KdlError> {
+        let name = node.name().value();
+        let ty = node.ty().map(|ty| ty.value());
+        if let Some(field) = fields.iter().find(|field| {
+            field_role(field) == Some(FieldRole::Child)
+                && child_field_matches(field, name, ty, &self.options.naming)
+        }) {
+            // A singleton field deserializes its pre-merged node when the
+            // first occurrence is reached; the rest were folded into it.
+            if let Some((_, first, merged)) = merged_singletons
+                .iter()
+                .find(|(seen, _, _)| *seen == field.name)
+            {
+                if *first != node.span() {
+                    if self.trace.is_some() {
+                        let note = format!(
+                            "node -> `{}` (merged into the first occurrence)",
+                            self.field_path(field.name)
+                        );
+                        self.trace_note(node.span(), note);
+                    }
+                    return Ok(());
+                }
+                if self.trace.is_some() {
+                    let note = format!("node -> `{}`", self.field_path(field.name));
+                    self.trace_note(node.span(), note);
+                }
+                self.record_origin(field.name, FieldOrigin::Document);
+                self.push_field_path(|| field.name.to_string());
+                let result = self.deserialize_child_field(partial, field, merged);
+                self.origin_path.pop();
+                return result;
+            }
+            // Under `LastWins` every occurrence but the winning one was
+            // dropped before routing started, so the field is entered once.
+            if let Some((_, winner)) = last_child_spans
+                .iter()
+                .find(|(seen, _)| *seen == field.name)
+            {
+                if *winner != node.span() {
+                    if self.trace.is_some() {
+                        let note = format!(
+                            "node -> `{}` (superseded by a later occurrence)",
+                            self.field_path(field.name)
+                        );
+                        self.trace_note(node.span(), note);
+                    }
+                    return Ok(());
+                }
+            }
+            if let Some((_, first)) = seen_children
+                .iter()
+                .find(|(seen, _)| *seen == field.name)
+                .copied()
+            {
+                if self.options.duplicate_children == DuplicateNodePolicy::Error {
+                    let error = self.error(
+                        KdlErrorKind::DuplicateNode {
+                            field: field.name,
+                            name: name.to_string(),
+                            first,
+                            offending: node.span(),
+                        },
+                        node.span(),
+                    );
+                    self.recover(error)?;
+                    return Ok(());
+                }
+            } else {
+                seen_children.push((field.name, node.span()));
+            }
+            if self.trace.is_some() {
+                let note = format!("node -> `{}`", self.field_path(field.name));
+                self.trace_note(node.span(), note);
+            }
+            self.record_origin(field.name, FieldOrigin::Document);
+            self.push_field_path(|| field.name.to_string());
+            let result = self.deserialize_child_field(partial, field, node);
+            self.origin_path.pop();
+            result?;
+        } else if let Some(field) = fields.iter().find(|field| {
+            field_role(field) == Some(FieldRole::Children)
+                && children_field_matches(field, name, ty, &self.options.naming)
+        }) {
+            let index = match children_counts
+                .iter_mut()
+                .find(|(seen, _)| *seen == field.name)
+            {
+                Some((_, count)) => {
+                    *count += 1;
+                    *count
+                }
+                None => {
+                    children_counts.push((field.name, 0));
+                    0
+                }
+            };
+            // A children field consumes every matching node in one visit —
+            // re-entering the field per node works for struct frames but an
+            // enum variant frame doesn't remember which fields it has begun,
+            // so `begin_list`/`begin_map` would reinitialize the container
+            // and drop the elements appended so far.
+            if index == 0 {
+                if matches!(field.shape().def, Def::Map(_)) {
+                    self.deserialize_map_children(partial, field, nodes)?;
+                } else {
+                    self.deserialize_list_children(partial, field, nodes)?;
+                }
+            }
+            return Ok(());
+        } else {
+            // The node spelling of a `kdl(node_or_property)` field was
+            // already consumed by the entry pass.
+            if fields.iter().any(|field| {
+                field_role(field) == Some(FieldRole::Property)
+                    && kdl_node_or_property(field).is_some()
+                    && self.options.naming.matches(field.name, name)
+            }) {
+                return Ok(());
+            }
+            let expected = fields
+                .iter()
+                .filter(|field| {
+                    matches!(
+                        field_role(field),
+                        Some(FieldRole::Child | FieldRole::Children)
+                    )
+                })
+                .flat_map(|field| accepted_node_names(field, &self.options.naming))
+                .collect();
+            let error = self.error(
+                KdlErrorKind::NoMatchingNode {
+                    name: name.to_string(),
+                    expected,
+                },
+                node.span(),
+            );
+            self.recover(error)?;
+        }
+        Ok(())
+    }
+
+    /// Deserializes a single `#[facet(child)]` field from its node.
+    fn deserialize_child_field(
+        &mut self,
+        partial: &mut Partial,
+        field: &'static Field,
+        node: &KdlNode,
+    ) -> Result<(), KdlError> {
+        let span = node.span();
+        partial
+            .begin_field(field.name)
+            .map_err(|error| self.reflect(error, span))?;
+        let mut shape = field.shape();
+        // Unwrap `Option` and smart-pointer layers in any order, so
+        // `Option<Box<T>>` (the usual recursive-config spelling) works.
+        let mut wrappers = 0;
+        loop {
+            if let Def::Option(option_def) = shape.def {
+                partial
+                    .begin_some()
+                    .map_err(|error| self.reflect(error, span))?;
+                shape = option_def.t();
+                wrappers += 1;
+                continue;
+            }
+            if let Some(inner) = pointee(shape) {
+                partial
+                    .begin_smart_ptr()
+                    .map_err(|error| self.reflect(error, span))?;
+                shape = inner;
+                wrappers += 1;
+                continue;
+            }
+            // A `#[facet(transparent)]` wrapper fills its single field.
+            if let Some(inner) = transparent_inner(shape) {
+                partial
+                    .begin_field(inner.name)
+                    .map_err(|error| self.reflect(error, span))?;
+                shape = inner.shape();
+                wrappers += 1;
+                continue;
+            }
+            break;
+        }
+        // A `kdl(verbatim)` string captures the node's children as text,
+        // re-rendered in canonical formatting, for passthrough to another
+        // tool. Entries on the node itself have no field to land in.
+        if has_kdl_attr(field, "verbatim") {
+            if let Some(entry) = node.entries().first() {
+                let error = self.error(
+                    KdlErrorKind::UnsupportedShape(format!(
+                        "verbatim field `{}` takes children only, not entries",
+                        field.name
+                    )),
+                    entry.span(),
+                );
+                self.recover(error)?;
+            }
+            let text = match node.children() {
+                Some(children) => {
+                    let mut children = children.clone();
+                    children.autoformat();
+                    children.to_string()
+                }
+                None => String::new(),
+            };
+            partial.set(text).map_err(|error| self.reflect(error, span))?;
+            for _ in 0..wrappers {
+                partial.end().map_err(|error| self.reflect(error, span))?;
+            }
+            partial.end().map_err(|error| self.reflect(error, span))?;
+            return Ok(());
+        }
+        // A map child soaks up the node's properties as entries:
+        // `env FOO="1" BAR="2"` fills a `HashMap<String, String>`.
+        if let Def::Map(map_def) = shape.def {
+            self.deserialize_property_map(partial, field, node, map_def.k(), map_def.v())?;
+            for _ in 0..wrappers {
+                partial.end().map_err(|error| self.reflect(error, span))?;
+            }
+            partial.end().map_err(|error| self.reflect(error, span))?;
+            return Ok(());
+        }
+        match &shape.ty {
+            Type::User(UserType::Struct(_)) => {
+                self.deserialize_node(partial, node, shape)?;
+            }
+            Type::User(UserType::Enum(enum_type)) => {
+                let variant = self.find_variant_by_name(enum_type.variants, node)?;
+                self.verify_variant_property(node, variant)?;
+                if self.trace.is_some() {
+                    self.trace_note(span, format!("variant `{}`", variant.name));
+                }
+                partial
+                    .select_variant_named(variant.name)
+                    .map_err(|error| self.reflect(error, span))?;
+                self.strict_properties = !self.options.allow_unknown_properties
+                    || variant_denies_unknown_fields(variant);
+                self.variant_node = true;
+                if let Some(payload) = variant_list_payload(variant) {
+                    self.deserialize_list_variant(partial, node, payload)?;
+                } else {
+                    self.deserialize_node_with_fields(partial, node, variant.data.fields)?;
+                }
+            }
+            _ => {
+                return Err(self.error(
+                    KdlErrorKind::UnsupportedShape(format!(
+                        "child field `{}` has unsupported type `{}`",
+                        field.name,
+                        field.shape()
+                    )),
+                    span,
+                ));
+            }
+        }
+        for _ in 0..wrappers {
+            partial.end().map_err(|error| self.reflect(error, span))?;
+        }
+        partial.end().map_err(|error| self.reflect(error, span))?;
+        Ok(())
+    }
+
+    /// Fills a map-typed `child` field from the node's properties, property
+    /// keys as map keys.
+    fn deserialize_property_map(
+        &mut self,
+        partial: &mut Partial,
+        field: &'static Field,
+        node: &KdlNode,
+        key_shape: &'static Shape,
+        value_shape: &'static Shape,
+    ) -> Result<(), KdlError> {
+        let span = node.span();
+        partial
+            .begin_map()
+            .map_err(|error| self.reflect(error, span))?;
+        for entry in node.entries() {
+            let Some(entry_name) = entry.name() else {
+                return Err(self.error(
+                    KdlErrorKind::UnsupportedShape(format!(
+                        "map child field `{}` takes only properties, not positional \
+                         arguments",
+                        field.name
+                    )),
+                    entry.span(),
+                ));
+            };
+            partial
+                .begin_key()
+                .map_err(|error| self.reflect(error, entry.span()))?;
+            if spanned_inner(key_shape).is_some() {
+                partial
+                    .begin_field("value")
+                    .and_then(|partial| partial.set(entry_name.value().to_string()))
+                    .and_then(|partial| partial.end())
+                    .and_then(|partial| partial.begin_field("span"))
+                    .and_then(|partial| partial.set(Span::from(entry_name.span())))
+                    .and_then(|partial| partial.end())
+                    .map_err(|error| self.reflect(error, entry.span()))?;
+            } else {
+                partial
+                    .set(entry_name.value().to_string())
+                    .map_err(|error| self.reflect(error, entry.span()))?;
+            }
+            partial
+                .end()
+                .and_then(|partial| partial.begin_value())
+                .map_err(|error| self.reflect(error, entry.span()))?;
+            self.deserialize_value(partial, entry, value_shape)?;
+            partial
+                .end()
+                .map_err(|error| self.reflect(error, entry.span()))?;
+        }
+        if node.children().is_some_and(|children| !children.nodes().is_empty()) {
+            return Err(self.error(
+                KdlErrorKind::UnsupportedShape(format!(
+                    "map child field `{}` takes no children",
+                    field.name
+                )),
+                span,
+            ));
+        }
+        Ok(())
+    }
+
+    /// Deserializes every matching node of a list `#[facet(children)]` field
+    /// in one visit, appending elements in document order.
+    fn deserialize_list_children(
+        &mut self,
+        partial: &mut Partial,
+        field: &'static Field,
+        nodes: &[KdlNode],
+    ) -> Result<(), KdlError> {
+        let list_def = match field.shape().def {
+            Def::List(list_def) => list_def,
+            Def::Set(_) => {
+                // The reflection backend has no incremental set insertion;
+                // sets serialize fine but cannot be deserialized yet.
+                return Err(self.error(
+                    KdlErrorKind::UnsupportedShape(format!(
+                        "children field `{}` targets set container `{}`, which \
+                         reflection cannot build element by element",
+                        field.name,
+                        field.shape()
+                    )),
+                    None,
+                ));
+            }
+            _ => {
+                return Err(self.error(
+                    KdlErrorKind::UnsupportedShape(format!(
+                        "children field `{}` has non-container type `{}`",
+                        field.name,
+                        field.shape()
+                    )),
+                    None,
+                ));
+            }
+        };
+        let matching: Vec<&KdlNode> = nodes
+            .iter()
+            .filter(|node| {
+                let name = node.name().value();
+                let ty = node.ty().map(|ty| ty.value());
+                children_field_matches(field, name, ty, &self.options.naming)
+            })
+            .collect();
+        let field_span = matching.first().map(|node| node.span());
+        partial
+            .begin_field(field.name)
+            .and_then(|partial| partial.begin_list())
+            .map_err(|error| self.error(KdlErrorKind::Reflect(error), field_span))?;
+        for (index, node) in matching.into_iter().enumerate() {
+            let span = node.span();
+            if self.trace.is_some() {
+                let note = format!(
+                    "node -> `{}`",
+                    self.field_path(&format!("{}[{index}]", field.name))
+                );
+                self.trace_note(span, note);
+            }
+            self.record_origin(field.name, FieldOrigin::Document);
+            self.push_field_path(|| format!("{}[{index}]", field.name));
+            let result = self.deserialize_list_children_item(partial, node, list_def.t());
+            self.origin_path.pop();
+            result?;
+        }
+        partial
+            .end()
+            .map_err(|error| self.error(KdlErrorKind::Reflect(error), field_span))?;
+        Ok(())
+    }
+
+    /// Appends one node to an open list children frame.
+    fn deserialize_list_children_item(
+        &mut self,
+        partial: &mut Partial,
+        node: &KdlNode,
+        element_shape: &'static Shape,
+    ) -> Result<(), KdlError> {
+        let span = node.span();
+        partial
+            .begin_list_item()
+            .map_err(|error| self.reflect(error, span))?;
+        self.deserialize_element(partial, node, element_shape, false)?;
+        partial.end().map_err(|error| self.reflect(error, span))?;
+        Ok(())
+    }
+
+    /// Deserializes every matching node of a map `#[facet(children)]` field
+    /// in one visit, node names as keys.
+    fn deserialize_map_children(
+        &mut self,
+        partial: &mut Partial,
+        field: &'static Field,
+        nodes: &[KdlNode],
+    ) -> Result<(), KdlError> {
+        let Def::Map(map_def) = field.shape().def else {
+            return Err(self.error(
+                KdlErrorKind::UnsupportedShape(format!(
+                    "children field `{}` has non-map type `{}`",
+                    field.name,
+                    field.shape()
+                )),
+                None,
+            ));
+        };
+        let matching: Vec<&KdlNode> = nodes
+            .iter()
+            .filter(|node| {
+                let name = node.name().value();
+                let ty = node.ty().map(|ty| ty.value());
+                children_field_matches(field, name, ty, &self.options.naming)
+            })
+            .collect();
+        let field_span = matching.first().map(|node| node.span());
+        partial
+            .begin_field(field.name)
+            .and_then(|partial| partial.begin_map())
+            .map_err(|error| self.error(KdlErrorKind::Reflect(error), field_span))?;
+        for (index, node) in matching.into_iter().enumerate() {
+            let span = node.span();
+            if self.trace.is_some() {
+                let note = format!(
+                    "node -> `{}`",
+                    self.field_path(&format!("{}[{index}]", field.name))
+                );
+                self.trace_note(span, note);
+            }
+            self.record_origin(field.name, FieldOrigin::Document);
+            self.push_field_path(|| format!("{}[{index}]", field.name));
+            let result =
+                self.deserialize_map_children_entry(partial, node, map_def.k(), map_def.v());
+            self.origin_path.pop();
+            result?;
+        }
+        partial
+            .end()
+            .map_err(|error| self.error(KdlErrorKind::Reflect(error), field_span))?;
+        Ok(())
+    }
+
+    /// Inserts one node into an open map children frame: the node name as
+    /// the key, the node's contents as the value.
+    ///
+    /// A `Spanned` key carries the name's own span, so lint-style tooling
+    /// can point at the duplicate key rather than the whole node.
+    fn deserialize_map_children_entry(
+        &mut self,
+        partial: &mut Partial,
+        node: &KdlNode,
+        key_shape: &'static Shape,
+        value_shape: &'static Shape,
+    ) -> Result<(), KdlError> {
+        let span = node.span();
+        partial
+            .begin_key()
+            .map_err(|error| self.reflect(error, span))?;
+        if spanned_inner(key_shape).is_some() {
+            let name_span = node.name().span();
+            partial
+                .begin_field("value")
+                .and_then(|partial| partial.set(node.name().value().to_string()))
+                .and_then(|partial| partial.end())
+                .and_then(|partial| partial.begin_field("span"))
+                .and_then(|partial| partial.set(Span::from(name_span)))
+                .and_then(|partial| partial.end())
+                .map_err(|error| self.reflect(error, span))?;
+        } else {
+            partial
+                .set(node.name().value().to_string())
+                .map_err(|error| self.reflect(error, span))?;
+        }
+        partial.end().map_err(|error| self.reflect(error, span))?;
+        partial
+            .begin_value()
+            .map_err(|error| self.reflect(error, span))?;
+        self.deserialize_element(partial, node, value_shape, true)?;
+        partial.end().map_err(|error| self.reflect(error, span))?;
+        Ok(())
+    }
+
+    /// Deserializes one element of a children container from its node.
+    ///
+    /// With `in_map`, the node name is the map key, so enum variants are
+    /// selected by type annotation instead.
+    fn deserialize_element(
+        &mut self,
+        partial: &mut Partial,
+        node: &KdlNode,
+        shape: &'static Shape,
+        in_map: bool,
+    ) -> Result<(), KdlError> {
+        // A `Spanned` element records the node's span around its inner
+        // value, matching what the entry-value path does for scalars.
+        if let Some(inner) = spanned_inner(shape) {
+            let span = node.span();
+            partial
+                .begin_field("value")
+                .map_err(|error| self.reflect(error, span))?;
+            self.deserialize_element(partial, node, inner, in_map)?;
+            partial
+                .end()
+                .and_then(|partial| partial.begin_field("span"))
+                .and_then(|partial| partial.set(Span::from(span)))
+                .and_then(|partial| partial.end())
+                .map_err(|error| self.reflect(error, span))?;
+            return Ok(());
+        }
+        match &shape.ty {
+            Type::User(UserType::Struct(_)) => self.deserialize_node(partial, node, shape),
+            Type::User(UserType::Enum(enum_type)) => {
+                let variant = if in_map {
+                    self.find_variant_by_annotation(enum_type.variants, node)?
+                } else {
+                    self.find_variant_by_name(enum_type.variants, node)?
+                };
+                self.verify_variant_property(node, variant)?;
+                if self.trace.is_some() {
+                    self.trace_note(node.span(), format!("variant `{}`", variant.name));
+                }
+                partial
+                    .select_variant_named(variant.name)
+                    .map_err(|error| self.reflect(error, node.span()))?;
+                self.strict_properties = !self.options.allow_unknown_properties
+                    || variant_denies_unknown_fields(variant);
+                self.variant_node = true;
+                if let Some(payload) = variant_list_payload(variant) {
+                    self.deserialize_list_variant(partial, node, payload)
+                } else {
+                    self.deserialize_node_with_fields(partial, node, variant.data.fields)
+                }
+            }
+            _ => Err(self.error(
+                KdlErrorKind::UnsupportedValueDef(format!(
+                    "children container element type `{shape}` is not a struct or enum"
+                )),
+                node.span(),
+            )),
+        }
+    }
+
+    /// Deserializes a node's positional arguments into a list-payload tuple
+    /// variant like `Hosts(Vec<String>)`.
+    fn deserialize_list_variant(
+        &mut self,
+        partial: &mut Partial,
+        node: &KdlNode,
+        payload: &'static Field,
+    ) -> Result<(), KdlError> {
+        let span = node.span();
+        let element_shape = list_element_shape(payload.shape())
+            .expect("variant_list_payload only matches list shapes");
+        partial
+            .begin_field(payload.name)
+            .and_then(|partial| partial.begin_list())
+            .map_err(|error| self.reflect(error, span))?;
+        for entry in node.entries() {
+            if let Some(entry_name) = entry.name() {
+                // The verified discriminant property is the one named entry
+                // a list-payload variant node may carry.
+                if self.variant_node
+                    && self.options.variant_property.as_deref() == Some(entry_name.value())
+                {
+                    continue;
+                }
+                return Err(self.error(
+                    KdlErrorKind::UnsupportedShape(format!(
+                        "node `{}` maps to a list-payload variant and takes only \
+                         positional arguments",
+                        node.name().value()
+                    )),
+                    entry.span(),
+                ));
+            }
+            partial
+                .begin_list_item()
+                .map_err(|error| self.reflect(error, entry.span()))?;
+            self.deserialize_value(partial, entry, element_shape)?;
+            partial
+                .end()
+                .map_err(|error| self.reflect(error, entry.span()))?;
+        }
+        if node.children().is_some_and(|children| !children.nodes().is_empty()) {
+            return Err(self.error(
+                KdlErrorKind::UnsupportedShape(format!(
+                    "node `{}` maps to a list-payload variant and takes no children",
+                    node.name().value()
+                )),
+                span,
+            ));
+        }
+        partial.end().map_err(|error| self.reflect(error, span))?;
+        Ok(())
+    }
+
+    /// Deserializes a node into a struct shape.
+    fn deserialize_node(
+        &mut self,
+        partial: &mut Partial,
+        node: &KdlNode,
+        shape: &'static Shape,
+    ) -> Result<(), KdlError> {
+        let Type::User(UserType::Struct(struct_type)) = &shape.ty else {
+            return Err(self.error(
+                KdlErrorKind::UnsupportedShape(format!("expected a struct shape, got `{shape}`")),
+                node.span(),
+            ));
+        };
+        // The common case — no flattened enums anywhere — never touches the
+        // solver, so simple documents pay nothing for it.
+        if has_flattened_enum(struct_type.fields) {
+            #[cfg(feature = "solver")]
+            return self.deserialize_node_with_solver(partial, node, shape, struct_type.fields);
+            #[cfg(not(feature = "solver"))]
+            return Err(self.error(
+                KdlErrorKind::UnsupportedShape(format!(
+                    "shape `{shape}` contains flattened enums, which need the `solver` \
+                     cargo feature"
+                )),
+                node.span(),
+            ));
+        }
+        self.strict_properties =
+            !self.options.allow_unknown_properties || denies_unknown_fields(shape);
+        self.variant_node = false;
+        self.deserialize_node_with_fields(partial, node, struct_type.fields)
+    }
+
+    /// Deserializes a node's entries and children against a known field list.
+    fn deserialize_node_with_fields(
+        &mut self,
+        partial: &mut Partial,
+        node: &KdlNode,
+        fields: &'static [Field],
+    ) -> Result<(), KdlError> {
+        self.seen_keys.clear();
+        self.fill_raw_fields(partial, node, fields)?;
+        self.fill_unit_like_fields(partial, fields)?;
+        self.fill_wrapper_fields(partial, node, fields)?;
+        let mut argument_fields = fields
+            .iter()
+            .filter(|field| field_role(field) == Some(FieldRole::Argument));
+        let arguments_field = fields
+            .iter()
+            .find(|field| field_role(field) == Some(FieldRole::Arguments));
+        // An `Args` wrapper adds one frame around the list, so its run opens
+        // and closes one level deeper than a plain `Vec`.
+        let arguments_wrapped =
+            arguments_field.is_some_and(|field| args_inner(field.shape()).is_some());
+        let mut arguments_state = ArgumentsState::NotStarted;
+        // The byte range covered by the first run of positional arguments,
+        // for the two-span diagnostic when the run is illegally reopened.
+        let mut arguments_run: Option<(usize, usize)> = None;
+        // Properties that live behind a flatten path, replayed after the
+        // loop: a frame can only be popped once fully initialized and a
+        // built frame can't be reopened, so assignments are grouped per
+        // frame instead of switching frames in entry order.
+        let mut deferred: Vec<(&KdlEntry, &'static Field, Vec<&'static str>)> = Vec::new();
+
+        for entry in entry_order(node) {
+            match entry.name() {
+                None => {
+                    if let Some(field) = argument_fields.next() {
+                        self.deserialize_entry_into_field(partial, field, entry)?;
+                    } else if let Some(field) = arguments_field {
+                        match arguments_state {
+                            ArgumentsState::NotStarted => {
+                                self.record_origin(field.name, FieldOrigin::Document);
+                                partial
+                                    .begin_field(field.name)
+                                    .map_err(|error| self.reflect(error, entry.span()))?;
+                                if arguments_wrapped {
+                                    partial
+                                        .begin_field("0")
+                                        .map_err(|error| self.reflect(error, entry.span()))?;
+                                }
+                                partial
+                                    .begin_list()
+                                    .map_err(|error| self.reflect(error, entry.span()))?;
+                                arguments_state = ArgumentsState::Open;
+                            }
+                            ArgumentsState::Open => {}
+                            ArgumentsState::Completed => {
+                                let (offset, end) = arguments_run
+                                    .expect("completed arguments list implies a first run");
+                                return Err(self.error(
+                                    KdlErrorKind::ArgumentsReopened {
+                                        node: node.name().value().to_string(),
+                                        first_arguments: SourceSpan::new(
+                                            offset.into(),
+                                            end - offset,
+                                        ),
+                                        offending: entry.span(),
+                                    },
+                                    entry.span(),
+                                ));
+                            }
+                        }
+                        let element_shape = list_element_shape(field.shape())
+                            .ok_or_else(|| {
+                                self.error(
+                                    KdlErrorKind::UnsupportedShape(format!(
+                                        "arguments field `{}` is not a list",
+                                        field.name
+                                    )),
+                                    entry.span(),
+                                )
+                            })?;
+                        partial
+                            .begin_list_item()
+                            .map_err(|error| self.reflect(error, entry.span()))?;
+                        self.deserialize_value(partial, entry, element_shape)?;
+                        partial
+                            .end()
+                            .map_err(|error| self.reflect(error, entry.span()))?;
+                        let span = entry.span();
+                        let (offset, end) =
+                            arguments_run.get_or_insert((span.offset(), span.offset() + span.len()));
+                        *offset = (*offset).min(span.offset());
+                        *end = (*end).max(span.offset() + span.len());
+                    } else {
+                        return Err(self.error(
+                            KdlErrorKind::UnsupportedShape(format!(
+                                "node `{}` doesn't accept positional arguments",
+                                node.name().value()
+                            )),
+                            entry.span(),
+                        ));
+                    }
+                }
+                Some(name) => {
+                    if matches!(arguments_state, ArgumentsState::Open) {
+                        // `begin_list` initializes the field frame in place,
+                        // so one `end` closes the whole run.
+                        partial
+                            .end()
+                            .map_err(|error| self.reflect(error, entry.span()))?;
+                        if arguments_wrapped {
+                            partial
+                                .end()
+                                .map_err(|error| self.reflect(error, entry.span()))?;
+                        }
+                        arguments_state = ArgumentsState::Completed;
+                    }
+                    self.deserialize_property(
+                        partial,
+                        fields,
+                        node,
+                        entry,
+                        name.value(),
+                        &mut deferred,
+                    )?;
+                }
+            }
+        }
+        if matches!(arguments_state, ArgumentsState::Open) {
+            let span = node.span();
+            partial.end().map_err(|error| self.reflect(error, span))?;
+            if arguments_wrapped {
+                partial.end().map_err(|error| self.reflect(error, span))?;
+            }
+        }
+        // The stable sort groups deferred entries by flatten path while
+        // keeping document order within each frame; `open_flattened_field`
+        // then switches frames only at group boundaries, so every frame is
+        // opened once, filled completely, and closed for good.
+        deferred.sort_by(|(_, _, a), (_, _, b)| a.cmp(b));
+        for (entry, field, prefix) in &deferred {
+            self.open_flattened_field(partial, prefix, &[], entry.span())?;
+            self.push_field_path_prefix(prefix);
+            let result = self.deserialize_entry_into_field(partial, field, entry);
+            self.pop_field_path_prefix(prefix);
+            result?;
+        }
+        self.close_open_paths(partial, node.span())?;
+        self.fill_node_or_property_fields(partial, node, fields)?;
+        self.record_defaulted_entry_fields(partial, fields);
+        self.fill_missing_entry_fields(partial, fields)?;
+
+        let child_fields: Vec<&'static Field> = fields
+            .iter()
+            .filter(|field| {
+                matches!(
+                    field_role(field),
+                    Some(FieldRole::Child | FieldRole::Children)
+                )
+            })
+            .collect();
+        let empty = KdlDocument::new();
+        let children = node.children().unwrap_or(&empty);
+        // A `kdl(document)` field consumes the whole children block as an
+        // independent sub-document, deserialized under the top-level rules.
+        if let Some(field) = fields.iter().find(|field| has_kdl_attr(field, "document")) {
+            if !child_fields.is_empty() {
+                return Err(self.error(
+                    KdlErrorKind::SchemaError(format!(
+                        "field `{}` is a kdl(document) sub-document, which can't share \
+                         a node with child/children fields",
+                        field.name
+                    )),
+                    node.span(),
+                ));
+            }
+            return self.deserialize_document_field(partial, field, children, node.span());
+        }
+        // A `kdl(raw)` field captures the whole node verbatim; its children
+        // are the application's business, not ours.
+        let has_raw_field = fields.iter().any(|field| has_kdl_attr(field, "raw"));
+        // A variant with no child fields has nowhere to route a children
+        // block; reporting it here names the stray nodes instead of the
+        // puzzling "unknown node" with an empty expected list the generic
+        // routing below would produce.
+        if self.variant_node && child_fields.is_empty() && !children.nodes().is_empty() && !has_raw_field {
+            let names: Vec<String> = children
+                .nodes()
+                .iter()
+                .map(|child| child.name().value().to_string())
+                .collect();
+            match self.options.unexpected_children {
+                UnexpectedChildrenPolicy::Error => {
+                    let span = children
+                        .nodes()
+                        .first()
+                        .map(KdlNode::span)
+                        .unwrap_or_else(|| node.span());
+                    let error = self.error(
+                        KdlErrorKind::UnexpectedChildren {
+                            node: node.name().value().to_string(),
+                            children: names,
+                        },
+                        span,
+                    );
+                    self.recover(error)?;
+                }
+                UnexpectedChildrenPolicy::Warn => log::warn!(
+                    "node `{node}` takes no children; dropping: {names}",
+                    node = node.name().value(),
+                    names = names.join(", ")
+                ),
+            }
+            return Ok(());
+        }
+        if (!child_fields.is_empty() || !children.nodes().is_empty()) && !has_raw_field {
+            self.deserialize_document_with_fields(partial, children.nodes(), fields)?;
+        }
+        Ok(())
+    }
+
+    /// Records [`FieldOrigin::Default`] for entry fields the node never
+    /// mentioned.
+    ///
+    /// Nothing is set here — [`Self::fill_missing_entry_fields`] runs right
+    /// after and does the filling — so this only runs when origins are being
+    /// tracked, and before the fill pass marks those fields as set.
+    fn record_defaulted_entry_fields(&mut self, partial: &mut Partial, fields: &'static [Field]) {
+        if self.origins.is_none() {
+            return;
+        }
+        for field in fields {
+            if !matches!(
+                field_role(field),
+                Some(FieldRole::Property | FieldRole::Argument | FieldRole::Arguments)
+            ) {
+                continue;
+            }
+            if field_is_set(partial, field.name) {
+                continue;
+            }
+            if matches!(field.shape().def, Def::Option(_))
+                || has_default(field)
+                || args_inner(field.shape()).is_some()
+            {
+                self.record_origin(field.name, FieldOrigin::Default);
+            }
+        }
+    }
+
+    /// Defaults absent `Option` and `#[facet(default)]` entry fields.
+    ///
+    /// `build` refuses to finish a frame with uninitialized fields, so the
+    /// fields the node never mentioned are filled here: a field-level
+    /// `default = expr` function when the derive recorded one, the type's
+    /// `Default` otherwise (`None` for options). Required fields stay unset
+    /// and surface as reflect errors at build.
+    fn fill_missing_entry_fields(
+        &mut self,
+        partial: &mut Partial,
+        fields: &'static [Field],
+    ) -> Result<(), KdlError> {
+        for field in fields {
+            if !matches!(
+                field_role(field),
+                Some(FieldRole::Property | FieldRole::Argument | FieldRole::Arguments)
+            ) {
+                continue;
+            }
+            if field_is_set(partial, field.name) {
+                continue;
+            }
+            // An `Args` wrapper defaults to the empty list when the node has
+            // no positional arguments, without needing `#[facet(default)]`;
+            // it's built through reflection because the generic wrapper has
+            // no `Default` in its vtable.
+            if args_inner(field.shape()).is_some() {
+                partial
+                    .begin_field(field.name)
+                    .and_then(|partial| partial.begin_field("0"))
+                    .and_then(|partial| partial.begin_list())
+                    .and_then(|partial| partial.end())
+                    .and_then(|partial| partial.end())
+                    .map_err(|error| self.error(KdlErrorKind::Reflect(error), None))?;
+                continue;
+            }
+            if !matches!(field.shape().def, Def::Option(_)) && !has_default(field) {
+                continue;
+            }
+            partial
+                .begin_field(field.name)
+                .and_then(|partial| match field.vtable.default_fn {
+                    Some(default_fn) => partial.set_field_default(default_fn),
+                    None => partial.set_default(),
+                })
+                .and_then(|partial| partial.end())
+                .map_err(|error| self.error(KdlErrorKind::Reflect(error), None))?;
+        }
+        Ok(())
+    }
+
+    /// Fills `kdl(node_or_property)` property fields written in their node
+    /// spelling: a child node carrying the value as its one positional
+    /// argument, `timeout 30` next to `timeout=30`.
+    ///
+    /// This runs before the missing-field pass so an `Option` field provided
+    /// as a node isn't defaulted to `None` first, and before child routing so
+    /// the consuming node isn't reported as unknown there.
+    fn fill_node_or_property_fields(
+        &mut self,
+        partial: &mut Partial,
+        node: &KdlNode,
+        fields: &'static [Field],
+    ) -> Result<(), KdlError> {
+        let Some(children) = node.children() else {
+            return Ok(());
+        };
+        let mut seen: Vec<(&'static str, SourceSpan)> = Vec::new();
+        for child in children.nodes() {
+            let name = child.name().value();
+            let Some(field) = fields.iter().find(|field| {
+                field_role(field) == Some(FieldRole::Property)
+                    && kdl_node_or_property(field).is_some()
+                    && self.options.naming.matches(field.name, name)
+            }) else {
+                continue;
+            };
+            if field_is_set(partial, field.name) {
+                // Both spellings in one node — the property entry or an
+                // earlier node occurrence already claimed the field. No
+                // policy applies here: the field's frame is already built,
+                // so a later value couldn't win even under `LastWins`.
+                let first = node
+                    .entries()
+                    .iter()
+                    .find(|entry| {
+                        entry
+                            .name()
+                            .is_some_and(|entry_name| entry_name.value() == name)
+                    })
+                    .map(KdlEntry::span)
+                    .or_else(|| {
+                        seen.iter()
+                            .find(|(seen_name, _)| *seen_name == field.name)
+                            .map(|(_, span)| *span)
+                    })
+                    .unwrap_or_else(|| node.span());
+                let error = self.error(
+                    KdlErrorKind::DuplicateNode {
+                        field: field.name,
+                        name: name.to_string(),
+                        first,
+                        offending: child.span(),
+                    },
+                    child.span(),
+                );
+                self.recover(error)?;
+                continue;
+            }
+            let argument = match child.entries() {
+                [entry] if entry.name().is_none() => entry,
+                _ => {
+                    return Err(self.error(
+                        KdlErrorKind::UnsupportedShape(format!(
+                            "node `{name}` fills property field `{}`, so it takes \
+                             exactly one positional argument",
+                            field.name
+                        )),
+                        child.span(),
+                    ));
+                }
+            };
+            if child.children().is_some() {
+                return Err(self.error(
+                    KdlErrorKind::UnsupportedShape(format!(
+                        "node `{name}` fills property field `{}` and can't have \
+                         children of its own",
+                        field.name
+                    )),
+                    child.span(),
+                ));
+            }
+            if self.trace.is_some() {
+                let note = format!("node -> `{}`", self.field_path(field.name));
+                self.trace_note(child.span(), note);
+            }
+            seen.push((field.name, child.span()));
+            self.deserialize_entry_into_field(partial, field, argument)?;
+        }
+        Ok(())
+    }
+
+    /// Deserializes a node's children block into a `kdl(document)` field.
+    ///
+    /// The field's struct is treated exactly like a top-level document —
+    /// `child`/`children` detection, no entries — so the same fragment shape
+    /// can be embedded under different parents without flattening semantics.
+    fn deserialize_document_field(
+        &mut self,
+        partial: &mut Partial,
+        field: &'static Field,
+        children: &KdlDocument,
+        span: SourceSpan,
+    ) -> Result<(), KdlError> {
+        self.record_origin(field.name, FieldOrigin::Document);
+        self.push_field_path(|| field.name.to_string());
+        partial
+            .begin_field(field.name)
+            .map_err(|error| self.reflect(error, span))?;
+        let mut shape = field.shape();
+        // Unwrap `Option` and smart-pointer layers, like child fields do.
+        let mut wrappers = 0;
+        loop {
+            if let Def::Option(option_def) = shape.def {
+                partial
+                    .begin_some()
+                    .map_err(|error| self.reflect(error, span))?;
+                shape = option_def.t();
+                wrappers += 1;
+                continue;
+            }
+            if let Some(inner) = pointee(shape) {
+                partial
+                    .begin_smart_ptr()
+                    .map_err(|error| self.reflect(error, span))?;
+                shape = inner;
+                wrappers += 1;
+                continue;
+            }
+            break;
+        }
+        self.deserialize_document(partial, children, shape)?;
+        for _ in 0..wrappers {
+            partial.end().map_err(|error| self.reflect(error, span))?;
+        }
+        partial.end().map_err(|error| self.reflect(error, span))?;
+        self.origin_path.pop();
+        Ok(())
+    }
+
+    /// Deserializes one `key=value` entry into the right property field.
+    ///
+    /// A field behind a flatten path isn't assigned here: it's pushed onto
+    /// `deferred` for the caller to replay grouped by frame, so properties
+    /// may interleave freely in the document without the frame bookkeeping
+    /// ever reopening a built frame.
+    fn deserialize_property<'entry>(
+        &mut self,
+        partial: &mut Partial,
+        fields: &'static [Field],
+        node: &KdlNode,
+        entry: &'entry KdlEntry,
+        name: &str,
+        deferred: &mut Vec<(&'entry KdlEntry, &'static Field, Vec<&'static str>)>,
+    ) -> Result<(), KdlError> {
+        // A discriminant property was already verified against the selected
+        // variant; it doesn't correspond to any field.
+        if self.variant_node && self.options.variant_property.as_deref() == Some(name) {
+            return Ok(());
+        }
+        if self.seen_keys.iter().any(|key| key.as_ref() == name) {
+            let error = self.error(
+                KdlErrorKind::UnsupportedShape(format!("duplicate property `{name}`")),
+                entry.span(),
+            );
+            return self.recover(error);
+        }
+        // Keys spelled exactly like a field intern to the field's name; only
+        // renamed or unknown keys pay for an owned copy.
+        self.seen_keys
+            .push(match fields.iter().find(|field| field.name == name) {
+                Some(field) => Cow::Borrowed(field.name),
+                None => Cow::Owned(name.to_string()),
+            });
+        let Some(path) = self.find_property_field(fields, name, &mut Vec::new()) else {
+            // A `Props` field already collected this key in the wrapper
+            // pass; it's claimed, not unknown.
+            if let Some(field) = fields.iter().find(|field| is_props(field.shape())) {
+                if self.trace.is_some() {
+                    let note = format!("{name} -> `{}`", self.field_path(field.name));
+                    self.trace_note(entry.span(), note);
+                }
+                return Ok(());
+            }
+            if !self.strict_properties {
+                log::trace!(
+                    "skipping unknown property `{name}` on node `{node_name}`",
+                    node_name = node.name().value()
+                );
+                if self.trace.is_some() {
+                    self.trace_note(entry.span(), format!("property `{name}` unknown, skipped"));
+                }
+                return Ok(());
+            }
+            self.property_names.clear();
+            collect_property_names(fields, &mut self.property_names, &self.options.naming);
+            let error = self.error(
+                KdlErrorKind::NoMatchingProperty {
+                    name: name.to_string(),
+                    expected: self.property_names.clone(),
+                },
+                entry.span(),
+            );
+            return self.recover(error);
+        };
+        let (field, prefix) = path;
+        self.redacting = is_sensitive(field);
+        log::trace!(
+            "assigning property {name}={value} on node `{node_name}`",
+            value = self.render_value(entry.value()),
+            node_name = node.name().value()
+        );
+        self.push_field_path_prefix(&prefix);
+        if self.trace.is_some() {
+            let note = format!("{name} -> `{}`", self.field_path(field.name));
+            self.trace_note(entry.span(), note);
+        }
+        if !prefix.is_empty() {
+            self.pop_field_path_prefix(&prefix);
+            deferred.push((entry, field, prefix));
+            return Ok(());
+        }
+        let result = self.deserialize_entry_into_field(partial, field, entry);
+        self.pop_field_path_prefix(&prefix);
+        result
+    }
+
+    /// Finds the property field named `name`, searching flattened structs
+    /// depth-first. Returns the field and the flatten path leading to it.
+    fn find_property_field(
+        &self,
+        fields: &'static [Field],
+        name: &str,
+        prefix: &mut Vec<&'static str>,
+    ) -> Option<(&'static Field, Vec<&'static str>)> {
+        for field in fields {
+            match field_role(field) {
+                Some(FieldRole::Property) if self.options.naming.matches(field.name, name) => {
+                    return Some((field, prefix.clone()));
+                }
+                Some(FieldRole::Flatten) => {
+                    if let Type::User(UserType::Struct(struct_type)) = &field.shape().ty {
+                        // A grouped flatten answers only to its prefixed
+                        // spellings: `tls-cert` reaches `cert` inside a
+                        // `kdl(group = "tls")` field, and nothing else does.
+                        if let Some(group) = kdl_group(field) {
+                            let Some(inner) = name
+                                .strip_prefix(group)
+                                .and_then(|rest| rest.strip_prefix('-'))
+                            else {
+                                continue;
+                            };
+                            prefix.push(field.name);
+                            if let Some(found) =
+                                self.find_property_field(struct_type.fields, inner, prefix)
+                            {
+                                return Some(found);
+                            }
+                            prefix.pop();
+                            continue;
+                        }
+                        prefix.push(field.name);
+                        if let Some(found) =
+                            self.find_property_field(struct_type.fields, name, prefix)
+                        {
+                            return Some(found);
+                        }
+                        prefix.pop();
+                    }
+                }
+                // A `kdl(inline)` child also answers to its fields spelled
+                // as properties on the parent node, so callers can migrate
+                // from the nested-node form one document at a time.
+                Some(FieldRole::Child) if has_kdl_attr(field, "inline") => {
+                    if let Type::User(UserType::Struct(struct_type)) = &field.shape().ty {
+                        prefix.push(field.name);
+                        if let Some(found) =
+                            self.find_property_field(struct_type.fields, name, prefix)
+                        {
+                            return Some(found);
+                        }
+                        prefix.pop();
+                    }
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+
+    /// Opens the given flatten path in the `Partial`, closing whatever part of
+    /// the previously open path no longer applies.
+    ///
+    /// `selections` carries the solver's variant choices: when a newly opened
+    /// segment lands on a selected path, its variant is committed before
+    /// descending further. The non-solver path passes no selections.
+    fn open_flattened_field(
+        &mut self,
+        partial: &mut Partial,
+        path: &[&'static str],
+        selections: &[(Vec<&'static str>, &'static str)],
+        span: SourceSpan,
+    ) -> Result<(), KdlError> {
+        let common = self
+            .open_paths
+            .iter()
+            .zip(path.iter())
+            .take_while(|(open, wanted)| open == wanted)
+            .count();
+        while self.open_paths.len() > common {
+            self.fill_missing_frame_fields(partial, span)?;
+            partial.end().map_err(|error| self.reflect(error, span))?;
+            self.open_paths.pop();
+        }
+        for segment in &path[common..] {
+            partial
+                .begin_field(segment)
+                .map_err(|error| self.reflect(error, span))?;
+            self.open_paths.push(segment);
+            if let Some((_, variant_name)) = selections
+                .iter()
+                .find(|(selected, _)| selected.as_slice() == &self.open_paths[..])
+            {
+                partial
+                    .select_variant_named(variant_name)
+                    .map_err(|error| self.reflect(error, span))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Closes any flatten path left open after processing a node's entries.
+    fn close_open_paths(&mut self, partial: &mut Partial, span: SourceSpan) -> Result<(), KdlError> {
+        while self.open_paths.pop().is_some() {
+            self.fill_missing_frame_fields(partial, span)?;
+            partial.end().map_err(|error| self.reflect(error, span))?;
+        }
+        Ok(())
+    }
+
+    /// Defaults the unset `Option` and `#[facet(default)]` fields of the
+    /// current frame — the flattened struct or enum variant about to be
+    /// closed — since a frame can only be popped once fully initialized.
+    fn fill_missing_frame_fields(
+        &mut self,
+        partial: &mut Partial,
+        span: SourceSpan,
+    ) -> Result<(), KdlError> {
+        let frame_fields: &'static [Field] = match &partial.shape().ty {
+            Type::User(UserType::Struct(struct_type)) => struct_type.fields,
+            Type::User(UserType::Enum(_)) => match partial.selected_variant() {
+                Some(variant) => variant.data.fields,
+                None => return Ok(()),
+            },
+            _ => return Ok(()),
+        };
+        for (index, field) in frame_fields.iter().enumerate() {
+            if partial.is_field_set(index).unwrap_or(true) {
+                continue;
+            }
+            if !matches!(field.shape().def, Def::Option(_)) && !has_default(field) {
+                continue;
+            }
+            partial
+                .begin_nth_field(index)
+                .and_then(|partial| match field.vtable.default_fn {
+                    Some(default_fn) => partial.set_field_default(default_fn),
+                    None => partial.set_default(),
+                })
+                .and_then(|partial| partial.end())
+                .map_err(|error| self.reflect(error, span))?;
+        }
+        Ok(())
+    }
+
+    /// Solver path: a node whose shape contains flattened enums.
+    #[cfg(feature = "solver")]
+    fn deserialize_node_with_solver(
+        &mut self,
+        partial: &mut Partial,
+        node: &KdlNode,
+        shape: &'static Shape,
+        fields: &'static [Field],
+    ) -> Result<(), KdlError> {
+        let limit = self
+            .options
+            .max_resolutions
+            .unwrap_or(crate::solver::DEFAULT_MAX_RESOLUTIONS);
+        let schema = Schema::build(shape, limit)
+            .map_err(|error| self.error(KdlErrorKind::Solver(error), node.span()))?;
+        if log::log_enabled!(log::Level::Trace) {
+            let candidate_summaries: Vec<String> = schema
+                .resolutions
+                .iter()
+                .map(|resolution| resolution.describe())
+                .collect();
+            log::trace!(
+                "solver: {count} resolutions for `{shape}`: {candidate_summaries:?}",
+                count = schema.resolutions.len()
+            );
+        }
+
+        let properties: Vec<(&str, &KdlValue)> = node
+            .entries()
+            .iter()
+            .filter_map(|entry| entry.name().map(|name| (name.value(), entry.value())))
+            .collect();
+        let candidates = schema.candidates(&properties, &self.options);
+        let resolution = match candidates.as_slice() {
+            [single] => *single,
+            [] => {
+                return Err(self.error(
+                    KdlErrorKind::Solver(crate::solver::SolverError::NoMatch {
+                        candidates: schema
+                            .resolutions
+                            .iter()
+                            .map(|resolution| resolution.candidate(&properties, &self.options))
+                            .collect(),
+                    }),
+                    node.span(),
+                ));
+            }
+            _ => match self.options.tie_break {
+                crate::solver::TieBreakPolicy::FirstDeclared => {
+                    log::trace!(
+                        "solver: tie broken by declaration order: {}",
+                        candidates[0].describe()
+                    );
+                    candidates[0]
+                }
+                crate::solver::TieBreakPolicy::TightestType => {
+                    let winner = crate::solver::tightest(&candidates, &properties, &self.options);
+                    log::trace!("solver: tie broken by tightness: {}", winner.describe());
+                    winner
+                }
+                crate::solver::TieBreakPolicy::RequireAnnotation => {
+                    return Err(self.error(
+                        KdlErrorKind::Solver(crate::solver::SolverError::Ambiguous {
+                            candidates: candidates
+                                .iter()
+                                .map(|resolution| resolution.candidate(&properties, &self.options))
+                                .collect(),
+                        }),
+                        node.span(),
+                    ));
+                }
+            },
+        };
+
+        // Replay the node's entries against the now-unambiguous field
+        // layout, grouped by flatten path: a frame can only be popped once
+        // it is fully initialized, so each flattened enum is opened once,
+        // its variant committed on the way in, every entry that lands in it
+        // filled, and optional leftovers defaulted on the way out.
+        self.seen_keys.clear();
+        self.fill_raw_fields(partial, node, fields)?;
+        self.fill_unit_like_fields(partial, fields)?;
+        self.fill_wrapper_fields(partial, node, fields)?;
+        let mut slotted = Vec::new();
+        for entry in entry_order(node) {
+            let Some(name) = entry.name() else {
+                return Err(self.error(
+                    KdlErrorKind::UnsupportedShape(format!(
+                        "node `{}` with flattened enums doesn't accept positional arguments",
+                        node.name().value()
+                    )),
+                    entry.span(),
+                ));
+            };
+            let Some(slot) = resolution
+                .properties
+                .iter()
+                .find(|slot| self.options.naming.matches(slot.name, name.value()))
+            else {
+                // Reachable in lenient mode and when a `#[facet(other)]`
+                // fallback resolution won: both tolerate properties no slot
+                // claims, so they are simply dropped here.
+                log::trace!(
+                    "skipping unknown property `{name}` on node `{node_name}`",
+                    name = name.value(),
+                    node_name = node.name().value()
+                );
+                continue;
+            };
+            slotted.push((entry, slot));
+        }
+        slotted
+            .sort_by(|(_, a), (_, b)| a.path[..a.path.len() - 1].cmp(&b.path[..b.path.len() - 1]));
+        // Selections no entry touches — payload-less variants — still have
+        // to be committed; opening their path is enough, the close pass
+        // defaults whatever optional payload they carry.
+        for (path, _) in &resolution.selections {
+            let touched = slotted.iter().any(|(_, slot)| {
+                slot.path.len() > path.len() && slot.path[..path.len()] == path[..]
+            });
+            if !touched {
+                self.open_flattened_field(partial, path, &resolution.selections, node.span())?;
+            }
+        }
+        for &(entry, slot) in &slotted {
+            let (field_name, prefix) = slot
+                .path
+                .split_last()
+                .expect("property slots always have a non-empty path");
+            self.open_flattened_field(partial, prefix, &resolution.selections, entry.span())?;
+            for segment in prefix {
+                self.origin_path.push((*segment).to_string());
+            }
+            if matches!(entry.value(), KdlValue::Null)
+                && self.options.null_policy == NullPolicy::UseDefault
+                && !matches!(slot.shape.def, Def::Option(_))
+                && slot.has_default
+            {
+                self.record_origin(field_name, FieldOrigin::Default);
+                self.pop_field_path_prefix(prefix);
+                partial
+                    .begin_field(field_name)
+                    .and_then(|partial| partial.set_default())
+                    .and_then(|partial| partial.end())
+                    .map_err(|error| self.reflect(error, entry.span()))?;
+                continue;
+            }
+            self.record_origin(field_name, FieldOrigin::Document);
+            self.pop_field_path_prefix(prefix);
+            partial
+                .begin_field(field_name)
+                .map_err(|error| self.reflect(error, entry.span()))?;
+            self.redacting = slot.sensitive;
+            let result = self.deserialize_value(partial, entry, slot.shape);
+            self.redacting = false;
+            result?;
+            partial
+                .end()
+                .map_err(|error| self.reflect(error, entry.span()))?;
+            self.run_validator(slot.validator, field_name, entry)?;
+        }
+        self.close_open_paths(partial, node.span())?;
+        self.fill_node_or_property_fields(partial, node, fields)?;
+        self.record_defaulted_entry_fields(partial, fields);
+        self.fill_missing_entry_fields(partial, fields)?;
+
+        let empty = KdlDocument::new();
+        let children = node.children().unwrap_or(&empty);
+        if !children.nodes().is_empty() {
+            self.deserialize_document_with_fields(partial, children.nodes(), fields)?;
+        }
+        Ok(())
+    }
+
+    /// Deserializes a single entry's value into a named field.
+    fn deserialize_entry_into_field(
+        &mut self,
+        partial: &mut Partial,
+        field: &'static Field,
+        entry: &KdlEntry,
+    ) -> Result<(), KdlError> {
+        let span = entry.span();
+        if self.walk.is_some() {
+            let path = self.field_path(field.name);
+            if let Some(entries) = &mut self.walk {
+                entries.push(WalkEntry {
+                    path,
+                    value: entry.value().clone(),
+                    span: Span::from(span),
+                });
+            }
+        }
+        if matches!(entry.value(), KdlValue::Null)
+            && self.options.null_policy == NullPolicy::UseDefault
+            && !matches!(field.shape().def, Def::Option(_))
+            && has_default(field)
+        {
+            self.record_origin(field.name, FieldOrigin::Default);
+            partial
+                .begin_field(field.name)
+                .and_then(|partial| partial.set_default())
+                .and_then(|partial| partial.end())
+                .map_err(|error| self.reflect(error, span))?;
+            return Ok(());
+        }
+        self.record_origin(field.name, FieldOrigin::Document);
+        #[cfg(feature = "bitflags")]
+        if let Some(codec) = kdl_flags_with(field) {
+            return self.deserialize_flags_field(partial, field, codec, entry);
+        }
+        self.redacting = is_sensitive(field);
+        partial
+            .begin_field(field.name)
+            .map_err(|error| self.reflect(error, span))?;
+        let result = self.deserialize_value(partial, entry, field.shape());
+        self.redacting = false;
+        result?;
+        partial.end().map_err(|error| self.reflect(error, span))?;
+        self.run_validator(kdl_validator(field), field.name, entry)?;
+        Ok(())
+    }
+
+    /// Deserializes a flag string like `"READ|WRITE"` through the named
+    /// parser into a bitflags tuple struct's backing integer.
+    #[cfg(feature = "bitflags")]
+    fn deserialize_flags_field(
+        &mut self,
+        partial: &mut Partial,
+        field: &'static Field,
+        codec: &'static str,
+        entry: &KdlEntry,
+    ) -> Result<(), KdlError> {
+        let span = entry.span();
+        let KdlValue::String(text) = entry.value() else {
+            return Err(self.error(
+                KdlErrorKind::InvalidValueForShape {
+                    value: self.render_value(entry.value()),
+                    shape: field.shape(),
+                },
+                span,
+            ));
+        };
+        let Some((_, parse)) = self
+            .options
+            .flag_parsers
+            .iter()
+            .find(|(registered, _)| *registered == codec)
+        else {
+            return Err(self.error(
+                KdlErrorKind::SchemaError(format!(
+                    "field `{}` names flag parser `{codec}`, but no such parser is \
+                     registered on DeserializeOptions",
+                    field.name
+                )),
+                span,
+            ));
+        };
+        let bits = parse(text).map_err(|message| {
+            self.error(
+                KdlErrorKind::ValidationFailed {
+                    field: field.name,
+                    message,
+                },
+                span,
+            )
+        })?;
+        // Bitflags types declared in impl mode are tuple structs around
+        // their backing integer; write the parsed bits into that field.
+        let Type::User(UserType::Struct(struct_type)) = &field.shape().ty else {
+            return Err(self.error(
+                KdlErrorKind::SchemaError(format!(
+                    "field `{}` uses kdl(flags_with) but `{}` isn't a tuple struct",
+                    field.name,
+                    field.shape()
+                )),
+                span,
+            ));
+        };
+        let [inner] = struct_type.fields else {
+            return Err(self.error(
+                KdlErrorKind::SchemaError(format!(
+                    "field `{}` uses kdl(flags_with) but `{}` doesn't wrap a single \
+                     backing integer",
+                    field.name,
+                    field.shape()
+                )),
+                span,
+            ));
+        };
+        partial
+            .begin_field(field.name)
+            .and_then(|partial| partial.begin_field(inner.name))
+            .map_err(|error| self.reflect(error, span))?;
+        self.set_integer(partial, bits, inner.shape(), entry)?;
+        partial
+            .end()
+            .and_then(|partial| partial.end())
+            .map_err(|error| self.reflect(error, span))?;
+        Ok(())
+    }
+
+    /// Runs the field's registered validator, if it names one.
+    fn run_validator(
+        &self,
+        validator: Option<&'static str>,
+        field: &'static str,
+        entry: &KdlEntry,
+    ) -> Result<(), KdlError> {
+        let Some(name) = validator else {
+            return Ok(());
+        };
+        let annotation = entry.ty().map(|ty| ty.value());
+        let fail = |message| {
+            self.error(
+                KdlErrorKind::ValidationFailed {
+                    field,
+                    message,
+                },
+                entry.span(),
+            )
+        };
+        if let Some((_, validate)) = self
+            .options
+            .validators
+            .iter()
+            .find(|(registered, _)| *registered == name)
+        {
+            return validate(entry.value(), annotation).map_err(fail);
+        }
+        if let Some((_, validate)) = self
+            .options
+            .context_validators
+            .iter()
+            .find(|(registered, _)| *registered == name)
+        {
+            let Some(context) = self.context else {
+                return Err(self.error(
+                    KdlErrorKind::SchemaError(format!(
+                        "field `{field}` names context validator `{name}`, but this run \
+                         has no context; use from_str_with_context"
+                    )),
+                    entry.span(),
+                ));
+            };
+            return validate(context, entry.value(), annotation).map_err(fail);
+        }
+        Err(self.error(
+            KdlErrorKind::SchemaError(format!(
+                "field `{field}` names validator `{name}`, but no such validator is \
+                 registered on DeserializeOptions"
+            )),
+            entry.span(),
+        ))
+    }
+
+    /// Converts a KDL entry value into whatever shape the `Partial` currently
+    /// expects, unwrapping `Option` and `Spanned` along the way.
+    fn deserialize_value(
+        &mut self,
+        partial: &mut Partial,
+        entry: &KdlEntry,
+        shape: &'static Shape,
+    ) -> Result<(), KdlError> {
+        let span = entry.span();
+        let value = entry.value();
+
+        if let Def::Option(option_def) = shape.def {
+            if matches!(value, KdlValue::Null) {
+                partial
+                    .set_default()
+                    .map_err(|error| self.reflect(error, span))?;
+                return Ok(());
+            }
+            partial
+                .begin_some()
+                .map_err(|error| self.reflect(error, span))?;
+            self.deserialize_value(partial, entry, option_def.t())?;
+            partial.end().map_err(|error| self.reflect(error, span))?;
+            return Ok(());
+        }
+
+        if let Some(inner) = spanned_inner(shape) {
+            partial
+                .begin_field("value")
+                .map_err(|error| self.reflect(error, span))?;
+            self.deserialize_value(partial, entry, inner)?;
+            partial.end().map_err(|error| self.reflect(error, span))?;
+            partial
+                .begin_field("span")
+                .map_err(|error| self.reflect(error, span))?;
+            partial
+                .set(Span::from(span))
+                .map_err(|error| self.reflect(error, span))?;
+            partial.end().map_err(|error| self.reflect(error, span))?;
+            return Ok(());
+        }
+
+        match value {
+            KdlValue::String(text) => {
+                if shape.type_identifier == "String" {
+                    partial
+                        .set(text.clone())
+                        .map_err(|error| self.reflect(error, span))?;
+                } else if shape.type_identifier == "Cow"
+                    && shape.is_shape(<Cow<'_, str> as facet::Facet>::SHAPE)
+                {
+                    // `Cow<str>` has no string-parse entry in its vtable, so
+                    // it can't go through `parse_from_str` like other borrowy
+                    // scalars; build the owned variant directly.
+                    partial
+                        .set::<Cow<'_, str>>(Cow::Owned(text.clone()))
+                        .map_err(|error| self.reflect(error, span))?;
+                } else if is_integer_identifier(shape.type_identifier)
+                    || is_float_identifier(shape.type_identifier)
+                {
+                    // String fallback for numbers the literal syntax can't
+                    // carry (e.g. `"18446744073709551615"` for a u64, or
+                    // anything beyond i128). Parse failures point at the
+                    // value, not deep into reflection.
+                    partial.parse_from_str(text).map_err(|_| {
+                        self.error(
+                            KdlErrorKind::InvalidValueForShape {
+                                value: self.render_value(format!("\"{text}\"")),
+                                shape,
+                            },
+                            span,
+                        )
+                    })?;
+                } else if let Some(variants) = unit_only_variants(shape) {
+                    // A unit-only enum value: the string names the variant.
+                    let Some(variant) =
+                        variants.iter().find(|variant| variant.name == text.as_str())
+                    else {
+                        return Err(self.error(
+                            KdlErrorKind::NoMatchingVariant {
+                                value: self.render_value(format!("\"{text}\"")),
+                                expected: variant_value_candidates(variants),
+                            },
+                            span,
+                        ));
+                    };
+                    partial
+                        .select_variant_named(variant.name)
+                        .map_err(|error| self.reflect(error, span))?;
+                } else {
+                    partial
+                        .parse_from_str(text)
+                        .map_err(|error| self.reflect(error, span))?;
+                }
+            }
+            KdlValue::Integer(integer) => {
+                // A unit-only enum value: the integer is a discriminant.
+                if let Some(variants) = unit_only_variants(shape) {
+                    let Some(variant) = variants.iter().find(|variant| {
+                        variant
+                            .discriminant
+                            .is_some_and(|discriminant| i128::from(discriminant) == *integer)
+                    }) else {
+                        return Err(self.error(
+                            KdlErrorKind::NoMatchingVariant {
+                                value: self.render_value(value),
+                                expected: variant_value_candidates(variants),
+                            },
+                            span,
+                        ));
+                    };
+                    partial
+                        .select_variant_named(variant.name)
+                        .map_err(|error| self.reflect(error, span))?;
+                    return Ok(());
+                }
+                self.set_integer(partial, *integer, shape, entry)?;
+            }
+            KdlValue::Float(float) => {
+                self.set_float(partial, *float, shape, entry)?;
+            }
+            KdlValue::Bool(boolean) => {
+                if shape.type_identifier == "bool" {
+                    partial
+                        .set(*boolean)
+                        .map_err(|error| self.reflect(error, span))?;
+                } else {
+                    return Err(self.error(
+                        KdlErrorKind::InvalidValueForShape {
+                            value: self.render_value(value),
+                            shape,
+                        },
+                        span,
+                    ));
+                }
+            }
+            KdlValue::Null => {
+                return Err(self.error(
+                    KdlErrorKind::InvalidValueForShape {
+                        value: self.render_value(value),
+                        shape,
+                    },
+                    span,
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes an integer literal into a numeric shape, honoring the coercion
+    /// policy for width narrowing and integer→float conversion.
+    fn set_integer(
+        &mut self,
+        partial: &mut Partial,
+        integer: i128,
+        shape: &'static Shape,
+        entry: &KdlEntry,
+    ) -> Result<(), KdlError> {
+        let span = entry.span();
+        let invalid = |de: &Self| {
+            de.error(
+                KdlErrorKind::InvalidValueForShape {
+                    value: de.render_value(integer),
+                    shape,
+                },
+                span,
+            )
+        };
+        macro_rules! narrow {
+            ($ty:ty) => {{
+                let converted: $ty = match <$ty>::try_from(integer) {
+                    Ok(converted) => converted,
+                    Err(_) => match self.options.number_coercion {
+                        NumberCoercion::AllowLossyWithWarning => {
+                            log::warn!(
+                                "integer {} truncated to fit `{shape}`",
+                                self.render_value(integer)
+                            );
+                            integer as $ty
+                        }
+                        _ => return Err(invalid(self)),
+                    },
+                };
+                partial.set(converted)
+            }};
+        }
+        let result = match shape.type_identifier {
+            "u8" => narrow!(u8),
+            "u16" => narrow!(u16),
+            "u32" => narrow!(u32),
+            "u64" => narrow!(u64),
+            "u128" => narrow!(u128),
+            "usize" => narrow!(usize),
+            "i8" => narrow!(i8),
+            "i16" => narrow!(i16),
+            "i32" => narrow!(i32),
+            "i64" => narrow!(i64),
+            "i128" => partial.set(integer),
+            "isize" => narrow!(isize),
+            "f32" | "f64" => {
+                match self.options.number_coercion {
+                    NumberCoercion::Strict => return Err(invalid(self)),
+                    NumberCoercion::AllowLossless => {
+                        if !integer_is_exact_in_f64(integer) {
+                            return Err(invalid(self));
+                        }
+                    }
+                    NumberCoercion::AllowLossyWithWarning => {
+                        if !integer_is_exact_in_f64(integer) {
+                            log::warn!(
+                                "integer {} rounded to fit `{shape}`",
+                                self.render_value(integer)
+                            );
+                        }
+                    }
+                }
+                if shape.type_identifier == "f32" {
+                    partial.set(integer as f32)
+                } else {
+                    partial.set(integer as f64)
+                }
+            }
+            _ => return Err(invalid(self)),
+        };
+        result.map_err(|error| self.reflect(error, span))?;
+        Ok(())
+    }
+
+    /// Writes a float literal into a numeric shape, honoring the coercion
+    /// policy for float→integer conversion.
+    fn set_float(
+        &mut self,
+        partial: &mut Partial,
+        float: f64,
+        shape: &'static Shape,
+        entry: &KdlEntry,
+    ) -> Result<(), KdlError> {
+        let span = entry.span();
+        let invalid = |de: &Self| {
+            de.error(
+                KdlErrorKind::InvalidValueForShape {
+                    value: de.render_value(render_float(float)),
+                    shape,
+                },
+                span,
+            )
+        };
+        match shape.type_identifier {
+            "f32" => partial
+                .set(float as f32)
+                .map_err(|error| self.reflect(error, span))?,
+            "f64" => partial
+                .set(float)
+                .map_err(|error| self.reflect(error, span))?,
+            identifier if is_integer_identifier(identifier) => {
+                match self.options.number_coercion {
+                    NumberCoercion::Strict => return Err(invalid(self)),
+                    NumberCoercion::AllowLossless => {
+                        if float.fract() != 0.0 || !float.is_finite() {
+                            return Err(invalid(self));
+                        }
+                    }
+                    NumberCoercion::AllowLossyWithWarning => {
+                        if float.fract() != 0.0 {
+                            log::warn!(
+                            "float {} truncated to fit `{shape}`",
+                            self.render_value(float)
+                        );
+                        }
+                    }
+                }
+                if !float.is_finite() {
+                    return Err(invalid(self));
+                }
+                return self.set_integer(partial, float.trunc() as i128, shape, entry);
+            }
+            _ => return Err(invalid(self)),
+        };
+        Ok(())
+    }
+
+    /// Picks the enum variant whose name matches the node.
+    ///
+    /// A type annotation (`(Build)step`) names the variant directly and
+    /// takes precedence over the node name.
+    fn find_variant_by_name(
+        &self,
+        variants: &'static [Variant],
+        node: &KdlNode,
+    ) -> Result<&'static Variant, KdlError> {
+        let name = match node.ty() {
+            Some(ty) => ty.value(),
+            None => node.name().value(),
+        };
+        variants
+            .iter()
+            .find(|variant| self.options.naming.matches(variant.name, name))
+            .ok_or_else(|| {
+                self.error(
+                    KdlErrorKind::NoMatchingNode {
+                        name: name.to_string(),
+                        expected: variants
+                            .iter()
+                            .map(|variant| self.options.naming.kdl_name(variant.name).into_owned())
+                            .collect(),
+                    },
+                    node.span(),
+                )
+            })
+    }
+
+    /// Picks the enum variant for a map value from its type annotation.
+    ///
+    /// The node name is the map key, so it can't double as the variant name:
+    /// `(Web)frontend port=80` selects `Web` and keys the entry under
+    /// `frontend`.
+    fn find_variant_by_annotation(
+        &self,
+        variants: &'static [Variant],
+        node: &KdlNode,
+    ) -> Result<&'static Variant, KdlError> {
+        let Some(ty) = node.ty() else {
+            let example = variants
+                .first()
+                .map(|variant| self.options.naming.kdl_name(variant.name).into_owned())
+                .unwrap_or_else(|| "Variant".to_string());
+            return Err(self.error(
+                KdlErrorKind::UnsupportedShape(format!(
+                    "map value node `{name}` needs a type annotation naming the variant, \
+                     e.g. `({example}){name}`",
+                    name = node.name().value()
+                )),
+                node.span(),
+            ));
+        };
+        let name = ty.value();
+        variants
+            .iter()
+            .find(|variant| self.options.naming.matches(variant.name, name))
+            .ok_or_else(|| {
+                self.error(
+                    KdlErrorKind::NoMatchingNode {
+                        name: name.to_string(),
+                        expected: variants
+                            .iter()
+                            .map(|variant| self.options.naming.kdl_name(variant.name).into_owned())
+                            .collect(),
+                    },
+                    node.span(),
+                )
+            })
+    }
+
+    /// Fills `#[facet(kdl(raw))]` fields with the node's exact source text,
+    /// so applications can hand embedded DSL sections to their own parser
+    /// later.
+    fn fill_raw_fields(
+        &mut self,
+        partial: &mut Partial,
+        node: &KdlNode,
+        fields: &'static [Field],
+    ) -> Result<(), KdlError> {
+        for field in fields {
+            if !has_kdl_attr(field, "raw") {
+                continue;
+            }
+            let span = node.span();
+            if field.shape().type_identifier != "String" {
+                return Err(self.error(
+                    KdlErrorKind::UnsupportedShape(format!(
+                        "`kdl(raw)` field `{}` must be a String, not `{}`",
+                        field.name,
+                        field.shape()
+                    )),
+                    span,
+                ));
+            }
+            let text = self
+                .input
+                .get(span.offset()..span.offset() + span.len())
+                .unwrap_or_default();
+            partial
+                .begin_field(field.name)
+                .and_then(|partial| partial.set(text.to_string()))
+                .and_then(|partial| partial.end())
+                .map_err(|error| self.reflect(error, span))?;
+        }
+        Ok(())
+    }
+
+    /// Defaults zero-sized marker fields (`()`, `PhantomData`), which have no
+    /// document representation, so type-state configs don't need an explicit
+    /// `#[facet(skip)]`.
+    fn fill_unit_like_fields(
+        &mut self,
+        partial: &mut Partial,
+        fields: &'static [Field],
+    ) -> Result<(), KdlError> {
+        for field in fields {
+            if is_unit_like(field.shape()) && !field_is_set(partial, field.name) {
+                self.record_origin(field.name, FieldOrigin::Default);
+                partial
+                    .begin_field(field.name)
+                    .and_then(|partial| partial.set_default())
+                    .and_then(|partial| partial.end())
+                    .map_err(|error| self.error(KdlErrorKind::Reflect(error), None))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Fills the idiom wrapper fields from [`crate::wrappers`]: a `NodeName`
+    /// field gets the node's own name, a `Props` field collects every
+    /// property no explicit `property` field claims.
+    ///
+    /// `Props` is filled in one visit before the entry routing — variant
+    /// frames don't remember begun fields, so the map can't be reopened per
+    /// property — and [`Self::deserialize_property`] later skips the keys
+    /// collected here instead of reporting them as unknown.
+    fn fill_wrapper_fields(
+        &mut self,
+        partial: &mut Partial,
+        node: &KdlNode,
+        fields: &'static [Field],
+    ) -> Result<(), KdlError> {
+        for field in fields {
+            if field_is_set(partial, field.name) {
+                continue;
+            }
+            if is_node_name(field.shape()) {
+                let span = node.name().span();
+                self.record_origin(field.name, FieldOrigin::Document);
+                partial
+                    .begin_field(field.name)
+                    .and_then(|partial| partial.begin_field("0"))
+                    .and_then(|partial| partial.set(node.name().value().to_string()))
+                    .and_then(|partial| partial.end())
+                    .and_then(|partial| partial.end())
+                    .map_err(|error| self.reflect(error, span))?;
+                continue;
+            }
+            if !is_props(field.shape()) {
+                continue;
+            }
+            let span = node.span();
+            partial
+                .begin_field(field.name)
+                .and_then(|partial| partial.begin_field("0"))
+                .and_then(|partial| partial.begin_map())
+                .map_err(|error| self.reflect(error, span))?;
+            let mut collected = false;
+            for entry in entry_order(node) {
+                let Some(name) = entry.name() else {
+                    continue;
+                };
+                let name = name.value();
+                if self.variant_node && self.options.variant_property.as_deref() == Some(name) {
+                    continue;
+                }
+                if self
+                    .find_property_field(fields, name, &mut Vec::new())
+                    .is_some()
+                {
+                    continue;
+                }
+                // String values verbatim, everything else in its KDL
+                // spelling — the map is `String`-valued by design.
+                let text = match entry.value() {
+                    KdlValue::String(text) => text.clone(),
+                    other => other.to_string(),
+                };
+                partial
+                    .begin_key()
+                    .and_then(|partial| partial.set(name.to_string()))
+                    .and_then(|partial| partial.end())
+                    .and_then(|partial| partial.begin_value())
+                    .and_then(|partial| partial.set(text))
+                    .and_then(|partial| partial.end())
+                    .map_err(|error| self.reflect(error, entry.span()))?;
+                collected = true;
+            }
+            partial
+                .end()
+                .and_then(|partial| partial.end())
+                .map_err(|error| self.reflect(error, span))?;
+            if collected {
+                self.record_origin(field.name, FieldOrigin::Document);
+            }
+        }
+        Ok(())
+    }
+
+    /// Makes sure every children container is initialized, even when no node
+    /// matched it, so `build` doesn't trip over uninitialized collections.
+    fn finish_children_containers(
+        &mut self,
+        partial: &mut Partial,
+        fields: &'static [Field],
+    ) -> Result<(), KdlError> {
+        for field in fields {
+            if field_role(field) == Some(FieldRole::Children) && !field_is_set(partial, field.name) {
+                self.record_origin(field.name, FieldOrigin::Default);
+                partial
+                    .begin_field(field.name)
+                    .and_then(|partial| partial.set_default())
+                    .and_then(|partial| partial.end())
+                    .map_err(|error| self.error(KdlErrorKind::Reflect(error), None))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Defaults absent optional child fields and reports missing required
+    /// ones.
+    fn fill_missing_child_fields(
+        &mut self,
+        partial: &mut Partial,
+        nodes: &[KdlNode],
+        fields: &'static [Field],
+    ) -> Result<(), KdlError> {
+        for field in fields {
+            if field_role(field) != Some(FieldRole::Child) {
+                continue;
+            }
+            // Already filled — typically a `kdl(inline)` child whose fields
+            // arrived as properties on the parent node.
+            if field_is_set(partial, field.name) {
+                continue;
+            }
+            let present = nodes.iter().any(|node| {
+                child_field_matches(
+                    field,
+                    node.name().value(),
+                    node.ty().map(|ty| ty.value()),
+                    &self.options.naming,
+                )
+            });
+            if present {
+                continue;
+            }
+            if matches!(field.shape().def, Def::Option(_)) || has_default(field) {
+                self.record_origin(field.name, FieldOrigin::Default);
+                partial
+                    .begin_field(field.name)
+                    .and_then(|partial| partial.set_default())
+                    .and_then(|partial| partial.end())
+                    .map_err(|error| self.error(KdlErrorKind::Reflect(error), None))?;
+            } else {
+                return Err(self.error(
+                    KdlErrorKind::MissingField {
+                        field: field.name,
+                        node: "document".to_string(),
+                    },
+                    None,
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The `child`/`children` field a top-level node would route into, mirroring
+/// [`KdlDeserializer::route_node`]'s matching order without deserializing
+/// anything.
+///
+/// The incremental reparser uses this to decide which fields an edit
+/// dirties, and which nodes feed those fields.
+pub(crate) fn route_target(
+    fields: &'static [Field],
+    node: &KdlNode,
+    naming: &Naming,
+) -> Option<&'static str> {
+    let name = node.name().value();
+    let ty = node.ty().map(|ty| ty.value());
+    if let Some(field) = fields.iter().find(|field| {
+        field_role(field) == Some(FieldRole::Child) && child_field_matches(field, name, ty, naming)
+    }) {
+        return Some(field.name);
+    }
+    fields
+        .iter()
+        .find(|field| {
+            field_role(field) == Some(FieldRole::Children)
+                && children_field_matches(field, name, ty, naming)
+        })
+        .map(|field| field.name)
+}
+
+/// Whether a `child` field accepts a node with the given name.
+///
+/// Struct children match on the field name; enum children match on any
+/// variant name, with a type annotation (`(Build)step`) taking precedence
+/// over the node name. Aliases are explicit document names and bypass the
+/// naming convention.
+fn child_field_matches(
+    field: &'static Field,
+    name: &str,
+    ty: Option<&str>,
+    naming: &Naming,
+) -> bool {
+    let shape = unwrap_option(field.shape());
+    match &shape.ty {
+        Type::User(UserType::Enum(enum_type)) => {
+            let wanted = ty.unwrap_or(name);
+            enum_type
+                .variants
+                .iter()
+                .any(|variant| naming.matches(variant.name, wanted))
+        }
+        _ => naming.matches(field.name, name) || kdl_aliases(field).any(|alias| alias == name),
+    }
+}
+
+/// Whether a `children` container accepts a node with the given name.
+///
+/// Struct elements match the element type's identifier run through the naming
+/// convention (lowercased by default); enum elements match any variant name,
+/// with a type annotation taking precedence over the node name; map
+/// containers accept any name.
+fn children_field_matches(
+    field: &'static Field,
+    name: &str,
+    ty: Option<&str>,
+    naming: &Naming,
+) -> bool {
+    let element = match field.shape().def {
+        Def::List(list_def) => list_def.t(),
+        Def::Set(set_def) => set_def.t(),
+        Def::Map(_) => return true,
+        _ => return false,
+    };
+    // A `Spanned` wrapper matches as its inner type; the wrapper itself has
+    // no document name.
+    let element = spanned_inner(element).unwrap_or(element);
+    match &element.ty {
+        Type::User(UserType::Enum(enum_type)) => {
+            let wanted = ty.unwrap_or(name);
+            enum_type
+                .variants
+                .iter()
+                .any(|variant| naming.matches(variant.name, wanted))
+        }
+        // The field's own name (and its singular, for the usual plural
+        // spelling) answers too, so two fields sharing an element type —
+        // `input: Vec<Stage>` next to `output: Vec<Stage>` — stay distinct.
+        _ => {
+            naming.element_name(element.type_identifier) == name
+                || naming.matches(field.name, name)
+                || field
+                    .name
+                    .strip_suffix('s')
+                    .is_some_and(|singular| naming.matches(singular, name))
+        }
+    }
+}
+
+/// The node names a `child`/`children` field can accept, for diagnostics.
+fn accepted_node_names(field: &'static Field, naming: &Naming) -> Vec<String> {
+    match field_role(field) {
+        Some(FieldRole::Child) => {
+            let shape = unwrap_option(field.shape());
+            match &shape.ty {
+                Type::User(UserType::Enum(enum_type)) => enum_type
+                    .variants
+                    .iter()
+                    .map(|variant| naming.kdl_name(variant.name).into_owned())
+                    .collect(),
+                _ => core::iter::once(naming.kdl_name(field.name).into_owned())
+                    .chain(kdl_aliases(field).map(str::to_string))
+                    .collect(),
+            }
+        }
+        Some(FieldRole::Children) => {
+            let element = match field.shape().def {
+                Def::List(list_def) => list_def.t(),
+                Def::Set(set_def) => set_def.t(),
+                Def::Map(_) => return vec![format!("<any> (map field `{}`)", field.name)],
+                _ => return Vec::new(),
+            };
+            let element = spanned_inner(element).unwrap_or(element);
+            let mut names = element_node_names(element, naming);
+            if !matches!(element.ty, Type::User(UserType::Enum(_))) {
+                let field_name = naming.kdl_name(field.name).into_owned();
+                let singular = field
+                    .name
+                    .strip_suffix('s')
+                    .map(|singular| naming.kdl_name(singular).into_owned());
+                for candidate in core::iter::once(field_name).chain(singular) {
+                    if !names.contains(&candidate) {
+                        names.push(candidate);
+                    }
+                }
+            }
+            names
+        }
+        _ => Vec::new(),
+    }
+}
+
+fn element_node_names(element: &'static Shape, naming: &Naming) -> Vec<String> {
+    match &element.ty {
+        Type::User(UserType::Enum(enum_type)) => enum_type
+            .variants
+            .iter()
+            .map(|variant| naming.kdl_name(variant.name).into_owned())
+            .collect(),
+        _ => vec![naming.element_name(element.type_identifier)],
+    }
+}
+
+/// Collects the property names reachable from `fields`, including through
+/// flattened structs, for "expected one of" diagnostics.
+fn collect_property_names(
+    fields: &'static [Field],
+    names: &mut Vec<ExpectedProperty>,
+    naming: &Naming,
+) {
+    for field in fields {
+        match field_role(field) {
+            Some(FieldRole::Property) => names.push(ExpectedProperty {
+                name: naming.kdl_name(field.name).into_owned(),
+                doc: join_doc(field.doc),
+            }),
+            Some(FieldRole::Flatten) => {
+                if let Type::User(UserType::Struct(struct_type)) = &field.shape().ty {
+                    match kdl_group(field) {
+                        Some(group) => {
+                            // Candidates show the spellings the document
+                            // must use, so the prefix is applied here too.
+                            let mut grouped = Vec::new();
+                            collect_property_names(struct_type.fields, &mut grouped, naming);
+                            names.extend(grouped.into_iter().map(|mut property| {
+                                property.name = format!("{group}-{}", property.name);
+                                property
+                            }));
+                        }
+                        None => collect_property_names(struct_type.fields, names, naming),
+                    }
+                }
+            }
+            Some(FieldRole::Child) if has_kdl_attr(field, "inline") => {
+                if let Type::User(UserType::Struct(struct_type)) = &field.shape().ty {
+                    collect_property_names(struct_type.fields, names, naming);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// A field's doc comment joined to one line, the way diagnostics quote it.
+fn join_doc(doc: &'static [&'static str]) -> String {
+    doc.iter()
+        .map(|line| line.trim())
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+/// Whether the struct field named `name` is already set on the current frame.
+///
+/// A field the frame can't resolve reads as unset, which the fill passes
+/// treat the same as "still to do".
+fn field_is_set(partial: &Partial, name: &str) -> bool {
+    partial
+        .field_index(name)
+        .is_some_and(|index| partial.is_field_set(index).unwrap_or(false))
+}
+
+/// Whether any field — including through flattened structs — is a flattened
+/// enum, i.e. whether the node needs the solver to be interpreted.
+fn has_flattened_enum(fields: &'static [Field]) -> bool {
+    fields.iter().any(|field| {
+        if field_role(field) != Some(FieldRole::Flatten) {
+            return false;
+        }
+        match &field.shape().ty {
+            Type::User(UserType::Enum(_)) => true,
+            Type::User(UserType::Struct(struct_type)) => has_flattened_enum(struct_type.fields),
+            _ => false,
+        }
+    })
+}
+
+
+fn list_element_shape(shape: &'static Shape) -> Option<&'static Shape> {
+    let shape = args_inner(shape).unwrap_or(shape);
+    match shape.def {
+        Def::List(list_def) => Some(list_def.t()),
+        _ => None,
+    }
+}
+
+/// The accepted spellings of a unit-only enum's variants, for the candidate
+/// list of a [`KdlErrorKind::NoMatchingVariant`] error.
+fn variant_value_candidates(variants: &'static [facet_core::Variant]) -> Vec<String> {
+    variants
+        .iter()
+        .map(|variant| match variant.discriminant {
+            Some(discriminant) => format!("{} ({discriminant})", variant.name),
+            None => variant.name.to_string(),
+        })
+        .collect()
+}
@@ -0,0 +1,121 @@
+//! Semantic comparison of two KDL documents under one target type.
+
+use facet_core::Facet;
+use kdl::KdlValue;
+
+use crate::deserialize::{walk_with_options, DeserializeOptions, WalkEntry};
+use crate::error::KdlError;
+use crate::spanned::Span;
+
+/// One difference reported by [`diff`].
+///
+/// Paths are dotted Rust field names from the root (`server.port`), with
+/// children container elements indexed in document order — the same
+/// convention as [`FieldOriginMap`](crate::FieldOriginMap) and
+/// [`WalkEntry`](crate::WalkEntry). Spans point into whichever input the
+/// value came from: the old document for removals, the new one for
+/// additions, both for modifications.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    /// The path is set in the new document but not the old one.
+    Added {
+        /// Dotted field path of the value.
+        path: String,
+        /// The value in the new document.
+        value: KdlValue,
+        /// The value's span in the new input.
+        span: Span,
+    },
+    /// The path is set in the old document but not the new one.
+    Removed {
+        /// Dotted field path of the value.
+        path: String,
+        /// The value in the old document.
+        value: KdlValue,
+        /// The value's span in the old input.
+        span: Span,
+    },
+    /// The path is set in both documents with different values.
+    Modified {
+        /// Dotted field path of the value.
+        path: String,
+        /// The value in the old document.
+        before: KdlValue,
+        /// The value in the new document.
+        after: KdlValue,
+        /// The old value's span in the old input.
+        before_span: Span,
+        /// The new value's span in the new input.
+        after_span: Span,
+    },
+}
+
+impl Change {
+    /// The dotted field path this change is about.
+    pub fn path(&self) -> &str {
+        match self {
+            Change::Added { path, .. }
+            | Change::Removed { path, .. }
+            | Change::Modified { path, .. } => path,
+        }
+    }
+}
+
+/// Compares two KDL documents under `T`'s matching rules and reports every
+/// value-level difference.
+///
+/// Both inputs are resolved through the same traversal as deserialization,
+/// so renames, aliases, flattening and variant selection are already folded
+/// into the paths — two spellings of the same field compare as equal, and a
+/// reordered document with identical values diffs empty. This is the
+/// building block for "config reload" logs and review tooling: each
+/// [`Change`] carries before/after values and a span into each source.
+///
+/// Changes come out in the new document's order for additions and
+/// modifications, followed by removals in the old document's order. Either
+/// input failing to parse or to match `T` is an error; this compares
+/// configurations, not broken files.
+pub fn diff<'facet, T: Facet<'facet>>(old_kdl: &str, new_kdl: &str) -> Result<Vec<Change>, KdlError> {
+    diff_with_options::<T>(old_kdl, new_kdl, &DeserializeOptions::default())
+}
+
+/// Like [`diff`], with explicit [`DeserializeOptions`].
+pub fn diff_with_options<'facet, T: Facet<'facet>>(
+    old_kdl: &str,
+    new_kdl: &str,
+    options: &DeserializeOptions,
+) -> Result<Vec<Change>, KdlError> {
+    let old = walk_with_options::<T>(old_kdl, options)?;
+    let new = walk_with_options::<T>(new_kdl, options)?;
+    let find = |entries: &[WalkEntry], path: &str| -> Option<WalkEntry> {
+        entries.iter().find(|entry| entry.path == path).cloned()
+    };
+    let mut changes = Vec::new();
+    for entry in &new {
+        match find(&old, &entry.path) {
+            Some(before) if before.value == entry.value => {}
+            Some(before) => changes.push(Change::Modified {
+                path: entry.path.clone(),
+                before: before.value,
+                after: entry.value.clone(),
+                before_span: before.span,
+                after_span: entry.span,
+            }),
+            None => changes.push(Change::Added {
+                path: entry.path.clone(),
+                value: entry.value.clone(),
+                span: entry.span,
+            }),
+        }
+    }
+    for entry in old {
+        if find(&new, &entry.path).is_none() {
+            changes.push(Change::Removed {
+                path: entry.path,
+                value: entry.value,
+                span: entry.span,
+            });
+        }
+    }
+    Ok(changes)
+}
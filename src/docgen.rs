@@ -0,0 +1,178 @@
+//! Markdown snippets that keep config docs in sync with code.
+//!
+//! Downstream projects document their config formats by hand, and the prose
+//! drifts the moment someone renames a field. [`markdown_snippet`] renders
+//! what `examples/showcase.rs` does ad hoc — the Rust definition of a
+//! document type next to an example document serialized from a real value —
+//! as one markdown fragment, ready to paste (or `include!`) into a README
+//! from a test or an xtask.
+
+use facet_core::{Def, Facet, Field, Shape, Type, UserType};
+
+use crate::fields::{
+    FieldRole, field_role, has_default, is_sensitive, kdl_attrs, spanned_inner, unwrap_option,
+};
+
+/// Renders `T`'s definition and an example document into a markdown snippet.
+///
+/// The snippet is a ` ```rust ` fence holding the reconstructed definitions
+/// of `T` and every struct or enum reachable from it, followed by a
+/// ` ```kdl ` fence holding `value` serialized with the default options.
+/// Serialization failures propagate, so a type the serializer rejects can't
+/// produce half a snippet.
+#[cfg(feature = "ser")]
+pub fn markdown_snippet<'facet, T: Facet<'facet>>(
+    value: &T,
+) -> Result<String, crate::KdlError> {
+    let example = crate::to_string(value)?;
+    Ok(format!(
+        "```rust\n{}```\n\n```kdl\n{}```\n",
+        rust_definition::<T>(),
+        example
+    ))
+}
+
+/// Reconstructs the Rust definitions of `T` and every struct or enum it
+/// reaches, from the facet shapes alone.
+///
+/// Doc comments and `#[facet(...)]` attributes are carried over, so the
+/// output reads like the source that produced the shapes. Containers,
+/// `Option`s and smart pointers are traversed but not themselves rendered;
+/// only user-defined types get a definition block.
+pub fn rust_definition<'facet, T: Facet<'facet>>() -> String {
+    let mut output = String::new();
+    let mut visited = Vec::new();
+    render_shape(T::SHAPE, &mut visited, &mut output);
+    output
+}
+
+fn render_shape(shape: &'static Shape, visited: &mut Vec<*const Shape>, output: &mut String) {
+    let shape = match spanned_inner(shape) {
+        Some(inner) => inner,
+        None => shape,
+    };
+    let shape = reachable_user_shape(shape);
+    let address = shape as *const Shape;
+    if visited.contains(&address) {
+        return;
+    }
+    visited.push(address);
+    match &shape.ty {
+        Type::User(UserType::Struct(struct_type)) => {
+            if !output.is_empty() {
+                output.push('\n');
+            }
+            render_doc(shape.doc, "", output);
+            output.push_str("#[derive(Facet)]\n");
+            match struct_type.fields {
+                [] => {
+                    output.push_str(&format!("struct {};\n", shape.type_identifier));
+                }
+                [field] if field.name == "0" => {
+                    output.push_str(&format!(
+                        "struct {}({});\n",
+                        shape.type_identifier,
+                        field.shape()
+                    ));
+                }
+                fields => {
+                    output.push_str(&format!("struct {} {{\n", shape.type_identifier));
+                    for field in fields {
+                        render_field(field, "    ", output);
+                    }
+                    output.push_str("}\n");
+                }
+            }
+            for field in struct_type.fields {
+                render_shape(field.shape(), visited, output);
+            }
+        }
+        Type::User(UserType::Enum(enum_type)) => {
+            if !output.is_empty() {
+                output.push('\n');
+            }
+            render_doc(shape.doc, "", output);
+            output.push_str("#[derive(Facet)]\n");
+            output.push_str(&format!("enum {} {{\n", shape.type_identifier));
+            for variant in enum_type.variants {
+                render_doc(variant.doc, "    ", output);
+                match variant.data.fields {
+                    [] => output.push_str(&format!("    {},\n", variant.name)),
+                    [field] if field.name == "0" => {
+                        output.push_str(&format!("    {}({}),\n", variant.name, field.shape()));
+                    }
+                    fields => {
+                        output.push_str(&format!("    {} {{\n", variant.name));
+                        for field in fields {
+                            render_field(field, "        ", output);
+                        }
+                        output.push_str("    },\n");
+                    }
+                }
+            }
+            output.push_str("}\n");
+            for variant in enum_type.variants {
+                for field in variant.data.fields {
+                    render_shape(field.shape(), visited, output);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn render_field(field: &'static Field, indent: &str, output: &mut String) {
+    render_doc(field.doc, indent, output);
+    let attrs = facet_attrs(field);
+    if !attrs.is_empty() {
+        output.push_str(&format!("{indent}#[facet({})]\n", attrs.join(", ")));
+    }
+    output.push_str(&format!("{indent}{}: {},\n", field.name, field.shape()));
+}
+
+/// The `#[facet(...)]` attribute contents a field declares, reconstructed
+/// from its flags and attribute text.
+fn facet_attrs(field: &'static Field) -> Vec<String> {
+    let mut attrs = Vec::new();
+    let role = match field_role(field) {
+        Some(FieldRole::Argument) => Some("argument"),
+        Some(FieldRole::Arguments) => Some("arguments"),
+        Some(FieldRole::Property) => Some("property"),
+        Some(FieldRole::Child) => Some("child"),
+        Some(FieldRole::Children) => Some("children"),
+        Some(FieldRole::Flatten) => Some("flatten"),
+        Some(FieldRole::Skip) => Some("skip"),
+        None => None,
+    };
+    attrs.extend(role.map(str::to_string));
+    if has_default(field) {
+        attrs.push("default".to_string());
+    }
+    if is_sensitive(field) {
+        attrs.push("sensitive".to_string());
+    }
+    attrs.extend(kdl_attrs(field).map(|attr| format!("kdl({attr})")));
+    attrs
+}
+
+fn render_doc(doc: &[&str], indent: &str, output: &mut String) {
+    for line in doc {
+        output.push_str(&format!("{indent}///{line}\n"));
+    }
+}
+
+/// Follows `Option`s, containers and smart pointers down to the shape worth
+/// rendering a definition for.
+fn reachable_user_shape(shape: &'static Shape) -> &'static Shape {
+    let shape = unwrap_option(shape);
+    match shape.def {
+        Def::List(list_def) => reachable_user_shape(list_def.t()),
+        Def::Set(set_def) => reachable_user_shape(set_def.t()),
+        Def::Map(map_def) => reachable_user_shape(map_def.v()),
+        Def::Pointer(pointer_def) => match pointer_def.pointee() {
+            Some(pointee) => reachable_user_shape(pointee),
+            None => shape,
+        },
+        _ => shape,
+    }
+}
@@ -0,0 +1,640 @@
+//! Error types for KDL serialization and deserialization.
+
+use core::fmt;
+
+use facet_core::Shape;
+use facet_reflect::ReflectError;
+#[cfg(feature = "de")]
+use miette::{Diagnostic, LabeledSpan, NamedSource, SourceSpan};
+
+#[cfg(feature = "solver")]
+use crate::solver::SolverError;
+
+/// An error produced while deserializing or serializing a KDL document.
+///
+/// With the `de` feature enabled, `KdlError` is a [`miette::Diagnostic`]:
+/// when a span is available it points at the offending piece of the source
+/// document.
+#[derive(Debug)]
+pub struct KdlError {
+    /// What went wrong.
+    pub kind: KdlErrorKind,
+    /// The span in the source document this error refers to, if any.
+    #[cfg(feature = "de")]
+    pub span: Option<SourceSpan>,
+    /// The source document, for diagnostic rendering.
+    #[cfg(feature = "de")]
+    pub source_code: NamedSource<String>,
+}
+
+impl KdlError {
+    #[cfg(feature = "de")]
+    pub(crate) fn new(kind: KdlErrorKind, span: Option<SourceSpan>, input: &str) -> Self {
+        Self {
+            kind,
+            span,
+            source_code: NamedSource::new("document", input.to_string()),
+        }
+    }
+
+    /// Renames the source document shown in diagnostics (e.g. to a filename).
+    #[cfg(feature = "de")]
+    pub(crate) fn with_source_name(mut self, name: &str) -> Self {
+        self.source_code = NamedSource::new(name, self.source_code.inner().clone());
+        self
+    }
+
+    /// An error that isn't tied to any source document (e.g. serialization).
+    pub(crate) fn detached(kind: KdlErrorKind) -> Self {
+        Self {
+            kind,
+            #[cfg(feature = "de")]
+            span: None,
+            #[cfg(feature = "de")]
+            source_code: NamedSource::new("document", String::new()),
+        }
+    }
+}
+
+impl fmt::Display for KdlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.kind)
+    }
+}
+
+impl std::error::Error for KdlError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.kind {
+            #[cfg(feature = "de")]
+            KdlErrorKind::Parse(error) => Some(error),
+            KdlErrorKind::Reflect(error) => Some(error),
+            #[cfg(feature = "solver")]
+            KdlErrorKind::Solver(error) => Some(error),
+            KdlErrorKind::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "de")]
+impl Diagnostic for KdlError {
+    fn code<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        Some(Box::new(self.kind.code()))
+    }
+
+    fn source_code(&self) -> Option<&dyn miette::SourceCode> {
+        Some(&self.source_code)
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+        if let KdlErrorKind::ArgumentsReopened {
+            first_arguments,
+            offending,
+            ..
+        } = &self.kind
+        {
+            return Some(Box::new(
+                [
+                    LabeledSpan::new_with_span(
+                        Some("arguments list completed here".to_string()),
+                        *first_arguments,
+                    ),
+                    LabeledSpan::new_with_span(
+                        Some("this argument arrives too late".to_string()),
+                        *offending,
+                    ),
+                ]
+                .into_iter(),
+            ));
+        }
+        if let KdlErrorKind::SingletonConflict {
+            first, offending, ..
+        } = &self.kind
+        {
+            return Some(Box::new(
+                [
+                    LabeledSpan::new_with_span(
+                        Some("first defined here".to_string()),
+                        *first,
+                    ),
+                    LabeledSpan::new_with_span(
+                        Some("defined again here".to_string()),
+                        *offending,
+                    ),
+                ]
+                .into_iter(),
+            ));
+        }
+        if let KdlErrorKind::DuplicateNode {
+            first, offending, ..
+        } = &self.kind
+        {
+            return Some(Box::new(
+                [
+                    LabeledSpan::new_with_span(
+                        Some("first provided here".to_string()),
+                        *first,
+                    ),
+                    LabeledSpan::new_with_span(
+                        Some("provided again here".to_string()),
+                        *offending,
+                    ),
+                ]
+                .into_iter(),
+            ));
+        }
+        let span = self.span?;
+        Some(Box::new(core::iter::once(LabeledSpan::new_with_span(
+            Some(self.kind.label().to_string()),
+            span,
+        ))))
+    }
+
+    fn help<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        let KdlErrorKind::NoMatchingProperty { expected, .. } = &self.kind else {
+            return None;
+        };
+        // Only documented fields make it into the help text; the names alone
+        // are already part of the message.
+        let documented: Vec<String> = expected
+            .iter()
+            .filter(|property| !property.doc.is_empty())
+            .map(|property| format!("expected `{}` — {}", property.name, property.doc))
+            .collect();
+        if documented.is_empty() {
+            return None;
+        }
+        Some(Box::new(documented.join("\n")))
+    }
+}
+
+/// A collection of [`KdlError`]s produced by a collect-all-errors run.
+///
+/// Implements [`miette::Diagnostic`] so that `?`-ing a single value out of
+/// [`crate::from_str_collect_errors`] still renders every collected error,
+/// each with its own labeled span.
+#[cfg(feature = "de")]
+#[derive(Debug)]
+pub struct KdlErrors {
+    /// The collected errors, in document order.
+    pub errors: Vec<KdlError>,
+}
+
+#[cfg(feature = "de")]
+impl KdlErrors {
+    pub(crate) fn new(mut errors: Vec<KdlError>) -> Self {
+        debug_assert!(!errors.is_empty(), "KdlErrors must hold at least one error");
+        // Collection follows processing order, which can diverge from
+        // document order (entries are replayed grouped by flatten frame,
+        // and the test-only `shuffle-entries` feature permutes them); the
+        // stable sort makes the aggregate report deterministic. Span-less
+        // errors are document-level and sort last.
+        errors.sort_by_key(|error| match error.span {
+            Some(span) => span.offset(),
+            None => usize::MAX,
+        });
+        Self { errors }
+    }
+}
+
+#[cfg(feature = "de")]
+impl fmt::Display for KdlErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.errors.len() == 1 {
+            write!(f, "{}", self.errors[0])
+        } else {
+            write!(
+                f,
+                "{} errors while deserializing KDL document",
+                self.errors.len()
+            )
+        }
+    }
+}
+
+#[cfg(feature = "de")]
+impl std::error::Error for KdlErrors {}
+
+#[cfg(feature = "de")]
+impl Diagnostic for KdlErrors {
+    fn code<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        Some(Box::new("facet_kdl::multiple"))
+    }
+
+    fn related<'a>(&'a self) -> Option<Box<dyn Iterator<Item = &'a dyn Diagnostic> + 'a>> {
+        Some(Box::new(
+            self.errors.iter().map(|error| error as &dyn Diagnostic),
+        ))
+    }
+}
+
+/// The different kinds of [`KdlError`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum KdlErrorKind {
+    /// The document isn't valid KDL.
+    #[cfg(feature = "de")]
+    Parse(kdl::KdlError),
+    /// An error bubbled up from [`facet_reflect`] while building the value.
+    Reflect(ReflectError),
+    /// The top-level type doesn't have the shape of a KDL document.
+    InvalidDocumentShape(&'static Shape),
+    /// A shape was encountered that this crate doesn't know how to handle.
+    UnsupportedShape(String),
+    /// A value definition was encountered that this crate can't deserialize.
+    UnsupportedValueDef(String),
+    /// A KDL value can't be converted into the target shape.
+    InvalidValueForShape {
+        /// Rendering of the offending KDL value.
+        value: String,
+        /// The shape the value was supposed to fill.
+        shape: &'static Shape,
+    },
+    /// A required field was never filled by the document.
+    MissingField {
+        /// The Rust name of the missing field.
+        field: &'static str,
+        /// The node (or document) that was supposed to provide it.
+        node: String,
+    },
+    /// A node property doesn't correspond to any field.
+    NoMatchingProperty {
+        /// The property name found in the document.
+        name: String,
+        /// The properties the target shape accepts, each with its doc
+        /// comment.
+        expected: Vec<ExpectedProperty>,
+    },
+    /// A value aimed at a unit-only enum names no variant, by name or by
+    /// discriminant.
+    NoMatchingVariant {
+        /// The value found in the document.
+        value: String,
+        /// The variants the enum accepts, each as `Name (discriminant)`.
+        expected: Vec<String>,
+    },
+    /// A child node doesn't correspond to any field or variant.
+    NoMatchingNode {
+        /// The node name found in the document.
+        name: String,
+        /// The node names the target shape accepts.
+        expected: Vec<String>,
+    },
+    /// A positional argument appeared after the arguments list was already
+    /// closed by a named entry.
+    #[cfg(feature = "de")]
+    ArgumentsReopened {
+        /// The name of the node it happened on.
+        node: String,
+        /// The span of the first run of positional arguments.
+        first_arguments: SourceSpan,
+        /// The span of the offending later argument.
+        offending: SourceSpan,
+    },
+    /// A `child` field's node appeared more than once in one document or
+    /// children block.
+    #[cfg(feature = "de")]
+    DuplicateNode {
+        /// The Rust name of the field both nodes map to.
+        field: &'static str,
+        /// The node name of the later occurrence.
+        name: String,
+        /// The span of the first occurrence.
+        first: SourceSpan,
+        /// The span of the offending later occurrence.
+        offending: SourceSpan,
+    },
+    /// A node selecting an enum variant with no child fields carries a
+    /// children block.
+    #[cfg(feature = "de")]
+    UnexpectedChildren {
+        /// The name of the node it happened on.
+        node: String,
+        /// The names of the stray child nodes, in document order.
+        children: Vec<String>,
+    },
+    /// Two occurrences of a `#[facet(kdl(singleton))]` node define the same
+    /// property.
+    #[cfg(feature = "de")]
+    SingletonConflict {
+        /// The Rust name of the field the nodes merge into.
+        field: &'static str,
+        /// The property key defined twice.
+        key: String,
+        /// The span of the earlier definition.
+        first: SourceSpan,
+        /// The span of the offending later definition.
+        offending: SourceSpan,
+    },
+    /// The flatten solver couldn't settle on a single interpretation.
+    #[cfg(feature = "solver")]
+    Solver(SolverError),
+    /// Deserialization was aborted by a progress callback returning
+    /// `ControlFlow::Break`.
+    #[cfg(feature = "de")]
+    Cancelled {
+        /// Nodes that had been visited when the run was aborted.
+        nodes_visited: usize,
+    },
+    /// A registered `validate_with` hook rejected a value.
+    ValidationFailed {
+        /// The Rust name of the field being validated.
+        field: &'static str,
+        /// The message the validator returned.
+        message: String,
+    },
+    /// A discriminant property contradicts the variant the node selects.
+    #[cfg(feature = "de")]
+    VariantPropertyMismatch {
+        /// The configured discriminant property key.
+        key: String,
+        /// The variant the node name (or annotation) selects.
+        expected: &'static str,
+        /// The value the discriminant property carried.
+        found: String,
+    },
+    /// The derived schema itself is contradictory.
+    SchemaError(String),
+    /// A value of this shape can't be serialized to KDL.
+    SerializeUnknownValueType(&'static Shape),
+    /// A top-level document shape carries attributes only valid on nodes.
+    IllegalAttributesOnTopLevelShape {
+        /// The offending top-level shape.
+        shape: &'static Shape,
+        /// Each offending field, as `(field name, offending attribute)`.
+        offenders: Vec<(&'static str, &'static str)>,
+    },
+    /// An I/O error occurred while reading or writing.
+    Io(std::io::Error),
+    /// The input bytes aren't valid text in a supported encoding.
+    Encoding(String),
+}
+
+/// One property a shape accepts, as listed by
+/// [`KdlErrorKind::NoMatchingProperty`].
+///
+/// Carrying the field's doc comment lets CLIs print self-documenting
+/// diagnostics like ``expected `port` — The TCP port to listen on``.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExpectedProperty {
+    /// The document-facing property name, naming-converted.
+    pub name: String,
+    /// The field's doc comment, joined to one line; empty when the field
+    /// carries none.
+    pub doc: String,
+}
+
+impl KdlErrorKind {
+    #[cfg(feature = "de")]
+    fn code(&self) -> &'static str {
+        match self {
+            KdlErrorKind::Parse(_) => "facet_kdl::parse",
+            KdlErrorKind::Reflect(_) => "facet_kdl::reflect",
+            KdlErrorKind::InvalidDocumentShape(_) => "facet_kdl::invalid_document_shape",
+            KdlErrorKind::UnsupportedShape(_) => "facet_kdl::unsupported_shape",
+            KdlErrorKind::UnsupportedValueDef(_) => "facet_kdl::unsupported_value_def",
+            KdlErrorKind::InvalidValueForShape { .. } => "facet_kdl::invalid_value",
+            KdlErrorKind::MissingField { .. } => "facet_kdl::missing_field",
+            KdlErrorKind::NoMatchingProperty { .. } => "facet_kdl::no_matching_property",
+            KdlErrorKind::ArgumentsReopened { .. } => "facet_kdl::arguments_reopened",
+            KdlErrorKind::NoMatchingNode { .. } => "facet_kdl::no_matching_node",
+            KdlErrorKind::NoMatchingVariant { .. } => "facet_kdl::no_matching_variant",
+            KdlErrorKind::DuplicateNode { .. } => "facet_kdl::duplicate_node",
+            KdlErrorKind::UnexpectedChildren { .. } => "facet_kdl::unexpected_children",
+            KdlErrorKind::SingletonConflict { .. } => "facet_kdl::singleton_conflict",
+            #[cfg(feature = "solver")]
+            KdlErrorKind::Solver(_) => "facet_kdl::solver",
+            KdlErrorKind::Cancelled { .. } => "facet_kdl::cancelled",
+            KdlErrorKind::ValidationFailed { .. } => "facet_kdl::validation",
+            KdlErrorKind::VariantPropertyMismatch { .. } => {
+                "facet_kdl::variant_property_mismatch"
+            }
+            KdlErrorKind::SchemaError(_) => "facet_kdl::schema",
+            KdlErrorKind::SerializeUnknownValueType(_) => "facet_kdl::serialize_unknown_value",
+            KdlErrorKind::IllegalAttributesOnTopLevelShape { .. } => {
+                "facet_kdl::illegal_top_level_attributes"
+            }
+            KdlErrorKind::Io(_) => "facet_kdl::io",
+            KdlErrorKind::Encoding(_) => "facet_kdl::encoding",
+        }
+    }
+
+    #[cfg(feature = "de")]
+    fn label(&self) -> &'static str {
+        match self {
+            KdlErrorKind::Parse(_) => "invalid KDL here",
+            KdlErrorKind::InvalidValueForShape { .. } => "this value",
+            KdlErrorKind::ValidationFailed { .. } => "this value",
+            KdlErrorKind::VariantPropertyMismatch { .. } => "this property",
+            KdlErrorKind::MissingField { .. } => "in this node",
+            KdlErrorKind::NoMatchingProperty { .. } => "this property",
+            KdlErrorKind::NoMatchingNode { .. } => "this node",
+            KdlErrorKind::NoMatchingVariant { .. } => "this value",
+            KdlErrorKind::UnexpectedChildren { .. } => "this children block",
+            #[cfg(feature = "solver")]
+            KdlErrorKind::Solver(_) => "while interpreting this node",
+            _ => "here",
+        }
+    }
+}
+
+impl fmt::Display for KdlErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            // Wrapping kinds expose the inner error via `Error::source`;
+            // repeating its message here would make chains print it twice.
+            #[cfg(feature = "de")]
+            KdlErrorKind::Parse(_) => write!(f, "failed to parse KDL"),
+            KdlErrorKind::Reflect(_) => write!(f, "error while building the value"),
+            KdlErrorKind::InvalidDocumentShape(shape) => write!(
+                f,
+                "type `{shape}` can't represent a KDL document: expected a struct whose fields \
+                 are all `child` or `children`, or a map keyed by node name"
+            ),
+            KdlErrorKind::UnsupportedShape(message) => {
+                write!(f, "unsupported shape: {message}")
+            }
+            KdlErrorKind::UnsupportedValueDef(message) => {
+                write!(f, "unsupported value definition: {message}")
+            }
+            KdlErrorKind::InvalidValueForShape { value, shape } => {
+                write!(f, "value {value} doesn't fit `{shape}`")
+            }
+            KdlErrorKind::MissingField { field, node } => {
+                write!(f, "missing field `{field}` for `{node}`")
+            }
+            KdlErrorKind::NoMatchingProperty { name, expected } => {
+                write!(f, "unknown property `{name}`")?;
+                if !expected.is_empty() {
+                    let names: Vec<&str> = expected
+                        .iter()
+                        .map(|property| property.name.as_str())
+                        .collect();
+                    write!(f, ", expected one of: {}", names.join(", "))?;
+                }
+                Ok(())
+            }
+            KdlErrorKind::NoMatchingVariant { value, expected } => {
+                write!(f, "value {value} names no variant")?;
+                if !expected.is_empty() {
+                    write!(f, ", expected one of: {}", expected.join(", "))?;
+                }
+                Ok(())
+            }
+            KdlErrorKind::NoMatchingNode { name, expected } => {
+                write!(f, "unknown node `{name}`")?;
+                if !expected.is_empty() {
+                    write!(f, ", expected one of: {}", expected.join(", "))?;
+                }
+                Ok(())
+            }
+            #[cfg(feature = "de")]
+            KdlErrorKind::ArgumentsReopened { node, .. } => write!(
+                f,
+                "node `{node}`: positional argument after the arguments list was completed"
+            ),
+            #[cfg(feature = "de")]
+            KdlErrorKind::DuplicateNode { field, name, .. } => write!(
+                f,
+                "node `{name}` appears more than once; field `{field}` takes a single node"
+            ),
+            #[cfg(feature = "de")]
+            KdlErrorKind::UnexpectedChildren { node, children } => write!(
+                f,
+                "node `{node}` takes no children, but carries: {}",
+                children.join(", ")
+            ),
+            #[cfg(feature = "de")]
+            KdlErrorKind::SingletonConflict { field, key, .. } => write!(
+                f,
+                "property `{key}` is defined by more than one of the nodes merging \
+                 into field `{field}`"
+            ),
+            #[cfg(feature = "solver")]
+            KdlErrorKind::Solver(_) => write!(f, "failed to resolve flattened enums"),
+            #[cfg(feature = "de")]
+            KdlErrorKind::Cancelled { nodes_visited } => {
+                write!(f, "deserialization cancelled after {nodes_visited} nodes")
+            }
+            KdlErrorKind::ValidationFailed { field, message } => {
+                write!(f, "invalid value for `{field}`: {message}")
+            }
+            #[cfg(feature = "de")]
+            KdlErrorKind::VariantPropertyMismatch {
+                key,
+                expected,
+                found,
+            } => write!(
+                f,
+                "discriminant property `{key}` says `{found}`, but the node selects \
+                 variant `{expected}`"
+            ),
+            KdlErrorKind::SchemaError(message) => write!(f, "schema error: {message}"),
+            KdlErrorKind::SerializeUnknownValueType(shape) => {
+                write!(f, "can't serialize a value of type `{shape}` to KDL")
+            }
+            KdlErrorKind::IllegalAttributesOnTopLevelShape { shape, offenders } => {
+                let listing = offenders
+                    .iter()
+                    .map(|(field, attribute)| format!("`{field}` is `{attribute}`"))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                write!(
+                    f,
+                    "top-level shape `{shape}` has fields with node-only attributes \
+                     ({listing}); a document may only contain child and children fields"
+                )
+            }
+            KdlErrorKind::Io(_) => write!(f, "I/O error"),
+            KdlErrorKind::Encoding(message) => write!(f, "encoding error: {message}"),
+        }
+    }
+}
+
+#[cfg(feature = "json-errors")]
+mod json {
+    use miette::Diagnostic;
+
+    use super::{KdlError, KdlErrorKind, KdlErrors};
+
+    impl KdlError {
+        /// Renders this error as one JSON object, for machine consumers
+        /// (CI annotators, GitHub problem matchers, editor integrations).
+        ///
+        /// The shape is `{"code", "message", "span"?, "labels": [],
+        /// "suggestions": []}`; offsets are byte offsets into the source
+        /// document. The output is a single line.
+        pub fn to_json(&self) -> String {
+            let mut out = String::from("{");
+            if let Some(code) = Diagnostic::code(self) {
+                out.push_str(&format!("\"code\":\"{}\",", escape(&code.to_string())));
+            }
+            out.push_str(&format!("\"message\":\"{}\"", escape(&self.to_string())));
+            if let Some(span) = self.span {
+                out.push_str(&format!(
+                    ",\"span\":{{\"offset\":{},\"len\":{}}}",
+                    span.offset(),
+                    span.len()
+                ));
+            }
+            out.push_str(",\"labels\":[");
+            if let Some(labels) = Diagnostic::labels(self) {
+                let rendered: Vec<String> = labels
+                    .map(|label| {
+                        format!(
+                            "{{\"label\":\"{}\",\"offset\":{},\"len\":{}}}",
+                            escape(label.label().unwrap_or_default()),
+                            label.offset(),
+                            label.len()
+                        )
+                    })
+                    .collect();
+                out.push_str(&rendered.join(","));
+            }
+            out.push_str("],\"suggestions\":[");
+            let suggestions: Vec<&str> = match &self.kind {
+                KdlErrorKind::NoMatchingProperty { expected, .. } => expected
+                    .iter()
+                    .map(|property| property.name.as_str())
+                    .collect(),
+                KdlErrorKind::NoMatchingNode { expected, .. }
+                | KdlErrorKind::NoMatchingVariant { expected, .. } => {
+                    expected.iter().map(String::as_str).collect()
+                }
+                _ => Vec::new(),
+            };
+            let rendered: Vec<String> = suggestions
+                .iter()
+                .map(|suggestion| format!("\"{}\"", escape(suggestion)))
+                .collect();
+            out.push_str(&rendered.join(","));
+            out.push_str("]}");
+            out
+        }
+    }
+
+    impl KdlErrors {
+        /// Renders every collected error via [`KdlError::to_json`], as one
+        /// JSON array.
+        pub fn to_json(&self) -> String {
+            let rendered: Vec<String> = self.errors.iter().map(KdlError::to_json).collect();
+            format!("[{}]", rendered.join(","))
+        }
+    }
+
+    fn escape(text: &str) -> String {
+        let mut escaped = String::with_capacity(text.len());
+        for c in text.chars() {
+            match c {
+                '"' => escaped.push_str("\\\""),
+                '\\' => escaped.push_str("\\\\"),
+                '\n' => escaped.push_str("\\n"),
+                '\r' => escaped.push_str("\\r"),
+                '\t' => escaped.push_str("\\t"),
+                c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+                c => escaped.push(c),
+            }
+        }
+        escaped
+    }
+}
@@ -0,0 +1,403 @@
+//! The field attribute vocabulary shared by both directions.
+//!
+//! These helpers only inspect [`facet_core`] shapes — no parser, no
+//! diagnostics — so the spec-only modules ([`crate::cli`],
+//! [`crate::completion`], [`crate::validate`]) can use them regardless of
+//! which of the `ser`/`de` features are enabled.
+
+use facet_core::{Def, Field, FieldAttribute, Shape, ShapeAttribute, Type, UserType};
+#[cfg(any(feature = "ser", feature = "de"))]
+use facet_core::Variant;
+
+/// How a field participates in the KDL mapping.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum FieldRole {
+    /// One positional argument.
+    Argument,
+    /// All remaining positional arguments, collected into a list.
+    Arguments,
+    /// A `key=value` entry.
+    Property,
+    /// A single named child node.
+    Child,
+    /// A collection of child nodes.
+    Children,
+    /// Merged into the surrounding node.
+    Flatten,
+    /// Never read from the document.
+    Skip,
+}
+
+/// Reads the KDL role of a field off its facet attributes, if it has one.
+///
+/// `child` and `flatten` are keywords the facet derive recognizes and turns
+/// into field flags; the KDL-specific roles come through as arbitrary
+/// attribute text.
+pub(crate) fn field_role(field: &Field) -> Option<FieldRole> {
+    if field.flags.contains(facet_core::FieldFlags::CHILD) {
+        return Some(FieldRole::Child);
+    }
+    if field.flags.contains(facet_core::FieldFlags::FLATTEN) {
+        return Some(FieldRole::Flatten);
+    }
+    for attribute in field.attributes {
+        let FieldAttribute::Arbitrary(text) = attribute;
+        let role = match *text {
+            "argument" => FieldRole::Argument,
+            "arguments" => FieldRole::Arguments,
+            "property" => FieldRole::Property,
+            "children" => FieldRole::Children,
+            "skip" => FieldRole::Skip,
+            _ => continue,
+        };
+        return Some(role);
+    }
+    // The `Args` idiom wrapper carries its role in the type itself, so a
+    // bare field of it collects positional arguments with no attribute
+    // spelled out. `Props` and `NodeName` stay role-less: they're filled
+    // outside the entry routing the roles drive.
+    if args_inner(field.shape()).is_some() {
+        return Some(FieldRole::Arguments);
+    }
+    None
+}
+
+/// The contents of a `#[facet(kdl(...))]` attribute on a field, if present.
+///
+/// `#[facet(kdl(raw))]` yields `"raw"`; attributes outside the `kdl`
+/// namespace are ignored.
+pub(crate) fn kdl_attrs(field: &'static Field) -> impl Iterator<Item = &'static str> {
+    field.attributes.iter().filter_map(|attribute| {
+        let FieldAttribute::Arbitrary(text) = attribute;
+        text.strip_prefix("kdl(")?.strip_suffix(')')
+    })
+}
+
+/// Whether a field carries the given `#[facet(kdl(...))]` attribute.
+pub(crate) fn has_kdl_attr(field: &'static Field, name: &str) -> bool {
+    kdl_attrs(field).any(|attr| attr == name)
+}
+
+/// The alternate node names declared on a field via
+/// `#[facet(kdl(alias = name))]`.
+///
+/// A field may carry several alias attributes. The serializer always emits
+/// the canonical (field) name; aliases are accepted on input only.
+#[cfg(feature = "de")]
+pub(crate) fn kdl_aliases(field: &'static Field) -> impl Iterator<Item = &'static str> {
+    kdl_attrs(field).filter_map(|attr| {
+        let rest = attr.strip_prefix("alias")?.trim_start();
+        let name = rest.strip_prefix('=')?.trim();
+        Some(name.trim_matches('"'))
+    })
+}
+
+/// The integer base declared on a field via `#[facet(kdl(radix = 16))]`,
+/// if any. Only 2, 8 and 16 mean anything to the serializers; input accepts
+/// any radix the KDL literal syntax can spell regardless.
+#[cfg(feature = "ser")]
+pub(crate) fn kdl_radix(field: &'static Field) -> Option<u32> {
+    kdl_attrs(field).find_map(|attr| {
+        let rest = attr.strip_prefix("radix")?.trim_start();
+        rest.strip_prefix('=')?.trim().parse().ok()
+    })
+}
+
+/// The zero-padded digit width declared via `#[facet(kdl(width = 4))]`, used
+/// together with `kdl(radix)` to emit fixed-width literals like `0x00FF`.
+#[cfg(feature = "ser")]
+pub(crate) fn kdl_width(field: &'static Field) -> Option<usize> {
+    kdl_attrs(field).find_map(|attr| {
+        let rest = attr.strip_prefix("width")?.trim_start();
+        rest.strip_prefix('=')?.trim().parse().ok()
+    })
+}
+
+/// The canonical form declared via `#[facet(kdl(node_or_property))]`, if
+/// the attribute is present.
+///
+/// A marked property field additionally accepts the child-node spelling on
+/// input — `timeout 30` next to `timeout=30` — a pattern common in
+/// human-authored files. The serializer writes the canonical form only: the
+/// property by default, the node when the attribute says
+/// `node_or_property = node`.
+pub(crate) fn kdl_node_or_property(field: &'static Field) -> Option<&'static str> {
+    kdl_attrs(field).find_map(|attr| {
+        let rest = attr.strip_prefix("node_or_property")?.trim_start();
+        match rest.strip_prefix('=') {
+            Some(form) => Some(form.trim().trim_matches('"')),
+            None if rest.is_empty() => Some("property"),
+            None => None,
+        }
+    })
+}
+
+/// The validator name declared on a field via
+/// `#[facet(kdl(validate_with = name))]`, if any.
+///
+/// The name refers to a validator registered on
+/// `DeserializeOptions::validators`; the attribute itself can't carry a
+/// function pointer.
+#[cfg(feature = "de")]
+pub(crate) fn kdl_validator(field: &'static Field) -> Option<&'static str> {
+    kdl_attrs(field).find_map(|attr| {
+        let rest = attr.strip_prefix("validate_with")?.trim_start();
+        let name = rest.strip_prefix('=')?.trim();
+        Some(name.trim_matches('"'))
+    })
+}
+
+/// The flag codec name declared on a field via
+/// `#[facet(kdl(flags_with = name))]`, if any.
+///
+/// The name refers to a parser/formatter pair registered on
+/// `DeserializeOptions::flag_parsers` and `SerializeOptions::flag_formatters`;
+/// both exist only with the `bitflags` feature.
+#[cfg(all(feature = "bitflags", any(feature = "ser", feature = "de")))]
+pub(crate) fn kdl_flags_with(field: &'static Field) -> Option<&'static str> {
+    kdl_attrs(field).find_map(|attr| {
+        let rest = attr.strip_prefix("flags_with")?.trim_start();
+        let name = rest.strip_prefix('=')?.trim();
+        Some(name.trim_matches('"'))
+    })
+}
+
+/// The property prefix declared on a flatten field via
+/// `#[facet(kdl(group = "tls"))]`, if any.
+///
+/// The inner struct's properties appear on the parent node spelled
+/// `tls-cert=`, `tls-key=` — the flat style KDL dialects use instead of a
+/// children block — and the prefix keeps two flattened structs with
+/// same-named fields from colliding.
+#[cfg(any(feature = "ser", feature = "de"))]
+pub(crate) fn kdl_group(field: &'static Field) -> Option<&'static str> {
+    kdl_attrs(field).find_map(|attr| {
+        let rest = attr.strip_prefix("group")?.trim_start();
+        let name = rest.strip_prefix('=')?.trim();
+        Some(name.trim_matches('"'))
+    })
+}
+
+/// Whether a shape opts into strict property checking with
+/// `#[facet(deny_unknown_fields)]`.
+#[cfg(feature = "de")]
+pub(crate) fn denies_unknown_fields(shape: &'static Shape) -> bool {
+    shape
+        .attributes
+        .iter()
+        .any(|attribute| matches!(attribute, ShapeAttribute::DenyUnknownFields))
+}
+
+/// Whether an enum variant opts into strict property checking, independent
+/// of the enum's own shape attributes.
+///
+/// The facet derive consumes a bare `deny_unknown_fields` on variants
+/// without recording it, so the spelling that reaches the shape is the
+/// KDL-namespaced `#[facet(kdl(deny_unknown_fields))]`.
+#[cfg(feature = "de")]
+pub(crate) fn variant_denies_unknown_fields(variant: &'static Variant) -> bool {
+    variant.attributes.iter().any(|attribute| {
+        matches!(
+            attribute,
+            facet_core::VariantAttribute::Arbitrary(text)
+                if *text == "kdl(deny_unknown_fields)"
+        )
+    })
+}
+
+/// Whether a variant is the `#[facet(other)]` catch-all, which the flatten
+/// solver selects when no other variant combination matches a node.
+#[cfg(feature = "solver")]
+pub(crate) fn variant_is_other(variant: &'static Variant) -> bool {
+    variant.attributes.iter().any(|attribute| {
+        matches!(
+            attribute,
+            facet_core::VariantAttribute::Arbitrary(text) if *text == "other"
+        )
+    })
+}
+
+/// If `variant` is a tuple variant wrapping a single list — `Hosts(Vec<String>)`
+/// — returns the payload field.
+///
+/// Such variants map to a node whose positional arguments fill the list:
+/// `hosts "a" "b" "c"`. A role attribute on the payload opts out of this
+/// treatment.
+#[cfg(any(feature = "ser", feature = "de"))]
+pub(crate) fn variant_list_payload(variant: &'static Variant) -> Option<&'static Field> {
+    let [field] = variant.data.fields else {
+        return None;
+    };
+    if field.name != "0" || field_role(field).is_some() {
+        return None;
+    }
+    matches!(field.shape().def, Def::List(_)).then_some(field)
+}
+
+/// If `shape` is an enum whose variants all carry no payload, returns the
+/// variants.
+///
+/// Such enums read and write as scalar values: the variant name as a string,
+/// or — for `#[repr]` enums with meaningful discriminants — the numeric
+/// discriminant.
+#[cfg(any(feature = "ser", feature = "de"))]
+pub(crate) fn unit_only_variants(shape: &'static Shape) -> Option<&'static [Variant]> {
+    let Type::User(UserType::Enum(enum_type)) = &shape.ty else {
+        return None;
+    };
+    enum_type
+        .variants
+        .iter()
+        .all(|variant| variant.data.fields.is_empty())
+        .then_some(enum_type.variants)
+}
+
+/// The fields of a top-level document shape that carry node-only roles, as
+/// `(field name, offending role)`.
+///
+/// A document has no entries, so argument/property/flatten fields have
+/// nowhere to go at the top level. Both serializers and the deserializer
+/// reject them through this one list, so the two directions can't drift
+/// apart on what a valid document shape is.
+#[cfg(any(feature = "ser", feature = "de"))]
+pub(crate) fn top_level_offenders(
+    fields: &'static [Field],
+) -> Vec<(&'static str, &'static str)> {
+    fields
+        .iter()
+        .filter_map(|field| match field_role(field) {
+            Some(FieldRole::Argument) => Some((field.name, "argument")),
+            Some(FieldRole::Arguments) => Some((field.name, "arguments")),
+            Some(FieldRole::Property) => Some((field.name, "property")),
+            Some(FieldRole::Flatten) => Some((field.name, "flatten")),
+            _ => None,
+        })
+        .collect()
+}
+
+/// If `shape` is a newtype tuple struct — a single role-less `0` field —
+/// or a struct marked `#[facet(transparent)]`, returns the inner field.
+///
+/// A top-level `struct Doc(Inner)` document delegates to `Inner`, so the same
+/// document shape can be wrapped for type safety without changing the text.
+#[cfg(any(feature = "ser", feature = "de"))]
+pub(crate) fn newtype_inner(shape: &'static Shape) -> Option<&'static Field> {
+    if let Some(field) = transparent_inner(shape) {
+        return Some(field);
+    }
+    let Type::User(UserType::Struct(struct_type)) = &shape.ty else {
+        return None;
+    };
+    let [field] = struct_type.fields else {
+        return None;
+    };
+    (field.name == "0" && field_role(field).is_none()).then_some(field)
+}
+
+/// If `shape` is a struct marked `#[facet(transparent)]` wrapping a single
+/// field, returns that field.
+///
+/// A transparent wrapper has no representation of its own: wherever it
+/// appears — the document root, a child field, a value — it reads and writes
+/// as its inner type.
+pub(crate) fn transparent_inner(shape: &'static Shape) -> Option<&'static Field> {
+    if !shape
+        .attributes
+        .iter()
+        .any(|attribute| matches!(attribute, ShapeAttribute::Transparent))
+    {
+        return None;
+    }
+    let Type::User(UserType::Struct(struct_type)) = &shape.ty else {
+        return None;
+    };
+    let [field] = struct_type.fields else {
+        return None;
+    };
+    Some(field)
+}
+
+/// Whether a shape is a zero-sized marker — `()` or `PhantomData<T>` — with
+/// no document representation.
+///
+/// Type-state configs carry these for the compiler's benefit only; both
+/// serializers skip them and the deserializer defaults them, without
+/// requiring an explicit `#[facet(skip)]`.
+pub(crate) fn is_unit_like(shape: &'static Shape) -> bool {
+    matches!(shape.type_identifier, "()" | "PhantomData")
+}
+
+/// Whether a field is flagged `#[facet(sensitive)]`.
+pub(crate) fn is_sensitive(field: &'static Field) -> bool {
+    field.flags.contains(facet_core::FieldFlags::SENSITIVE)
+}
+
+/// Whether a field is flagged `#[facet(default)]`.
+pub(crate) fn has_default(field: &'static Field) -> bool {
+    field.flags.contains(facet_core::FieldFlags::DEFAULT)
+}
+
+/// If `shape` is a smart pointer (`Box`, `Rc`, `Arc`), returns the pointee.
+pub(crate) fn pointee(shape: &'static Shape) -> Option<&'static Shape> {
+    match shape.def {
+        Def::Pointer(pointer_def) => pointer_def.pointee(),
+        _ => None,
+    }
+}
+
+pub(crate) fn unwrap_option(shape: &'static Shape) -> &'static Shape {
+    match shape.def {
+        Def::Option(option_def) => option_def.t(),
+        _ => shape,
+    }
+}
+
+/// The single `0` field's shape of the idiom wrapper named `identifier`,
+/// from [`crate::wrappers`]; the structural check keeps an unrelated user
+/// type that happens to share the name from being treated as one.
+fn wrapper_inner(shape: &'static Shape, identifier: &str) -> Option<&'static Shape> {
+    if shape.type_identifier != identifier {
+        return None;
+    }
+    let Type::User(UserType::Struct(struct_type)) = &shape.ty else {
+        return None;
+    };
+    let [field] = struct_type.fields else {
+        return None;
+    };
+    (field.name == "0").then(|| field.shape())
+}
+
+/// If `shape` is the [`Args`](crate::wrappers::Args) idiom wrapper, returns
+/// the wrapped list's shape.
+pub(crate) fn args_inner(shape: &'static Shape) -> Option<&'static Shape> {
+    wrapper_inner(shape, "Args").filter(|inner| matches!(inner.def, Def::List(_)))
+}
+
+/// Whether `shape` is the [`Props`](crate::wrappers::Props) idiom wrapper.
+#[cfg(any(feature = "ser", feature = "de"))]
+pub(crate) fn is_props(shape: &'static Shape) -> bool {
+    wrapper_inner(shape, "Props").is_some_and(|inner| matches!(inner.def, Def::Map(_)))
+}
+
+/// Whether `shape` is the [`NodeName`](crate::wrappers::NodeName) idiom
+/// wrapper.
+#[cfg(any(feature = "ser", feature = "de"))]
+pub(crate) fn is_node_name(shape: &'static Shape) -> bool {
+    wrapper_inner(shape, "NodeName").is_some_and(|inner| inner.type_identifier == "String")
+}
+
+/// If `shape` is a `Spanned<T>`, returns `T`'s shape.
+pub(crate) fn spanned_inner(shape: &'static Shape) -> Option<&'static Shape> {
+    if shape.type_identifier != "Spanned" {
+        return None;
+    }
+    let Type::User(UserType::Struct(struct_type)) = &shape.ty else {
+        return None;
+    };
+    struct_type
+        .fields
+        .iter()
+        .find(|field| field.name == "value")
+        .map(|field| field.shape())
+}
@@ -0,0 +1,75 @@
+//! Bitflags-style flag strings.
+//!
+//! A property like `features="READ|WRITE"` maps to a [`bitflags`] type
+//! declared in "impl mode", so the struct itself can derive `Facet`:
+//!
+//! ```ignore
+//! #[derive(Debug, Facet, PartialEq)]
+//! struct Features(u32);
+//!
+//! bitflags::bitflags! {
+//!     impl Features: u32 {
+//!         const READ = 1;
+//!         const WRITE = 2;
+//!     }
+//! }
+//! ```
+//!
+//! The field opts in with `#[facet(kdl(flags_with = features))]`, naming a
+//! codec registered on `DeserializeOptions::flag_parsers` (and
+//! `SerializeOptions::flag_formatters` for output), built from the generic
+//! helpers here: `("features", parse_flags::<Features>)`. Flag names may be
+//! separated by `|` or spaces; unknown names error, listing the valid ones.
+
+/// Parses a flag string into the backing bits of a bitflags type.
+///
+/// Registered on `DeserializeOptions::flag_parsers` as a plain function
+/// pointer: `("features", parse_flags::<Features>)`.
+pub type FlagParser = fn(&str) -> Result<i128, String>;
+
+/// Renders backing bits as a `|`-separated flag string.
+///
+/// Registered on `SerializeOptions::flag_formatters` as a plain function
+/// pointer: `("features", format_flags::<Features>)`.
+pub type FlagFormatter = fn(i128) -> Result<String, String>;
+
+/// Parses a `|`- or space-separated flag string into `F`'s bits.
+///
+/// Unknown flag names are errors listing every valid name. `u128`-backed
+/// flags aren't supported; the bits travel as `i128` through the generic
+/// entry points.
+pub fn parse_flags<F>(text: &str) -> Result<i128, String>
+where
+    F: bitflags::Flags,
+    F::Bits: Into<i128>,
+{
+    let mut flags = F::empty();
+    for name in text
+        .split(['|', ' '])
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+    {
+        let Some(flag) = F::from_name(name) else {
+            let valid: Vec<&str> = F::FLAGS.iter().map(|flag| flag.name()).collect();
+            return Err(format!(
+                "unknown flag `{name}`; expected one of: {}",
+                valid.join(", ")
+            ));
+        };
+        flags.insert(flag);
+    }
+    Ok(flags.bits().into())
+}
+
+/// Renders `F`'s bits as a `|`-separated flag string.
+pub fn format_flags<F>(bits: i128) -> Result<String, String>
+where
+    F: bitflags::Flags,
+    F::Bits: TryFrom<i128>,
+{
+    let bits = F::Bits::try_from(bits).map_err(|_| "flag bits out of range".to_string())?;
+    let flags =
+        F::from_bits(bits).ok_or_else(|| "bits don't correspond to known flags".to_string())?;
+    let names: Vec<&str> = flags.iter_names().map(|(name, _)| name).collect();
+    Ok(names.join("|"))
+}
@@ -0,0 +1,254 @@
+//! Incremental re-deserialization for editor integrations.
+//!
+//! An editor holding a deserialized config doesn't want to pay full
+//! reflection cost on every keystroke. [`SpanMap`] records where a parse
+//! found each top-level node; [`reparse`] takes the previous value, that map,
+//! and a [`TextEdit`], skips deserialization entirely when the edit falls
+//! between nodes (whitespace, comments), and otherwise re-deserializes only
+//! the fields the dirty nodes route into, copying the rest out of the old
+//! value.
+
+use facet_core::{Facet, Type, UserType};
+use facet_reflect::{Partial, Peek};
+use kdl::{KdlDocument, KdlNode};
+
+use crate::deserialize::{route_target, DeserializeOptions, KdlDeserializer};
+use crate::error::{KdlError, KdlErrorKind};
+use crate::spanned::Span;
+
+/// A single contiguous text replacement, in byte offsets of the old text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TextEdit {
+    /// Where the replaced range starts.
+    pub offset: usize,
+    /// Length of the replaced range in the old text (0 for a pure insert).
+    pub old_len: usize,
+    /// Length of the replacement in the new text (0 for a pure delete).
+    pub new_len: usize,
+}
+
+/// The names and spans of a document's top-level nodes, as of one parse.
+///
+/// Built with a plain kdl-rs parse — no reflection — so it's cheap enough to
+/// rebuild after every edit.
+#[derive(Debug, Clone, Default)]
+pub struct SpanMap {
+    nodes: Vec<(String, Span)>,
+}
+
+impl SpanMap {
+    /// Parses `kdl` and records every top-level node's name and span.
+    pub fn build(kdl: &str) -> Result<SpanMap, KdlError> {
+        let document: KdlDocument = kdl
+            .parse()
+            .map_err(|error| KdlError::new(KdlErrorKind::Parse(error), None, kdl))?;
+        Ok(SpanMap::from_document(&document))
+    }
+
+    fn from_document(document: &KdlDocument) -> SpanMap {
+        SpanMap {
+            nodes: document
+                .nodes()
+                .iter()
+                .map(|node| (node.name().value().to_string(), Span::from(node.span())))
+                .collect(),
+        }
+    }
+
+    /// The recorded `(node name, span)` pairs, in document order.
+    pub fn nodes(&self) -> &[(String, Span)] {
+        &self.nodes
+    }
+
+    /// The indices of top-level nodes whose spans the edit touches.
+    ///
+    /// An edit at a node's end boundary counts as touching it: new bytes
+    /// inserted there glue onto the node's last token (`port=8` plus `0` is
+    /// `port=80`), even though the byte ranges don't strictly intersect. An
+    /// edit ending at a node's start boundary extends the node's *name*
+    /// instead, which [`reparse`] already catches by comparing name
+    /// sequences, so that side stays exclusive and inserts into interior
+    /// whitespace keep touching nothing.
+    pub fn dirty_nodes(&self, edit: &TextEdit) -> Vec<usize> {
+        self.nodes
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, span))| {
+                let node_end = span.offset + span.len;
+                span.offset < edit.offset + edit.old_len && edit.offset <= node_end
+            })
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    fn names(&self) -> impl Iterator<Item = &str> {
+        self.nodes.iter().map(|(name, _)| name.as_str())
+    }
+}
+
+/// Re-deserializes a document after a text edit, reusing the previous value
+/// where possible.
+///
+/// `old_spans` must come from the parse that produced `old`. When the edit
+/// touches no node and introduces none — it landed in whitespace or a comment
+/// — the old value is cloned back unchanged and only the span map is rebuilt,
+/// without running any reflection. When the edit dirties some nodes but the
+/// top-level node sequence is unchanged, only the fields those nodes route
+/// into are re-deserialized; every other field is copied out of `old`,
+/// including any [`Spanned`](crate::Spanned) values it holds, whose recorded
+/// offsets therefore still describe the *old* text. Edits that add, remove or
+/// rename top-level nodes re-deserialize the whole document.
+///
+/// Uses default [`DeserializeOptions`] throughout, like [`crate::from_str`].
+pub fn reparse<'facet, T: Facet<'facet> + Clone>(
+    old: &T,
+    old_spans: &SpanMap,
+    new_text: &str,
+    edit: TextEdit,
+) -> Result<(T, SpanMap), KdlError> {
+    let document: KdlDocument = new_text
+        .parse()
+        .map_err(|error| KdlError::new(KdlErrorKind::Parse(error), None, new_text))?;
+    let new_spans = SpanMap::from_document(&document);
+    if new_spans.names().eq(old_spans.names()) {
+        let dirty = old_spans.dirty_nodes(&edit);
+        if dirty.is_empty() {
+            return Ok((old.clone(), new_spans));
+        }
+        if let Some(value) = splice(old, &document, new_text, &dirty)? {
+            return Ok((value, new_spans));
+        }
+    }
+    let value = crate::from_str(new_text)?;
+    Ok((value, new_spans))
+}
+
+/// Re-deserializes only the fields the dirty nodes route into, copying every
+/// other field out of the old value.
+///
+/// Returns `None` for document shapes that aren't field-per-node — newtype
+/// and map documents — or when a dirty node routes into no field; those cases
+/// fall back to full re-deserialization, which produces the right value or
+/// the right error.
+fn splice<'facet, T: Facet<'facet>>(
+    old: &T,
+    document: &KdlDocument,
+    new_text: &str,
+    dirty: &[usize],
+) -> Result<Option<T>, KdlError> {
+    let Type::User(UserType::Struct(struct_type)) = &T::SHAPE.ty else {
+        return Ok(None);
+    };
+    let fields = struct_type.fields;
+    let options = DeserializeOptions::default();
+    let mut dirty_fields: Vec<&'static str> = Vec::new();
+    for &index in dirty {
+        let Some(field) = route_target(fields, &document.nodes()[index], &options.naming) else {
+            return Ok(None);
+        };
+        if !dirty_fields.contains(&field) {
+            dirty_fields.push(field);
+        }
+    }
+    // Every node routing into a dirty field is re-deserialized, not just the
+    // dirty ones, so children collections rebuild whole and duplicate-node
+    // checks see the same occurrences a full pass would.
+    let pruned: Vec<KdlNode> = document
+        .nodes()
+        .iter()
+        .filter(|node| {
+            route_target(fields, node, &options.naming)
+                .is_some_and(|field| dirty_fields.contains(&field))
+        })
+        .cloned()
+        .collect();
+    let reflect = |error: facet_reflect::ReflectError| {
+        KdlError::new(KdlErrorKind::Reflect(error), None, new_text)
+    };
+    let mut partial = Partial::alloc::<T>().expect("shape should be allocatable");
+    let old_peek = Peek::new(old).into_struct().map_err(reflect)?;
+    for (index, field) in fields.iter().enumerate() {
+        if dirty_fields.contains(&field.name) {
+            continue;
+        }
+        let Some(clone_into) = field
+            .shape()
+            .vtable
+            .sized()
+            .and_then(|vtable| (vtable.clone_into)())
+        else {
+            // No `Clone` in the field's vtable, so there's no way to copy it
+            // out of the old value without aliasing its heap data.
+            return Ok(None);
+        };
+        let field_peek = old_peek.field(index).map_err(|error| {
+            reflect(facet_reflect::ReflectError::FieldError {
+                shape: T::SHAPE,
+                field_error: error,
+            })
+        })?;
+        partial
+            .inner_mut()
+            .begin_field(field.name)
+            .and_then(|partial| {
+                partial.set_from_function(|target| {
+                    // SAFETY: `field_peek` borrows a live value of exactly
+                    // this field's shape, and `target` is the uninitialized
+                    // slot `begin_field` just opened for the same shape.
+                    unsafe { clone_into(field_peek.data().thin().unwrap(), target) };
+                    Ok(())
+                })
+            })
+            .and_then(|partial| partial.end())
+            .map_err(reflect)?;
+    }
+    // The pre-copied fields read as already set, so the fill passes at the
+    // end of document routing leave them alone.
+    let mut deserializer = KdlDeserializer::new(new_text);
+    deserializer.deserialize_document_with_fields(partial.inner_mut(), &pruned, fields)?;
+    partial.build().map(|boxed| Some(*boxed)).map_err(reflect)
+}
+
+/// The location a byte offset falls inside.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NodePath {
+    /// Node names from the document root down to the innermost node
+    /// containing the offset.
+    pub nodes: Vec<String>,
+    /// The property entry under the offset, if it falls on one.
+    pub property: Option<String>,
+}
+
+/// Maps a byte offset to the node (and property) it falls inside.
+///
+/// Like [`SpanMap::build`], this uses a plain kdl-rs parse — no reflection —
+/// so editors can call it on every cursor move to drive hover and completion.
+/// Returns `None` when the text doesn't parse or the offset falls outside
+/// every node.
+pub fn locate(kdl: &str, offset: usize) -> Option<NodePath> {
+    let document: KdlDocument = kdl.parse().ok()?;
+    locate_in(document.nodes(), offset, &mut Vec::new())
+}
+
+fn locate_in(candidates: &[KdlNode], offset: usize, path: &mut Vec<String>) -> Option<NodePath> {
+    let node = candidates.iter().find(|node| {
+        let span = node.span();
+        span.offset() <= offset && offset < span.offset() + span.len()
+    })?;
+    path.push(node.name().value().to_string());
+    if let Some(children) = node.children() {
+        if let Some(found) = locate_in(children.nodes(), offset, path) {
+            return Some(found);
+        }
+    }
+    let property = node.entries().iter().find_map(|entry| {
+        let name = entry.name()?;
+        let span = entry.span();
+        (span.offset() <= offset && offset < span.offset() + span.len())
+            .then(|| name.value().to_string())
+    });
+    Some(NodePath {
+        nodes: path.clone(),
+        property,
+    })
+}
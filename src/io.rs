@@ -0,0 +1,217 @@
+//! Filesystem convenience entry points.
+
+use std::path::Path;
+
+use facet_core::Facet;
+
+use crate::error::{KdlError, KdlErrorKind};
+
+#[cfg(feature = "de")]
+/// Reads and deserializes a KDL document from a file.
+///
+/// This handles the boring parts every application otherwise reimplements:
+/// reading the file, detecting and stripping a BOM (UTF-8, UTF-16 LE/BE),
+/// and attaching the filename to any error so diagnostics point at the real
+/// file instead of "document".
+pub fn from_path<'facet, T: Facet<'facet>>(path: impl AsRef<Path>) -> Result<T, KdlError> {
+    let path = path.as_ref();
+    let bytes =
+        std::fs::read(path).map_err(|error| KdlError::detached(KdlErrorKind::Io(error)))?;
+    let text = decode(&bytes)?;
+    crate::from_str(&text).map_err(|error| error.with_source_name(&path.display().to_string()))
+}
+
+#[cfg(feature = "de")]
+/// Deserializes a KDL document from raw bytes.
+///
+/// Runs the same decode step as [`from_path`] — BOM detection and stripping
+/// (UTF-8, UTF-16 LE/BE) followed by strict validation — so network servers
+/// receiving request bodies don't each reimplement it. Invalid bytes surface
+/// as [`KdlErrorKind::Encoding`](crate::KdlErrorKind::Encoding).
+pub fn from_slice<'facet, T: Facet<'facet>>(bytes: &[u8]) -> Result<T, KdlError> {
+    let text = decode(bytes)?;
+    crate::from_str(&text)
+}
+
+#[cfg(feature = "de")]
+/// Like [`from_slice`], but replaces invalid sequences with U+FFFD instead
+/// of failing.
+///
+/// For inputs known to be mostly right — log ingestion, editor tooling —
+/// where a best-effort parse beats an encoding error. A replacement
+/// character inside a value still fails at the exact offending spot, which
+/// reads better than "invalid utf-8 at byte 512".
+pub fn from_slice_lossy<'facet, T: Facet<'facet>>(bytes: &[u8]) -> Result<T, KdlError> {
+    let text = decode_lossy(bytes);
+    crate::from_str(&text)
+}
+
+#[cfg(feature = "ser")]
+/// Options for [`to_path_with_options`].
+#[derive(Debug, Clone)]
+pub struct WriteOptions {
+    /// Copy the permissions of an existing target file onto the replacement.
+    /// Defaults to `true`; with `false` the replacement gets fresh default
+    /// permissions.
+    pub preserve_permissions: bool,
+}
+
+#[cfg(feature = "ser")]
+impl Default for WriteOptions {
+    fn default() -> Self {
+        Self {
+            preserve_permissions: true,
+        }
+    }
+}
+
+#[cfg(feature = "ser")]
+/// Serializes `value` and writes it to a file atomically.
+///
+/// The document is written to a temporary file next to the target and then
+/// renamed over it, so a crash mid-write never leaves a torn config file
+/// behind.
+pub fn to_path<'facet, T: Facet<'facet>>(
+    path: impl AsRef<Path>,
+    value: &T,
+) -> Result<(), KdlError> {
+    to_path_with_options(path, value, &WriteOptions::default())
+}
+
+#[cfg(feature = "ser")]
+/// Like [`to_path`], with explicit [`WriteOptions`].
+pub fn to_path_with_options<'facet, T: Facet<'facet>>(
+    path: impl AsRef<Path>,
+    value: &T,
+    options: &WriteOptions,
+) -> Result<(), KdlError> {
+    let path = path.as_ref();
+    let text = crate::to_string(value)?;
+    let io = |error| KdlError::detached(KdlErrorKind::Io(error));
+
+    let mut temp = path.to_path_buf();
+    let file_name = temp
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "config.kdl".to_string());
+    temp.set_file_name(format!(".{file_name}.tmp-{}", std::process::id()));
+
+    let result = (|| {
+        std::fs::write(&temp, &text)?;
+        if options.preserve_permissions {
+            if let Ok(metadata) = std::fs::metadata(path) {
+                std::fs::set_permissions(&temp, metadata.permissions())?;
+            }
+        }
+        std::fs::rename(&temp, path)
+    })();
+    if result.is_err() {
+        std::fs::remove_file(&temp).ok();
+    }
+    result.map_err(io)
+}
+
+#[cfg(all(feature = "de", feature = "tokio"))]
+/// Reads and deserializes a KDL document from an async reader.
+///
+/// KDL documents parse as a unit, so the whole input is buffered first, then
+/// decoded and deserialized exactly like [`from_path`] — BOM detection
+/// included. For fully async services loading configs over async file or
+/// network streams; requires the `tokio` cargo feature.
+pub async fn from_async_reader<'facet, T, R>(reader: &mut R) -> Result<T, KdlError>
+where
+    T: Facet<'facet>,
+    R: tokio::io::AsyncRead + Unpin,
+{
+    use tokio::io::AsyncReadExt as _;
+    let mut bytes = Vec::new();
+    reader
+        .read_to_end(&mut bytes)
+        .await
+        .map_err(|error| KdlError::detached(KdlErrorKind::Io(error)))?;
+    let text = decode(&bytes)?;
+    crate::from_str(&text)
+}
+
+#[cfg(all(feature = "ser", feature = "tokio"))]
+/// Serializes `value` and writes it to an async writer.
+///
+/// The document is rendered to a string first — serialization itself never
+/// blocks — and written in one `write_all`, followed by a flush. Requires
+/// the `tokio` cargo feature.
+pub async fn to_async_writer<'facet, T, W>(writer: &mut W, value: &T) -> Result<(), KdlError>
+where
+    T: Facet<'facet>,
+    W: tokio::io::AsyncWrite + Unpin,
+{
+    use tokio::io::AsyncWriteExt as _;
+    let text = crate::to_string(value)?;
+    let io = |error| KdlError::detached(KdlErrorKind::Io(error));
+    writer.write_all(text.as_bytes()).await.map_err(io)?;
+    writer.flush().await.map_err(io)
+}
+
+#[cfg(feature = "de")]
+/// Decodes raw file bytes into a string, honoring a leading BOM.
+pub(crate) fn decode(bytes: &[u8]) -> Result<String, KdlError> {
+    if let Some(rest) = bytes.strip_prefix(&[0xEF, 0xBB, 0xBF]) {
+        // UTF-8 with BOM.
+        return String::from_utf8(rest.to_vec())
+            .map_err(|error| KdlError::detached(KdlErrorKind::Encoding(error.to_string())));
+    }
+    if let Some(rest) = bytes.strip_prefix(&[0xFF, 0xFE]) {
+        return decode_utf16(rest, u16::from_le_bytes);
+    }
+    if let Some(rest) = bytes.strip_prefix(&[0xFE, 0xFF]) {
+        return decode_utf16(rest, u16::from_be_bytes);
+    }
+    String::from_utf8(bytes.to_vec())
+        .map_err(|error| KdlError::detached(KdlErrorKind::Encoding(error.to_string())))
+}
+
+#[cfg(feature = "de")]
+/// Decodes raw bytes into a string, honoring a leading BOM and substituting
+/// U+FFFD for anything invalid.
+fn decode_lossy(bytes: &[u8]) -> String {
+    if let Some(rest) = bytes.strip_prefix(&[0xEF, 0xBB, 0xBF]) {
+        return String::from_utf8_lossy(rest).into_owned();
+    }
+    if let Some(rest) = bytes.strip_prefix(&[0xFF, 0xFE]) {
+        return decode_utf16_lossy(rest, u16::from_le_bytes);
+    }
+    if let Some(rest) = bytes.strip_prefix(&[0xFE, 0xFF]) {
+        return decode_utf16_lossy(rest, u16::from_be_bytes);
+    }
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+#[cfg(feature = "de")]
+fn decode_utf16(bytes: &[u8], combine: fn([u8; 2]) -> u16) -> Result<String, KdlError> {
+    if !bytes.len().is_multiple_of(2) {
+        return Err(KdlError::detached(KdlErrorKind::Encoding(
+            "UTF-16 input has an odd number of bytes".to_string(),
+        )));
+    }
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|pair| combine([pair[0], pair[1]]))
+        .collect();
+    String::from_utf16(&units)
+        .map_err(|error| KdlError::detached(KdlErrorKind::Encoding(error.to_string())))
+}
+
+#[cfg(feature = "de")]
+fn decode_utf16_lossy(bytes: &[u8], combine: fn([u8; 2]) -> u16) -> String {
+    let units = bytes
+        .chunks_exact(2)
+        .map(|pair| combine([pair[0], pair[1]]));
+    let mut text: String = char::decode_utf16(units)
+        .map(|unit| unit.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect();
+    // An odd trailing byte can't be half of any code unit; it becomes one
+    // replacement character rather than an error.
+    if !bytes.len().is_multiple_of(2) {
+        text.push(char::REPLACEMENT_CHARACTER);
+    }
+    text
+}
@@ -0,0 +1,1020 @@
+//! The shared node tree both serializers emit.
+//!
+//! [`build`] walks a `Facet` value once — names, entries, children blocks,
+//! attribute-driven representations — and produces a typed tree. The string
+//! writer renders that tree as text and the document builder converts it
+//! into a [`kdl::KdlDocument`], so the two output paths can no longer drift
+//! on *what* a value maps to; they only differ in how the tree is spelled
+//! out. A parsed document converts into the same tree via `From`, the
+//! deserializer-side entry point for tools that compare a value against
+//! existing text.
+
+use facet_core::{Def, Facet, Field, Shape, Type, UserType};
+use facet_reflect::Peek;
+use kdl::{KdlDocument, KdlEntry, KdlNode, KdlValue};
+
+use crate::error::KdlError;
+use crate::error::KdlErrorKind as Kind;
+#[cfg(feature = "bitflags")]
+use crate::fields::kdl_flags_with;
+use crate::fields::{
+    FieldRole, args_inner, field_role, has_kdl_attr, is_node_name, is_props, is_unit_like,
+    kdl_group, kdl_node_or_property, kdl_radix,
+    kdl_width,
+    newtype_inner, pointee, spanned_inner, top_level_offenders, transparent_inner,
+    unit_only_variants, variant_list_payload,
+};
+use crate::writer::{EmptyChildrenPolicy, SerializeOptions, UnitVariantFormat};
+
+/// A run of nodes: a whole document, or one node's children block.
+pub(crate) struct IrDocument {
+    pub(crate) nodes: Vec<IrNode>,
+}
+
+/// One node of the tree.
+pub(crate) struct IrNode {
+    /// The node name, already naming-converted and unescaped.
+    pub(crate) name: String,
+    /// The `(ty)` type annotation, if any.
+    pub(crate) annotation: Option<String>,
+    pub(crate) entries: Vec<IrEntry>,
+    /// `Some` whenever the originating shape has child fields, even if they
+    /// all turned out empty; consumers decide what an empty block becomes.
+    pub(crate) children: Option<IrDocument>,
+    /// The shape this node was built from; `None` on trees converted from
+    /// parsed documents, which have text provenance instead of a value.
+    pub(crate) provenance: Option<&'static Shape>,
+}
+
+impl IrNode {
+    fn new(name: String, provenance: Option<&'static Shape>) -> Self {
+        IrNode {
+            name,
+            annotation: None,
+            entries: Vec::new(),
+            children: None,
+            provenance,
+        }
+    }
+
+    /// Whether this node's children block survives output even when empty.
+    ///
+    /// An all-empty block carries no information, so [`Omit`] drops it — but
+    /// only on nodes built from a value; a tree converted from parsed text
+    /// keeps an explicit `node { }` as written.
+    ///
+    /// [`Omit`]: EmptyChildrenPolicy::Omit
+    pub(crate) fn keeps_empty_children(&self, options: &SerializeOptions) -> bool {
+        options.empty_children == EmptyChildrenPolicy::Emit || self.provenance.is_none()
+    }
+}
+
+/// A positional argument (`name` is `None`) or a `key=value` property.
+pub(crate) struct IrEntry {
+    pub(crate) name: Option<String>,
+    pub(crate) value: KdlValue,
+    /// The exact literal to emit instead of the default rendering: radix
+    /// integers (`0x00FF`), and finite `f32`s, whose shortest representation
+    /// an `f64` round-trip would lengthen.
+    pub(crate) repr: Option<String>,
+}
+
+impl From<&KdlDocument> for IrDocument {
+    fn from(document: &KdlDocument) -> Self {
+        IrDocument {
+            nodes: document.nodes().iter().map(IrNode::from).collect(),
+        }
+    }
+}
+
+impl From<&KdlNode> for IrNode {
+    fn from(node: &KdlNode) -> Self {
+        IrNode {
+            name: node.name().value().to_string(),
+            annotation: node.ty().map(|ty| ty.value().to_string()),
+            entries: node.entries().iter().map(IrEntry::from).collect(),
+            children: node.children().map(IrDocument::from),
+            provenance: None,
+        }
+    }
+}
+
+impl From<&KdlEntry> for IrEntry {
+    fn from(entry: &KdlEntry) -> Self {
+        IrEntry {
+            name: entry.name().map(|name| name.value().to_string()),
+            value: entry.value().clone(),
+            repr: None,
+        }
+    }
+}
+
+/// Folds peek's field-lookup error into the reflect error kind the rest of
+/// the crate reports.
+fn field_error(shape: &'static Shape, error: facet_core::FieldError) -> KdlError {
+    KdlError::detached(Kind::Reflect(facet_reflect::ReflectError::FieldError {
+        shape,
+        field_error: error,
+    }))
+}
+
+/// Reports a failure to read the active variant of an enum at `shape`.
+///
+/// Peek's variant errors carry no context of their own, so the shape is what
+/// makes the diagnostic actionable.
+fn variant_error(shape: &'static Shape) -> KdlError {
+    KdlError::detached(Kind::Reflect(
+        facet_reflect::ReflectError::OperationFailed {
+            shape,
+            operation: "could not read the active enum variant",
+        },
+    ))
+}
+
+/// Builds the tree representing `value`.
+pub(crate) fn build<'facet, T: Facet<'facet>>(
+    value: &T,
+    options: &SerializeOptions,
+) -> Result<IrDocument, KdlError> {
+    let mut peek = Peek::new(value);
+    let mut shape = T::SHAPE;
+    // A newtype document delegates to its inner type, mirroring the
+    // deserializer's treatment of `struct Doc(Inner)`.
+    while let Some(inner) = newtype_inner(shape) {
+        peek = peek
+            .into_struct()
+            .map_err(|error| KdlError::detached(Kind::Reflect(error)))?
+            .field(0)
+            .map_err(|error| field_error(shape, error))?;
+        shape = inner.shape();
+    }
+    let Type::User(UserType::Struct(struct_type)) = &shape.ty else {
+        return Err(KdlError::detached(Kind::InvalidDocumentShape(shape)));
+    };
+    let offenders = top_level_offenders(struct_type.fields);
+    if !offenders.is_empty() {
+        return Err(KdlError::detached(
+            Kind::IllegalAttributesOnTopLevelShape { shape, offenders },
+        ));
+    }
+    let mut nodes = Vec::new();
+    build_fields_into(&mut nodes, peek, struct_type.fields, options)?;
+    Ok(IrDocument { nodes })
+}
+
+/// Builds the `child`/`children` fields of a struct as a run of nodes (the
+/// top level, or a node's children block).
+fn build_fields_into(
+    nodes: &mut Vec<IrNode>,
+    peek: Peek<'_, '_>,
+    fields: &'static [Field],
+    options: &SerializeOptions,
+) -> Result<(), KdlError> {
+    let shape = peek.shape();
+    let peek_struct = peek
+        .into_struct()
+        .map_err(|error| KdlError::detached(Kind::Reflect(error)))?;
+    for (index, field) in fields.iter().enumerate() {
+        let field_peek = peek_struct
+            .field(index)
+            .map_err(|error| field_error(shape, error))?;
+        match field_role(field) {
+            Some(FieldRole::Child) => build_child_field(nodes, field, field_peek, options)?,
+            Some(FieldRole::Children) => build_children_field(nodes, field, field_peek, options)?,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Builds a single `child` field as one named node.
+fn build_child_field(
+    nodes: &mut Vec<IrNode>,
+    field: &'static Field,
+    peek: Peek<'_, '_>,
+    options: &SerializeOptions,
+) -> Result<(), KdlError> {
+    let Some(peek) = strip_wrappers(peek)? else {
+        return Ok(());
+    };
+    // A `kdl(verbatim)` string holds pre-formatted KDL text; parse it (the
+    // one chance to catch typos before another tool reads the output) and
+    // emit the parsed nodes as the field node's children block.
+    if has_kdl_attr(field, "verbatim") {
+        let Some(Scalar::Text(text)) = probe_scalar(peek) else {
+            return Err(KdlError::detached(Kind::SchemaError(format!(
+                "field `{}` uses kdl(verbatim) but `{}` is not a string",
+                field.name,
+                field.shape()
+            ))));
+        };
+        let children: KdlDocument = text.parse().map_err(|error: kdl::KdlError| {
+            KdlError::detached(Kind::ValidationFailed {
+                field: field.name,
+                message: format!("verbatim KDL does not parse: {error}"),
+            })
+        })?;
+        nodes.push(IrNode {
+            name: options.naming.kdl_name(field.name).into_owned(),
+            annotation: None,
+            entries: Vec::new(),
+            children: Some(IrDocument::from(&children)),
+            provenance: None,
+        });
+        return Ok(());
+    }
+    let node = build_node(options.naming.kdl_name(field.name).into_owned(), peek, options)?;
+    nodes.push(node);
+    Ok(())
+}
+
+/// Builds a `children` container field as a run of nodes.
+fn build_children_field(
+    nodes: &mut Vec<IrNode>,
+    field: &'static Field,
+    peek: Peek<'_, '_>,
+    options: &SerializeOptions,
+) -> Result<(), KdlError> {
+    // A `None` container means "no nodes", like a `None` child field; unwrap
+    // `Option` and smart-pointer layers so the match below sees the
+    // container itself. Empty containers fall out of the loops naturally.
+    let Some(peek) = strip_wrappers(peek)? else {
+        return Ok(());
+    };
+    let mut shape = field.shape();
+    loop {
+        if let Def::Option(option_def) = shape.def {
+            shape = option_def.t();
+            continue;
+        }
+        if let Some(inner) = pointee(shape) {
+            shape = inner;
+            continue;
+        }
+        break;
+    }
+    match shape.def {
+        Def::List(list_def) => {
+            let peek_list = peek
+                .into_list()
+                .map_err(|error| KdlError::detached(Kind::Reflect(error)))?;
+            let name = element_node_name(list_def.t());
+            for element in peek_list.iter() {
+                nodes.push(build_element(name, element, options)?);
+            }
+        }
+        Def::Set(set_def) => {
+            let peek_set = peek
+                .into_set()
+                .map_err(|error| KdlError::detached(Kind::Reflect(error)))?;
+            let name = element_node_name(set_def.t());
+            for element in peek_set.iter() {
+                nodes.push(build_element(name, element, options)?);
+            }
+        }
+        Def::Map(_) => {
+            let peek_map = peek
+                .into_map()
+                .map_err(|error| KdlError::detached(Kind::Reflect(error)))?;
+            for (key, value) in peek_map.iter() {
+                // Map keys are data, not Rust names; they bypass the naming
+                // convention.
+                let name = key
+                    .get::<String>()
+                    .map_err(|error| KdlError::detached(Kind::Reflect(error)))?;
+                let value = strip_spanned(value)?;
+                let node = if let Type::User(UserType::Enum(_)) = &value.shape().ty {
+                    // The key owns the node name, so the variant moves into a
+                    // type annotation: `(Web)frontend`.
+                    let peek_enum = value
+                        .into_enum()
+                        .map_err(|error| KdlError::detached(Kind::Reflect(error)))?;
+                    let variant = peek_enum
+                        .active_variant()
+                        .map_err(|_| variant_error(value.shape()))?;
+                    let annotation = options.naming.kdl_name(variant.name).into_owned();
+                    build_variant_node(name.clone(), Some(annotation), value, options)?
+                } else {
+                    build_node(name.clone(), value, options)?
+                };
+                nodes.push(node);
+            }
+        }
+        _ => {
+            return Err(KdlError::detached(Kind::UnsupportedShape(format!(
+                "children field `{}` has non-container type `{}`",
+                field.name,
+                field.shape()
+            ))));
+        }
+    }
+    Ok(())
+}
+
+/// The node name used when emitting one element of a children container.
+fn element_node_name(element: &'static Shape) -> Option<&'static str> {
+    let element = spanned_inner(element).unwrap_or(element);
+    match &element.ty {
+        // Enum elements are named after their active variant, resolved per
+        // element in `build_element`.
+        Type::User(UserType::Enum(_)) => None,
+        _ => Some(element.type_identifier),
+    }
+}
+
+/// Builds one element of a children container.
+fn build_element(
+    name: Option<&'static str>,
+    peek: Peek<'_, '_>,
+    options: &SerializeOptions,
+) -> Result<IrNode, KdlError> {
+    let peek = strip_spanned(peek)?;
+    match name {
+        Some(name) => build_node(options.naming.element_name(name), peek, options),
+        None => {
+            let peek_enum = peek
+                .into_enum()
+                .map_err(|error| KdlError::detached(Kind::Reflect(error)))?;
+            let variant = peek_enum
+                .active_variant()
+                .map_err(|_| variant_error(peek.shape()))?;
+            build_variant_node(
+                options.naming.kdl_name(variant.name).into_owned(),
+                None,
+                peek,
+                options,
+            )
+        }
+    }
+}
+
+/// Builds a struct or enum value as a node with the given name.
+fn build_node(
+    name: String,
+    peek: Peek<'_, '_>,
+    options: &SerializeOptions,
+) -> Result<IrNode, KdlError> {
+    let peek = strip_spanned(peek)?;
+    let shape = peek.shape();
+    match &shape.ty {
+        Type::User(UserType::Struct(struct_type)) => {
+            // A `NodeName` idiom field carries the node's own name as data,
+            // overriding the spelling the field or element type supplied.
+            let name = match node_name_value(peek, struct_type.fields)? {
+                Some(own_name) => own_name,
+                None => name,
+            };
+            let mut node = IrNode::new(name, Some(shape));
+            build_node_fields(&mut node, peek, struct_type.fields, options)?;
+            Ok(node)
+        }
+        Type::User(UserType::Enum(_)) => {
+            let peek_enum = peek
+                .into_enum()
+                .map_err(|error| KdlError::detached(Kind::Reflect(error)))?;
+            let variant = peek_enum
+                .active_variant()
+                .map_err(|_| variant_error(peek.shape()))?;
+            // The variant, not the field, names the node — matching how the
+            // deserializer selects variants by node name.
+            build_variant_node(
+                options.naming.kdl_name(variant.name).into_owned(),
+                None,
+                peek,
+                options,
+            )
+        }
+        _ => Err(KdlError::detached(Kind::SerializeUnknownValueType(shape))),
+    }
+}
+
+/// The value of a struct's `NodeName` idiom field, if it has one.
+fn node_name_value(
+    peek: Peek<'_, '_>,
+    fields: &'static [Field],
+) -> Result<Option<String>, KdlError> {
+    let Some(index) = fields
+        .iter()
+        .position(|field| is_node_name(field.shape()))
+    else {
+        return Ok(None);
+    };
+    let shape = peek.shape();
+    let wrapper = peek
+        .into_struct()
+        .map_err(|error| KdlError::detached(Kind::Reflect(error)))?
+        .field(index)
+        .map_err(|error| field_error(shape, error))?;
+    let inner = wrapper
+        .into_struct()
+        .map_err(|error| KdlError::detached(Kind::Reflect(error)))?
+        .field(0)
+        .map_err(|error| field_error(wrapper.shape(), error))?;
+    let name = inner
+        .get::<String>()
+        .map_err(|error| KdlError::detached(Kind::Reflect(error)))?;
+    Ok(Some(name.clone()))
+}
+
+/// Builds the active variant of an enum value as a node.
+fn build_variant_node(
+    name: String,
+    annotation: Option<String>,
+    peek: Peek<'_, '_>,
+    options: &SerializeOptions,
+) -> Result<IrNode, KdlError> {
+    let peek_enum = peek
+        .into_enum()
+        .map_err(|error| KdlError::detached(Kind::Reflect(error)))?;
+    let variant = peek_enum
+        .active_variant()
+        .map_err(|_| variant_error(peek.shape()))?;
+    let mut node = IrNode::new(name, Some(peek.shape()));
+    node.annotation = annotation;
+    if let Some(key) = &options.variant_property {
+        node.entries.push(IrEntry {
+            name: Some(key.clone()),
+            value: KdlValue::String(variant.name.to_string()),
+            repr: None,
+        });
+    }
+    // A list-payload variant like `Hosts(Vec<String>)` spreads its elements
+    // over the node's positional arguments.
+    if variant_list_payload(variant).is_some() {
+        let payload = peek_enum
+            .field(0)
+            .map_err(|_| variant_error(peek.shape()))?
+            .ok_or_else(|| variant_error(peek.shape()))?;
+        let peek_list = payload
+            .into_list()
+            .map_err(|error| KdlError::detached(Kind::Reflect(error)))?;
+        for element in peek_list.iter() {
+            let (value, repr) = scalar_value(element, options)?;
+            node.entries.push(IrEntry {
+                name: None,
+                value,
+                repr,
+            });
+        }
+        return Ok(node);
+    }
+    for (index, field) in variant.data.fields.iter().enumerate() {
+        let field_peek = peek_enum
+            .field(index)
+            .map_err(|_| variant_error(peek.shape()))?
+            .ok_or_else(|| variant_error(peek.shape()))?;
+        build_node_field(&mut node, field, field_peek, options)?;
+    }
+    Ok(node)
+}
+
+/// Builds a `kdl(group)` flatten field's properties onto the node, each
+/// under its prefixed spelling (`tls-cert=`).
+fn build_grouped_fields(
+    node: &mut IrNode,
+    field: &'static Field,
+    peek: Peek<'_, '_>,
+    fields: &'static [Field],
+    group: &str,
+    options: &SerializeOptions,
+) -> Result<(), KdlError> {
+    let mut grouped = IrNode {
+        name: String::new(),
+        annotation: None,
+        entries: Vec::new(),
+        children: None,
+        provenance: None,
+    };
+    build_node_fields(&mut grouped, peek, fields, options)?;
+    if grouped.children.is_some() || grouped.entries.iter().any(|entry| entry.name.is_none()) {
+        return Err(KdlError::detached(Kind::UnsupportedShape(format!(
+            "group field `{}` may only contain property fields; arguments and \
+             child nodes have no prefixed spelling",
+            field.name
+        ))));
+    }
+    for mut entry in grouped.entries {
+        if let Some(name) = entry.name.take() {
+            entry.name = Some(format!("{group}-{name}"));
+        }
+        node.entries.push(entry);
+    }
+    Ok(())
+}
+
+/// Builds every field of a struct onto a node.
+fn build_node_fields(
+    node: &mut IrNode,
+    peek: Peek<'_, '_>,
+    fields: &'static [Field],
+    options: &SerializeOptions,
+) -> Result<(), KdlError> {
+    let shape = peek.shape();
+    let peek_struct = peek
+        .into_struct()
+        .map_err(|error| KdlError::detached(Kind::Reflect(error)))?;
+    for (index, field) in fields.iter().enumerate() {
+        let field_peek = peek_struct
+            .field(index)
+            .map_err(|error| field_error(shape, error))?;
+        build_node_field(node, field, field_peek, options)?;
+    }
+    Ok(())
+}
+
+/// Builds one field onto a node, according to its role.
+fn build_node_field(
+    node: &mut IrNode,
+    field: &'static Field,
+    peek: Peek<'_, '_>,
+    options: &SerializeOptions,
+) -> Result<(), KdlError> {
+    if is_unit_like(field.shape()) {
+        return Ok(());
+    }
+    // A `kdl(document)` field's nodes become this node's children block.
+    if has_kdl_attr(field, "document") {
+        let children = children_of(node);
+        let Some(peek) = strip_wrappers(peek)? else {
+            return Ok(());
+        };
+        let Type::User(UserType::Struct(struct_type)) = &peek.shape().ty else {
+            return Err(KdlError::detached(Kind::UnsupportedShape(format!(
+                "document field `{}` has unsupported type `{}`",
+                field.name,
+                field.shape()
+            ))));
+        };
+        build_fields_into(&mut children.nodes, peek, struct_type.fields, options)?;
+        return Ok(());
+    }
+    match field_role(field) {
+        Some(FieldRole::Argument) => {
+            let entry = build_entry(field, peek, options)?;
+            node.entries.push(entry);
+        }
+        Some(FieldRole::Arguments) => {
+            // An `Args` idiom wrapper reads as its inner list.
+            let peek = if args_inner(field.shape()).is_some() {
+                peek.into_struct()
+                    .map_err(|error| KdlError::detached(Kind::Reflect(error)))?
+                    .field(0)
+                    .map_err(|error| field_error(field.shape(), error))?
+            } else {
+                peek
+            };
+            let peek_list = peek
+                .into_list()
+                .map_err(|error| KdlError::detached(Kind::Reflect(error)))?;
+            for element in peek_list.iter() {
+                let entry = build_entry(field, element, options)?;
+                node.entries.push(entry);
+            }
+        }
+        Some(FieldRole::Property) => {
+            let peek = match peek.into_option() {
+                Ok(peek_option) => match peek_option.value() {
+                    Some(inner) => inner,
+                    None => return Ok(()),
+                },
+                Err(_) => peek,
+            };
+            let mut entry = build_entry(field, peek, options)?;
+            // A `kdl(node_or_property = node)` field's canonical form is the
+            // node spelling: a child carrying the value as its one argument.
+            if kdl_node_or_property(field) == Some("node") {
+                children_of(node).nodes.push(IrNode {
+                    name: options.naming.kdl_name(field.name).into_owned(),
+                    annotation: None,
+                    entries: vec![entry],
+                    children: None,
+                    provenance: None,
+                });
+                return Ok(());
+            }
+            entry.name = Some(options.naming.kdl_name(field.name).into_owned());
+            node.entries.push(entry);
+        }
+        // A `kdl(inline)` child spreads its fields onto this node instead of
+        // nesting a named child node.
+        Some(FieldRole::Child) if has_kdl_attr(field, "inline") => {
+            let Some(peek) = strip_wrappers(peek)? else {
+                return Ok(());
+            };
+            let Type::User(UserType::Struct(struct_type)) = &peek.shape().ty else {
+                return Err(KdlError::detached(Kind::UnsupportedShape(format!(
+                    "inline field `{}` has unsupported type `{}`",
+                    field.name,
+                    field.shape()
+                ))));
+            };
+            build_node_fields(node, peek, struct_type.fields, options)?;
+        }
+        Some(FieldRole::Child) => {
+            let children = children_of(node);
+            build_child_field(&mut children.nodes, field, peek, options)?;
+        }
+        Some(FieldRole::Children) => {
+            let children = children_of(node);
+            build_children_field(&mut children.nodes, field, peek, options)?;
+        }
+        Some(FieldRole::Flatten) => match &field.shape().ty {
+            Type::User(UserType::Struct(struct_type)) => {
+                if let Some(group) = kdl_group(field) {
+                    build_grouped_fields(node, field, peek, struct_type.fields, group, options)?;
+                } else {
+                    build_node_fields(node, peek, struct_type.fields, options)?;
+                }
+            }
+            Type::User(UserType::Enum(_)) => {
+                let peek_enum = peek
+                    .into_enum()
+                    .map_err(|error| KdlError::detached(Kind::Reflect(error)))?;
+                let variant = peek_enum
+                    .active_variant()
+                    .map_err(|_| variant_error(peek.shape()))?;
+                for (index, variant_field) in variant.data.fields.iter().enumerate() {
+                    let field_peek = peek_enum
+                        .field(index)
+                        .map_err(|_| variant_error(peek.shape()))?
+                        .ok_or_else(|| variant_error(peek.shape()))?;
+                    build_node_field(node, variant_field, field_peek, options)?;
+                }
+            }
+            _ => {
+                return Err(KdlError::detached(Kind::UnsupportedShape(format!(
+                    "flatten field `{}` has unsupported type `{}`",
+                    field.name,
+                    field.shape()
+                ))));
+            }
+        },
+        // A `Props` idiom field writes its entries back as string
+        // properties, sorted by key so the output is deterministic.
+        None if is_props(field.shape()) => {
+            let shape = peek.shape();
+            let peek_map = peek
+                .into_struct()
+                .map_err(|error| KdlError::detached(Kind::Reflect(error)))?
+                .field(0)
+                .map_err(|error| field_error(shape, error))?
+                .into_map()
+                .map_err(|error| KdlError::detached(Kind::Reflect(error)))?;
+            let mut properties: Vec<(String, String)> = Vec::new();
+            for (key, value) in peek_map.iter() {
+                let key = key
+                    .get::<String>()
+                    .map_err(|error| KdlError::detached(Kind::Reflect(error)))?;
+                let value = value
+                    .get::<String>()
+                    .map_err(|error| KdlError::detached(Kind::Reflect(error)))?;
+                properties.push((key.clone(), value.clone()));
+            }
+            properties.sort();
+            for (key, value) in properties {
+                node.entries.push(IrEntry {
+                    name: Some(key),
+                    value: KdlValue::String(value),
+                    repr: None,
+                });
+            }
+        }
+        // A `NodeName` idiom field was consumed when the node was named.
+        None if is_node_name(field.shape()) => {}
+        Some(FieldRole::Skip) | None => {}
+    }
+    Ok(())
+}
+
+/// The node's children block, created on first use so a shape with child
+/// fields always carries `Some` — even an empty one, which the
+/// [`EmptyChildrenPolicy`] later decides on.
+fn children_of(node: &mut IrNode) -> &mut IrDocument {
+    node.children
+        .get_or_insert_with(|| IrDocument { nodes: Vec::new() })
+}
+
+/// Builds a field's scalar entry, honoring any `kdl(radix)` or
+/// `kdl(flags_with)` attribute.
+fn build_entry(
+    field: &'static Field,
+    peek: Peek<'_, '_>,
+    options: &SerializeOptions,
+) -> Result<IrEntry, KdlError> {
+    #[cfg(feature = "bitflags")]
+    if let Some(codec) = kdl_flags_with(field) {
+        return build_flags_entry(field, codec, peek, options);
+    }
+    #[cfg(not(feature = "bitflags"))]
+    let _ = options;
+    let (value, mut repr) = scalar_value(peek, options)?;
+    if let Some(radix) = kdl_radix(field) {
+        if let KdlValue::Integer(integer) = &value {
+            if let Some(text) = crate::writer::format_radix(*integer, radix, kdl_width(field)) {
+                repr = Some(text);
+            }
+        }
+    }
+    Ok(IrEntry {
+        name: None,
+        value,
+        repr,
+    })
+}
+
+/// Builds a bitflags tuple struct as a flag string like `"READ|WRITE"`,
+/// through the formatter named by `kdl(flags_with)`.
+#[cfg(feature = "bitflags")]
+fn build_flags_entry(
+    field: &'static Field,
+    codec: &'static str,
+    peek: Peek<'_, '_>,
+    options: &SerializeOptions,
+) -> Result<IrEntry, KdlError> {
+    let Some((_, format)) = options
+        .flag_formatters
+        .iter()
+        .find(|(registered, _)| *registered == codec)
+    else {
+        return Err(KdlError::detached(Kind::SchemaError(format!(
+            "field `{}` names flag formatter `{codec}`, but no such formatter is \
+             registered on SerializeOptions",
+            field.name
+        ))));
+    };
+    // Bitflags types declared in impl mode are tuple structs around their
+    // backing integer; read the bits back out of that field.
+    let bits = strip_spanned(peek)?
+        .into_struct()
+        .ok()
+        .and_then(|peek_struct| peek_struct.field(0).ok())
+        .and_then(integer_value)
+        .ok_or_else(|| {
+            KdlError::detached(Kind::SchemaError(format!(
+                "field `{}` uses kdl(flags_with) but `{}` doesn't wrap a single \
+                 backing integer",
+                field.name,
+                field.shape()
+            )))
+        })?;
+    let text = format(bits).map_err(|message| {
+        KdlError::detached(Kind::ValidationFailed {
+            field: field.name,
+            message,
+        })
+    })?;
+    Ok(IrEntry {
+        name: None,
+        value: KdlValue::String(text),
+        repr: None,
+    })
+}
+
+/// The field's value as an `i128`, if it's one of the integer types.
+#[cfg(feature = "bitflags")]
+fn integer_value(peek: Peek<'_, '_>) -> Option<i128> {
+    match probe_scalar(peek) {
+        Some(Scalar::Integer(integer)) => Some(integer),
+        _ => None,
+    }
+}
+
+/// Peeks through `Option`, `Spanned`, smart-pointer, and transparent layers
+/// in any order, so `Option<Box<T>>` children and `Box<Spanned<String>>`
+/// properties serialize like plain `T`. Returns `None` when a `None` option
+/// is encountered, meaning "emit nothing".
+pub(crate) fn strip_wrappers<'mem, 'facet>(
+    mut peek: Peek<'mem, 'facet>,
+) -> Result<Option<Peek<'mem, 'facet>>, KdlError> {
+    loop {
+        if spanned_inner(peek.shape()).is_some() {
+            peek = peek
+                .into_struct()
+                .map_err(|error| KdlError::detached(Kind::Reflect(error)))?
+                .field_by_name("value")
+                .map_err(|error| field_error(peek.shape(), error))?;
+            continue;
+        }
+        if let Ok(peek_option) = peek.into_option() {
+            match peek_option.value() {
+                Some(inner) => {
+                    peek = inner;
+                    continue;
+                }
+                None => return Ok(None),
+            }
+        }
+        if matches!(peek.shape().def, Def::Pointer(_)) {
+            let pointer = peek
+                .into_pointer()
+                .map_err(|error| KdlError::detached(Kind::Reflect(error)))?;
+            let Some(inner) = pointer.borrow_inner() else {
+                return Err(KdlError::detached(Kind::UnsupportedShape(format!(
+                    "smart pointer `{}` does not expose its pointee",
+                    peek.shape()
+                ))));
+            };
+            peek = inner;
+            continue;
+        }
+        // A `#[facet(transparent)]` wrapper reads as its single field.
+        if transparent_inner(peek.shape()).is_some() {
+            peek = peek
+                .into_struct()
+                .map_err(|error| KdlError::detached(Kind::Reflect(error)))?
+                .field(0)
+                .map_err(|error| field_error(peek.shape(), error))?;
+            continue;
+        }
+        return Ok(Some(peek));
+    }
+}
+
+/// Peeks through any number of `Spanned` wrappers to the inner value.
+///
+/// Spans are source locations from a previous parse; on output they have no
+/// representation, so every `Spanned<T>` — property value, child struct,
+/// list element — serializes as its inner `T`.
+pub(crate) fn strip_spanned<'mem, 'facet>(
+    mut peek: Peek<'mem, 'facet>,
+) -> Result<Peek<'mem, 'facet>, KdlError> {
+    while spanned_inner(peek.shape()).is_some() {
+        peek = peek
+            .into_struct()
+            .map_err(|error| KdlError::detached(Kind::Reflect(error)))?
+            .field_by_name("value")
+            .map_err(|error| field_error(peek.shape(), error))?;
+    }
+    Ok(peek)
+}
+
+/// A scalar read out of a `Peek`, before any output path formats it.
+///
+/// `f32` stays separate from `f64` so the string writer can print each float
+/// width's own shortest representation.
+pub(crate) enum Scalar {
+    Text(String),
+    Bool(bool),
+    Integer(i128),
+    F32(f32),
+    F64(f64),
+}
+
+/// Probes the concrete scalar types the walk understands: `String`,
+/// `Cow<str>`, `&str`, `char`, `bool`, every integer width, `f32`/`f64`.
+pub(crate) fn probe_scalar(peek: Peek<'_, '_>) -> Option<Scalar> {
+    if let Ok(string) = peek.get::<String>() {
+        return Some(Scalar::Text(string.clone()));
+    }
+    if let Ok(cow) = peek.get::<std::borrow::Cow<'_, str>>() {
+        return Some(Scalar::Text(cow.to_string()));
+    }
+    if let Ok(text) = peek.get::<&str>() {
+        return Some(Scalar::Text((*text).to_string()));
+    }
+    if let Ok(character) = peek.get::<char>() {
+        return Some(Scalar::Text(character.to_string()));
+    }
+    if let Ok(boolean) = peek.get::<bool>() {
+        return Some(Scalar::Bool(*boolean));
+    }
+    macro_rules! probe_integer {
+        ($($ty:ty),*) => {
+            $(
+                if let Ok(integer) = peek.get::<$ty>() {
+                    return Some(Scalar::Integer(*integer as i128));
+                }
+            )*
+        };
+    }
+    probe_integer!(u8, u16, u32, u64, usize, i8, i16, i32, i64, i128, isize);
+    if let Ok(float) = peek.get::<f32>() {
+        return Some(Scalar::F32(*float));
+    }
+    if let Ok(float) = peek.get::<f64>() {
+        return Some(Scalar::F64(*float));
+    }
+    None
+}
+
+/// Reads a scalar out of a `Peek`, returning its value and — for finite
+/// `f32`s, whose shortest representation an `f64` round-trip would lengthen
+/// (e.g. `0.1` to 17 digits) — the exact literal to emit.
+fn scalar_value(
+    peek: Peek<'_, '_>,
+    options: &SerializeOptions,
+) -> Result<(KdlValue, Option<String>), KdlError> {
+    // Wrapper layers can nest in any order — `Option<Spanned<u16>>`,
+    // `Box<Spanned<String>>` — so loop through all of them, mirroring what
+    // the deserializer accepts. A `None` below the outermost layer (which
+    // the property path already handled by omission) writes `#null`.
+    let Some(peek) = strip_wrappers(peek)? else {
+        return Ok((KdlValue::Null, None));
+    };
+    // A unit-only enum is a scalar in disguise: the variant name, or — under
+    // [`UnitVariantFormat::Discriminant`] — its number.
+    if unit_only_variants(peek.shape()).is_some() {
+        let peek_enum = peek
+            .into_enum()
+            .map_err(|error| KdlError::detached(Kind::Reflect(error)))?;
+        let variant = peek_enum
+            .active_variant()
+            .map_err(|_| variant_error(peek.shape()))?;
+        return match options.unit_variant_format {
+            UnitVariantFormat::Name => Ok((KdlValue::String(variant.name.to_string()), None)),
+            UnitVariantFormat::Discriminant => {
+                let Some(discriminant) = variant.discriminant else {
+                    return Err(KdlError::detached(Kind::SchemaError(format!(
+                        "variant `{}` of `{}` has no discriminant to serialize",
+                        variant.name,
+                        peek.shape()
+                    ))));
+                };
+                Ok((KdlValue::Integer(i128::from(discriminant)), None))
+            }
+        };
+    }
+    match probe_scalar(peek) {
+        Some(Scalar::Text(text)) => Ok((KdlValue::String(text), None)),
+        Some(Scalar::Bool(boolean)) => Ok((KdlValue::Bool(boolean), None)),
+        Some(Scalar::Integer(integer)) => Ok((KdlValue::Integer(integer), None)),
+        Some(Scalar::F32(float)) => {
+            let repr = if float.is_finite() {
+                Some(format!("{float:?}"))
+            } else {
+                None
+            };
+            Ok((KdlValue::Float(f64::from(float)), repr))
+        }
+        Some(Scalar::F64(float)) => Ok((KdlValue::Float(float), None)),
+        None => Err(KdlError::detached(Kind::SerializeUnknownValueType(
+            peek.shape(),
+        ))),
+    }
+}
+
+/// Converts the tree into a [`kdl::KdlDocument`].
+pub(crate) fn into_document(ir: &IrDocument, options: &SerializeOptions) -> KdlDocument {
+    let mut document = KdlDocument::new();
+    for node in &ir.nodes {
+        document.nodes_mut().push(kdl_node(node, options));
+    }
+    document
+}
+
+fn kdl_node(node: &IrNode, options: &SerializeOptions) -> KdlNode {
+    let mut kdl_node = KdlNode::new(node.name.as_str());
+    if let Some(annotation) = &node.annotation {
+        kdl_node.set_ty(annotation.clone());
+    }
+    for entry in &node.entries {
+        kdl_node.entries_mut().push(kdl_entry(entry, options));
+    }
+    if let Some(children) = &node.children {
+        if !children.nodes.is_empty() || node.keeps_empty_children(options) {
+            *kdl_node.children_mut() = Some(into_document(children, options));
+        }
+    }
+    kdl_node
+}
+
+fn kdl_entry(entry: &IrEntry, options: &SerializeOptions) -> KdlEntry {
+    let mut kdl_entry = match &entry.name {
+        Some(name) => KdlEntry::new_prop(name.clone(), entry.value.clone()),
+        None => KdlEntry::new(entry.value.clone()),
+    };
+    kdl_entry.set_format(entry_format(entry, options));
+    kdl_entry
+}
+
+/// The explicit format for one entry. Left to its own devices kdl-rs
+/// re-decides the value syntax when a document renders — plain identifier
+/// strings come out bare, radix literals collapse to decimal — so every
+/// entry carries the same text the string writer would emit.
+fn entry_format(entry: &IrEntry, options: &SerializeOptions) -> kdl::KdlEntryFormat {
+    kdl::KdlEntryFormat {
+        value_repr: crate::writer::value_text(entry, options),
+        // A fresh format replaces the implicit one-space separator, so
+        // spell it out.
+        leading: " ".to_string(),
+        ..Default::default()
+    }
+}
+
+/// Re-applies entry formats after [`kdl::KdlDocument::autoformat_config`],
+/// which resets every entry to kdl-rs's own syntax. The IR and the document
+/// it produced have identical shape, so the two walk in lockstep.
+pub(crate) fn stamp_entry_formats(
+    ir: &IrDocument,
+    document: &mut KdlDocument,
+    options: &SerializeOptions,
+) {
+    for (ir_node, node) in ir.nodes.iter().zip(document.nodes_mut()) {
+        for (ir_entry, entry) in ir_node.entries.iter().zip(node.entries_mut()) {
+            entry.set_format(entry_format(ir_entry, options));
+        }
+        if let (Some(ir_children), Some(children)) = (&ir_node.children, node.children_mut()) {
+            stamp_entry_formats(ir_children, children, options);
+        }
+    }
+}
@@ -0,0 +1,93 @@
+#![doc = include_str!("../README.md")]
+#![warn(missing_docs)]
+// `KdlError` carries the source document for miette rendering, which makes
+// every `Result` in the crate bigger than clippy's threshold. That's the
+// point of the type; boxing it would push the indirection onto every caller.
+#![allow(clippy::result_large_err)]
+
+pub mod cli;
+pub mod completion;
+pub mod docgen;
+#[cfg(feature = "de")]
+mod deserialize;
+#[cfg(feature = "de")]
+mod diff;
+#[cfg(any(feature = "ser", feature = "de"))]
+mod error;
+mod fields;
+#[cfg(feature = "bitflags")]
+mod flags;
+#[cfg(feature = "de")]
+mod incremental;
+#[cfg(any(feature = "ser", feature = "de"))]
+mod io;
+#[cfg(feature = "ser")]
+mod ir;
+#[cfg(any(feature = "ser", feature = "de"))]
+mod naming;
+#[cfg(feature = "ser")]
+pub mod snapshot;
+#[cfg(feature = "solver")]
+mod solver;
+mod spanned;
+mod validate;
+#[cfg(any(feature = "ser", feature = "de"))]
+mod version;
+mod wrappers;
+#[cfg(feature = "ser")]
+mod writer;
+
+/// The `kdl` crate this build of facet-kdl links against.
+///
+/// Public APIs exchange [`kdl::KdlValue`], [`kdl::KdlNode`] and
+/// [`kdl::KdlDocument`]; code constructing or inspecting those should use
+/// this re-export instead of pinning its own `kdl` dependency, which would
+/// have to match the exact version to avoid compile errors.
+#[cfg(any(feature = "ser", feature = "de"))]
+pub use kdl;
+
+#[cfg(feature = "de")]
+pub use deserialize::{
+    annotate, from_document, from_document_with_options, from_nodes, from_nodes_with_options,
+    from_str, from_str_collect_errors, from_str_collect_errors_with_options,
+    from_str_multi, from_str_multi_with_options, from_str_with_context, from_str_with_options,
+    from_str_with_origins, from_str_with_version, inspect, parse,
+    CancellationToken, ContextValidator, DeserializeOptions, DocumentStats, DuplicateNodePolicy, FieldOrigin,
+    FieldOriginMap, NullPolicy, NumberCoercion, Progress, ProgressReport,
+    walk, walk_with_options, UnexpectedChildrenPolicy, Validator, WalkEntry,
+};
+#[cfg(feature = "de")]
+pub use diff::{diff, diff_with_options, Change};
+#[cfg(any(feature = "ser", feature = "de"))]
+pub use error::{ExpectedProperty, KdlError, KdlErrorKind};
+#[cfg(feature = "de")]
+pub use error::KdlErrors;
+#[cfg(feature = "bitflags")]
+pub use flags::{format_flags, parse_flags, FlagFormatter, FlagParser};
+#[cfg(feature = "de")]
+pub use incremental::{locate, reparse, NodePath, SpanMap, TextEdit};
+#[cfg(feature = "de")]
+pub use io::{from_path, from_slice, from_slice_lossy};
+#[cfg(all(feature = "de", feature = "tokio"))]
+pub use io::from_async_reader;
+#[cfg(all(feature = "ser", feature = "tokio"))]
+pub use io::to_async_writer;
+#[cfg(any(feature = "ser", feature = "de"))]
+pub use naming::{Naming, NamingConvention};
+#[cfg(feature = "ser")]
+pub use io::{to_path, to_path_with_options, WriteOptions};
+#[cfg(feature = "solver")]
+pub use solver::{Candidate, SolverError, TieBreakPolicy, VariantSelection};
+pub use spanned::{Span, Spanned};
+#[cfg(any(feature = "ser", feature = "de"))]
+pub use version::{KdlVersion, VersionPolicy};
+pub use validate::{check, lint, validate_attributes, AttributeIssue, Lint};
+pub use wrappers::{Args, NodeName, Props};
+#[cfg(feature = "ser")]
+pub use writer::{
+    to_document, to_document_with_options, to_string, to_string_compact, to_string_formatted,
+    to_string_with_options, to_writer,
+    to_writer_with_options, update_document, update_document_with_options, BraceStyle,
+    EmptyChildrenPolicy, FormatConfig, NodeSeparator,
+    SerializeOptions, UnitVariantFormat,
+};
@@ -0,0 +1,118 @@
+//! Pluggable Rust-name to KDL-name conversion.
+//!
+//! By default Rust field and variant names are used in the document verbatim
+//! (children container elements get their type identifier lowercased). A
+//! [`NamingConvention`] replaces that mapping wholesale — SCREAMING-KEBAB,
+//! prefix stripping, whatever the document format demands — without
+//! per-field rename attributes. The same convention is consulted on both
+//! deserialization (matching document names against fields) and
+//! serialization (choosing emitted names).
+//!
+//! `#[facet(rename = "...")]` attributes — and the container-level
+//! `#[facet(rename_all = "kebab-case")]` — need no handling here: the facet
+//! derive folds them into the reflected field or variant name itself, so a
+//! renamed field matches, emits, and appears in error candidate lists
+//! under its renamed form — with any convention applied on top.
+
+use core::fmt;
+use std::borrow::Cow;
+use std::sync::Arc;
+
+/// Converts Rust field/variant names into the names used in KDL documents.
+///
+/// Implemented for any `Fn(&str) -> String` closure.
+pub trait NamingConvention: Send + Sync {
+    /// The KDL name for a Rust field or variant name.
+    fn kdl_name(&self, rust_name: &str) -> String;
+}
+
+impl<F> NamingConvention for F
+where
+    F: Fn(&str) -> String + Send + Sync,
+{
+    fn kdl_name(&self, rust_name: &str) -> String {
+        self(rust_name)
+    }
+}
+
+/// A shareable [`NamingConvention`] handle; the default is the identity
+/// mapping.
+#[derive(Clone, Default)]
+pub struct Naming(Option<Arc<dyn NamingConvention>>);
+
+impl Naming {
+    /// Wraps a convention (or closure) for use in options.
+    pub fn new(convention: impl NamingConvention + 'static) -> Self {
+        Self(Some(Arc::new(convention)))
+    }
+
+    /// The idiomatic-KDL kebab-case convention: `HttpSource` and
+    /// `max_connections` become `http-source` and `max-connections`.
+    ///
+    /// This is the convention to reach for when an enum's variants name
+    /// child nodes — document names like `http-source` then match the
+    /// `HttpSource` variant and serialize back under the converted name,
+    /// with no per-variant `rename` attributes. Acronym runs fold into one
+    /// word: `HTTPSource` also maps to `http-source`.
+    pub fn kebab_case() -> Self {
+        Self::new(to_kebab_case)
+    }
+
+    /// The KDL name for a Rust field or variant name.
+    pub(crate) fn kdl_name<'name>(&self, rust_name: &'name str) -> Cow<'name, str> {
+        match &self.0 {
+            Some(convention) => Cow::Owned(convention.kdl_name(rust_name)),
+            None => Cow::Borrowed(rust_name),
+        }
+    }
+
+    /// Whether a document name corresponds to a Rust name under this
+    /// convention.
+    #[cfg(feature = "de")]
+    pub(crate) fn matches(&self, rust_name: &str, kdl_name: &str) -> bool {
+        self.kdl_name(rust_name) == kdl_name
+    }
+
+    /// The node name for one element of a children container.
+    ///
+    /// The built-in default lowercases the element's type identifier; a
+    /// custom convention sees the identifier as written.
+    pub(crate) fn element_name(&self, type_identifier: &str) -> String {
+        match &self.0 {
+            Some(convention) => convention.kdl_name(type_identifier),
+            None => type_identifier.to_lowercase(),
+        }
+    }
+}
+
+/// Converts `PascalCase`, `camelCase` and `snake_case` names to kebab-case.
+fn to_kebab_case(name: &str) -> String {
+    let mut kdl_name = String::with_capacity(name.len() + 2);
+    let chars: Vec<char> = name.chars().collect();
+    for (index, &c) in chars.iter().enumerate() {
+        if c == '_' {
+            kdl_name.push('-');
+            continue;
+        }
+        if c.is_uppercase() && index > 0 {
+            let prev = chars[index - 1];
+            let next_lower = chars.get(index + 1).is_some_and(|next| next.is_lowercase());
+            // A word starts at lower-to-upper transitions and at the last
+            // capital of an acronym run (`HTTPSource` -> `http-source`).
+            if prev.is_lowercase() || prev.is_ascii_digit() || (prev.is_uppercase() && next_lower) {
+                kdl_name.push('-');
+            }
+        }
+        kdl_name.extend(c.to_lowercase());
+    }
+    kdl_name
+}
+
+impl fmt::Debug for Naming {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(_) => f.write_str("Naming(custom)"),
+            None => f.write_str("Naming(identity)"),
+        }
+    }
+}
@@ -0,0 +1,46 @@
+//! Golden-file rendering for snapshot test suites.
+//!
+//! Serializer output is a compatibility surface: a formatting tweak that
+//! looks harmless in a diff of the code can churn every config file a
+//! downstream project writes. [`golden`] renders one value through every
+//! output mode worth pinning — the plain writer, the compact form, the
+//! autoformatted form, and the KDL 1 dialect — as a single labeled string,
+//! so a snapshot harness like `insta` can track it as one golden file and
+//! formatting changes show up as deliberate snapshot diffs.
+//!
+//! The crate's own suite lives in `tests/golden.rs`; downstream crates can
+//! reuse the helper for their own document types:
+//!
+//! ```ignore
+//! insta::assert_snapshot!(facet_kdl::snapshot::golden(&my_config)?);
+//! ```
+
+use facet_core::Facet;
+
+use crate::error::KdlError;
+use crate::writer::{
+    FormatConfig, SerializeOptions, to_string_compact, to_string_formatted,
+    to_string_with_options,
+};
+
+/// Renders `value` through every serializer output mode, labeled per section.
+///
+/// The sections are stable — new output modes are appended, existing ones
+/// keep their labels — so golden files only change when the output itself
+/// does.
+pub fn golden<'facet, T: Facet<'facet>>(value: &T) -> Result<String, KdlError> {
+    let default = to_string_with_options(value, &SerializeOptions::default())?;
+    let compact = to_string_compact(value)?;
+    let formatted = to_string_formatted(value, FormatConfig::default())?;
+    let v1 = to_string_with_options(
+        value,
+        &SerializeOptions {
+            dialect: crate::version::KdlVersion::V1,
+            ..Default::default()
+        },
+    )?;
+    Ok(format!(
+        "== default ==\n{default}\n== compact ==\n{compact}\n== formatted ==\n{formatted}\n\
+         == v1 ==\n{v1}"
+    ))
+}
@@ -0,0 +1,615 @@
+//! Disambiguation of flattened enums.
+//!
+//! When a node struct contains `#[facet(flatten)]` enum fields, a single KDL
+//! node can in principle be interpreted as several different variant
+//! combinations. The solver enumerates every combination ("resolution"),
+//! filters them against the properties actually present on the node, and
+//! demands that exactly one survives.
+
+use facet_core::{Field, Shape, Type, UserType};
+
+use crate::deserialize::{DeserializeOptions, NullPolicy, NumberCoercion, kdl_value_fits_shape};
+use crate::fields::{
+    FieldRole, denies_unknown_fields, field_role, has_default, is_sensitive, kdl_validator,
+    variant_denies_unknown_fields, variant_is_other,
+};
+
+/// One way of assigning variants to every flattened enum field of a shape.
+#[derive(Debug, Clone)]
+pub(crate) struct Resolution {
+    /// `(flattened field path, variant name)` pairs, in field order. The
+    /// path has more than one segment when the enum sits behind flattened
+    /// structs.
+    pub(crate) selections: Vec<(Vec<&'static str>, &'static str)>,
+    /// Every property slot this resolution exposes.
+    pub(crate) properties: Vec<PropertySlot>,
+    /// Whether this resolution rejects properties it has no slot for, even
+    /// when unknown properties are allowed globally. Set when the node shape
+    /// or any selected variant carries `deny_unknown_fields`.
+    pub(crate) denies_unknown: bool,
+    /// Whether any selection picked a `#[facet(other)]` catch-all variant.
+    /// Fallback resolutions sit out regular candidate filtering and are only
+    /// consulted when nothing else matches.
+    pub(crate) fallback: bool,
+}
+
+/// A property that some resolution can accept.
+#[derive(Debug, Clone)]
+pub(crate) struct PropertySlot {
+    /// The KDL property name.
+    pub(crate) name: &'static str,
+    /// The shape of the value it accepts.
+    pub(crate) shape: &'static Shape,
+    /// Path of fields to open in the `Partial` to reach the slot.
+    pub(crate) path: Vec<&'static str>,
+    /// Whether the slot must be filled for the resolution to be valid.
+    pub(crate) required: bool,
+    /// Whether the slot's field is `#[facet(sensitive)]`.
+    pub(crate) sensitive: bool,
+    /// Whether the slot's field is `#[facet(default)]`, which under
+    /// [`NullPolicy::UseDefault`] lets `#null` stand in for the default.
+    pub(crate) has_default: bool,
+    /// The validator named by `#[facet(kdl(validate_with = ...))]`, if any.
+    pub(crate) validator: Option<&'static str>,
+}
+
+impl Resolution {
+    /// Whether this resolution can accept every property on the node and has
+    /// all of its required slots satisfied.
+    ///
+    /// With `allow_unknown_properties` — or `tolerate_unknown`, the fallback
+    /// pass — properties no slot claims are tolerated instead of
+    /// disqualifying, unless the resolution denies unknown properties.
+    pub(crate) fn matches(
+        &self,
+        properties: &[(&str, &kdl::KdlValue)],
+        options: &DeserializeOptions,
+        cache: &mut FitsCache,
+        tolerate_unknown: bool,
+    ) -> bool {
+        let naming = &options.naming;
+        for (index, (name, value)) in properties.iter().enumerate() {
+            let Some(slot) = self
+                .properties
+                .iter()
+                .find(|slot| naming.matches(slot.name, name))
+            else {
+                if (options.allow_unknown_properties || tolerate_unknown)
+                    && !self.denies_unknown
+                {
+                    continue;
+                }
+                return false;
+            };
+            if null_means_default(value, slot, options) {
+                continue;
+            }
+            if !cache.fits(index, value, slot.shape, options.number_coercion) {
+                return false;
+            }
+        }
+        self.properties
+            .iter()
+            .filter(|slot| slot.required)
+            .all(|slot| properties.iter().any(|(name, _)| naming.matches(slot.name, name)))
+    }
+
+    /// How exactly this resolution's slots accept the node's property
+    /// values: two points per value fitting its slot with no coercion at
+    /// all, one per value that only fits under the run's coercion policy.
+    ///
+    /// The score drives [`TieBreakPolicy::TightestType`]; values without a
+    /// slot contribute nothing either way.
+    fn tightness(
+        &self,
+        properties: &[(&str, &kdl::KdlValue)],
+        options: &DeserializeOptions,
+    ) -> usize {
+        let naming = &options.naming;
+        properties
+            .iter()
+            .map(|(name, value)| {
+                let Some(slot) = self
+                    .properties
+                    .iter()
+                    .find(|slot| naming.matches(slot.name, name))
+                else {
+                    return 0;
+                };
+                if kdl_value_fits_shape(value, slot.shape, NumberCoercion::Strict) {
+                    2
+                } else if kdl_value_fits_shape(value, slot.shape, options.number_coercion) {
+                    1
+                } else {
+                    0
+                }
+            })
+            .sum()
+    }
+
+    /// A human-readable rendering of this resolution, for log lines.
+    pub(crate) fn describe(&self) -> String {
+        self.selections
+            .iter()
+            .map(|(path, variant)| format!("{} = {variant}", path.join(".")))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// The typed [`Candidate`] view of this resolution, evaluated against the
+    /// properties actually present on the node.
+    pub(crate) fn candidate(
+        &self,
+        properties: &[(&str, &kdl::KdlValue)],
+        options: &DeserializeOptions,
+    ) -> Candidate {
+        let naming = &options.naming;
+        Candidate {
+            selections: self
+                .selections
+                .iter()
+                .map(|(path, variant)| VariantSelection {
+                    path: path.clone(),
+                    variant,
+                })
+                .collect(),
+            missing: self
+                .properties
+                .iter()
+                .filter(|slot| {
+                    slot.required && !properties.iter().any(|(name, _)| naming.matches(slot.name, name))
+                })
+                .map(|slot| slot.name)
+                .collect(),
+            extra: properties
+                .iter()
+                .filter(|(name, _)| {
+                    !self.properties.iter().any(|slot| naming.matches(slot.name, name))
+                })
+                .map(|(name, _)| name.to_string())
+                .collect(),
+            mismatched: properties
+                .iter()
+                .filter(|(name, value)| {
+                    self.properties.iter().any(|slot| {
+                        naming.matches(slot.name, name)
+                            && !null_means_default(value, slot, options)
+                            && !kdl_value_fits_shape(value, slot.shape, options.number_coercion)
+                    })
+                })
+                .map(|(name, _)| name.to_string())
+                .collect(),
+        }
+    }
+}
+
+/// Memoizes [`kdl_value_fits_shape`] probes across one node's resolutions.
+///
+/// Heavily ambiguous schemas multiply resolutions, but the distinct
+/// `(property, slot shape)` pairs those resolutions probe stay small — most
+/// of them share their common slots. Caching by property index and shape
+/// identity inspects each value once per distinct shape instead of once per
+/// resolution; the actual conversion still happens exactly once, during
+/// replay of the winning resolution.
+#[derive(Default)]
+pub(crate) struct FitsCache {
+    probes: Vec<(usize, &'static Shape, bool)>,
+}
+
+impl FitsCache {
+    fn fits(
+        &mut self,
+        index: usize,
+        value: &kdl::KdlValue,
+        shape: &'static Shape,
+        coercion: NumberCoercion,
+    ) -> bool {
+        if let Some((_, _, fits)) = self
+            .probes
+            .iter()
+            .find(|(seen, probed, _)| *seen == index && core::ptr::eq(*probed, shape))
+        {
+            return *fits;
+        }
+        let fits = kdl_value_fits_shape(value, shape, coercion);
+        self.probes.push((index, shape, fits));
+        fits
+    }
+}
+
+/// How a tie between several surviving resolutions is broken.
+///
+/// When more than one variant combination accepts a node as written, the
+/// solver has to either guess or ask for help. The default refuses to guess:
+/// documents that round-trip today keep round-tripping when a new variant
+/// makes an old one ambiguous.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TieBreakPolicy {
+    /// Pick the surviving combination built from the earliest-declared
+    /// variants — resolutions are enumerated in declaration order, so the
+    /// first survivor wins.
+    FirstDeclared,
+    /// Prefer the combination whose slots accept the node's values with the
+    /// least coercion — an integer literal landing in an integer slot beats
+    /// one squeezed into a float slot. Falls back to declaration order when
+    /// the scores tie.
+    TightestType,
+    /// Refuse to guess: ambiguity stays an error listing every surviving
+    /// candidate, so the document has to name its variant — typically via a
+    /// [`DeserializeOptions::variant_property`] discriminant.
+    ///
+    /// [`DeserializeOptions::variant_property`]: crate::DeserializeOptions::variant_property
+    #[default]
+    RequireAnnotation,
+}
+
+/// Picks the tightest-fitting resolution under
+/// [`TieBreakPolicy::TightestType`], first declared among equals.
+pub(crate) fn tightest<'schema>(
+    candidates: &[&'schema Resolution],
+    properties: &[(&str, &kdl::KdlValue)],
+    options: &DeserializeOptions,
+) -> &'schema Resolution {
+    candidates
+        .iter()
+        .copied()
+        // `max_by_key` would return the *last* maximum; declaration order
+        // must win ties, so keep the first strictly-greater score.
+        .fold(None::<(&Resolution, usize)>, |best, candidate| {
+            let score = candidate.tightness(properties, options);
+            match best {
+                Some((_, best_score)) if best_score >= score => best,
+                _ => Some((candidate, score)),
+            }
+        })
+        .map(|(candidate, _)| candidate)
+        .expect("tie-breaking runs on at least two candidates")
+}
+
+/// One candidate interpretation of a node, as reported by solver errors.
+///
+/// UIs can use this to build disambiguation pickers — "did you mean the
+/// `Deny` form? then `reason` is missing" — without parsing rendered strings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candidate {
+    /// The variant selected for each flattened enum field, in field order.
+    pub selections: Vec<VariantSelection>,
+    /// Required properties of this candidate the node didn't provide.
+    pub missing: Vec<&'static str>,
+    /// Node properties this candidate has no slot for.
+    pub extra: Vec<String>,
+    /// Node properties whose values don't fit this candidate's slot types.
+    pub mismatched: Vec<String>,
+}
+
+impl core::fmt::Display for Candidate {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let selections = self
+            .selections
+            .iter()
+            .map(|selection| selection.to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        write!(f, "{selections}")?;
+        if !self.missing.is_empty() {
+            write!(f, " (missing: {})", self.missing.join(", "))?;
+        }
+        if !self.extra.is_empty() {
+            write!(f, " (unexpected: {})", self.extra.join(", "))?;
+        }
+        if !self.mismatched.is_empty() {
+            write!(f, " (mismatched: {})", self.mismatched.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// One flattened-enum-to-variant choice inside a [`Candidate`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VariantSelection {
+    /// The Rust field path of the flattened enum, root-first.
+    pub path: Vec<&'static str>,
+    /// The variant this candidate selects.
+    pub variant: &'static str,
+}
+
+impl core::fmt::Display for VariantSelection {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{} = {}", self.path.join("."), self.variant)
+    }
+}
+
+/// All resolutions derivable from one node shape.
+#[derive(Debug)]
+pub(crate) struct Schema {
+    pub(crate) resolutions: Vec<Resolution>,
+}
+
+/// The resolution cap applied when [`DeserializeOptions::max_resolutions`]
+/// is left unset.
+///
+/// [`DeserializeOptions::max_resolutions`]: crate::DeserializeOptions::max_resolutions
+pub(crate) const DEFAULT_MAX_RESOLUTIONS: usize = 4096;
+
+impl Schema {
+    /// Enumerates every resolution of `shape`'s flattened enum fields, up to
+    /// `limit` of them.
+    ///
+    /// The combinatorial size is counted from the variant counts alone before
+    /// anything is built, so a schema over the cap fails fast instead of
+    /// allocating the full resolution set first.
+    pub(crate) fn build(shape: &'static Shape, limit: usize) -> Result<Schema, SolverError> {
+        let mut multipliers = Vec::new();
+        count_multipliers(shape, &mut Vec::new(), &mut multipliers);
+        let total = multipliers
+            .iter()
+            .fold(1usize, |total, (_, count)| total.saturating_mul(*count));
+        if total > limit {
+            return Err(SolverError::TooManyResolutions {
+                total,
+                limit,
+                multipliers,
+            });
+        }
+        let mut resolutions = vec![Resolution {
+            selections: Vec::new(),
+            properties: Vec::new(),
+            denies_unknown: denies_unknown_fields(shape),
+            fallback: false,
+        }];
+        expand(shape, &mut Vec::new(), &mut resolutions)?;
+        Ok(Schema { resolutions })
+    }
+
+    /// The resolutions compatible with the given set of node properties.
+    ///
+    /// Resolutions selecting a `#[facet(other)]` catch-all variant are held
+    /// back: they are only consulted when no regular resolution matches, and
+    /// then tolerate properties they have no slot for — that's the whole
+    /// point of a catch-all written for configs from newer app versions.
+    pub(crate) fn candidates<'schema>(
+        &'schema self,
+        properties: &[(&str, &kdl::KdlValue)],
+        options: &DeserializeOptions,
+    ) -> Vec<&'schema Resolution> {
+        let mut cache = FitsCache::default();
+        let regular: Vec<&Resolution> = self
+            .resolutions
+            .iter()
+            .filter(|resolution| {
+                !resolution.fallback && resolution.matches(properties, options, &mut cache, false)
+            })
+            .collect();
+        if !regular.is_empty() {
+            return regular;
+        }
+        self.resolutions
+            .iter()
+            .filter(|resolution| {
+                resolution.fallback && resolution.matches(properties, options, &mut cache, true)
+            })
+            .collect()
+    }
+}
+
+/// Walks `shape`, multiplying `resolutions` by each flattened enum's variants
+/// and extending every resolution with the property slots found along `path`.
+fn expand(
+    shape: &'static Shape,
+    path: &mut Vec<&'static str>,
+    resolutions: &mut Vec<Resolution>,
+) -> Result<(), SolverError> {
+    let Type::User(UserType::Struct(struct_type)) = &shape.ty else {
+        return Ok(());
+    };
+    for field in struct_type.fields {
+        match field_role(field) {
+            Some(FieldRole::Property) => {
+                for resolution in resolutions.iter_mut() {
+                    resolution.properties.push(PropertySlot {
+                        name: field.name,
+                        shape: field.shape(),
+                        path: path_with(path, field.name),
+                        required: !is_optional(field),
+                        sensitive: is_sensitive(field),
+                        has_default: has_default(field),
+                        validator: kdl_validator(field),
+                    });
+                }
+            }
+            Some(FieldRole::Flatten) => match &field.shape().ty {
+                Type::User(UserType::Struct(_)) => {
+                    path.push(field.name);
+                    expand(field.shape(), path, resolutions)?;
+                    path.pop();
+                }
+                Type::User(UserType::Enum(enum_type)) => {
+                    let mut expanded = Vec::new();
+                    for variant in enum_type.variants {
+                        for resolution in resolutions.iter() {
+                            let mut resolution = resolution.clone();
+                            resolution
+                                .selections
+                                .push((path_with(path, field.name), variant.name));
+                            resolution.denies_unknown |= variant_denies_unknown_fields(variant);
+                            resolution.fallback |= variant_is_other(variant);
+                            for variant_field in variant.data.fields {
+                                if field_role(variant_field) == Some(FieldRole::Property) {
+                                    let mut slot_path = path_with(path, field.name);
+                                    slot_path.push(variant_field.name);
+                                    resolution.properties.push(PropertySlot {
+                                        name: variant_field.name,
+                                        shape: variant_field.shape(),
+                                        path: slot_path,
+                                        required: !is_optional(variant_field),
+                                        sensitive: is_sensitive(variant_field),
+                                        has_default: has_default(variant_field),
+                                        validator: kdl_validator(variant_field),
+                                    });
+                                }
+                            }
+                            expanded.push(resolution);
+                        }
+                    }
+                    *resolutions = expanded;
+                }
+                _ => {
+                    return Err(SolverError::UnsupportedFlatten {
+                        field: field.name,
+                        shape: field.shape(),
+                    });
+                }
+            },
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Collects the variant count each flattened enum field contributes, as
+/// `(dotted field path, variant count)` — the factors whose product is the
+/// resolution count [`expand`] would produce.
+///
+/// Shapes [`expand`] rejects are skipped rather than reported here; the
+/// count only exists to veto oversized schemas before expansion starts.
+fn count_multipliers(
+    shape: &'static Shape,
+    path: &mut Vec<&'static str>,
+    multipliers: &mut Vec<(String, usize)>,
+) {
+    let Type::User(UserType::Struct(struct_type)) = &shape.ty else {
+        return;
+    };
+    for field in struct_type.fields {
+        if field_role(field) != Some(FieldRole::Flatten) {
+            continue;
+        }
+        match &field.shape().ty {
+            Type::User(UserType::Struct(_)) => {
+                path.push(field.name);
+                count_multipliers(field.shape(), path, multipliers);
+                path.pop();
+            }
+            Type::User(UserType::Enum(enum_type)) => {
+                let mut dotted = path.join(".");
+                if !dotted.is_empty() {
+                    dotted.push('.');
+                }
+                dotted.push_str(field.name);
+                multipliers.push((dotted, enum_type.variants.len()));
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Whether a `#null` value on this slot means "use the default" under the
+/// run's [`NullPolicy`].
+fn null_means_default(
+    value: &kdl::KdlValue,
+    slot: &PropertySlot,
+    options: &DeserializeOptions,
+) -> bool {
+    matches!(value, kdl::KdlValue::Null)
+        && options.null_policy == NullPolicy::UseDefault
+        && slot.has_default
+}
+
+fn path_with(path: &[&'static str], name: &'static str) -> Vec<&'static str> {
+    let mut path = path.to_vec();
+    path.push(name);
+    path
+}
+
+fn is_optional(field: &Field) -> bool {
+    matches!(field.shape().def, facet_core::Def::Option(_))
+}
+
+/// An error from the flatten solver.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum SolverError {
+    /// No variant combination can accept the node as written.
+    NoMatch {
+        /// The resolutions that were considered, with what each was missing.
+        candidates: Vec<Candidate>,
+    },
+    /// More than one variant combination accepts the node.
+    Ambiguous {
+        /// The resolutions that survived filtering.
+        candidates: Vec<Candidate>,
+    },
+    /// A `flatten` field has a shape the solver can't work with.
+    UnsupportedFlatten {
+        /// The Rust name of the flattened field.
+        field: &'static str,
+        /// Its shape.
+        shape: &'static Shape,
+    },
+    /// The shape's flattened enums multiply into more resolutions than the
+    /// configured cap allows.
+    TooManyResolutions {
+        /// The resolution count the shape would expand into (saturating).
+        total: usize,
+        /// The cap that was exceeded; see
+        /// [`DeserializeOptions::max_resolutions`].
+        ///
+        /// [`DeserializeOptions::max_resolutions`]: crate::DeserializeOptions::max_resolutions
+        limit: usize,
+        /// The flattened enum fields responsible, as
+        /// `(dotted field path, variant count)` — the factors of `total`.
+        multipliers: Vec<(String, usize)>,
+    },
+}
+
+fn render_candidates(candidates: &[Candidate]) -> String {
+    candidates
+        .iter()
+        .map(|candidate| candidate.to_string())
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+impl std::error::Error for SolverError {}
+
+impl core::fmt::Display for SolverError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            SolverError::NoMatch { candidates } => {
+                write!(f, "no variant combination matches this node")?;
+                if !candidates.is_empty() {
+                    write!(f, "; considered: {}", render_candidates(candidates))?;
+                }
+                Ok(())
+            }
+            SolverError::Ambiguous { candidates } => write!(
+                f,
+                "ambiguous node: {} variant combinations match: {}; name the variant \
+                 explicitly, or pick a `DeserializeOptions::tie_break` policy",
+                candidates.len(),
+                render_candidates(candidates)
+            ),
+            SolverError::UnsupportedFlatten { field, shape } => write!(
+                f,
+                "field `{field}` of type `{shape}` can't be flattened: expected a struct or enum"
+            ),
+            SolverError::TooManyResolutions {
+                total,
+                limit,
+                multipliers,
+            } => {
+                let factors = multipliers
+                    .iter()
+                    .map(|(path, count)| format!("`{path}` ({count} variants)"))
+                    .collect::<Vec<_>>()
+                    .join(" x ");
+                write!(
+                    f,
+                    "flattened enums multiply into {total} variant combinations, over the \
+                     cap of {limit}: {factors}; raise `DeserializeOptions::max_resolutions` \
+                     if the schema is intentional"
+                )
+            }
+        }
+    }
+}
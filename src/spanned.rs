@@ -0,0 +1,114 @@
+//! Source-location aware wrappers.
+//!
+//! Wrapping a field type in [`Spanned`] makes the deserializer record where in
+//! the document the value came from, which is what you want for "defined at
+//! line 12" style follow-up diagnostics in applications.
+
+use core::fmt;
+use core::ops::{Deref, DerefMut};
+
+use facet::Facet;
+
+/// A byte range into the source document.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Facet)]
+pub struct Span {
+    /// Byte offset of the start of the range.
+    pub offset: usize,
+    /// Length of the range in bytes.
+    pub len: usize,
+}
+
+impl Span {
+    /// The empty span at offset zero — the placeholder for values that were
+    /// never parsed, such as expected values built by hand in tests.
+    pub const DUMMY: Span = Span { offset: 0, len: 0 };
+
+    /// A span covering `len` bytes starting at `offset`.
+    pub const fn new(offset: usize, len: usize) -> Self {
+        Span { offset, len }
+    }
+
+    /// Byte offset one past the end of the range.
+    pub fn end(&self) -> usize {
+        self.offset + self.len
+    }
+}
+
+#[cfg(feature = "de")]
+impl From<miette::SourceSpan> for Span {
+    fn from(span: miette::SourceSpan) -> Self {
+        Span {
+            offset: span.offset(),
+            len: span.len(),
+        }
+    }
+}
+
+#[cfg(feature = "de")]
+impl From<Span> for miette::SourceSpan {
+    fn from(span: Span) -> Self {
+        miette::SourceSpan::new(span.offset.into(), span.len)
+    }
+}
+
+/// A value along with the [`Span`] it was deserialized from.
+///
+/// `Spanned<T>` dereferences to `T`, so it can usually be dropped into an
+/// existing field without touching the code that reads it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Facet)]
+pub struct Spanned<T> {
+    /// The deserialized value.
+    pub value: T,
+    /// Where in the document the value came from.
+    pub span: Span,
+}
+
+impl<T> Spanned<T> {
+    /// Wraps `value` with an explicit span.
+    pub const fn new(value: T, span: Span) -> Self {
+        Spanned { value, span }
+    }
+
+    /// Wraps `value` with [`Span::DUMMY`] — for expected values in test
+    /// assertions and for migrating existing data that has no source text.
+    pub const fn with_dummy_span(value: T) -> Self {
+        Spanned {
+            value,
+            span: Span::DUMMY,
+        }
+    }
+
+    /// Transforms the value while keeping the span.
+    pub fn map_value<U>(self, f: impl FnOnce(T) -> U) -> Spanned<U> {
+        Spanned {
+            value: f(self.value),
+            span: self.span,
+        }
+    }
+}
+
+impl<T: Default> Default for Spanned<T> {
+    fn default() -> Self {
+        Spanned::with_dummy_span(T::default())
+    }
+}
+
+impl<T> Deref for Spanned<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T> DerefMut for Spanned<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for Spanned<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.value.fmt(f)
+    }
+}
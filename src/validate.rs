@@ -0,0 +1,523 @@
+//! Static validation of a type's KDL attribute usage.
+//!
+//! Attribute mistakes (a field marked both `argument` and `property`, a
+//! `children` field that isn't a container) otherwise surface as confusing
+//! runtime errors halfway through deserialization. [`validate_attributes`]
+//! catches them up front, typically from a unit test next to the type
+//! definition.
+
+use core::fmt;
+
+use facet_core::{Def, Facet, Field, FieldAttribute, Shape, Type, UserType};
+
+/// A problem found in a type's KDL attribute usage.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttributeIssue {
+    /// The shape the offending field belongs to.
+    pub shape: &'static Shape,
+    /// The Rust name of the offending field.
+    pub field: &'static str,
+    /// What's wrong with it.
+    pub message: String,
+}
+
+impl fmt::Display for AttributeIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}: {}", self.shape, self.field, self.message)
+    }
+}
+
+/// Checks `T` (and every shape reachable from it) for conflicting or
+/// nonsensical KDL attributes, returning one issue per problem found.
+///
+/// An empty result means the type's attribute usage is coherent; it does not
+/// guarantee that every document will deserialize.
+pub fn validate_attributes<'facet, T: Facet<'facet>>() -> Vec<AttributeIssue> {
+    let mut issues = Vec::new();
+    let mut visited = Vec::new();
+    validate_shape(T::SHAPE, &mut visited, &mut issues);
+    issues
+}
+
+/// One-line schema check for unit tests: `facet_kdl::check::<Config>()?`.
+///
+/// Runs [`validate_attributes`] and formats every issue found — including
+/// duplicate property definitions across flattened structs — one per line,
+/// so `check::<Config>().unwrap()` next to the type definition fails with a
+/// readable report.
+pub fn check<'facet, T: Facet<'facet>>() -> Result<(), String> {
+    let issues = validate_attributes::<T>();
+    if issues.is_empty() {
+        return Ok(());
+    }
+    Err(issues
+        .iter()
+        .map(AttributeIssue::to_string)
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+/// A likely misconfiguration found by [`lint`].
+///
+/// Unlike an [`AttributeIssue`], the usage is legal — it just can't do what
+/// its author probably meant, the kind of bug otherwise discovered only when
+/// a value silently comes back default.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lint {
+    /// The shape the suspicious field belongs to.
+    pub shape: &'static Shape,
+    /// The Rust name of the suspicious field.
+    pub field: &'static str,
+    /// What's suspicious about it.
+    pub message: String,
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}: {}", self.shape, self.field, self.message)
+    }
+}
+
+/// Checks `T` (and every shape reachable from it) for suspicious-but-legal
+/// KDL mappings: fields no part of a document can ever populate, renames
+/// that collide with another field or variant, and `children` containers of
+/// `Option` elements.
+///
+/// An empty result is a hint, not a guarantee; [`validate_attributes`] is
+/// the companion check for outright attribute errors.
+pub fn lint<'facet, T: Facet<'facet>>() -> Vec<Lint> {
+    let mut lints = Vec::new();
+    let mut visited = Vec::new();
+    lint_shape(T::SHAPE, &mut visited, &mut lints);
+    lints
+}
+
+fn lint_shape(shape: &'static Shape, visited: &mut Vec<*const Shape>, lints: &mut Vec<Lint>) {
+    let address = shape as *const Shape;
+    if visited.contains(&address) {
+        return;
+    }
+    visited.push(address);
+    match &shape.ty {
+        Type::User(UserType::Struct(struct_type)) => {
+            lint_duplicate_names(shape, struct_type.fields.iter().map(|field| field.name), lints);
+            for field in struct_type.fields {
+                lint_field(shape, field, visited, lints);
+            }
+        }
+        Type::User(UserType::Enum(enum_type)) => {
+            // The derive folds `#[facet(rename)]` into the variant name, so
+            // a colliding rename shows up as two variants sharing one name.
+            lint_duplicate_names(
+                shape,
+                enum_type.variants.iter().map(|variant| variant.name),
+                lints,
+            );
+            for variant in enum_type.variants {
+                lint_duplicate_names(
+                    shape,
+                    variant.data.fields.iter().map(|field| field.name),
+                    lints,
+                );
+                for field in variant.data.fields {
+                    lint_field(shape, field, visited, lints);
+                }
+            }
+        }
+        _ => {}
+    }
+    match shape.def {
+        Def::Option(option_def) => lint_shape(option_def.t(), visited, lints),
+        Def::List(list_def) => lint_shape(list_def.t(), visited, lints),
+        Def::Set(set_def) => lint_shape(set_def.t(), visited, lints),
+        Def::Map(map_def) => lint_shape(map_def.v(), visited, lints),
+        Def::Pointer(pointer_def) => {
+            if let Some(pointee) = pointer_def.pointee() {
+                lint_shape(pointee, visited, lints);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn lint_field(
+    shape: &'static Shape,
+    field: &'static Field,
+    visited: &mut Vec<*const Shape>,
+    lints: &mut Vec<Lint>,
+) {
+    // A field without a role (and without one of the role-less capture
+    // attributes) is dead weight: no document content ever reaches it.
+    // Zero-sized markers and newtype payload fields are populated by other
+    // means, so they don't count.
+    if crate::fields::field_role(field).is_none()
+        && !crate::fields::has_kdl_attr(field, "raw")
+        && !crate::fields::has_kdl_attr(field, "document")
+        && !crate::fields::is_unit_like(field.shape())
+        && field.name != "0"
+    {
+        lints.push(Lint {
+            shape,
+            field: field.name,
+            message: "no KDL attribute claims this field, so it can never be \
+                      populated from a document; mark it `argument`, `property`, \
+                      `child`, `children`, `flatten`, or `skip`"
+                .to_string(),
+        });
+    }
+    // Every matching node fills `Some`, so an `Option` element can never
+    // observe `None`; the layer only obscures the element type.
+    if crate::fields::field_role(field) == Some(crate::fields::FieldRole::Children) {
+        let element = match field.shape().def {
+            Def::List(list_def) => Some(list_def.t()),
+            Def::Set(set_def) => Some(set_def.t()),
+            Def::Map(map_def) => Some(map_def.v()),
+            _ => None,
+        };
+        if let Some(element) = element {
+            let element = crate::fields::spanned_inner(element).unwrap_or(element);
+            if matches!(element.def, Def::Option(_)) {
+                lints.push(Lint {
+                    shape,
+                    field: field.name,
+                    message: format!(
+                        "`children` element type `{element}` wraps `Option`, but a \
+                         matching node always fills `Some`; use the inner type \
+                         directly"
+                    ),
+                });
+            }
+        }
+    }
+    lint_shape(field.shape(), visited, lints);
+}
+
+/// Reports names appearing more than once in one namespace — the mark of a
+/// `#[facet(rename)]` colliding with a sibling.
+fn lint_duplicate_names(
+    shape: &'static Shape,
+    names: impl Iterator<Item = &'static str>,
+    lints: &mut Vec<Lint>,
+) {
+    let names: Vec<&'static str> = names.collect();
+    for (index, name) in names.iter().enumerate() {
+        if names[..index].contains(name) {
+            lints.push(Lint {
+                shape,
+                field: name,
+                message: format!(
+                    "the name `{name}` is defined more than once; a rename \
+                     collides with a sibling and the two shadow each other"
+                ),
+            });
+        }
+    }
+}
+
+fn validate_shape(
+    shape: &'static Shape,
+    visited: &mut Vec<*const Shape>,
+    issues: &mut Vec<AttributeIssue>,
+) {
+    let address = shape as *const Shape;
+    if visited.contains(&address) {
+        return;
+    }
+    visited.push(address);
+    match &shape.ty {
+        Type::User(UserType::Struct(struct_type)) => {
+            for field in struct_type.fields {
+                validate_field(shape, field, visited, issues);
+            }
+            check_duplicate_properties(shape, issues);
+        }
+        Type::User(UserType::Enum(enum_type)) => {
+            for variant in enum_type.variants {
+                for field in variant.data.fields {
+                    validate_field(shape, field, visited, issues);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn validate_field(
+    shape: &'static Shape,
+    field: &'static Field,
+    visited: &mut Vec<*const Shape>,
+    issues: &mut Vec<AttributeIssue>,
+) {
+    let roles = kdl_roles(field);
+    if roles.len() > 1 {
+        issues.push(AttributeIssue {
+            shape,
+            field: field.name,
+            message: format!(
+                "conflicting KDL attributes: {}; a field can play only one role",
+                roles.join(" and ")
+            ),
+        });
+    }
+    match roles.first().copied() {
+        Some("children")
+            if !matches!(
+                field.shape().def,
+                Def::List(_) | Def::Set(_) | Def::Map(_)
+            ) =>
+        {
+            issues.push(AttributeIssue {
+                shape,
+                field: field.name,
+                message: format!(
+                    "`children` requires a container type, but `{}` is not a \
+                     list, set, or map; did you mean `child`?",
+                    field.shape()
+                ),
+            });
+        }
+        // Each children node fills one element, so the element type must be
+        // able to stand for a node on its own.
+        Some("children") => {
+            let element = match field.shape().def {
+                Def::List(list_def) => list_def.t(),
+                Def::Set(set_def) => set_def.t(),
+                Def::Map(map_def) => map_def.v(),
+                _ => unreachable!("the container check above matched"),
+            };
+            let element = crate::fields::spanned_inner(element).unwrap_or(element);
+            if !matches!(
+                &element.ty,
+                Type::User(UserType::Struct(_) | UserType::Enum(_))
+            ) {
+                issues.push(AttributeIssue {
+                    shape,
+                    field: field.name,
+                    message: format!(
+                        "`children` element type `{element}` can't fill a node; \
+                         elements must be structs or enums (scalar elements \
+                         aren't supported yet)"
+                    ),
+                });
+            }
+        }
+        // A child node's entries and children need fields to land in, so the
+        // field must unwrap to a struct, enum, or property map; a scalar
+        // otherwise only fails deep inside reflection, far from the typo.
+        Some("child") if !crate::fields::has_kdl_attr(field, "verbatim") => {
+            let mut inner = field.shape();
+            loop {
+                if let Def::Option(option_def) = inner.def {
+                    inner = option_def.t();
+                    continue;
+                }
+                if let Some(pointee) = crate::fields::pointee(inner) {
+                    inner = pointee;
+                    continue;
+                }
+                if let Some(wrapped) = crate::fields::transparent_inner(inner) {
+                    inner = wrapped.shape();
+                    continue;
+                }
+                break;
+            }
+            if !matches!(inner.def, Def::Map(_))
+                && !matches!(&inner.ty, Type::User(UserType::Struct(_) | UserType::Enum(_)))
+            {
+                issues.push(AttributeIssue {
+                    shape,
+                    field: field.name,
+                    message: format!(
+                        "`child` points at scalar type `{inner}`, which can't fill a \
+                         node of its own; did you mean `property` or `argument`?"
+                    ),
+                });
+            }
+        }
+        Some("arguments") if !matches!(field.shape().def, Def::List(_)) => {
+            issues.push(AttributeIssue {
+                shape,
+                field: field.name,
+                message: format!(
+                    "`arguments` requires a list type, but `{}` is not one; \
+                     did you mean `argument`?",
+                    field.shape()
+                ),
+            });
+        }
+        Some("flatten")
+            if !matches!(
+                &field.shape().ty,
+                Type::User(UserType::Struct(_) | UserType::Enum(_))
+            ) =>
+        {
+            issues.push(AttributeIssue {
+                shape,
+                field: field.name,
+                message: format!(
+                    "`flatten` requires a struct or enum, but `{}` is neither",
+                    field.shape()
+                ),
+            });
+        }
+        _ => {}
+    }
+    if crate::fields::has_kdl_attr(field, "singleton")
+        && roles.first().copied() != Some("child")
+    {
+        issues.push(AttributeIssue {
+            shape,
+            field: field.name,
+            message: "`kdl(singleton)` requires the `child` role".to_string(),
+        });
+    }
+    if let Some(form) = crate::fields::kdl_node_or_property(field) {
+        if roles.first().copied() != Some("property") {
+            issues.push(AttributeIssue {
+                shape,
+                field: field.name,
+                message: "`kdl(node_or_property)` requires the `property` role".to_string(),
+            });
+        } else if !matches!(form, "node" | "property") {
+            issues.push(AttributeIssue {
+                shape,
+                field: field.name,
+                message: format!(
+                    "`kdl(node_or_property = {form})` names an unknown canonical \
+                     form; use `node` or `property`"
+                ),
+            });
+        }
+    }
+    if crate::fields::has_kdl_attr(field, "verbatim") {
+        if roles.first().copied() != Some("child") {
+            issues.push(AttributeIssue {
+                shape,
+                field: field.name,
+                message: "`kdl(verbatim)` requires the `child` role".to_string(),
+            });
+        } else if crate::fields::unwrap_option(field.shape()).type_identifier != "String" {
+            issues.push(AttributeIssue {
+                shape,
+                field: field.name,
+                message: format!(
+                    "`kdl(verbatim)` requires a `String` to hold the KDL text, \
+                     but `{}` is not one",
+                    field.shape()
+                ),
+            });
+        }
+    }
+    if crate::fields::has_kdl_attr(field, "inline") {
+        if roles.first().copied() != Some("child") {
+            issues.push(AttributeIssue {
+                shape,
+                field: field.name,
+                message: "`kdl(inline)` requires the `child` role".to_string(),
+            });
+        } else if !matches!(&field.shape().ty, Type::User(UserType::Struct(_))) {
+            issues.push(AttributeIssue {
+                shape,
+                field: field.name,
+                message: format!(
+                    "`kdl(inline)` requires a struct, but `{}` is not one",
+                    field.shape()
+                ),
+            });
+        }
+    }
+    validate_shape(field.shape(), visited, issues);
+}
+
+/// Reports property names a node shape defines more than once, counting the
+/// properties its flattened structs pull in.
+///
+/// Two definitions of the same name — `Server.port` next to a flattened
+/// `ConnectionSettings.port` — would silently shadow each other at runtime;
+/// the issue names both defining types and the flatten path of each.
+fn check_duplicate_properties(shape: &'static Shape, issues: &mut Vec<AttributeIssue>) {
+    let mut found = Vec::new();
+    collect_property_definitions(shape, &mut Vec::new(), &mut found);
+    for (index, (name, owner, path)) in found.iter().enumerate() {
+        let Some((_, first_owner, first_path)) =
+            found[..index].iter().find(|(seen, _, _)| seen == name)
+        else {
+            continue;
+        };
+        issues.push(AttributeIssue {
+            shape,
+            field: name,
+            message: format!(
+                "property `{name}` is defined twice: by {} and by {}",
+                describe_definition(first_owner, first_path, name),
+                describe_definition(owner, path, name),
+            ),
+        });
+    }
+}
+
+/// Every property definition a shape exposes, directly or through flattened
+/// structs, as `(field name, defining shape, flatten path)`.
+fn collect_property_definitions(
+    shape: &'static Shape,
+    prefix: &mut Vec<&'static str>,
+    found: &mut Vec<(&'static str, &'static Shape, Vec<&'static str>)>,
+) {
+    let Type::User(UserType::Struct(struct_type)) = &shape.ty else {
+        return;
+    };
+    for field in struct_type.fields {
+        match kdl_roles(field).first().copied() {
+            Some("property") => found.push((field.name, shape, prefix.clone())),
+            Some("flatten") => {
+                if matches!(&field.shape().ty, Type::User(UserType::Struct(_))) {
+                    prefix.push(field.name);
+                    collect_property_definitions(field.shape(), prefix, found);
+                    prefix.pop();
+                }
+            }
+            // A `kdl(inline)` child shares the parent's property namespace.
+            Some("child") if crate::fields::has_kdl_attr(field, "inline") => {
+                if matches!(&field.shape().ty, Type::User(UserType::Struct(_))) {
+                    prefix.push(field.name);
+                    collect_property_definitions(field.shape(), prefix, found);
+                    prefix.pop();
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Renders one property definition for a duplicate-property issue.
+fn describe_definition(owner: &'static Shape, path: &[&'static str], field: &str) -> String {
+    if path.is_empty() {
+        format!("`{owner}.{field}`")
+    } else {
+        format!("`{owner}.{field}` (flattened via `{}`)", path.join("."))
+    }
+}
+
+/// All KDL role attributes present on a field.
+///
+/// `child` and `flatten` are keywords the facet derive turns into field
+/// flags rather than attribute text, so they're read off the flags; the
+/// KDL-specific roles arrive as arbitrary attributes, in declaration order.
+fn kdl_roles(field: &'static Field) -> Vec<&'static str> {
+    let mut roles = Vec::new();
+    if field.flags.contains(facet_core::FieldFlags::CHILD) {
+        roles.push("child");
+    }
+    if field.flags.contains(facet_core::FieldFlags::FLATTEN) {
+        roles.push("flatten");
+    }
+    roles.extend(field.attributes.iter().filter_map(|attribute| {
+        let FieldAttribute::Arbitrary(text) = attribute;
+        matches!(
+            *text,
+            "argument" | "arguments" | "property" | "children" | "skip"
+        )
+        .then_some(*text)
+    }));
+    roles
+}
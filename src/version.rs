@@ -0,0 +1,42 @@
+//! KDL dialect selection and detection.
+//!
+//! kdl-rs still understands the legacy KDL 1 syntax (`true` instead of
+//! `#true`, `r"raw"` strings, `/-` on entries only) alongside the current
+//! KDL 2 spec. [`VersionPolicy`] picks which dialects the deserializer
+//! accepts, and [`KdlVersion`] records which one a document actually matched
+//! so tools rewriting user files can emit the same dialect back.
+
+/// Which KDL dialects the deserializer accepts.
+///
+/// Set on [`DeserializeOptions::version`](crate::DeserializeOptions).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum VersionPolicy {
+    /// Try KDL 2 first, then fall back to KDL 1. The matched dialect is
+    /// reported through [`from_str_with_version`](crate::from_str_with_version).
+    /// This is the default, and mirrors what bare `kdl` parsing does.
+    #[default]
+    Auto,
+    /// KDL 2 syntax only. Legacy documents — a bare `true`, a `r"raw"`
+    /// string — are parse errors, which is what linters and format
+    /// migrations want.
+    V2Only,
+    /// KDL 1 syntax only, for pinning tools that must not silently start
+    /// accepting the new spec.
+    V1Only,
+}
+
+/// A KDL dialect, as matched by the parse.
+///
+/// Returned by [`from_str_with_version`](crate::from_str_with_version) and
+/// consumed by [`SerializeOptions::dialect`](crate::SerializeOptions) so a
+/// rewrite can round-trip a legacy file without upgrading its literals.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum KdlVersion {
+    /// The legacy KDL 1 spec: keyword literals are written bare (`true`,
+    /// `null`).
+    V1,
+    /// The current KDL 2 spec: keyword literals carry a `#` sigil (`#true`,
+    /// `#null`). This is the default.
+    #[default]
+    V2,
+}
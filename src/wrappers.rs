@@ -0,0 +1,116 @@
+//! Ready-made wrapper types for common KDL idioms.
+//!
+//! Each wrapper carries its role in the type itself, so a field needs no
+//! facet attribute to get the matching behavior: [`Args`] collects a node's
+//! positional arguments, [`Props`] soaks up its properties, and [`NodeName`]
+//! captures the name of the node a value was read from. They exist so
+//! document types can be assembled without memorizing attribute
+//! combinations; a field spelled with an explicit role attribute behaves
+//! identically.
+
+use core::fmt;
+use core::ops::{Deref, DerefMut};
+use std::collections::HashMap;
+
+use facet::Facet;
+
+/// The name of the KDL node a value was deserialized from.
+///
+/// A struct with a `NodeName` field records the node's own name on the way
+/// in, and serializes under that name instead of the spelling the field or
+/// element type would otherwise supply. Most useful for values reached
+/// through a top-level map or a `child` field, where the name isn't fixed
+/// by the schema.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default, Facet)]
+pub struct NodeName(pub String);
+
+impl Deref for NodeName {
+    type Target = String;
+
+    fn deref(&self) -> &String {
+        &self.0
+    }
+}
+
+impl DerefMut for NodeName {
+    fn deref_mut(&mut self) -> &mut String {
+        &mut self.0
+    }
+}
+
+impl From<String> for NodeName {
+    fn from(name: String) -> Self {
+        NodeName(name)
+    }
+}
+
+impl From<&str> for NodeName {
+    fn from(name: &str) -> Self {
+        NodeName(name.to_string())
+    }
+}
+
+impl fmt::Display for NodeName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// All of a node's positional arguments, in document order.
+///
+/// A field of this type behaves exactly like `#[facet(arguments)]` on a
+/// `Vec<T>`: every positional entry lands in the list, and serialization
+/// writes the elements back as positional entries. An empty list is the
+/// default when the node carries no positional arguments at all.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default, Facet)]
+pub struct Args<T>(pub Vec<T>);
+
+impl<T> Deref for Args<T> {
+    type Target = Vec<T>;
+
+    fn deref(&self) -> &Vec<T> {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for Args<T> {
+    fn deref_mut(&mut self) -> &mut Vec<T> {
+        &mut self.0
+    }
+}
+
+impl<T> From<Vec<T>> for Args<T> {
+    fn from(arguments: Vec<T>) -> Self {
+        Args(arguments)
+    }
+}
+
+/// Every property of a node that no explicit `property` field claims.
+///
+/// Values are captured as text: string values verbatim, other scalars in
+/// their KDL spelling (`8080`, `#true`). Serialization writes the entries
+/// back as string properties, sorted by key for deterministic output; an
+/// empty map is the default when nothing is left over. Combine with regular
+/// `property` fields for "known keys typed, the rest collected" schemas.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Facet)]
+pub struct Props(pub HashMap<String, String>);
+
+impl Deref for Props {
+    type Target = HashMap<String, String>;
+
+    fn deref(&self) -> &HashMap<String, String> {
+        &self.0
+    }
+}
+
+impl DerefMut for Props {
+    fn deref_mut(&mut self) -> &mut HashMap<String, String> {
+        &mut self.0
+    }
+}
+
+impl From<HashMap<String, String>> for Props {
+    fn from(properties: HashMap<String, String>) -> Self {
+        Props(properties)
+    }
+}
@@ -0,0 +1,611 @@
+//! The direct string/writer serializer behind [`to_string`] and friends.
+//!
+//! This path renders the shared tree built by [`crate::ir`] straight into an
+//! `io::Write`; [`to_string_formatted`] is the document-building counterpart,
+//! which converts the same tree into a [`kdl::KdlDocument`] and lets kdl-rs
+//! format it.
+
+use facet_core::Facet;
+use kdl::KdlValue;
+
+use crate::error::KdlError;
+use crate::error::KdlErrorKind as Kind;
+use crate::ir::{IrDocument, IrEntry, IrNode};
+use crate::naming::Naming;
+use crate::version::KdlVersion;
+
+/// Formatting settings for [`to_string_formatted`].
+#[derive(Debug, Clone)]
+pub struct FormatConfig {
+    /// The string used for one level of indentation.
+    pub indent: String,
+}
+
+impl Default for FormatConfig {
+    fn default() -> Self {
+        Self {
+            indent: "    ".to_string(),
+        }
+    }
+}
+
+/// Options controlling serialization behavior.
+#[derive(Debug, Clone)]
+pub struct SerializeOptions {
+    /// The convention mapping Rust field/variant names to document names.
+    pub naming: Naming,
+    /// Whether nodes whose child fields are all empty still get a `{ }`
+    /// block.
+    pub empty_children: EmptyChildrenPolicy,
+    /// The string written once per depth level in front of a node. Four
+    /// spaces by default; style guides built on tabs or two spaces set
+    /// theirs here.
+    pub indent: String,
+    /// Where the `{` opening a children block goes.
+    pub brace_style: BraceStyle,
+    /// What ends a node: the newline alone, or an explicit `;` first.
+    pub node_separator: NodeSeparator,
+    /// The KDL dialect keyword literals are written in. [`KdlVersion::V2`]
+    /// (the default) writes `#true`/`#null`; [`KdlVersion::V1`] writes the
+    /// bare legacy forms, so a file detected as KDL 1 on the way in (see
+    /// `from_str_with_version`) can be handed back in its own dialect.
+    ///
+    /// Only the string writer honors this — [`to_string_formatted`]
+    /// delegates rendering to kdl-rs, which emits KDL 2 — and non-finite
+    /// floats keep their `#inf`/`#nan` forms, which KDL 1 cannot express.
+    pub dialect: KdlVersion,
+    /// Named flag formatters referenced by `#[facet(kdl(flags_with = name))]`
+    /// field attributes; see the [`crate::format_flags`] helper.
+    #[cfg(feature = "bitflags")]
+    pub flag_formatters: Vec<(&'static str, crate::flags::FlagFormatter)>,
+    /// When set, every enum-valued node also carries a
+    /// `key="VariantName"` property naming its active variant, so generated
+    /// documents are self-describing for non-facet consumers. The
+    /// deserializer accepts and verifies the property under the matching
+    /// `DeserializeOptions::variant_property` setting.
+    pub variant_property: Option<String>,
+    /// The spelling used for unit-only enum values (enums whose variants all
+    /// carry no payload) in arguments and properties.
+    pub unit_variant_format: UnitVariantFormat,
+}
+
+impl Default for SerializeOptions {
+    fn default() -> Self {
+        Self {
+            naming: Naming::default(),
+            empty_children: EmptyChildrenPolicy::default(),
+            indent: "    ".to_string(),
+            brace_style: BraceStyle::default(),
+            node_separator: NodeSeparator::default(),
+            dialect: KdlVersion::default(),
+            #[cfg(feature = "bitflags")]
+            flag_formatters: Vec::new(),
+            variant_property: None,
+            unit_variant_format: UnitVariantFormat::default(),
+        }
+    }
+}
+
+/// How a unit-only enum value is written.
+///
+/// The deserializer accepts both spellings regardless of this setting, so
+/// switching it never invalidates existing documents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnitVariantFormat {
+    /// The variant name, as a string: `level="Medium"`. This is the default.
+    #[default]
+    Name,
+    /// The numeric discriminant: `level=5` — the compact form for
+    /// machine-generated configs. A variant without a discriminant is an
+    /// error.
+    Discriminant,
+}
+
+/// Where the string writer places the `{` opening a children block.
+///
+/// Only the [`to_string`] family is affected: [`to_string_compact`] keeps its
+/// inline blocks, and [`to_string_formatted`] delegates layout to kdl-rs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BraceStyle {
+    /// `node {` — the brace shares the node's line, K&R style. This is the
+    /// default.
+    #[default]
+    SameLine,
+    /// The brace gets its own line below the node, indented to the node's
+    /// depth (Allman style). The node's line ends in a `\` continuation, as
+    /// KDL would otherwise terminate the node at the newline.
+    NextLine,
+}
+
+/// How the string writer ends each node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NodeSeparator {
+    /// The newline alone terminates the node. This is the default.
+    #[default]
+    Newline,
+    /// An explicit `;` before the newline, for style guides that require
+    /// terminated nodes.
+    Semicolon,
+}
+
+/// What happens to a node's children block when every child field turned out
+/// empty or `None`.
+///
+/// The deserializer treats `node { }` and `node` identically, so the block
+/// carries no information either way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EmptyChildrenPolicy {
+    /// Omit the block. This is the default.
+    #[default]
+    Omit,
+    /// Emit the block whenever the shape has child fields, for consumers
+    /// that want the structure visible.
+    Emit,
+}
+
+/// Serializes `value` as a canonically formatted KDL document string.
+///
+/// Unlike [`to_string`], which writes text directly, this builds a
+/// [`kdl::KdlDocument`] and runs kdl-rs autoformatting over it, so the output
+/// matches what `kdlfmt`-style tooling would produce for the same document.
+pub fn to_string_formatted<'facet, T: Facet<'facet>>(
+    value: &T,
+    config: FormatConfig,
+) -> Result<String, KdlError> {
+    let options = SerializeOptions::default();
+    let ir = crate::ir::build(value, &options)?;
+    let mut document = crate::ir::into_document(&ir, &options);
+    document.autoformat_config(
+        &kdl::FormatConfig::builder().indent(&config.indent).build(),
+    );
+    // Autoformatting resets every entry to kdl-rs's own value syntax; put
+    // this crate's spellings (quoted strings, radix literals) back.
+    crate::ir::stamp_entry_formats(&ir, &mut document, &options);
+    Ok(document.to_string())
+}
+
+/// Serializes `value` as a [`kdl::KdlDocument`] instead of text.
+///
+/// The structured form is for callers that post-process before writing out —
+/// adding comments, reordering nodes, merging into an existing file — using
+/// the kdl crate's own APIs. Rendering the returned document with
+/// `to_string()` produces kdl-rs's formatting, not this crate's writer; run
+/// it through [`to_string_formatted`]-style autoformatting or edit it into a
+/// document whose formatting you want to keep.
+pub fn to_document<'facet, T: Facet<'facet>>(value: &T) -> Result<kdl::KdlDocument, KdlError> {
+    to_document_with_options(value, &SerializeOptions::default())
+}
+
+/// Like [`to_document`], with explicit [`SerializeOptions`].
+pub fn to_document_with_options<'facet, T: Facet<'facet>>(
+    value: &T,
+    options: &SerializeOptions,
+) -> Result<kdl::KdlDocument, KdlError> {
+    let ir = crate::ir::build(value, options)?;
+    Ok(crate::ir::into_document(&ir, options))
+}
+
+/// Patches an existing document in place so it deserializes back to `value`,
+/// preserving comments, whitespace and node order.
+///
+/// Only entries whose values actually changed are rewritten; everything else
+/// — leading comments, indentation, value spellings, the order nodes appear
+/// in — is left exactly as the user wrote it. Nodes and entries the new
+/// value no longer produces are removed, and newly appearing ones are
+/// appended (with default formatting) to their node or document.
+///
+/// One deliberate exception: a node whose name the serialized form doesn't
+/// produce *at all* is left alone rather than removed. The merge can't tell
+/// a field that vanished (an `Option` child set to `None`) from content that
+/// was never part of the type, and silently deleting the latter would
+/// destroy user content; callers that need the former removed do it through
+/// the kdl APIs.
+pub fn update_document<'facet, T: Facet<'facet>>(
+    document: &mut kdl::KdlDocument,
+    value: &T,
+) -> Result<(), KdlError> {
+    update_document_with_options(document, value, &SerializeOptions::default())
+}
+
+/// Like [`update_document`], with explicit [`SerializeOptions`].
+pub fn update_document_with_options<'facet, T: Facet<'facet>>(
+    document: &mut kdl::KdlDocument,
+    value: &T,
+    options: &SerializeOptions,
+) -> Result<(), KdlError> {
+    let desired = to_document_with_options(value, options)?;
+    merge_document(document, &desired);
+    Ok(())
+}
+
+/// Merges the freshly serialized `desired` document into `target`, pairing
+/// nodes by name and occurrence index so repeated children keep their slots.
+fn merge_document(target: &mut kdl::KdlDocument, desired: &kdl::KdlDocument) {
+    let mut occurrences: Vec<(&str, usize)> = Vec::new();
+    for node in desired.nodes() {
+        let name = node.name().value();
+        let occurrence = match occurrences.iter_mut().find(|(seen, _)| *seen == name) {
+            Some((_, count)) => {
+                *count += 1;
+                *count
+            }
+            None => {
+                occurrences.push((name, 0));
+                0
+            }
+        };
+        let existing = target
+            .nodes_mut()
+            .iter_mut()
+            .filter(|node| node.name().value() == name)
+            .nth(occurrence);
+        match existing {
+            Some(existing) => merge_node(existing, node),
+            None => {
+                let mut added = node.clone();
+                added.autoformat();
+                target.nodes_mut().push(added);
+            }
+        }
+    }
+    // Trim extra occurrences of names the desired form still produces; see
+    // [`update_document`] for why wholly unknown names survive.
+    let mut seen: Vec<(String, usize)> = Vec::new();
+    target.nodes_mut().retain(|node| {
+        let name = node.name().value();
+        let Some((_, allowed)) = occurrences.iter().find(|(seen, _)| *seen == name) else {
+            return true;
+        };
+        let count = match seen.iter_mut().find(|(counted, _)| counted == name) {
+            Some((_, count)) => {
+                *count += 1;
+                *count
+            }
+            None => {
+                seen.push((name.to_string(), 0));
+                0
+            }
+        };
+        count <= *allowed
+    });
+}
+
+/// Merges one serialized node into its existing counterpart.
+fn merge_node(target: &mut kdl::KdlNode, desired: &kdl::KdlNode) {
+    let desired_args = desired.entries().iter().filter(|entry| entry.name().is_none());
+    let mut arg_count = 0;
+    for (index, entry) in desired_args.enumerate() {
+        arg_count = index + 1;
+        let existing = target
+            .entries_mut()
+            .iter_mut()
+            .filter(|entry| entry.name().is_none())
+            .nth(index);
+        match existing {
+            Some(existing) => rewrite_entry_value(existing, entry),
+            None => target.entries_mut().push(entry.clone()),
+        }
+    }
+    for entry in desired.entries().iter().filter(|entry| entry.name().is_some()) {
+        let name = entry.name().expect("filtered to properties").value();
+        let existing = target
+            .entries_mut()
+            .iter_mut()
+            .find(|entry| entry.name().is_some_and(|seen| seen.value() == name));
+        match existing {
+            Some(existing) => rewrite_entry_value(existing, entry),
+            None => target.entries_mut().push(entry.clone()),
+        }
+    }
+    // Drop arguments past the new count and properties the new value no
+    // longer carries.
+    let mut args_seen = 0;
+    target.entries_mut().retain(|entry| match entry.name() {
+        None => {
+            args_seen += 1;
+            args_seen <= arg_count
+        }
+        Some(name) => desired
+            .entries()
+            .iter()
+            .any(|entry| entry.name().is_some_and(|seen| seen.value() == name.value())),
+    });
+    if let Some(desired_children) = desired.children() {
+        if target.children().is_none() {
+            // A brand-new block gets default formatting; merging into an
+            // unformatted node would jam the brace against the last entry.
+            target.set_children(desired_children.clone());
+            target.autoformat();
+        } else if let Some(children) = target.children_mut().as_mut() {
+            merge_document(children, desired_children);
+        }
+    }
+}
+
+/// Rewrites `target`'s value in place when it differs, keeping the entry's
+/// own spacing but replacing the stale value text.
+fn rewrite_entry_value(target: &mut kdl::KdlEntry, desired: &kdl::KdlEntry) {
+    if target.value() == desired.value() && target.ty().map(kdl::KdlIdentifier::value) == desired.ty().map(kdl::KdlIdentifier::value) {
+        return;
+    }
+    target.set_value(desired.value().clone());
+    if let Some(ty) = desired.ty() {
+        target.set_ty(ty.clone());
+    }
+    if let Some(format) = target.format_mut() {
+        format.value_repr = desired
+            .format()
+            .map(|format| format.value_repr.clone())
+            .unwrap_or_else(|| desired.value().to_string());
+    }
+}
+
+/// Serializes `value` as a KDL document string.
+pub fn to_string<'facet, T: Facet<'facet>>(value: &T) -> Result<String, KdlError> {
+    to_string_with_options(value, &SerializeOptions::default())
+}
+
+/// Like [`to_string`], with explicit [`SerializeOptions`].
+pub fn to_string_with_options<'facet, T: Facet<'facet>>(
+    value: &T,
+    options: &SerializeOptions,
+) -> Result<String, KdlError> {
+    let mut buffer = Vec::new();
+    to_writer_with_options(&mut buffer, value, options)?;
+    buffer_to_string(buffer)
+}
+
+/// Serializes `value` as a single-line KDL snippet.
+///
+/// Nodes are terminated with `;` and children blocks stay inline, which is
+/// the form to use when embedding KDL in CLI flags or log lines. The
+/// deserializer accepts this form like any other KDL.
+pub fn to_string_compact<'facet, T: Facet<'facet>>(value: &T) -> Result<String, KdlError> {
+    let mut buffer = Vec::new();
+    to_writer_styled(&mut buffer, value, Style::Compact, &SerializeOptions::default())?;
+    let text = buffer_to_string(buffer)?;
+    Ok(text.trim_end().to_string())
+}
+
+/// Serializes `value` as a KDL document into a writer.
+pub fn to_writer<'facet, W: std::io::Write, T: Facet<'facet>>(
+    writer: &mut W,
+    value: &T,
+) -> Result<(), KdlError> {
+    to_writer_with_options(writer, value, &SerializeOptions::default())
+}
+
+/// Like [`to_writer`], with explicit [`SerializeOptions`].
+pub fn to_writer_with_options<'facet, W: std::io::Write, T: Facet<'facet>>(
+    writer: &mut W,
+    value: &T,
+    options: &SerializeOptions,
+) -> Result<(), KdlError> {
+    to_writer_styled(writer, value, Style::Block, options)
+}
+
+/// Recovers the rendered text from the write buffer.
+///
+/// The renderer only ever writes UTF-8, so the conversion can't fail today;
+/// it is still surfaced as an error rather than an `expect` so a bug in the
+/// rendering code reaches callers as a diagnostic, never a panic.
+fn buffer_to_string(buffer: Vec<u8>) -> Result<String, KdlError> {
+    String::from_utf8(buffer).map_err(|error| {
+        KdlError::detached(Kind::Encoding(format!(
+            "serializer produced invalid UTF-8: {error}"
+        )))
+    })
+}
+
+/// How the string writer lays out nodes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Style {
+    /// One node per line, children in indented `{ }` blocks.
+    Block,
+    /// Everything on one line, `;`-terminated nodes, inline `{ }` blocks.
+    Compact,
+}
+
+fn to_writer_styled<'facet, W: std::io::Write, T: Facet<'facet>>(
+    writer: &mut W,
+    value: &T,
+    style: Style,
+    options: &SerializeOptions,
+) -> Result<(), KdlError> {
+    let ir = crate::ir::build(value, options)?;
+    render_document(writer, &ir, 0, style, options)
+}
+
+/// Renders a run of nodes at the given depth.
+fn render_document<W: std::io::Write>(
+    writer: &mut W,
+    document: &IrDocument,
+    depth: usize,
+    style: Style,
+    options: &SerializeOptions,
+) -> Result<(), KdlError> {
+    for node in &document.nodes {
+        render_node(writer, node, depth, style, options)?;
+    }
+    Ok(())
+}
+
+/// Renders one node: annotation, name, entries, and children block.
+fn render_node<W: std::io::Write>(
+    writer: &mut W,
+    node: &IrNode,
+    depth: usize,
+    style: Style,
+    options: &SerializeOptions,
+) -> Result<(), KdlError> {
+    indent(writer, depth, style, options)?;
+    if let Some(annotation) = &node.annotation {
+        write!(writer, "({})", escape_identifier(annotation)).map_err(io_error)?;
+    }
+    write!(writer, "{}", escape_identifier(&node.name)).map_err(io_error)?;
+    for entry in &node.entries {
+        write!(writer, " ").map_err(io_error)?;
+        if let Some(name) = &entry.name {
+            write!(writer, "{}=", escape_identifier(name)).map_err(io_error)?;
+        }
+        render_value(writer, entry, options)?;
+    }
+    let children = node
+        .children
+        .as_ref()
+        .filter(|children| !children.nodes.is_empty() || node.keeps_empty_children(options));
+    let Some(children) = children else {
+        return terminate_node(writer, style, options);
+    };
+    match style {
+        Style::Block => match options.brace_style {
+            BraceStyle::SameLine => writeln!(writer, " {{").map_err(io_error)?,
+            BraceStyle::NextLine => {
+                // A bare newline would terminate the node, so the `\` line
+                // continuation keeps it open until the brace.
+                writeln!(writer, " \\").map_err(io_error)?;
+                indent(writer, depth, style, options)?;
+                writeln!(writer, "{{").map_err(io_error)?;
+            }
+        },
+        Style::Compact => write!(writer, " {{ ").map_err(io_error)?,
+    }
+    render_document(writer, children, depth + 1, style, options)?;
+    indent(writer, depth, style, options)?;
+    write!(writer, "}}").map_err(io_error)?;
+    terminate_node(writer, style, options)
+}
+
+fn terminate_node<W: std::io::Write>(
+    writer: &mut W,
+    style: Style,
+    options: &SerializeOptions,
+) -> Result<(), KdlError> {
+    match style {
+        Style::Block => match options.node_separator {
+            NodeSeparator::Newline => writeln!(writer).map_err(io_error),
+            NodeSeparator::Semicolon => writeln!(writer, ";").map_err(io_error),
+        },
+        Style::Compact => write!(writer, "; ").map_err(io_error),
+    }
+}
+
+/// Renders an entry's value, preferring the exact `repr` when the build
+/// recorded one (radix integers, finite `f32`s).
+fn render_value<W: std::io::Write>(
+    writer: &mut W,
+    entry: &IrEntry,
+    options: &SerializeOptions,
+) -> Result<(), KdlError> {
+    write!(writer, "{}", value_text(entry, options)).map_err(io_error)
+}
+
+/// The canonical text for an entry's value: always-quoted strings, `repr`
+/// literals verbatim, keyword sigils per the dialect.
+///
+/// Also stamped onto [`kdl::KdlDocument`]s built for kdl-rs formatting,
+/// which would otherwise re-decide the value syntax itself — plain
+/// identifier strings come out bare there.
+pub(crate) fn value_text(entry: &IrEntry, options: &SerializeOptions) -> String {
+    if let Some(repr) = &entry.repr {
+        return repr.clone();
+    }
+    let sigil = match options.dialect {
+        KdlVersion::V1 => "",
+        KdlVersion::V2 => "#",
+    };
+    match &entry.value {
+        KdlValue::String(text) => escape_string(text),
+        KdlValue::Bool(boolean) => format!("{sigil}{boolean}"),
+        KdlValue::Integer(integer) => integer.to_string(),
+        KdlValue::Float(float) => format_float(*float),
+        KdlValue::Null => format!("{sigil}null"),
+    }
+}
+
+/// Renders an integer as a prefixed KDL literal in the given base — `0xFF`,
+/// `0o755`, `0b1010` — zero-padding the digits to `width` when asked
+/// (`0x00FF`). Bases other than 2, 8 and 16 have no literal syntax and fall
+/// back to decimal.
+pub(crate) fn format_radix(value: i128, radix: u32, width: Option<usize>) -> Option<String> {
+    let magnitude = value.unsigned_abs();
+    let (prefix, digits) = match radix {
+        2 => ("0b", format!("{magnitude:b}")),
+        8 => ("0o", format!("{magnitude:o}")),
+        16 => ("0x", format!("{magnitude:X}")),
+        _ => return None,
+    };
+    let sign = if value < 0 { "-" } else { "" };
+    let padded = match width {
+        Some(width) if width > digits.len() => {
+            format!("{}{digits}", "0".repeat(width - digits.len()))
+        }
+        _ => digits,
+    };
+    Some(format!("{sign}{prefix}{padded}"))
+}
+
+/// Renders a float as a KDL literal, using the keyword forms (`#inf`,
+/// `#-inf`, `#nan`) for the values the decimal syntax can't express.
+fn format_float(float: f64) -> String {
+    if float.is_nan() {
+        "#nan".to_string()
+    } else if float == f64::INFINITY {
+        "#inf".to_string()
+    } else if float == f64::NEG_INFINITY {
+        "#-inf".to_string()
+    } else {
+        format!("{float:?}")
+    }
+}
+
+fn indent<W: std::io::Write>(
+    writer: &mut W,
+    depth: usize,
+    style: Style,
+    options: &SerializeOptions,
+) -> Result<(), KdlError> {
+    if style == Style::Compact {
+        return Ok(());
+    }
+    for _ in 0..depth {
+        write!(writer, "{}", options.indent).map_err(io_error)?;
+    }
+    Ok(())
+}
+
+/// Quotes an identifier if it isn't a bare KDL identifier.
+fn escape_identifier(name: &str) -> String {
+    let bare = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        && !name.starts_with(|c: char| c.is_ascii_digit());
+    if bare {
+        name.to_string()
+    } else {
+        escape_string(name)
+    }
+}
+
+/// Renders a string as a quoted KDL string literal.
+fn escape_string(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len() + 2);
+    escaped.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+fn io_error(error: std::io::Error) -> KdlError {
+    KdlError::detached(Kind::Io(error))
+}
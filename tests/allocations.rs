@@ -0,0 +1,80 @@
+#![cfg(feature = "de")]
+
+//! Allocation accounting for the happy path.
+//!
+//! Field paths, node paths, and solver summaries exist for origin
+//! recording, progress callbacks, and traces; a plain `from_str` should not
+//! pay for them. The single test compares allocation counts directly, so it
+//! keeps holding as dependencies shift the absolute numbers around.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use facet::Facet;
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn allocations_during(run: impl Fn()) -> usize {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    run();
+    ALLOCATIONS.load(Ordering::Relaxed) - before
+}
+
+#[derive(Debug, Facet)]
+struct Config {
+    #[facet(children)]
+    servers: Vec<Server>,
+}
+
+#[derive(Debug, Facet)]
+struct Server {
+    #[facet(argument)]
+    name: String,
+    #[facet(property)]
+    port: u16,
+}
+
+// One test only: a second test in this binary would run concurrently and
+// bleed its allocations into the counts.
+#[test]
+fn plain_deserialization_skips_bookkeeping_allocations() {
+    let kdl = "server \"alpha\" port=1\nserver \"beta\" port=2\nserver \"gamma\" port=3\n";
+    let run_plain = || {
+        let config: Config = facet_kdl::from_str(kdl).unwrap();
+        assert_eq!(config.servers.len(), 3);
+    };
+    let options = facet_kdl::DeserializeOptions::default();
+    let run_with_origins = || {
+        let (config, origins): (Config, _) =
+            facet_kdl::from_str_with_origins(kdl, &options).unwrap();
+        assert_eq!(config.servers.len(), 3);
+        assert!(origins.origin("servers[0].name").is_some());
+    };
+    // Warm both paths once so lazily initialized state doesn't skew the
+    // counts.
+    run_plain();
+    run_with_origins();
+    let plain = allocations_during(run_plain);
+    let with_origins = allocations_during(run_with_origins);
+    assert!(
+        plain < with_origins,
+        "plain run made {plain} allocations, origin-tracking run {with_origins}; \
+         the plain path should skip path bookkeeping"
+    );
+}
@@ -0,0 +1,52 @@
+#![cfg(all(feature = "tokio", feature = "ser", feature = "de"))]
+
+use facet::Facet;
+
+#[derive(Debug, Facet, PartialEq)]
+struct Config {
+    #[facet(child)]
+    server: Server,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct Server {
+    #[facet(argument)]
+    name: String,
+    #[facet(property)]
+    port: u16,
+}
+
+fn sample() -> Config {
+    Config {
+        server: Server {
+            name: "main".to_string(),
+            port: 8080,
+        },
+    }
+}
+
+#[tokio::test]
+async fn from_async_reader_deserializes_buffered_input() {
+    let mut input: &[u8] = b"server \"main\" port=8080";
+    let config: Config = facet_kdl::from_async_reader(&mut input).await.unwrap();
+    assert_eq!(config, sample());
+}
+
+#[tokio::test]
+async fn from_async_reader_strips_a_bom() {
+    let mut bytes = vec![0xEF, 0xBB, 0xBF];
+    bytes.extend_from_slice(b"server \"main\" port=8080");
+    let mut input: &[u8] = &bytes;
+    let config: Config = facet_kdl::from_async_reader(&mut input).await.unwrap();
+    assert_eq!(config.server.port, 8080);
+}
+
+#[tokio::test]
+async fn to_async_writer_round_trips() {
+    let mut buffer = Vec::new();
+    facet_kdl::to_async_writer(&mut buffer, &sample()).await.unwrap();
+    let text = String::from_utf8(buffer).unwrap();
+    assert_eq!(text, "server \"main\" port=8080\n");
+    let back: Config = facet_kdl::from_str(&text).unwrap();
+    assert_eq!(back, sample());
+}
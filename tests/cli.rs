@@ -0,0 +1,51 @@
+use facet::Facet;
+use facet_kdl::cli::{command_spec, ValueKind};
+
+#[derive(Debug, Facet)]
+struct Config {
+    #[facet(child)]
+    server: Server,
+    #[facet(children)]
+    plugins: Vec<Plugin>,
+}
+
+#[derive(Debug, Facet)]
+struct Server {
+    #[facet(argument)]
+    name: String,
+    /// The TCP port to listen on.
+    #[facet(property)]
+    port: u16,
+    #[facet(property)]
+    verbose: Option<bool>,
+}
+
+#[derive(Debug, Facet)]
+struct Plugin {
+    #[facet(argument)]
+    path: String,
+}
+
+#[test]
+fn children_become_subcommands() {
+    let spec = command_spec::<Config>("mytool").unwrap();
+    assert_eq!(spec.name, "mytool");
+    assert_eq!(spec.subcommands.len(), 2);
+    assert_eq!(spec.subcommands[0].name, "server");
+    assert!(!spec.subcommands[0].repeated);
+    assert_eq!(spec.subcommands[1].name, "plugin");
+    assert!(spec.subcommands[1].repeated);
+}
+
+#[test]
+fn properties_become_flags() {
+    let spec = command_spec::<Config>("mytool").unwrap();
+    let server = &spec.subcommands[0];
+    assert_eq!(server.flags.len(), 2);
+    assert_eq!(server.flags[0].name, "port");
+    assert_eq!(server.flags[0].value_kind, ValueKind::Integer);
+    assert!(server.flags[0].required);
+    assert!(!server.flags[1].required);
+    assert_eq!(server.positionals.len(), 1);
+    assert_eq!(server.positionals[0].name, "name");
+}
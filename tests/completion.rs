@@ -0,0 +1,93 @@
+#![cfg(feature = "ser")]
+
+use facet::Facet;
+use facet_kdl::completion::completion_model;
+
+#[derive(Debug, Facet)]
+struct Config {
+    #[facet(child)]
+    server: Server,
+    #[facet(children)]
+    plugins: Vec<Plugin>,
+}
+
+#[derive(Debug, Facet)]
+struct Server {
+    #[facet(property)]
+    port: u16,
+    #[facet(property)]
+    level: Option<Level>,
+    #[facet(child)]
+    tls: Tls,
+}
+
+#[derive(Debug, Facet)]
+#[repr(u8)]
+#[allow(dead_code)]
+enum Level {
+    Debug,
+    Info,
+    Warn,
+}
+
+#[derive(Debug, Facet)]
+struct Tls {
+    #[facet(property)]
+    cert: String,
+}
+
+#[derive(Debug, Facet)]
+struct Plugin {
+    #[facet(argument)]
+    path: String,
+}
+
+#[test]
+fn root_scope_lists_top_level_nodes() {
+    let model = completion_model::<Config>();
+    let root = &model.scopes[0];
+    assert!(root.path.is_empty());
+    let names: Vec<_> = root.nodes.iter().map(|node| node.name.as_str()).collect();
+    assert_eq!(names, ["server", "plugin"]);
+    assert!(!root.nodes[0].repeated);
+    assert!(root.nodes[1].repeated);
+}
+
+#[test]
+fn nested_scopes_follow_the_node_path() {
+    let model = completion_model::<Config>();
+    let server = model
+        .scopes
+        .iter()
+        .find(|scope| scope.path == ["server"])
+        .unwrap();
+    assert_eq!(server.nodes[0].name, "tls");
+    assert_eq!(server.nodes[0].properties[0].name, "cert");
+}
+
+#[test]
+fn enum_properties_list_allowed_values() {
+    let model = completion_model::<Config>();
+    let server = &model.scopes[0].nodes[0];
+    let level = server
+        .properties
+        .iter()
+        .find(|property| property.name == "level")
+        .unwrap();
+    assert!(!level.required);
+    assert_eq!(level.values, ["Debug", "Info", "Warn"]);
+    let port = server
+        .properties
+        .iter()
+        .find(|property| property.name == "port")
+        .unwrap();
+    assert_eq!(port.value_type, "integer");
+    assert!(port.required);
+}
+
+#[test]
+fn model_itself_serializes_to_kdl() {
+    let model = completion_model::<Config>();
+    let text = facet_kdl::to_string(&model).unwrap();
+    assert!(text.contains("scopecompletions"));
+}
@@ -0,0 +1,2750 @@
+// Flatten-enum documents route through the solver, and several tests pin
+// its errors and policies, so the whole target wants both features.
+#![cfg(all(feature = "de", feature = "solver"))]
+
+use facet::Facet;
+use facet_kdl::Spanned;
+
+#[derive(Clone, Debug, Facet, PartialEq)]
+struct Config {
+    #[facet(child)]
+    server: Server,
+    #[facet(children)]
+    plugins: Vec<Plugin>,
+}
+
+#[derive(Clone, Debug, Facet, PartialEq)]
+struct Server {
+    #[facet(argument)]
+    name: String,
+    #[facet(property)]
+    port: u16,
+    #[facet(property)]
+    verbose: Option<bool>,
+}
+
+#[derive(Clone, Debug, Facet, PartialEq)]
+struct Plugin {
+    #[facet(argument)]
+    path: String,
+}
+
+#[test]
+fn basic_document() {
+    let config: Config = facet_kdl::from_str(
+        r#"
+server "main" port=8080
+plugin "/usr/lib/a.so"
+plugin "/usr/lib/b.so"
+"#,
+    )
+    .unwrap();
+    assert_eq!(config.server.name, "main");
+    assert_eq!(config.server.port, 8080);
+    assert_eq!(config.server.verbose, None);
+    assert_eq!(config.plugins.len(), 2);
+    assert_eq!(config.plugins[1].path, "/usr/lib/b.so");
+}
+
+#[test]
+fn optional_property() {
+    let config: Config = facet_kdl::from_str(r#"server "main" port=1 verbose=#true"#).unwrap();
+    assert_eq!(config.server.verbose, Some(true));
+}
+
+#[test]
+fn unknown_property_errors() {
+    let error = facet_kdl::from_str::<Config>(r#"server "main" port=1 bogus=2"#).unwrap_err();
+    let message = error.to_string();
+    assert!(message.contains("bogus"), "unexpected message: {message}");
+    assert!(message.contains("port"), "unexpected message: {message}");
+}
+
+#[test]
+fn unknown_node_errors() {
+    let error = facet_kdl::from_str::<Config>("mystery-node").unwrap_err();
+    assert!(error.to_string().contains("mystery-node"));
+}
+
+#[test]
+fn parse_error_is_reported() {
+    let error = facet_kdl::from_str::<Config>("server \"unterminated").unwrap_err();
+    assert!(matches!(error.kind, facet_kdl::KdlErrorKind::Parse(_)));
+}
+
+#[test]
+fn collect_errors_reports_every_unknown() {
+    let errors = facet_kdl::from_str_collect_errors::<Config>(
+        r#"
+server "main" port=1 bogus=2 wrong=3
+mystery-node
+"#,
+    )
+    .unwrap_err();
+    assert_eq!(errors.errors.len(), 3);
+    let rendered: Vec<String> = errors.errors.iter().map(|e| e.to_string()).collect();
+    assert!(rendered[0].contains("bogus"));
+    assert!(rendered[1].contains("wrong"));
+    assert!(rendered[2].contains("mystery-node"));
+}
+
+#[test]
+fn parse_error_chains_its_source() {
+    use std::error::Error as _;
+    let error = facet_kdl::from_str::<Config>("server \"unterminated").unwrap_err();
+    let source = error.source().expect("parse errors carry a source");
+    // The top-level message stays short; the detail lives in the source.
+    assert!(!error.to_string().contains(&source.to_string()));
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct NumberDoc {
+    #[facet(child)]
+    numbers: Numbers,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct Numbers {
+    #[facet(property)]
+    timeout: Option<u32>,
+    #[facet(property)]
+    ratio: Option<f64>,
+}
+
+#[test]
+fn lossless_coercion_is_the_default() {
+    // Whole-number float into an integer field, integer into a float field.
+    let doc: NumberDoc = facet_kdl::from_str("numbers timeout=1.0 ratio=2").unwrap();
+    assert_eq!(doc.numbers.timeout, Some(1));
+    assert_eq!(doc.numbers.ratio, Some(2.0));
+}
+
+#[test]
+fn lossless_coercion_rejects_fractional_floats() {
+    let error = facet_kdl::from_str::<NumberDoc>("numbers timeout=1.5").unwrap_err();
+    assert!(matches!(
+        error.kind,
+        facet_kdl::KdlErrorKind::InvalidValueForShape { .. }
+    ));
+}
+
+#[test]
+fn strict_coercion_rejects_cross_kind_literals() {
+    let options = facet_kdl::DeserializeOptions {
+        number_coercion: facet_kdl::NumberCoercion::Strict,
+        ..Default::default()
+    };
+    assert!(facet_kdl::from_str_with_options::<NumberDoc>("numbers ratio=2", &options).is_err());
+    assert!(facet_kdl::from_str_with_options::<NumberDoc>("numbers timeout=1.0", &options).is_err());
+    let doc: NumberDoc =
+        facet_kdl::from_str_with_options("numbers timeout=1 ratio=2.0", &options).unwrap();
+    assert_eq!(doc.numbers.timeout, Some(1));
+    assert_eq!(doc.numbers.ratio, Some(2.0));
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct BigDoc {
+    #[facet(child)]
+    counter: Counter,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct Counter {
+    #[facet(property)]
+    max: u64,
+}
+
+#[test]
+fn u64_beyond_i64_parses_from_literal() {
+    let doc: BigDoc = facet_kdl::from_str("counter max=18446744073709551615").unwrap();
+    assert_eq!(doc.counter.max, u64::MAX);
+}
+
+#[test]
+fn u64_parses_from_string_fallback() {
+    let doc: BigDoc = facet_kdl::from_str(r#"counter max="18446744073709551615""#).unwrap();
+    assert_eq!(doc.counter.max, u64::MAX);
+}
+
+#[test]
+fn bad_numeric_string_reports_the_value() {
+    let error = facet_kdl::from_str::<BigDoc>(r#"counter max="not-a-number""#).unwrap_err();
+    match error.kind {
+        facet_kdl::KdlErrorKind::InvalidValueForShape { value, .. } => {
+            assert!(value.contains("not-a-number"));
+        }
+        other => panic!("unexpected error kind: {other:?}"),
+    }
+    assert!(error.span.is_some(), "error should carry the value's span");
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct SecretDoc {
+    #[facet(child)]
+    auth: Auth,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct Auth {
+    #[facet(property, sensitive)]
+    token: u64,
+}
+
+#[test]
+fn sensitive_values_are_redacted_in_errors() {
+    // A boolean can't fill a u64, so the value would normally be echoed.
+    let error = facet_kdl::from_str::<SecretDoc>("auth token=#true").unwrap_err();
+    match error.kind {
+        facet_kdl::KdlErrorKind::InvalidValueForShape { value, .. } => {
+            assert_eq!(value, "<redacted>");
+        }
+        other => panic!("unexpected error kind: {other:?}"),
+    }
+}
+
+#[test]
+fn out_of_range_integer_errors() {
+    let error = facet_kdl::from_str::<NumberDoc>("numbers timeout=4294967296").unwrap_err();
+    assert!(matches!(
+        error.kind,
+        facet_kdl::KdlErrorKind::InvalidValueForShape { .. }
+    ));
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct SpannedDoc {
+    #[facet(child)]
+    entry: SpannedEntry,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct SpannedEntry {
+    #[facet(property)]
+    name: Spanned<String>,
+}
+
+#[test]
+fn spanned_property_records_location() {
+    let kdl = r#"entry name="hello""#;
+    let doc: SpannedDoc = facet_kdl::from_str(kdl).unwrap();
+    assert_eq!(*doc.entry.name, "hello");
+    let span = doc.entry.name.span;
+    assert_eq!(&kdl[span.offset..span.end()], r#"name="hello""#);
+    assert_eq!(
+        doc.entry.name,
+        Spanned::new("hello".to_string(), facet_kdl::Span::new(6, 12))
+    );
+    assert_eq!(
+        doc.entry.name.map_value(|name| name.len()),
+        Spanned::new(5, facet_kdl::Span::new(6, 12))
+    );
+}
+
+// `const` contexts are part of the constructors' contract: expected values
+// for assertions can live in statics.
+const _: Spanned<u16> = Spanned::with_dummy_span(8080);
+const _: Spanned<u16> = Spanned::new(8080, facet_kdl::Span::new(3, 4));
+
+#[derive(Debug, Facet, PartialEq)]
+struct EnumDoc {
+    #[facet(children)]
+    outputs: Vec<Output>,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+#[repr(u8)]
+#[allow(dead_code)] // `Stdout` is only ever built through reflection
+enum Output {
+    Stdout {},
+    File {
+        #[facet(property)]
+        path: String,
+    },
+}
+
+#[test]
+fn enum_children_match_variant_names() {
+    let doc: EnumDoc = facet_kdl::from_str(
+        r#"
+Stdout
+File path="/tmp/log"
+"#,
+    )
+    .unwrap();
+    assert_eq!(doc.outputs.len(), 2);
+    assert_eq!(
+        doc.outputs[1],
+        Output::File {
+            path: "/tmp/log".to_string()
+        }
+    );
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct ArgsDoc {
+    #[facet(child)]
+    hosts: Hosts,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct Hosts {
+    #[facet(arguments)]
+    names: Vec<String>,
+}
+
+#[test]
+fn arguments_collect_remaining_positionals() {
+    let doc: ArgsDoc = facet_kdl::from_str(r#"hosts "a" "b" "c""#).unwrap();
+    assert_eq!(doc.hosts.names, vec!["a", "b", "c"]);
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct MixedArgsDoc {
+    #[facet(child)]
+    hosts: MixedHosts,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct MixedHosts {
+    #[facet(arguments)]
+    names: Vec<String>,
+    #[facet(property)]
+    region: Option<String>,
+}
+
+#[test]
+fn reopened_arguments_list_reports_both_spans() {
+    let kdl = r#"hosts "a" "b" region="eu" "c""#;
+    let error = facet_kdl::from_str::<MixedArgsDoc>(kdl).unwrap_err();
+    match error.kind {
+        facet_kdl::KdlErrorKind::ArgumentsReopened {
+            node,
+            first_arguments,
+            offending,
+        } => {
+            assert_eq!(node, "hosts");
+            let run = &kdl[first_arguments.offset()..first_arguments.offset() + first_arguments.len()];
+            assert!(run.contains("\"a\"") && run.contains("\"b\""), "run: {run}");
+            let late = &kdl[offending.offset()..offending.offset() + offending.len()];
+            assert!(late.contains("\"c\""), "offending: {late}");
+        }
+        other => panic!("unexpected error kind: {other:?}"),
+    }
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct RawDoc {
+    #[facet(child)]
+    script: Script,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct Script {
+    #[facet(property)]
+    lang: String,
+    #[facet(kdl(raw))]
+    raw: String,
+}
+
+#[test]
+fn raw_field_captures_node_source_text() {
+    let doc: RawDoc = facet_kdl::from_str(r#"script lang="lua" { print "hi"; }"#).unwrap();
+    assert_eq!(doc.script.lang, "lua");
+    assert_eq!(doc.script.raw, r#"script lang="lua" { print "hi"; }"#);
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct FlatDoc {
+    #[facet(child)]
+    server: FlatServer,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct FlatServer {
+    #[facet(property)]
+    port: u16,
+    #[facet(flatten)]
+    limits: Limits,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct Limits {
+    #[facet(property)]
+    max_connections: Option<u32>,
+}
+
+#[test]
+fn flattened_struct_properties() {
+    let doc: FlatDoc = facet_kdl::from_str("server port=80 max_connections=10").unwrap();
+    assert_eq!(doc.server.limits.max_connections, Some(10));
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct AliasDoc {
+    #[facet(child, kdl(alias = db))]
+    database: Database,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct Database {
+    #[facet(property)]
+    url: String,
+}
+
+#[test]
+fn alias_accepts_alternate_node_name() {
+    let doc: AliasDoc = facet_kdl::from_str(r#"db url="postgres://x""#).unwrap();
+    assert_eq!(doc.database.url, "postgres://x");
+    let doc: AliasDoc = facet_kdl::from_str(r#"database url="postgres://y""#).unwrap();
+    assert_eq!(doc.database.url, "postgres://y");
+}
+
+#[test]
+fn duplicate_child_nodes_report_both_spans() {
+    let error = facet_kdl::from_str::<AliasDoc>("database url=\"a\"\ndb url=\"b\"").unwrap_err();
+    assert!(matches!(
+        error.kind,
+        facet_kdl::KdlErrorKind::DuplicateNode {
+            field: "database",
+            ..
+        }
+    ));
+}
+
+#[test]
+fn duplicate_child_nodes_last_wins_when_configured() {
+    let options = facet_kdl::DeserializeOptions {
+        duplicate_children: facet_kdl::DuplicateNodePolicy::LastWins,
+        ..Default::default()
+    };
+    let doc: AliasDoc =
+        facet_kdl::from_str_with_options("database url=\"a\"\ndb url=\"b\"", &options).unwrap();
+    assert_eq!(doc.database.url, "b");
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct EnvDoc {
+    #[facet(child, kdl(singleton))]
+    env: std::collections::HashMap<String, String>,
+}
+
+#[test]
+fn singleton_child_nodes_merge_their_properties() {
+    let doc: EnvDoc =
+        facet_kdl::from_str("env FOO=\"1\"\nenv BAR=\"2\" BAZ=\"3\"").unwrap();
+    assert_eq!(doc.env.len(), 3);
+    assert_eq!(doc.env["FOO"], "1");
+    assert_eq!(doc.env["BAR"], "2");
+    assert_eq!(doc.env["BAZ"], "3");
+}
+
+#[test]
+fn singleton_merge_conflicts_error_by_default() {
+    let error =
+        facet_kdl::from_str::<EnvDoc>("env FOO=\"1\"\nenv FOO=\"2\"").unwrap_err();
+    assert!(matches!(
+        error.kind,
+        facet_kdl::KdlErrorKind::SingletonConflict {
+            field: "env",
+            ref key,
+            ..
+        } if key == "FOO"
+    ));
+}
+
+#[test]
+fn singleton_merge_conflicts_last_wins_when_configured() {
+    let options = facet_kdl::DeserializeOptions {
+        singleton_conflicts: facet_kdl::DuplicateNodePolicy::LastWins,
+        ..Default::default()
+    };
+    let doc: EnvDoc =
+        facet_kdl::from_str_with_options("env FOO=\"1\"\nenv FOO=\"2\"", &options).unwrap();
+    assert_eq!(doc.env["FOO"], "2");
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct BudgetDoc {
+    #[facet(child)]
+    budget: Budget,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct Budget {
+    #[facet(property, kdl(node_or_property))]
+    timeout: u64,
+    #[facet(property, kdl(node_or_property))]
+    retries: Option<u32>,
+}
+
+#[test]
+fn node_or_property_accepts_the_property_spelling() {
+    let doc: BudgetDoc = facet_kdl::from_str("budget timeout=30 retries=3").unwrap();
+    assert_eq!(doc.budget.timeout, 30);
+    assert_eq!(doc.budget.retries, Some(3));
+}
+
+#[test]
+fn node_or_property_accepts_the_node_spelling() {
+    let doc: BudgetDoc = facet_kdl::from_str("budget {\n    timeout 30\n}").unwrap();
+    assert_eq!(doc.budget.timeout, 30);
+    assert_eq!(doc.budget.retries, None);
+}
+
+#[test]
+fn node_or_property_rejects_both_spellings_at_once() {
+    let error =
+        facet_kdl::from_str::<BudgetDoc>("budget timeout=30 {\n    timeout 60\n}").unwrap_err();
+    assert!(matches!(
+        error.kind,
+        facet_kdl::KdlErrorKind::DuplicateNode {
+            field: "timeout",
+            ..
+        }
+    ));
+}
+
+#[test]
+fn node_or_property_node_takes_exactly_one_argument() {
+    let error = facet_kdl::from_str::<BudgetDoc>("budget {\n    timeout 30 60\n}").unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("exactly one positional argument"));
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct MeterDoc {
+    #[facet(child)]
+    meter: Meter,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct Meter {
+    #[facet(property, kdl(validate_with = percentage))]
+    level: u8,
+}
+
+fn percentage(value: &kdl::KdlValue, _annotation: Option<&str>) -> Result<(), String> {
+    match value.as_integer() {
+        Some(0..=100) => Ok(()),
+        _ => Err("expected a percentage in 0..=100".to_string()),
+    }
+}
+
+fn meter_options() -> facet_kdl::DeserializeOptions {
+    facet_kdl::DeserializeOptions {
+        validators: vec![("percentage", percentage)],
+        ..Default::default()
+    }
+}
+
+#[test]
+fn validator_accepts_in_range_values() {
+    let doc: MeterDoc =
+        facet_kdl::from_str_with_options("meter level=85", &meter_options()).unwrap();
+    assert_eq!(doc.meter.level, 85);
+}
+
+#[test]
+fn validator_rejects_out_of_range_values() {
+    let error =
+        facet_kdl::from_str_with_options::<MeterDoc>("meter level=150", &meter_options())
+            .unwrap_err();
+    assert!(matches!(
+        error.kind,
+        facet_kdl::KdlErrorKind::ValidationFailed { field: "level", .. }
+    ));
+    assert!(error.span.is_some());
+}
+
+#[test]
+fn unregistered_validator_is_a_schema_error() {
+    let error = facet_kdl::from_str::<MeterDoc>("meter level=85").unwrap_err();
+    assert!(matches!(
+        error.kind,
+        facet_kdl::KdlErrorKind::SchemaError(_)
+    ));
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct RulesDoc {
+    #[facet(children)]
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct Rule {
+    #[facet(property)]
+    priority: u8,
+    #[facet(flatten)]
+    action: Action,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+#[repr(u8)]
+enum Action {
+    Allow {
+        #[facet(property)]
+        scope: String,
+    },
+    Deny {
+        #[facet(property)]
+        reason: String,
+    },
+}
+
+#[test]
+fn flattened_enum_is_solved_per_list_element() {
+    let doc: RulesDoc = facet_kdl::from_str(
+        "rule priority=1 scope=\"admin\"\nrule priority=2 reason=\"banned\"",
+    )
+    .unwrap();
+    assert_eq!(doc.rules.len(), 2);
+    assert_eq!(
+        doc.rules[0].action,
+        Action::Allow {
+            scope: "admin".to_string()
+        }
+    );
+    assert_eq!(
+        doc.rules[1].action,
+        Action::Deny {
+            reason: "banned".to_string()
+        }
+    );
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct NestedRulesDoc {
+    #[facet(children)]
+    rules: Vec<NestedRule>,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct NestedRule {
+    #[facet(property)]
+    priority: u8,
+    // The flattened enum sits one flatten level down; the element must still
+    // get the solver treatment.
+    #[facet(flatten)]
+    common: RuleCommon,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct RuleCommon {
+    #[facet(flatten)]
+    action: Action,
+}
+
+#[test]
+fn ambiguous_node_reports_typed_candidates() {
+    // Both variants require their distinguishing property, so a node with
+    // neither matches no candidate — and each rejected candidate reports
+    // what it was missing.
+    let error = facet_kdl::from_str::<RulesDoc>("rule priority=1").unwrap_err();
+    let facet_kdl::KdlErrorKind::Solver(facet_kdl::SolverError::NoMatch { candidates }) =
+        error.kind
+    else {
+        panic!("expected a solver NoMatch error");
+    };
+    assert_eq!(candidates.len(), 2);
+    assert_eq!(candidates[0].selections[0].path, ["action"]);
+    assert_eq!(candidates[0].selections[0].variant, "Allow");
+    assert_eq!(candidates[0].missing, ["scope"]);
+    assert_eq!(candidates[1].selections[0].variant, "Deny");
+    assert_eq!(candidates[1].missing, ["reason"]);
+}
+
+#[test]
+fn resolution_cap_reports_the_multiplying_fields() {
+    let options = facet_kdl::DeserializeOptions {
+        max_resolutions: Some(1),
+        ..Default::default()
+    };
+    let error =
+        facet_kdl::from_str_with_options::<RulesDoc>("rule priority=1 scope=\"admin\"", &options)
+            .unwrap_err();
+    let facet_kdl::KdlErrorKind::Solver(facet_kdl::SolverError::TooManyResolutions {
+        total,
+        limit,
+        multipliers,
+    }) = error.kind
+    else {
+        panic!("expected a solver TooManyResolutions error");
+    };
+    assert_eq!(total, 2);
+    assert_eq!(limit, 1);
+    assert_eq!(multipliers, [("action".to_string(), 2)]);
+}
+
+#[test]
+fn default_resolution_cap_leaves_small_schemas_alone() {
+    let doc: RulesDoc = facet_kdl::from_str("rule priority=1 scope=\"admin\"").unwrap();
+    assert_eq!(doc.rules.len(), 1);
+}
+
+#[test]
+fn scalar_children_elements_error_before_any_node_routes() {
+    #[derive(Debug, Facet, PartialEq)]
+    struct Doc {
+        #[facet(children)]
+        ports: Vec<u16>,
+    }
+
+    let error = facet_kdl::from_str::<Doc>("port 8080").unwrap_err();
+    let facet_kdl::KdlErrorKind::SchemaError(message) = &error.kind else {
+        panic!("expected a schema error, got {:?}", error.kind);
+    };
+    assert!(message.contains("u16"), "unexpected message: {message}");
+    assert!(
+        message.contains("structs or enums"),
+        "unexpected message: {message}"
+    );
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct AmountsDoc {
+    #[facet(children)]
+    amounts: Vec<Amount>,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct Amount {
+    #[facet(flatten)]
+    kind: AmountKind,
+}
+
+// Both variants accept `amount value=3`: `Approximate` through lossless
+// integer-to-float coercion, `Exact` exactly. `Approximate` is declared
+// first so the two tie-break policies pick different winners.
+#[derive(Debug, Facet, PartialEq)]
+#[repr(u8)]
+enum AmountKind {
+    Approximate {
+        #[facet(property)]
+        value: f64,
+    },
+    Exact {
+        #[facet(property)]
+        value: i64,
+    },
+}
+
+#[test]
+fn ties_are_an_error_by_default() {
+    let error = facet_kdl::from_str::<AmountsDoc>("amount value=3").unwrap_err();
+    assert!(matches!(
+        error.kind,
+        facet_kdl::KdlErrorKind::Solver(facet_kdl::SolverError::Ambiguous { .. })
+    ));
+    // The top-level message stays short; the guidance lives in the source.
+    use std::error::Error as _;
+    let source = error.source().expect("solver errors carry a source");
+    assert!(source.to_string().contains("tie_break"));
+}
+
+#[test]
+fn first_declared_tie_break_follows_declaration_order() {
+    let options = facet_kdl::DeserializeOptions {
+        tie_break: facet_kdl::TieBreakPolicy::FirstDeclared,
+        ..Default::default()
+    };
+    let doc: AmountsDoc = facet_kdl::from_str_with_options("amount value=3", &options).unwrap();
+    assert_eq!(doc.amounts[0].kind, AmountKind::Approximate { value: 3.0 });
+}
+
+#[test]
+fn tightest_type_tie_break_prefers_the_uncoerced_slot() {
+    let options = facet_kdl::DeserializeOptions {
+        tie_break: facet_kdl::TieBreakPolicy::TightestType,
+        ..Default::default()
+    };
+    let doc: AmountsDoc = facet_kdl::from_str_with_options("amount value=3", &options).unwrap();
+    assert_eq!(doc.amounts[0].kind, AmountKind::Exact { value: 3 });
+    // A fractional literal doesn't even reach the tie: only `Approximate`
+    // accepts it in the first place.
+    let doc: AmountsDoc = facet_kdl::from_str_with_options("amount value=2.5", &options).unwrap();
+    assert_eq!(doc.amounts[0].kind, AmountKind::Approximate { value: 2.5 });
+}
+
+#[test]
+fn flattened_enum_behind_flattened_struct_is_solved() {
+    let doc: NestedRulesDoc =
+        facet_kdl::from_str("nestedrule priority=1 reason=\"quota\"").unwrap();
+    assert_eq!(
+        doc.rules[0].common.action,
+        Action::Deny {
+            reason: "quota".to_string()
+        }
+    );
+}
+
+#[test]
+fn empty_children_block_parses_like_no_block() {
+    #[derive(Debug, Facet, PartialEq)]
+    struct Doc {
+        #[facet(child)]
+        node: Node,
+    }
+
+    #[derive(Debug, Facet, PartialEq)]
+    struct Node {
+        #[facet(property)]
+        id: u32,
+        #[facet(child)]
+        extra: Option<Extra>,
+    }
+
+    #[derive(Debug, Facet, PartialEq)]
+    struct Extra {
+        #[facet(property)]
+        tag: String,
+    }
+
+    let plain: Doc = facet_kdl::from_str("node id=1").unwrap();
+    let braced: Doc = facet_kdl::from_str("node id=1 { }").unwrap();
+    assert_eq!(plain, braced);
+    assert_eq!(plain.node.extra, None);
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct StatsDoc {
+    #[facet(child)]
+    stats: Stats,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct Stats {
+    #[facet(property)]
+    ratio: f64,
+    #[facet(property)]
+    low: Option<f32>,
+}
+
+#[test]
+fn keyword_floats_fill_float_fields() {
+    let doc: StatsDoc = facet_kdl::from_str("stats ratio=#inf low=#-inf").unwrap();
+    assert_eq!(doc.stats.ratio, f64::INFINITY);
+    assert_eq!(doc.stats.low, Some(f32::NEG_INFINITY));
+    let doc: StatsDoc = facet_kdl::from_str("stats ratio=#nan").unwrap();
+    assert!(doc.stats.ratio.is_nan());
+}
+
+#[test]
+fn keyword_floats_are_rejected_for_integer_fields() {
+    #[derive(Debug, Facet, PartialEq)]
+    struct Doc {
+        #[facet(child)]
+        node: Node,
+    }
+
+    #[derive(Debug, Facet, PartialEq)]
+    struct Node {
+        #[facet(property)]
+        count: u32,
+    }
+
+    let error = facet_kdl::from_str::<Doc>("node count=#inf").unwrap_err();
+    match error.kind {
+        facet_kdl::KdlErrorKind::InvalidValueForShape { value, .. } => {
+            // The diagnostic echoes the keyword as written, not Rust's `inf`.
+            assert_eq!(value, "#inf");
+        }
+        other => panic!("expected InvalidValueForShape, got {other:?}"),
+    }
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct Pipeline {
+    #[facet(children)]
+    steps: Vec<Step>,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+#[repr(u8)]
+enum Step {
+    Build {
+        #[facet(property)]
+        target: String,
+    },
+    Test {
+        #[facet(property)]
+        suite: String,
+    },
+}
+
+#[test]
+fn type_annotation_selects_the_variant_over_the_node_name() {
+    // The annotation wins even when the node name matches another variant.
+    let pipeline: Pipeline =
+        facet_kdl::from_str("(Build)Test target=\"app\"\nTest suite=\"unit\"").unwrap();
+    assert_eq!(
+        pipeline.steps,
+        vec![
+            Step::Build {
+                target: "app".to_string()
+            },
+            Step::Test {
+                suite: "unit".to_string()
+            },
+        ]
+    );
+}
+
+#[test]
+fn type_annotation_matches_arbitrary_node_names() {
+    let pipeline: Pipeline = facet_kdl::from_str("(Build)step target=\"app\"").unwrap();
+    assert_eq!(
+        pipeline.steps,
+        vec![Step::Build {
+            target: "app".to_string()
+        }]
+    );
+}
+
+#[test]
+fn unknown_type_annotation_is_an_error() {
+    let error = facet_kdl::from_str::<Pipeline>("(Deploy)step env=\"prod\"").unwrap_err();
+    assert!(matches!(
+        error.kind,
+        facet_kdl::KdlErrorKind::NoMatchingNode { .. }
+    ));
+}
+
+#[test]
+fn type_annotation_selects_child_field_enum_variants() {
+    #[derive(Debug, Facet, PartialEq)]
+    struct Doc {
+        #[facet(child)]
+        step: Step,
+    }
+
+    let doc: Doc = facet_kdl::from_str("(Test)step suite=\"unit\"").unwrap();
+    assert_eq!(
+        doc.step,
+        Step::Test {
+            suite: "unit".to_string()
+        }
+    );
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct PolicyDoc {
+    #[facet(children)]
+    policies: Vec<Policy>,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct Policy {
+    #[facet(property)]
+    priority: u8,
+    #[facet(flatten)]
+    effect: Effect,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+#[repr(u8)]
+enum Effect {
+    Allow {
+        #[facet(property)]
+        scope: String,
+    },
+    #[facet(kdl(deny_unknown_fields))]
+    Deny {
+        #[facet(property)]
+        reason: String,
+    },
+}
+
+fn lenient_options() -> facet_kdl::DeserializeOptions {
+    facet_kdl::DeserializeOptions {
+        allow_unknown_properties: true,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn unknown_properties_are_errors_by_default() {
+    let error = facet_kdl::from_str::<RulesDoc>("rule priority=1 scope=\"admin\" color=\"red\"")
+        .unwrap_err();
+    assert!(matches!(
+        error.kind,
+        facet_kdl::KdlErrorKind::Solver(facet_kdl::SolverError::NoMatch { .. })
+    ));
+}
+
+#[test]
+fn unknown_properties_can_be_allowed() {
+    let doc: RulesDoc = facet_kdl::from_str_with_options(
+        "rule priority=1 scope=\"admin\" color=\"red\"",
+        &lenient_options(),
+    )
+    .unwrap();
+    assert_eq!(
+        doc.rules[0].action,
+        Action::Allow {
+            scope: "admin".to_string()
+        }
+    );
+}
+
+#[test]
+fn lenient_mode_skips_unknown_properties_on_plain_structs() {
+    #[derive(Debug, Facet, PartialEq)]
+    struct Doc {
+        #[facet(child)]
+        server: Server,
+    }
+
+    #[derive(Debug, Facet, PartialEq)]
+    struct Server {
+        #[facet(property)]
+        port: u16,
+    }
+
+    let doc: Doc =
+        facet_kdl::from_str_with_options("server port=8080 legacy=#true", &lenient_options())
+            .unwrap();
+    assert_eq!(doc.server.port, 8080);
+}
+
+#[test]
+fn deny_marked_variants_stay_strict_in_lenient_mode() {
+    // The `Deny` variant carries `deny_unknown_fields`, so its resolution
+    // rejects the extra property even though unknowns are allowed globally.
+    let error = facet_kdl::from_str_with_options::<PolicyDoc>(
+        "policy priority=1 reason=\"quota\" color=\"red\"",
+        &lenient_options(),
+    )
+    .unwrap_err();
+    assert!(matches!(
+        error.kind,
+        facet_kdl::KdlErrorKind::Solver(facet_kdl::SolverError::NoMatch { .. })
+    ));
+
+    // Without the extra property the same variant still matches.
+    let doc: PolicyDoc = facet_kdl::from_str_with_options(
+        "policy priority=1 reason=\"quota\"",
+        &lenient_options(),
+    )
+    .unwrap();
+    assert_eq!(
+        doc.policies[0].effect,
+        Effect::Deny {
+            reason: "quota".to_string()
+        }
+    );
+}
+
+#[test]
+fn lenient_variants_of_the_same_enum_accept_unknowns() {
+    let doc: PolicyDoc = facet_kdl::from_str_with_options(
+        "policy priority=1 scope=\"admin\" color=\"red\"",
+        &lenient_options(),
+    )
+    .unwrap();
+    assert_eq!(
+        doc.policies[0].effect,
+        Effect::Allow {
+            scope: "admin".to_string()
+        }
+    );
+}
+
+#[test]
+fn unit_and_phantom_data_fields_are_auto_defaulted() {
+    // Type-state configs carry `()`/`PhantomData` markers for the compiler
+    // only; they need no attribute and never appear in the document.
+    #[derive(Debug, Facet, PartialEq)]
+    struct Doc {
+        #[facet(child)]
+        server: TypedServer,
+        _reserved: (),
+    }
+
+    #[derive(Debug, Facet, PartialEq)]
+    struct TypedServer {
+        #[facet(property)]
+        port: u16,
+        _state: core::marker::PhantomData<u8>,
+    }
+
+    let doc: Doc = facet_kdl::from_str("server port=8080").unwrap();
+    assert_eq!(doc.server.port, 8080);
+    assert_eq!(doc.server._state, core::marker::PhantomData);
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct ReleaseDoc {
+    #[facet(child)]
+    release: Release,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct Release {
+    #[facet(property, kdl(validate_with = tagged))]
+    version: String,
+}
+
+fn tagged(value: &kdl::KdlValue, annotation: Option<&str>) -> Result<(), String> {
+    let Some(text) = value.as_string() else {
+        return Err("expected a string".to_string());
+    };
+    match annotation {
+        Some("semver") if text.split('.').count() == 3 => Ok(()),
+        Some("semver") => Err("expected three dot-separated components".to_string()),
+        Some(other) => Err(format!("unknown annotation `{other}`")),
+        None => Ok(()),
+    }
+}
+
+fn release_options() -> facet_kdl::DeserializeOptions {
+    facet_kdl::DeserializeOptions {
+        validators: vec![("tagged", tagged)],
+        ..Default::default()
+    }
+}
+
+#[test]
+fn validators_receive_the_value_type_annotation() {
+    let doc: ReleaseDoc = facet_kdl::from_str_with_options(
+        "release version=(semver)\"1.2.3\"",
+        &release_options(),
+    )
+    .unwrap();
+    assert_eq!(doc.release.version, "1.2.3");
+
+    let error = facet_kdl::from_str_with_options::<ReleaseDoc>(
+        "release version=(semver)\"1.2\"",
+        &release_options(),
+    )
+    .unwrap_err();
+    assert!(matches!(
+        error.kind,
+        facet_kdl::KdlErrorKind::ValidationFailed { field: "version", .. }
+    ));
+}
+
+#[test]
+fn unannotated_values_pass_none_to_validators() {
+    let doc: ReleaseDoc =
+        facet_kdl::from_str_with_options("release version=\"nightly\"", &release_options())
+            .unwrap();
+    assert_eq!(doc.release.version, "nightly");
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct ServicesDoc {
+    #[facet(children)]
+    services: std::collections::HashMap<String, Service>,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+#[repr(u8)]
+enum Service {
+    Web {
+        #[facet(property)]
+        port: u16,
+    },
+    Worker {
+        #[facet(property)]
+        queue: String,
+    },
+}
+
+#[test]
+fn map_children_select_enum_variants_by_annotation() {
+    // The node name is the map key; the annotation picks the variant.
+    let doc: ServicesDoc =
+        facet_kdl::from_str("(Web)frontend port=80\n(Worker)mailer queue=\"outbound\"").unwrap();
+    assert_eq!(doc.services.len(), 2);
+    assert_eq!(doc.services["frontend"], Service::Web { port: 80 });
+    assert_eq!(
+        doc.services["mailer"],
+        Service::Worker {
+            queue: "outbound".to_string()
+        }
+    );
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct SpannedServicesDoc {
+    #[facet(children)]
+    services: std::collections::HashMap<Spanned<String>, Spanned<Service>>,
+}
+
+#[test]
+fn spanned_map_keys_and_values_record_locations() {
+    let kdl = "(Web)frontend port=80\n(Worker)mailer queue=\"outbound\"";
+    let doc: SpannedServicesDoc = facet_kdl::from_str(kdl).unwrap();
+    assert_eq!(doc.services.len(), 2);
+    let (key, value) = doc
+        .services
+        .iter()
+        .find(|(key, _)| ***key == *"mailer")
+        .unwrap();
+    assert_eq!(&kdl[key.span.offset..key.span.end()], "mailer");
+    assert_eq!(
+        **value,
+        Service::Worker {
+            queue: "outbound".to_string()
+        }
+    );
+    assert_eq!(
+        &kdl[value.span.offset..value.span.end()],
+        "(Worker)mailer queue=\"outbound\""
+    );
+}
+
+#[test]
+fn map_enum_values_require_an_annotation() {
+    let error = facet_kdl::from_str::<ServicesDoc>("frontend port=80").unwrap_err();
+    let message = error.to_string();
+    assert!(
+        message.contains("type annotation"),
+        "unexpected message: {message}"
+    );
+}
+
+#[test]
+fn map_enum_annotations_must_name_a_variant() {
+    let error = facet_kdl::from_str::<ServicesDoc>("(Database)frontend port=80").unwrap_err();
+    assert!(matches!(
+        error.kind,
+        facet_kdl::KdlErrorKind::NoMatchingNode { .. }
+    ));
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct CacheDoc {
+    #[facet(child)]
+    cache: Cache,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct Cache {
+    #[facet(property, default)]
+    size: u32,
+    #[facet(property)]
+    path: String,
+}
+
+#[test]
+fn null_on_non_option_fields_is_an_error_by_default() {
+    let error =
+        facet_kdl::from_str::<CacheDoc>("cache size=#null path=\"/tmp\"").unwrap_err();
+    assert!(matches!(
+        error.kind,
+        facet_kdl::KdlErrorKind::InvalidValueForShape { .. }
+    ));
+}
+
+#[test]
+fn null_can_mean_default_for_flagged_fields() {
+    let options = facet_kdl::DeserializeOptions {
+        null_policy: facet_kdl::NullPolicy::UseDefault,
+        ..Default::default()
+    };
+    let doc: CacheDoc =
+        facet_kdl::from_str_with_options("cache size=#null path=\"/tmp\"", &options).unwrap();
+    assert_eq!(doc.cache.size, 0);
+    assert_eq!(doc.cache.path, "/tmp");
+}
+
+#[test]
+fn null_still_errors_without_the_default_flag() {
+    let options = facet_kdl::DeserializeOptions {
+        null_policy: facet_kdl::NullPolicy::UseDefault,
+        ..Default::default()
+    };
+    let error =
+        facet_kdl::from_str_with_options::<CacheDoc>("cache size=1 path=#null", &options)
+            .unwrap_err();
+    assert!(matches!(
+        error.kind,
+        facet_kdl::KdlErrorKind::InvalidValueForShape { .. }
+    ));
+}
+
+#[test]
+fn parse_returns_the_raw_document() {
+    let document = facet_kdl::parse("server port=8080\nplugin \"/usr/lib/a.so\"").unwrap();
+    let names: Vec<&str> = document
+        .nodes()
+        .iter()
+        .map(|node| node.name().value())
+        .collect();
+    assert_eq!(names, ["server", "plugin"]);
+}
+
+#[test]
+fn parse_reports_errors_with_the_crate_error_type() {
+    let error = facet_kdl::parse("server \"unterminated").unwrap_err();
+    assert!(matches!(error.kind, facet_kdl::KdlErrorKind::Parse(_)));
+}
+
+#[test]
+fn the_kdl_crate_is_re_exported() {
+    // Validators and document APIs exchange kdl types; the re-export saves
+    // callers from pinning a matching kdl version themselves.
+    let value = facet_kdl::kdl::KdlValue::Integer(80);
+    assert_eq!(value.as_integer(), Some(80));
+}
+
+#[test]
+fn integer_literals_deserialize_from_any_radix() {
+    // The KDL literal syntax carries the radix; no attribute needed on input.
+    let doc: BigDoc = facet_kdl::from_str("counter max=0xFF").unwrap();
+    assert_eq!(doc.counter.max, 255);
+    let doc: BigDoc = facet_kdl::from_str("counter max=0b1010").unwrap();
+    assert_eq!(doc.counter.max, 10);
+    let doc: BigDoc = facet_kdl::from_str("counter max=0o755").unwrap();
+    assert_eq!(doc.counter.max, 493);
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct HostDoc {
+    #[facet(child)]
+    service: EmbeddingService,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct EmbeddingService {
+    #[facet(argument)]
+    name: String,
+    #[facet(kdl(document))]
+    body: Fragment,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct Fragment {
+    #[facet(child)]
+    limits: FragmentLimits,
+    #[facet(children)]
+    mounts: Vec<Mount>,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct FragmentLimits {
+    #[facet(property)]
+    cpu: u32,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct Mount {
+    #[facet(argument)]
+    path: String,
+}
+
+#[test]
+fn document_fields_deserialize_the_children_block() {
+    let kdl = "service \"api\" {\n    limits cpu=2\n    mount \"/data\"\n    mount \"/logs\"\n}";
+    let doc: HostDoc = facet_kdl::from_str(kdl).unwrap();
+    assert_eq!(doc.service.name, "api");
+    assert_eq!(doc.service.body.limits.cpu, 2);
+    assert_eq!(doc.service.body.mounts.len(), 2);
+    assert_eq!(doc.service.body.mounts[1].path, "/logs");
+}
+
+#[test]
+fn document_fields_apply_their_own_top_level_rules() {
+    // An unknown node inside the fragment is reported against the fragment's
+    // own child/children vocabulary.
+    let kdl = "service \"api\" {\n    limits cpu=2\n    bogus\n}";
+    let error = facet_kdl::from_str::<HostDoc>(kdl).unwrap_err();
+    match error.kind {
+        facet_kdl::KdlErrorKind::NoMatchingNode { name, expected } => {
+            assert_eq!(name, "bogus");
+            assert!(expected.contains(&"limits".to_string()));
+        }
+        other => panic!("expected NoMatchingNode, got {other:?}"),
+    }
+}
+
+#[test]
+fn missing_children_still_run_the_sub_document() {
+    // Without a children block the fragment sees an empty document, so its
+    // required child is reported missing rather than the field erroring.
+    let error = facet_kdl::from_str::<HostDoc>("service \"api\"").unwrap_err();
+    assert!(matches!(
+        error.kind,
+        facet_kdl::KdlErrorKind::MissingField { .. }
+    ));
+}
+
+#[test]
+fn progress_callbacks_see_counts_and_paths() {
+    use std::sync::{Arc, Mutex};
+
+    type Reports = Vec<(usize, Vec<String>)>;
+    let seen: Arc<Mutex<Reports>> = Arc::new(Mutex::new(Vec::new()));
+    let sink = Arc::clone(&seen);
+    let options = facet_kdl::DeserializeOptions {
+        progress: Some(facet_kdl::Progress::every(1, move |report| {
+            sink.lock()
+                .unwrap()
+                .push((report.nodes_visited, report.path.to_vec()));
+            std::ops::ControlFlow::Continue(())
+        })),
+        ..Default::default()
+    };
+    let _config: Config = facet_kdl::from_str_with_options(
+        "server \"main\" port=8080\nplugin \"/usr/lib/a.so\"\nplugin \"/usr/lib/b.so\"",
+        &options,
+    )
+    .unwrap();
+    let seen = seen.lock().unwrap();
+    assert_eq!(seen.len(), 3);
+    assert_eq!(seen[0], (1, vec!["server".to_string()]));
+    assert_eq!(seen[2], (3, vec!["plugin".to_string()]));
+}
+
+#[test]
+fn progress_paths_descend_into_children() {
+    use std::sync::{Arc, Mutex};
+
+    let seen: Arc<Mutex<Vec<Vec<String>>>> = Arc::new(Mutex::new(Vec::new()));
+    let sink = Arc::clone(&seen);
+    let options = facet_kdl::DeserializeOptions {
+        progress: Some(facet_kdl::Progress::every(1, move |report| {
+            sink.lock().unwrap().push(report.path.to_vec());
+            std::ops::ControlFlow::Continue(())
+        })),
+        ..Default::default()
+    };
+    let kdl = "service \"api\" {\n    limits cpu=2\n    mount \"/data\"\n}";
+    let _doc: HostDoc = facet_kdl::from_str_with_options(kdl, &options).unwrap();
+    let seen = seen.lock().unwrap();
+    assert_eq!(seen[1], vec!["service".to_string(), "limits".to_string()]);
+}
+
+#[test]
+fn progress_break_cancels_the_run() {
+    let options = facet_kdl::DeserializeOptions {
+        progress: Some(facet_kdl::Progress::every(2, |_| {
+            std::ops::ControlFlow::Break(())
+        })),
+        ..Default::default()
+    };
+    let error = facet_kdl::from_str_with_options::<Config>(
+        "server \"main\" port=8080\nplugin \"/usr/lib/a.so\"\nplugin \"/usr/lib/b.so\"",
+        &options,
+    )
+    .unwrap_err();
+    match error.kind {
+        facet_kdl::KdlErrorKind::Cancelled { nodes_visited } => assert_eq!(nodes_visited, 2),
+        other => panic!("expected Cancelled, got {other:?}"),
+    }
+}
+
+#[test]
+fn a_cancelled_token_aborts_at_the_first_node() {
+    let token = facet_kdl::CancellationToken::new();
+    token.cancel();
+    let options = facet_kdl::DeserializeOptions {
+        cancellation: Some(token),
+        ..Default::default()
+    };
+    let error = facet_kdl::from_str_with_options::<Config>(
+        "server \"main\" port=8080\nplugin \"/usr/lib/a.so\"",
+        &options,
+    )
+    .unwrap_err();
+    match error.kind {
+        facet_kdl::KdlErrorKind::Cancelled { nodes_visited } => assert_eq!(nodes_visited, 1),
+        other => panic!("expected Cancelled, got {other:?}"),
+    }
+}
+
+#[test]
+fn an_untouched_token_lets_the_run_finish() {
+    let token = facet_kdl::CancellationToken::new();
+    let options = facet_kdl::DeserializeOptions {
+        cancellation: Some(token.clone()),
+        ..Default::default()
+    };
+    let config: Config =
+        facet_kdl::from_str_with_options("server \"main\" port=8080", &options).unwrap();
+    assert_eq!(config.server.port, 8080);
+    assert!(!token.is_cancelled());
+}
+
+#[test]
+fn cancelling_from_a_progress_callback_works() {
+    // A progress callback can flip the shared token instead of returning
+    // Break, which is the natural shape when the callback only forwards to a
+    // channel.
+    let token = facet_kdl::CancellationToken::new();
+    let cancel = token.clone();
+    let options = facet_kdl::DeserializeOptions {
+        progress: Some(facet_kdl::Progress::every(1, move |_| {
+            cancel.cancel();
+            std::ops::ControlFlow::Continue(())
+        })),
+        cancellation: Some(token),
+        ..Default::default()
+    };
+    let error = facet_kdl::from_str_with_options::<Config>(
+        "server \"main\" port=8080\nplugin \"/usr/lib/a.so\"",
+        &options,
+    )
+    .unwrap_err();
+    assert!(matches!(
+        error.kind,
+        facet_kdl::KdlErrorKind::Cancelled { nodes_visited: 2 }
+    ));
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct ClusterDoc {
+    #[facet(children)]
+    entries: Vec<ClusterEntry>,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+#[repr(u8)]
+enum ClusterEntry {
+    Hosts(Vec<String>),
+    Ports(Vec<u16>),
+    Label {
+        #[facet(argument)]
+        text: String,
+    },
+}
+
+#[test]
+fn list_payload_variants_consume_all_arguments() {
+    let doc: ClusterDoc = facet_kdl::from_str(
+        r#"
+Hosts "alpha" "beta" "gamma"
+Ports 80 443
+Label "prod"
+"#,
+    )
+    .unwrap();
+    assert_eq!(
+        doc.entries[0],
+        ClusterEntry::Hosts(vec![
+            "alpha".to_string(),
+            "beta".to_string(),
+            "gamma".to_string()
+        ])
+    );
+    assert_eq!(doc.entries[1], ClusterEntry::Ports(vec![80, 443]));
+    assert_eq!(
+        doc.entries[2],
+        ClusterEntry::Label {
+            text: "prod".to_string()
+        }
+    );
+}
+
+#[test]
+fn list_payload_variants_accept_empty_argument_runs() {
+    let doc: ClusterDoc = facet_kdl::from_str("Hosts").unwrap();
+    assert_eq!(doc.entries[0], ClusterEntry::Hosts(Vec::new()));
+}
+
+#[test]
+fn list_payload_variants_reject_properties() {
+    let error = facet_kdl::from_str::<ClusterDoc>("Hosts \"alpha\" region=\"eu\"").unwrap_err();
+    let message = error.to_string();
+    assert!(
+        message.contains("positional arguments"),
+        "unexpected message: {message}"
+    );
+}
+
+#[test]
+fn collect_errors_with_options_reports_every_unknown() {
+    // Strict checking plus multi-error collection: one CI run lists every
+    // unknown with a span and the expected-names suggestion.
+    let errors = facet_kdl::from_str_collect_errors_with_options::<Config>(
+        "server \"main\" port=1 bogus=2\nmystery-node\nanother-mystery",
+        &facet_kdl::DeserializeOptions::default(),
+    )
+    .unwrap_err();
+    assert_eq!(errors.errors.len(), 3);
+    assert!(errors.errors.iter().all(|error| error.span.is_some()));
+    let rendered: Vec<String> = errors.errors.iter().map(|e| e.to_string()).collect();
+    assert!(rendered[0].contains("bogus") && rendered[0].contains("port"));
+    assert!(rendered[1].contains("mystery-node") && rendered[1].contains("server"));
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct JobsDoc {
+    #[facet(children)]
+    jobs: Vec<StrictJob>,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+#[facet(deny_unknown_fields)]
+struct StrictJob {
+    #[facet(property)]
+    name: String,
+}
+
+#[test]
+fn collect_errors_respects_lenient_options_and_deny_marks() {
+    // `allow_unknown_properties` silences unknowns on ordinary shapes, but a
+    // deny-marked shape still reports each of its own — all in one run.
+    let errors = facet_kdl::from_str_collect_errors_with_options::<JobsDoc>(
+        "strictjob name=\"a\" first=1 second=2",
+        &lenient_options(),
+    )
+    .unwrap_err();
+    assert_eq!(errors.errors.len(), 2);
+    let rendered: Vec<String> = errors.errors.iter().map(|e| e.to_string()).collect();
+    assert!(rendered[0].contains("first"), "{rendered:?}");
+    assert!(rendered[1].contains("second"), "{rendered:?}");
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct OriginDoc {
+    #[facet(child)]
+    server: OriginServer,
+    #[facet(child)]
+    cache: Option<OriginCache>,
+    #[facet(children)]
+    mount: Vec<Mount>,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct OriginServer {
+    #[facet(property)]
+    port: u16,
+    #[facet(property, default)]
+    timeout: u64,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct OriginCache {
+    #[facet(property)]
+    size: u64,
+}
+
+#[test]
+fn origins_distinguish_document_values_from_defaults() {
+    use facet_kdl::FieldOrigin;
+    let (doc, origins): (OriginDoc, _) = facet_kdl::from_str_with_origins(
+        "server port=80\nmount \"/data\"\nmount \"/logs\"",
+        &facet_kdl::DeserializeOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(doc.server.port, 80);
+    assert_eq!(doc.server.timeout, 0);
+    assert_eq!(doc.cache, None);
+    assert_eq!(origins.origin("server"), Some(FieldOrigin::Document));
+    assert_eq!(origins.origin("server.port"), Some(FieldOrigin::Document));
+    assert_eq!(origins.origin("server.timeout"), Some(FieldOrigin::Default));
+    assert_eq!(origins.origin("cache"), Some(FieldOrigin::Default));
+    assert_eq!(origins.origin("mount"), Some(FieldOrigin::Document));
+    assert_eq!(origins.origin("mount[0].path"), Some(FieldOrigin::Document));
+    assert_eq!(origins.origin("mount[1].path"), Some(FieldOrigin::Document));
+    assert_eq!(origins.origin("nonsense"), None);
+    assert!(!origins.is_empty());
+}
+
+#[test]
+fn origins_record_null_policy_defaults() {
+    use facet_kdl::FieldOrigin;
+    let options = facet_kdl::DeserializeOptions {
+        null_policy: facet_kdl::NullPolicy::UseDefault,
+        ..Default::default()
+    };
+    let (doc, origins): (OriginDoc, _) =
+        facet_kdl::from_str_with_origins("server port=80 timeout=#null", &options).unwrap();
+    assert_eq!(doc.server.timeout, 0);
+    assert_eq!(origins.origin("server.timeout"), Some(FieldOrigin::Default));
+    // The untouched children container is defaulted to empty, and says so.
+    assert_eq!(origins.origin("mount"), Some(FieldOrigin::Default));
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct WrappedConfig(Config);
+
+#[test]
+fn newtype_documents_deserialize_via_the_inner_type() {
+    let doc: WrappedConfig =
+        facet_kdl::from_str("server \"main\" port=8080\nplugin \"auth\"").unwrap();
+    assert_eq!(doc.0.server.name, "main");
+    assert_eq!(doc.0.server.port, 8080);
+    assert_eq!(doc.0.plugins.len(), 1);
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct InlineDoc {
+    #[facet(child)]
+    endpoint: Endpoint,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct Endpoint {
+    #[facet(property)]
+    host: String,
+    #[facet(child, kdl(inline))]
+    limits: InlineLimits,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct InlineLimits {
+    #[facet(property)]
+    cpu: u32,
+    #[facet(property)]
+    memory: u32,
+}
+
+#[test]
+fn inline_children_accept_properties_on_the_parent_node() {
+    let doc: InlineDoc =
+        facet_kdl::from_str("endpoint host=\"api\" cpu=2 memory=512").unwrap();
+    assert_eq!(doc.endpoint.host, "api");
+    assert_eq!(doc.endpoint.limits, InlineLimits { cpu: 2, memory: 512 });
+}
+
+#[test]
+fn inline_children_accept_interleaved_parent_properties() {
+    // The inline child's properties may interleave with the parent's own;
+    // routing doesn't depend on the order they appear in.
+    let doc: InlineDoc =
+        facet_kdl::from_str("endpoint cpu=2 host=\"api\" memory=512").unwrap();
+    assert_eq!(doc.endpoint.host, "api");
+    assert_eq!(doc.endpoint.limits, InlineLimits { cpu: 2, memory: 512 });
+}
+
+#[test]
+fn inline_children_still_accept_the_nested_node_form() {
+    // The migration window: old documents with the nested node keep working.
+    let doc: InlineDoc =
+        facet_kdl::from_str("endpoint host=\"api\" {\n    limits cpu=2 memory=512\n}").unwrap();
+    assert_eq!(doc.endpoint.limits, InlineLimits { cpu: 2, memory: 512 });
+}
+
+#[test]
+fn inline_children_show_up_in_property_suggestions() {
+    let error = facet_kdl::from_str::<InlineDoc>("endpoint host=\"api\" cpi=2").unwrap_err();
+    let message = error.to_string();
+    assert!(message.contains("cpu"), "unexpected message: {message}");
+}
+
+#[derive(Debug, Facet)]
+struct BadChildrenDoc {
+    #[facet(children)]
+    member: BadMember,
+}
+
+#[derive(Debug, Facet)]
+struct BadMember {
+    #[facet(property)]
+    name: String,
+}
+
+#[test]
+fn non_container_children_fields_get_a_targeted_diagnostic() {
+    let error = facet_kdl::from_str::<BadChildrenDoc>("member name=\"a\"").unwrap_err();
+    match error.kind {
+        facet_kdl::KdlErrorKind::SchemaError(message) => {
+            assert!(message.contains("`member`"), "{message}");
+            assert!(message.contains("Vec<BadMember>"), "{message}");
+            assert!(message.contains("#[facet(child)]"), "{message}");
+        }
+        other => panic!("unexpected error kind: {other:?}"),
+    }
+    // The shape is wrong regardless of what the document contains.
+    assert!(facet_kdl::from_str::<BadChildrenDoc>("").is_err());
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct IncludeDoc {
+    #[facet(child)]
+    include: Include,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct Include {
+    #[facet(argument, kdl(validate_with = known_name))]
+    name: String,
+}
+
+struct KnownNames {
+    names: Vec<&'static str>,
+}
+
+fn known_name(
+    context: &dyn std::any::Any,
+    value: &kdl::KdlValue,
+    _annotation: Option<&str>,
+) -> Result<(), String> {
+    let known = context
+        .downcast_ref::<KnownNames>()
+        .ok_or("context is not a KnownNames registry")?;
+    match value.as_string() {
+        Some(name) if known.names.contains(&name) => Ok(()),
+        Some(name) => Err(format!("`{name}` is not a known include")),
+        None => Err("expected a string".to_string()),
+    }
+}
+
+fn include_options() -> facet_kdl::DeserializeOptions {
+    facet_kdl::DeserializeOptions {
+        context_validators: vec![("known_name", known_name)],
+        ..Default::default()
+    }
+}
+
+#[test]
+fn context_validator_sees_the_injected_context() {
+    let registry = KnownNames {
+        names: vec!["base", "extra"],
+    };
+    let doc: IncludeDoc =
+        facet_kdl::from_str_with_context("include \"base\"", &include_options(), &registry)
+            .unwrap();
+    assert_eq!(doc.include.name, "base");
+
+    let error = facet_kdl::from_str_with_context::<IncludeDoc, _>(
+        "include \"rogue\"",
+        &include_options(),
+        &registry,
+    )
+    .unwrap_err();
+    match error.kind {
+        facet_kdl::KdlErrorKind::ValidationFailed { field, message } => {
+            assert_eq!(field, "name");
+            assert!(message.contains("rogue"));
+        }
+        other => panic!("expected a validation failure, got {other:?}"),
+    }
+}
+
+#[test]
+fn context_validator_without_a_context_is_a_schema_error() {
+    let error =
+        facet_kdl::from_str_with_options::<IncludeDoc>("include \"base\"", &include_options())
+            .unwrap_err();
+    match error.kind {
+        facet_kdl::KdlErrorKind::SchemaError(message) => {
+            assert!(message.contains("from_str_with_context"));
+        }
+        other => panic!("expected a schema error, got {other:?}"),
+    }
+}
+
+#[test]
+fn annotate_describes_the_mapping_inline() {
+    let annotated = facet_kdl::annotate::<Config>(
+        "server \"main\" port=8080\nplugin \"/usr/lib/a.so\"\nplugin \"/usr/lib/b.so\"",
+        &facet_kdl::DeserializeOptions::default(),
+    )
+    .unwrap();
+    assert!(
+        annotated.contains("server \"main\" port=8080  // node -> `server`; port -> `server.port`"),
+        "unexpected output: {annotated}"
+    );
+    assert!(
+        annotated.contains("plugin \"/usr/lib/a.so\"  // node -> `plugins[0]`"),
+        "unexpected output: {annotated}"
+    );
+    assert!(
+        annotated.contains("plugin \"/usr/lib/b.so\"  // node -> `plugins[1]`"),
+        "unexpected output: {annotated}"
+    );
+    assert!(
+        annotated.contains("// defaulted: server.verbose"),
+        "unexpected output: {annotated}"
+    );
+}
+
+#[test]
+fn annotate_marks_skipped_unknown_properties() {
+    let options = facet_kdl::DeserializeOptions {
+        allow_unknown_properties: true,
+        ..Default::default()
+    };
+    let annotated =
+        facet_kdl::annotate::<Config>("server \"main\" port=1 bogus=2", &options).unwrap();
+    assert!(
+        annotated.contains("property `bogus` unknown, skipped"),
+        "unexpected output: {annotated}"
+    );
+}
+
+#[derive(Debug, Facet, PartialEq)]
+#[facet(transparent)]
+struct TaggedConfig(Config);
+
+#[derive(Debug, Facet, PartialEq)]
+struct HandleDoc {
+    #[facet(child)]
+    server: ServerHandle,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+#[facet(transparent)]
+struct ServerHandle(Server);
+
+#[test]
+fn transparent_document_delegates_to_its_inner_type() {
+    let tagged: TaggedConfig =
+        facet_kdl::from_str("server \"main\" port=8080\nplugin \"auth\"").unwrap();
+    assert_eq!(tagged.0.server.port, 8080);
+    assert_eq!(tagged.0.plugins[0].path, "auth");
+}
+
+#[test]
+fn transparent_child_field_fills_its_inner_type() {
+    let doc: HandleDoc = facet_kdl::from_str("server \"main\" port=8080").unwrap();
+    assert_eq!(doc.server.0.name, "main");
+    assert_eq!(doc.server.0.port, 8080);
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct VersionedDoc {
+    #[facet(children)]
+    rules: Vec<VersionedRule>,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct VersionedRule {
+    #[facet(property)]
+    priority: u8,
+    #[facet(flatten)]
+    action: VersionedAction,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+#[repr(u8)]
+enum VersionedAction {
+    Allow {
+        #[facet(property)]
+        scope: String,
+    },
+    #[facet(other)]
+    Unknown,
+}
+
+#[test]
+fn fallback_variant_catches_unmatched_nodes() {
+    // The second node carries properties only a newer app version knows; the
+    // `#[facet(other)]` variant absorbs it instead of failing the whole load.
+    let doc: VersionedDoc = facet_kdl::from_str(
+        "rule priority=1 scope=\"admin\"\nrule priority=2 frobnicate=\"x\" level=3",
+    )
+    .unwrap();
+    assert_eq!(
+        doc.rules[0].action,
+        VersionedAction::Allow {
+            scope: "admin".to_string()
+        }
+    );
+    assert_eq!(doc.rules[0].priority, 1);
+    assert_eq!(doc.rules[1].action, VersionedAction::Unknown);
+    assert_eq!(doc.rules[1].priority, 2);
+}
+
+#[test]
+fn fallback_variant_does_not_shadow_a_regular_match() {
+    // Without a catch-all this document still solves to `Allow`; with one it
+    // must pick the same answer, not become ambiguous.
+    let doc: VersionedDoc = facet_kdl::from_str("rule priority=1 scope=\"admin\"").unwrap();
+    assert!(matches!(doc.rules[0].action, VersionedAction::Allow { .. }));
+}
+
+#[test]
+fn children_elements_follow_document_order() {
+    let kdl = r#"
+server "main" port=8080
+plugin "/usr/lib/a.so"
+plugin "/usr/lib/b.so"
+plugin "/usr/lib/c.so"
+"#;
+    let config: Config = facet_kdl::from_str(kdl).unwrap();
+    let paths: Vec<&str> = config
+        .plugins
+        .iter()
+        .map(|plugin| plugin.path.as_str())
+        .collect();
+    assert_eq!(paths, ["/usr/lib/a.so", "/usr/lib/b.so", "/usr/lib/c.so"]);
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct PipelineDoc {
+    #[facet(children)]
+    input: Vec<Stage>,
+    #[facet(children)]
+    output: Vec<Stage>,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct Stage {
+    #[facet(argument)]
+    name: String,
+}
+
+#[test]
+fn interleaved_children_keep_their_relative_document_order() {
+    let kdl = r#"
+input "a"
+output "x"
+input "b"
+output "y"
+input "c"
+"#;
+    let doc: PipelineDoc = facet_kdl::from_str(kdl).unwrap();
+    let inputs: Vec<&str> = doc.input.iter().map(|stage| stage.name.as_str()).collect();
+    let outputs: Vec<&str> = doc.output.iter().map(|stage| stage.name.as_str()).collect();
+    assert_eq!(inputs, ["a", "b", "c"]);
+    assert_eq!(outputs, ["x", "y"]);
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct ListenDoc {
+    #[facet(child)]
+    listen: Listen,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct Listen {
+    /// The TCP port to listen on.
+    #[facet(property)]
+    port: u16,
+    #[facet(property)]
+    host: Option<String>,
+}
+
+#[test]
+fn unknown_property_errors_carry_field_docs() {
+    let error = facet_kdl::from_str::<ListenDoc>("listen prot=8080").unwrap_err();
+    let facet_kdl::KdlErrorKind::NoMatchingProperty { name, expected } = error.kind else {
+        panic!("expected NoMatchingProperty, got {:?}", error.kind);
+    };
+    assert_eq!(name, "prot");
+    let port = expected
+        .iter()
+        .find(|property| property.name == "port")
+        .unwrap();
+    assert_eq!(port.doc, "The TCP port to listen on.");
+    let host = expected
+        .iter()
+        .find(|property| property.name == "host")
+        .unwrap();
+    assert!(host.doc.is_empty());
+}
+
+#[test]
+fn auto_detection_reports_legacy_documents() {
+    let options = facet_kdl::DeserializeOptions::default();
+    let (config, version) = facet_kdl::from_str_with_version::<Config>(
+        r#"server "main" port=1 verbose=true"#,
+        &options,
+    )
+    .unwrap();
+    assert_eq!(version, facet_kdl::KdlVersion::V1);
+    assert_eq!(config.server.verbose, Some(true));
+}
+
+#[test]
+fn auto_detection_reports_current_documents() {
+    let options = facet_kdl::DeserializeOptions::default();
+    let (_, version) = facet_kdl::from_str_with_version::<Config>(
+        r#"server "main" port=1 verbose=#true"#,
+        &options,
+    )
+    .unwrap();
+    assert_eq!(version, facet_kdl::KdlVersion::V2);
+}
+
+#[test]
+fn strict_v2_rejects_legacy_literals() {
+    let options = facet_kdl::DeserializeOptions {
+        version: facet_kdl::VersionPolicy::V2Only,
+        ..Default::default()
+    };
+    let error = facet_kdl::from_str_with_options::<Config>(
+        r#"server "main" port=1 verbose=true"#,
+        &options,
+    )
+    .unwrap_err();
+    assert!(matches!(error.kind, facet_kdl::KdlErrorKind::Parse(_)));
+}
+
+#[test]
+fn inspect_tallies_structure_in_one_pass() {
+    let stats = facet_kdl::inspect(
+        r#"
+server "main" port=8080 verbose=#true {
+    tls cert="/etc/cert.pem" strict=#null
+}
+plugin ratio=0.5
+"#,
+    )
+    .unwrap();
+    assert_eq!(stats.nodes, 3);
+    assert_eq!(stats.max_depth, 2);
+    assert_eq!(stats.properties, 5);
+    assert_eq!(stats.arguments, 1);
+    assert_eq!(stats.strings, 2);
+    assert_eq!(stats.integers, 1);
+    assert_eq!(stats.floats, 1);
+    assert_eq!(stats.booleans, 1);
+    assert_eq!(stats.nulls, 1);
+}
+
+#[test]
+fn inspect_of_an_empty_document_is_all_zeroes() {
+    let stats = facet_kdl::inspect("").unwrap();
+    assert_eq!(stats, facet_kdl::DocumentStats::default());
+}
+
+#[test]
+fn inspect_reports_parse_errors() {
+    let error = facet_kdl::inspect("server \"unterminated").unwrap_err();
+    assert!(matches!(error.kind, facet_kdl::KdlErrorKind::Parse(_)));
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct PassthroughDoc {
+    #[facet(child, kdl(verbatim))]
+    snippet: String,
+}
+
+#[test]
+fn verbatim_child_captures_children_as_text() {
+    let doc: PassthroughDoc = facet_kdl::from_str(
+        "snippet {\n    route \"/api\"   upstream=\"backend\"\n  route \"/\"\n}",
+    )
+    .unwrap();
+    // kdl-rs's autoformat spells identifier-safe strings bare.
+    assert_eq!(doc.snippet, "route \"/api\" upstream=backend\nroute \"/\"\n");
+}
+
+#[test]
+fn verbatim_child_without_children_is_empty() {
+    let doc: PassthroughDoc = facet_kdl::from_str("snippet").unwrap();
+    assert_eq!(doc.snippet, "");
+}
+
+#[test]
+fn verbatim_child_rejects_entries() {
+    let error = facet_kdl::from_str::<PassthroughDoc>("snippet \"inline\"").unwrap_err();
+    assert!(
+        error.to_string().contains("takes children only"),
+        "unexpected message: {error}"
+    );
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct SinksDoc {
+    #[facet(children)]
+    sinks: Vec<Sink>,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+#[repr(u8)]
+#[allow(dead_code)] // `Discard` is only ever built through reflection
+enum Sink {
+    Discard {},
+    File {
+        #[facet(property)]
+        path: String,
+    },
+}
+
+#[test]
+fn children_on_a_payload_less_variant_error_with_their_names() {
+    let error =
+        facet_kdl::from_str::<SinksDoc>("Discard {\n    filter level=3\n    rotate\n}").unwrap_err();
+    assert!(matches!(
+        &error.kind,
+        facet_kdl::KdlErrorKind::UnexpectedChildren { node, children }
+            if node == "Discard" && children == &["filter".to_string(), "rotate".to_string()]
+    ));
+}
+
+#[test]
+fn children_on_a_payload_less_variant_warn_and_drop_under_the_lenient_policy() {
+    let options = facet_kdl::DeserializeOptions {
+        unexpected_children: facet_kdl::UnexpectedChildrenPolicy::Warn,
+        ..Default::default()
+    };
+    let doc: SinksDoc =
+        facet_kdl::from_str_with_options("Discard {\n    filter level=3\n}", &options).unwrap();
+    assert_eq!(doc.sinks, [Sink::Discard {}]);
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct Record {
+    #[facet(child)]
+    event: Event,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct Event {
+    #[facet(argument)]
+    name: String,
+    #[facet(property)]
+    level: Option<u8>,
+}
+
+#[test]
+fn multi_documents_split_on_blank_lines() {
+    let kdl = "event \"start\" level=1\n\nevent \"tick\"\n\n\nevent \"stop\" level=2\n";
+    let records: Vec<Record> = facet_kdl::from_str_multi(kdl).unwrap();
+    let names: Vec<&str> = records
+        .iter()
+        .map(|record| record.event.name.as_str())
+        .collect();
+    assert_eq!(names, ["start", "tick", "stop"]);
+}
+
+#[test]
+fn multi_documents_split_on_explicit_separators() {
+    // With a `---` line present, blank lines are ordinary document content.
+    let kdl = "event \"start\"\n\nevent-count 1\n---\nevent \"stop\"\n---\n";
+    #[derive(Debug, Facet, PartialEq)]
+    struct LooseRecord {
+        #[facet(child)]
+        event: Event,
+        #[facet(child)]
+        event_count: Option<Count>,
+    }
+    #[derive(Debug, Facet, PartialEq)]
+    struct Count {
+        #[facet(argument)]
+        value: u32,
+    }
+    let naming = facet_kdl::Naming::new(|name: &str| name.replace('_', "-"));
+    let options = facet_kdl::DeserializeOptions {
+        naming,
+        ..Default::default()
+    };
+    let records: Vec<LooseRecord> = facet_kdl::from_str_multi_with_options(kdl, &options).unwrap();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0].event_count, Some(Count { value: 1 }));
+    assert_eq!(records[1].event_count, None);
+}
+
+#[test]
+fn multi_documents_report_the_failing_record() {
+    let kdl = "event \"start\"\n\nevent 42\n";
+    let error = facet_kdl::from_str_multi::<Record>(kdl).unwrap_err();
+    assert!(matches!(
+        error.kind,
+        facet_kdl::KdlErrorKind::InvalidValueForShape { .. }
+    ));
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct TelemetryDoc {
+    #[facet(child)]
+    sample: Sample,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct Sample {
+    #[facet(property)]
+    level: Level,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+#[repr(u8)]
+#[allow(dead_code)] // `High` is only ever built through reflection
+enum Level {
+    Low = 1,
+    Medium = 5,
+    High = 9,
+}
+
+#[test]
+fn unit_only_enum_values_parse_from_name_or_discriminant() {
+    let by_name: TelemetryDoc = facet_kdl::from_str("sample level=\"Medium\"").unwrap();
+    let by_number: TelemetryDoc = facet_kdl::from_str("sample level=5").unwrap();
+    assert_eq!(by_name, by_number);
+    assert_eq!(by_name.sample.level, Level::Medium);
+}
+
+#[test]
+fn unit_only_enum_values_reject_unknown_spellings() {
+    let error = facet_kdl::from_str::<TelemetryDoc>("sample level=7").unwrap_err();
+    assert!(matches!(
+        &error.kind,
+        facet_kdl::KdlErrorKind::NoMatchingVariant { expected, .. }
+            if expected.contains(&"Medium (5)".to_string())
+    ));
+    let error = facet_kdl::from_str::<TelemetryDoc>("sample level=\"Extreme\"").unwrap_err();
+    assert!(matches!(
+        error.kind,
+        facet_kdl::KdlErrorKind::NoMatchingVariant { .. }
+    ));
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct BackendsDoc {
+    #[facet(children)]
+    backends: Vec<Backend>,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+#[repr(u8)]
+#[allow(dead_code)] // `Local` is only ever built through reflection
+enum Backend {
+    Proxy {
+        #[facet(property)]
+        port: u16,
+        #[facet(child)]
+        health: BackendHealth,
+        #[facet(children)]
+        upstreams: Vec<BackendUpstream>,
+    },
+    Local {},
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct BackendHealth {
+    #[facet(property)]
+    interval: u32,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct BackendUpstream {
+    #[facet(argument)]
+    host: String,
+}
+
+#[test]
+fn variant_payload_mixes_child_and_children_fields() {
+    // The `health` node sits between the two upstreams: an enum variant
+    // frame doesn't remember which fields it has begun, so re-entering the
+    // `upstreams` list after the interleaved child used to restart it and
+    // drop the first element.
+    let kdl = r#"
+Proxy port=80 {
+    upstream "a"
+    health interval=5
+    upstream "b"
+}
+"#;
+    let doc: BackendsDoc = facet_kdl::from_str(kdl).unwrap();
+    assert_eq!(
+        doc.backends[0],
+        Backend::Proxy {
+            port: 80,
+            health: BackendHealth { interval: 5 },
+            upstreams: vec![
+                BackendUpstream {
+                    host: "a".to_string()
+                },
+                BackendUpstream {
+                    host: "b".to_string()
+                },
+            ],
+        }
+    );
+}
+
+#[test]
+fn variant_payload_children_preserve_document_order() {
+    let kdl = r#"
+Proxy port=80 {
+    upstream "c"
+    upstream "a"
+    health interval=5
+    upstream "b"
+}
+"#;
+    let doc: BackendsDoc = facet_kdl::from_str(kdl).unwrap();
+    let Backend::Proxy { upstreams, .. } = &doc.backends[0] else {
+        panic!("expected the Proxy variant");
+    };
+    let hosts: Vec<&str> = upstreams.iter().map(|u| u.host.as_str()).collect();
+    assert_eq!(hosts, ["c", "a", "b"]);
+}
+
+#[test]
+fn walk_lists_scalars_with_paths_in_document_order() {
+    let kdl = "server \"main\" port=8080 {\n    tls enabled=#false\n}\nplugin \"/usr/lib/a.so\"\n";
+    let entries = facet_kdl::walk::<WalkedConfig>(kdl).unwrap();
+    let paths: Vec<&str> = entries.iter().map(|entry| entry.path.as_str()).collect();
+    assert_eq!(
+        paths,
+        [
+            "server.name",
+            "server.port",
+            "server.tls.enabled",
+            // The derive folds `rename` into the reflected field name, so
+            // paths show the KDL spelling — same as origin maps.
+            "plugin[0].path"
+        ]
+    );
+    let disabled = entries
+        .iter()
+        .find(|entry| entry.path == "server.tls.enabled")
+        .unwrap();
+    assert_eq!(disabled.value, facet_kdl::kdl::KdlValue::Bool(false));
+    // The span points into the input, so audit tooling can quote the line.
+    let quoted = &kdl[disabled.span.offset..disabled.span.offset + disabled.span.len];
+    assert!(quoted.contains("#false"), "unexpected span text: {quoted}");
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct WalkedConfig {
+    #[facet(child)]
+    server: WalkedServer,
+    #[facet(children, rename = "plugin")]
+    plugins: Vec<WalkedPlugin>,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct WalkedServer {
+    #[facet(argument)]
+    name: String,
+    #[facet(property)]
+    port: u16,
+    #[facet(child)]
+    tls: WalkedTls,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct WalkedTls {
+    #[facet(property)]
+    enabled: bool,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct WalkedPlugin {
+    #[facet(argument)]
+    path: String,
+}
+
+#[test]
+fn walk_aborts_on_errors_like_deserialization() {
+    let error = facet_kdl::walk::<WalkedConfig>("mystery-node").unwrap_err();
+    assert!(matches!(
+        error.kind,
+        facet_kdl::KdlErrorKind::NoMatchingNode { .. }
+    ));
+}
+
+#[test]
+fn from_document_reuses_an_existing_parse() {
+    let document = facet_kdl::parse("server \"main\" port=8080\nplugin \"/usr/lib/a.so\"\n").unwrap();
+    let config: Config = facet_kdl::from_document(&document).unwrap();
+    assert_eq!(config.server.port, 8080);
+    assert_eq!(config.plugins.len(), 1);
+}
+
+#[test]
+fn from_document_error_spans_point_into_the_source() {
+    let kdl = "server \"main\" port=8080\nmystery\n";
+    let document = facet_kdl::parse(kdl).unwrap();
+    let error = facet_kdl::from_document::<Config>(&document).unwrap_err();
+    let span = error.span.unwrap();
+    assert_eq!(&kdl[span.offset()..span.offset() + span.len()], "mystery");
+}
+
+#[test]
+fn reflection_failures_surface_as_errors_not_panics() {
+    // `&str` fields can't be built by reflection from an owned document;
+    // the failure must come back through the `Reflect` error channel — the
+    // same one `Partial::alloc` failures at the entry points now use —
+    // never as a panic.
+    #[derive(Debug, Facet)]
+    struct BorrowedDoc<'a> {
+        #[facet(child)]
+        node: BorrowedNode<'a>,
+    }
+
+    #[derive(Debug, Facet)]
+    struct BorrowedNode<'a> {
+        #[facet(property)]
+        text: &'a str,
+    }
+
+    let error = facet_kdl::from_str::<BorrowedDoc>("node text=\"x\"").unwrap_err();
+    assert!(matches!(
+        error.kind,
+        facet_kdl::KdlErrorKind::Reflect(_)
+    ));
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct GroupedDoc {
+    #[facet(child)]
+    server: GroupedServer,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct GroupedServer {
+    #[facet(property)]
+    port: u16,
+    #[facet(flatten, kdl(group = "tls"))]
+    tls: TlsGroup,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct TlsGroup {
+    #[facet(property)]
+    cert: String,
+    #[facet(property)]
+    key: String,
+}
+
+#[test]
+fn grouped_flatten_strips_the_property_prefix() {
+    let doc: GroupedDoc =
+        facet_kdl::from_str("server port=443 tls-cert=\"/etc/cert\" tls-key=\"/etc/key\"").unwrap();
+    assert_eq!(
+        doc.server,
+        GroupedServer {
+            port: 443,
+            tls: TlsGroup {
+                cert: "/etc/cert".to_string(),
+                key: "/etc/key".to_string(),
+            },
+        }
+    );
+}
+
+#[test]
+fn grouped_flatten_accepts_interleaved_prefixed_properties() {
+    // The prefixed spellings don't have to be contiguous; `port` between
+    // them lands in the parent frame without disturbing the group's.
+    let doc: GroupedDoc =
+        facet_kdl::from_str("server tls-cert=\"/etc/cert\" port=443 tls-key=\"/etc/key\"").unwrap();
+    assert_eq!(
+        doc.server,
+        GroupedServer {
+            port: 443,
+            tls: TlsGroup {
+                cert: "/etc/cert".to_string(),
+                key: "/etc/key".to_string(),
+            },
+        }
+    );
+}
+
+#[test]
+fn grouped_flatten_rejects_the_bare_inner_spellings() {
+    let error =
+        facet_kdl::from_str::<GroupedDoc>("server port=443 cert=\"/etc/cert\"").unwrap_err();
+    let facet_kdl::KdlErrorKind::NoMatchingProperty { expected, .. } = error.kind else {
+        panic!("expected NoMatchingProperty, got {:?}", error.kind);
+    };
+    // The candidates list the prefixed spellings the document must use.
+    assert!(expected.iter().any(|property| property.name == "tls-cert"));
+    assert!(!expected.iter().any(|property| property.name == "cert"));
+}
+
+#[test]
+fn from_nodes_accepts_spliced_sections() {
+    // A generated section and a user-maintained one, parsed separately and
+    // combined without ever rendering the assembly to text.
+    let generated = facet_kdl::parse("server \"main\" port=8080\n").unwrap();
+    let user = facet_kdl::parse("plugin \"/usr/lib/a.so\"\nplugin \"/usr/lib/b.so\"\n").unwrap();
+    let mut nodes = generated.nodes().to_vec();
+    nodes.extend(user.nodes().iter().cloned());
+    let config: Config = facet_kdl::from_nodes(&nodes).unwrap();
+    assert_eq!(config.server.port, 8080);
+    assert_eq!(config.plugins.len(), 2);
+}
+
+#[test]
+fn from_nodes_keeps_the_nodes_own_spans() {
+    let section = "mystery\n";
+    let parsed = facet_kdl::parse(section).unwrap();
+    let error = facet_kdl::from_nodes::<Config>(parsed.nodes()).unwrap_err();
+    let span = error.span.unwrap();
+    // The span still points into the section the node was parsed from.
+    assert_eq!(&section[span.offset()..span.offset() + span.len()], "mystery");
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct MetricDoc {
+    #[facet(children)]
+    metrics: Vec<Metric>,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct Metric {
+    #[facet(flatten)]
+    value: MetricValue,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+#[repr(u8)]
+enum MetricValue {
+    Count {
+        #[facet(property)]
+        value: u64,
+    },
+    Gauge {
+        #[facet(property)]
+        value: f64,
+    },
+}
+
+#[test]
+fn strict_coercion_disambiguates_by_literal_kind() {
+    // Under `Strict` the solver's fit check honors the same policy as the
+    // conversion: `1` can only be a `Count`, `1.5` only a `Gauge`, so the
+    // document needs no discriminant.
+    let options = facet_kdl::DeserializeOptions {
+        number_coercion: facet_kdl::NumberCoercion::Strict,
+        ..Default::default()
+    };
+    let doc: MetricDoc =
+        facet_kdl::from_str_with_options("metric value=1\nmetric value=1.5", &options).unwrap();
+    assert_eq!(
+        doc.metrics,
+        [
+            Metric {
+                value: MetricValue::Count { value: 1 }
+            },
+            Metric {
+                value: MetricValue::Gauge { value: 1.5 }
+            },
+        ]
+    );
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct HostEntry {
+    #[facet(property)]
+    address: String,
+    #[facet(property)]
+    port: u16,
+}
+
+#[test]
+fn top_level_map_turns_nodes_into_entries() {
+    // A map document needs no wrapper struct: every top-level node becomes
+    // one entry, node name as key.
+    let hosts: std::collections::HashMap<String, HostEntry> = facet_kdl::from_str(
+        "primary address=\"10.0.0.1\" port=5432\nreplica address=\"10.0.0.2\" port=5433",
+    )
+    .unwrap();
+    assert_eq!(hosts.len(), 2);
+    assert_eq!(
+        hosts["primary"],
+        HostEntry {
+            address: "10.0.0.1".to_string(),
+            port: 5432
+        }
+    );
+    assert_eq!(
+        hosts["replica"],
+        HostEntry {
+            address: "10.0.0.2".to_string(),
+            port: 5433
+        }
+    );
+}
+
+#[test]
+fn top_level_btreemap_reorders_entries_by_key() {
+    let hosts: std::collections::BTreeMap<String, HostEntry> = facet_kdl::from_str(
+        "replica address=\"10.0.0.2\" port=5433\nprimary address=\"10.0.0.1\" port=5432",
+    )
+    .unwrap();
+    assert_eq!(
+        hosts.keys().collect::<Vec<_>>(),
+        ["primary", "replica"]
+    );
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct CommandDoc {
+    #[facet(child)]
+    run: Command,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct Command {
+    name: facet_kdl::NodeName,
+    args: facet_kdl::Args<String>,
+    env: facet_kdl::Props,
+}
+
+#[test]
+fn wrapper_fields_need_no_attributes() {
+    // The idiom wrappers carry their roles in the types: the node's own
+    // name, its positional arguments, and the leftover properties.
+    let doc: CommandDoc =
+        facet_kdl::from_str("run \"cargo\" \"build\" RUST_LOG=\"debug\" jobs=4").unwrap();
+    assert_eq!(*doc.run.name, "run");
+    assert_eq!(*doc.run.args, ["cargo".to_string(), "build".to_string()]);
+    assert_eq!(doc.run.env["RUST_LOG"], "debug");
+    // Non-string values keep their KDL spelling.
+    assert_eq!(doc.run.env["jobs"], "4");
+}
+
+#[test]
+fn args_and_props_default_to_empty() {
+    let doc: CommandDoc = facet_kdl::from_str("run").unwrap();
+    assert!(doc.run.args.is_empty());
+    assert!(doc.run.env.is_empty());
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct ImageDoc {
+    #[facet(child)]
+    step: ImageStep,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct ImageStep {
+    #[facet(property)]
+    image: String,
+    rest: facet_kdl::Props,
+}
+
+#[test]
+fn props_collects_only_unclaimed_properties() {
+    let doc: ImageDoc =
+        facet_kdl::from_str("step image=\"alpine\" cpu=2 memory=\"1Gi\"").unwrap();
+    assert_eq!(doc.step.image, "alpine");
+    assert_eq!(doc.step.rest.len(), 2);
+    assert_eq!(doc.step.rest["cpu"], "2");
+    assert_eq!(doc.step.rest["memory"], "1Gi");
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct InterleavedDoc {
+    #[facet(child)]
+    server: InterleavedServer,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct InterleavedServer {
+    #[facet(property)]
+    port: u16,
+    #[facet(flatten)]
+    tls: InterleavedTls,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct InterleavedTls {
+    #[facet(property)]
+    cert: String,
+    #[facet(property)]
+    key: Option<String>,
+}
+
+#[test]
+fn flattened_properties_may_interleave_with_top_level_ones() {
+    // `cert` and `key` land in one flatten frame even with `port` between
+    // them in the document: assignments are grouped per frame and replayed
+    // after the entry loop, so the frame opens once and closes once.
+    let doc: InterleavedDoc =
+        facet_kdl::from_str("server cert=\"a\" port=443 key=\"b\"").unwrap();
+    assert_eq!(
+        doc.server,
+        InterleavedServer {
+            port: 443,
+            tls: InterleavedTls {
+                cert: "a".to_string(),
+                key: Some("b".to_string()),
+            },
+        }
+    );
+}
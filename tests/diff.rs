@@ -0,0 +1,80 @@
+#![cfg(feature = "de")]
+
+use facet::Facet;
+use facet_kdl::{kdl::KdlValue, Change};
+
+#[derive(Debug, Facet, PartialEq)]
+struct Config {
+    #[facet(child)]
+    server: Server,
+    #[facet(children)]
+    plugins: Vec<Plugin>,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct Server {
+    #[facet(property)]
+    port: u16,
+    #[facet(property)]
+    tls: Option<bool>,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct Plugin {
+    #[facet(argument)]
+    path: String,
+}
+
+#[test]
+fn diff_reports_modified_added_and_removed_paths() {
+    let old = "server port=8080 tls=#true\nplugin \"/usr/lib/a.so\"\n";
+    let new = "server port=9090\nplugin \"/usr/lib/a.so\"\nplugin \"/usr/lib/b.so\"\n";
+    let changes = facet_kdl::diff::<Config>(old, new).unwrap();
+    let paths: Vec<&str> = changes.iter().map(|change| change.path()).collect();
+    assert_eq!(paths, ["server.port", "plugins[1].path", "server.tls"]);
+    assert!(matches!(
+        &changes[0],
+        Change::Modified { before, after, .. }
+            if *before == KdlValue::Integer(8080) && *after == KdlValue::Integer(9090)
+    ));
+    assert!(matches!(
+        &changes[1],
+        Change::Added { value, .. } if *value == KdlValue::String("/usr/lib/b.so".to_string())
+    ));
+    assert!(matches!(
+        &changes[2],
+        Change::Removed { value, .. } if *value == KdlValue::Bool(true)
+    ));
+}
+
+#[test]
+fn diff_spans_point_into_their_own_sources() {
+    let old = "server port=8080\n";
+    let new = "server port=9090\n";
+    let changes = facet_kdl::diff::<Config>(old, new).unwrap();
+    let Change::Modified {
+        before_span,
+        after_span,
+        ..
+    } = &changes[0]
+    else {
+        panic!("expected a modification, got {changes:?}");
+    };
+    assert!(old[before_span.offset..before_span.offset + before_span.len].contains("8080"));
+    assert!(new[after_span.offset..after_span.offset + after_span.len].contains("9090"));
+}
+
+#[test]
+fn diff_ignores_reordering() {
+    // Same values, different node order: semantically identical, so the
+    // diff is empty.
+    let old = "plugin \"/usr/lib/a.so\"\nserver port=8080\n";
+    let new = "server port=8080\nplugin \"/usr/lib/a.so\"\n";
+    assert_eq!(facet_kdl::diff::<Config>(old, new).unwrap(), vec![]);
+}
+
+#[test]
+fn diff_surfaces_parse_errors_from_either_side() {
+    let error = facet_kdl::diff::<Config>("server port=", "server port=1").unwrap_err();
+    assert!(matches!(error.kind, facet_kdl::KdlErrorKind::Parse(_)));
+}
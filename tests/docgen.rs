@@ -0,0 +1,100 @@
+use facet::Facet;
+#[cfg(feature = "ser")]
+use facet_kdl::docgen::markdown_snippet;
+use facet_kdl::docgen::rust_definition;
+
+/// The main configuration.
+#[derive(Debug, Facet)]
+struct Config {
+    /// The server to run.
+    #[facet(child)]
+    server: Server,
+    #[facet(children)]
+    plugins: Vec<Plugin>,
+}
+
+#[derive(Debug, Facet)]
+struct Server {
+    #[facet(argument)]
+    name: String,
+    /// The port to listen on.
+    #[facet(property)]
+    port: u16,
+}
+
+#[derive(Debug, Facet)]
+struct Plugin {
+    #[facet(argument)]
+    path: String,
+    #[facet(property)]
+    enabled: Option<bool>,
+}
+
+#[cfg(feature = "ser")]
+fn example() -> Config {
+    Config {
+        server: Server {
+            name: "main".to_string(),
+            port: 8080,
+        },
+        plugins: vec![Plugin {
+            path: "/usr/lib/a.so".to_string(),
+            enabled: Some(true),
+        }],
+    }
+}
+
+#[test]
+fn definition_covers_every_reachable_type() {
+    let definition = rust_definition::<Config>();
+    assert!(definition.contains("struct Config {"));
+    assert!(definition.contains("struct Server {"));
+    assert!(definition.contains("struct Plugin {"));
+}
+
+#[test]
+fn definition_carries_attributes_and_docs() {
+    let definition = rust_definition::<Config>();
+    assert!(definition.contains("    #[facet(child)]\n    server: Server,\n"));
+    assert!(definition.contains("    #[facet(children)]\n    plugins:"));
+    assert!(definition.contains("    /// The port to listen on.\n"));
+    assert!(definition.contains("/// The main configuration.\n#[derive(Facet)]\nstruct Config"));
+}
+
+#[cfg(feature = "ser")]
+#[test]
+fn snippet_pairs_definition_with_example_document() {
+    let snippet = markdown_snippet(&example()).unwrap();
+    let (rust, kdl) = snippet
+        .split_once("```\n\n```kdl\n")
+        .expect("snippet has a rust fence followed by a kdl fence");
+    assert!(rust.starts_with("```rust\n"));
+    assert!(rust.contains("struct Config {"));
+    assert!(kdl.contains("server \"main\" port=8080"));
+    assert!(kdl.contains("plugin \"/usr/lib/a.so\" enabled=#true"));
+    assert!(kdl.ends_with("```\n"));
+}
+
+#[test]
+fn enum_definitions_render_each_variant_form() {
+    /// Where log output goes.
+    #[derive(Debug, Facet)]
+    #[repr(u8)]
+    #[allow(dead_code)]
+    enum Sink {
+        /// Discard everything.
+        Null,
+        File(String),
+        Syslog {
+            #[facet(property)]
+            facility: String,
+        },
+    }
+
+    let definition = rust_definition::<Sink>();
+    assert!(definition.contains("enum Sink {"));
+    assert!(definition.contains("    /// Discard everything.\n    Null,\n"));
+    assert!(definition.contains("    File(String),\n"));
+    assert!(definition.contains("    Syslog {\n"));
+    assert!(definition.contains("        #[facet(property)]\n        facility: String,\n"));
+}
@@ -0,0 +1,41 @@
+#![cfg(feature = "json-errors")]
+
+use facet::Facet;
+
+#[derive(Debug, Facet, PartialEq)]
+struct Config {
+    #[facet(child)]
+    server: Server,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct Server {
+    #[facet(property)]
+    port: u16,
+}
+
+#[test]
+fn to_json_includes_code_message_and_span() {
+    let error = facet_kdl::from_str::<Config>("server porf=80").unwrap_err();
+    let json = error.to_json();
+    assert!(json.starts_with('{') && json.ends_with('}'));
+    assert!(json.contains("\"code\":\"facet_kdl::no_matching_property\""));
+    assert!(json.contains("\"span\":{\"offset\":"));
+    assert!(json.contains("\"suggestions\":[\"port\"]"));
+}
+
+#[test]
+fn to_json_escapes_quotes_in_messages() {
+    let error = facet_kdl::from_str::<Config>("server port=\"x\"").unwrap_err();
+    let json = error.to_json();
+    assert!(json.contains("\\\""));
+}
+
+#[test]
+fn collected_errors_render_as_a_json_array() {
+    let errors =
+        facet_kdl::from_str_collect_errors::<Config>("server a=1 b=2 port=80").unwrap_err();
+    let json = errors.to_json();
+    assert!(json.starts_with('[') && json.ends_with(']'));
+    assert_eq!(json.matches("\"code\"").count(), 2);
+}
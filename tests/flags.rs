@@ -0,0 +1,104 @@
+#![cfg(all(feature = "bitflags", feature = "ser", feature = "de"))]
+
+use facet::Facet;
+use facet_kdl::{format_flags, parse_flags, DeserializeOptions, KdlErrorKind, SerializeOptions};
+
+#[derive(Debug, Facet, PartialEq)]
+struct Doc {
+    #[facet(child)]
+    mount: Mount,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct Mount {
+    #[facet(argument)]
+    path: String,
+    #[facet(property, kdl(flags_with = features))]
+    features: Features,
+}
+
+// Declared in bitflags "impl mode" so the struct itself derives `Facet`.
+#[derive(Debug, Facet, PartialEq)]
+struct Features(u32);
+
+bitflags::bitflags! {
+    impl Features: u32 {
+        const READ = 1;
+        const WRITE = 2;
+        const EXEC = 4;
+    }
+}
+
+fn deserialize_options() -> DeserializeOptions {
+    DeserializeOptions {
+        flag_parsers: vec![("features", parse_flags::<Features>)],
+        ..Default::default()
+    }
+}
+
+fn serialize_options() -> SerializeOptions {
+    SerializeOptions {
+        flag_formatters: vec![("features", format_flags::<Features>)],
+        ..Default::default()
+    }
+}
+
+#[test]
+fn pipe_separated_flags_deserialize() {
+    let kdl = "mount \"/data\" features=\"READ|WRITE\"\n";
+    let doc: Doc = facet_kdl::from_str_with_options(kdl, &deserialize_options()).unwrap();
+    assert_eq!(doc.mount.features, Features::READ | Features::WRITE);
+}
+
+#[test]
+fn space_separated_flags_deserialize() {
+    let kdl = "mount \"/data\" features=\"READ EXEC\"\n";
+    let doc: Doc = facet_kdl::from_str_with_options(kdl, &deserialize_options()).unwrap();
+    assert_eq!(doc.mount.features, Features::READ | Features::EXEC);
+}
+
+#[test]
+fn unknown_flags_error_lists_the_valid_names() {
+    let kdl = "mount \"/data\" features=\"READ|APPEND\"\n";
+    let error = facet_kdl::from_str_with_options::<Doc>(kdl, &deserialize_options()).unwrap_err();
+    match error.kind {
+        KdlErrorKind::ValidationFailed { field, message } => {
+            assert_eq!(field, "features");
+            assert!(message.contains("unknown flag `APPEND`"), "{message}");
+            assert!(message.contains("READ, WRITE, EXEC"), "{message}");
+        }
+        other => panic!("expected ValidationFailed, got {other:?}"),
+    }
+}
+
+#[test]
+fn unregistered_parser_is_a_schema_error() {
+    let kdl = "mount \"/data\" features=\"READ\"\n";
+    let error = facet_kdl::from_str::<Doc>(kdl).unwrap_err();
+    assert!(matches!(error.kind, KdlErrorKind::SchemaError(_)));
+}
+
+#[test]
+fn flags_serialize_as_pipe_separated_strings() {
+    let doc = Doc {
+        mount: Mount {
+            path: "/data".to_string(),
+            features: Features::READ | Features::WRITE,
+        },
+    };
+    let kdl = facet_kdl::to_string_with_options(&doc, &serialize_options()).unwrap();
+    assert_eq!(kdl, "mount \"/data\" features=\"READ|WRITE\"\n");
+}
+
+#[test]
+fn flag_strings_round_trip() {
+    let doc = Doc {
+        mount: Mount {
+            path: "/data".to_string(),
+            features: Features::READ | Features::EXEC,
+        },
+    };
+    let kdl = facet_kdl::to_string_with_options(&doc, &serialize_options()).unwrap();
+    let back: Doc = facet_kdl::from_str_with_options(&kdl, &deserialize_options()).unwrap();
+    assert_eq!(back, doc);
+}
@@ -0,0 +1,116 @@
+#![cfg(feature = "ser")]
+
+//! Golden-file coverage of the serializer's output modes.
+//!
+//! Each test pins [`facet_kdl::snapshot::golden`] output for one
+//! representative document shape. A failing snapshot means the serializer's
+//! text changed; review the diff and run `cargo insta review` (or set
+//! `INSTA_UPDATE=always`) only when the change is deliberate.
+
+use facet::Facet;
+
+#[derive(Debug, Facet)]
+struct Config {
+    #[facet(child)]
+    server: Server,
+    #[facet(children)]
+    plugins: Vec<Plugin>,
+}
+
+#[derive(Debug, Facet)]
+struct Server {
+    #[facet(argument)]
+    name: String,
+    #[facet(property)]
+    port: u16,
+    #[facet(property)]
+    verbose: Option<bool>,
+}
+
+#[derive(Debug, Facet)]
+struct Plugin {
+    #[facet(argument)]
+    path: String,
+    #[facet(property)]
+    enabled: Option<bool>,
+}
+
+#[test]
+fn nested_document() {
+    let config = Config {
+        server: Server {
+            name: "main".to_string(),
+            port: 8080,
+            verbose: Some(true),
+        },
+        plugins: vec![
+            Plugin {
+                path: "/usr/lib/a.so".to_string(),
+                enabled: Some(true),
+            },
+            Plugin {
+                path: "/usr/lib/b.so".to_string(),
+                enabled: None,
+            },
+        ],
+    };
+    insta::assert_snapshot!(facet_kdl::snapshot::golden(&config).unwrap());
+}
+
+#[derive(Debug, Facet)]
+struct HardwareDoc {
+    #[facet(child)]
+    device: Device,
+}
+
+#[derive(Debug, Facet)]
+struct Device {
+    #[facet(property, kdl(radix = 16), kdl(width = 4))]
+    address: u32,
+    #[facet(property)]
+    ratio: f64,
+    #[facet(property)]
+    label: String,
+}
+
+#[test]
+fn scalar_spellings() {
+    let doc = HardwareDoc {
+        device: Device {
+            address: 0xBEEF,
+            ratio: 0.5,
+            label: "needs \"quoting\"".to_string(),
+        },
+    };
+    insta::assert_snapshot!(facet_kdl::snapshot::golden(&doc).unwrap());
+}
+
+#[derive(Debug, Facet)]
+struct RulesDoc {
+    #[facet(children)]
+    outputs: Vec<Output>,
+}
+
+#[derive(Debug, Facet)]
+#[repr(u8)]
+#[allow(dead_code)] // `Stdout` is only ever built through reflection
+enum Output {
+    Stdout {},
+    File {
+        #[facet(property)]
+        path: String,
+    },
+}
+
+#[test]
+fn enum_children() {
+    let doc = RulesDoc {
+        outputs: vec![
+            Output::Stdout {},
+            Output::File {
+                path: "/tmp/log".to_string(),
+            },
+        ],
+    };
+    insta::assert_snapshot!(facet_kdl::snapshot::golden(&doc).unwrap());
+}
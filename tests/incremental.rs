@@ -0,0 +1,169 @@
+#![cfg(feature = "de")]
+
+use facet::Facet;
+use facet_kdl::{SpanMap, TextEdit};
+
+#[derive(Debug, Clone, Facet, PartialEq)]
+struct Config {
+    #[facet(child)]
+    server: Server,
+    #[facet(children)]
+    plugins: Vec<Plugin>,
+}
+
+#[derive(Debug, Clone, Facet, PartialEq)]
+struct Server {
+    #[facet(property)]
+    port: u16,
+}
+
+#[derive(Debug, Clone, Facet, PartialEq)]
+struct Plugin {
+    #[facet(argument)]
+    path: String,
+}
+
+const KDL: &str = "server port=8080\nplugin \"/usr/lib/a.so\"\n";
+
+#[test]
+fn span_map_records_top_level_nodes() {
+    let spans = SpanMap::build(KDL).unwrap();
+    let names: Vec<&str> = spans.nodes().iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(names, ["server", "plugin"]);
+    assert_eq!(spans.nodes()[0].1.offset, 0);
+    assert!(spans.nodes()[1].1.offset > spans.nodes()[0].1.offset);
+}
+
+#[test]
+fn comment_edit_reuses_the_old_value() {
+    let old: Config = facet_kdl::from_str(KDL).unwrap();
+    let old_spans = SpanMap::build(KDL).unwrap();
+    let offset = KDL.find("plugin").unwrap();
+    let inserted = "// tuning note\n";
+    let new_text = format!("{}{inserted}{}", &KDL[..offset], &KDL[offset..]);
+    let edit = TextEdit {
+        offset,
+        old_len: 0,
+        new_len: inserted.len(),
+    };
+    assert!(old_spans.dirty_nodes(&edit).is_empty());
+    let (new, new_spans) = facet_kdl::reparse(&old, &old_spans, &new_text, edit).unwrap();
+    assert_eq!(new, old);
+    // The rebuilt span map reflects the shifted offsets.
+    assert_eq!(new_spans.nodes()[1].1.offset, offset + inserted.len());
+}
+
+#[test]
+fn node_edit_re_deserializes() {
+    let old: Config = facet_kdl::from_str(KDL).unwrap();
+    let old_spans = SpanMap::build(KDL).unwrap();
+    let offset = KDL.find("8080").unwrap();
+    let new_text = KDL.replace("8080", "9090");
+    let edit = TextEdit {
+        offset,
+        old_len: 4,
+        new_len: 4,
+    };
+    assert_eq!(old_spans.dirty_nodes(&edit), [0]);
+    let (new, _) = facet_kdl::reparse(&old, &old_spans, &new_text, edit).unwrap();
+    assert_eq!(new.server.port, 9090);
+    assert_eq!(old.server.port, 8080);
+    // The untouched field is copied out of the old value, not re-parsed.
+    assert_eq!(new.plugins, old.plugins);
+}
+
+#[test]
+fn inserting_at_a_node_end_boundary_dirties_it() {
+    // kdl-rs node spans end at the last content byte, so a pure insert right
+    // after `port=8` glues onto the value without intersecting the span.
+    let kdl = "server port=8\nplugin \"/usr/lib/a.so\"\n";
+    let old: Config = facet_kdl::from_str(kdl).unwrap();
+    let old_spans = SpanMap::build(kdl).unwrap();
+    let offset = kdl.find('\n').unwrap();
+    let new_text = format!("{}0{}", &kdl[..offset], &kdl[offset..]);
+    let edit = TextEdit {
+        offset,
+        old_len: 0,
+        new_len: 1,
+    };
+    assert_eq!(old_spans.dirty_nodes(&edit), [0]);
+    let (new, _) = facet_kdl::reparse(&old, &old_spans, &new_text, edit).unwrap();
+    assert_eq!(new.server.port, 80);
+}
+
+#[test]
+fn editing_a_collected_node_rebuilds_the_whole_collection() {
+    let kdl = "server port=8080\nplugin \"/usr/lib/a.so\"\nplugin \"/usr/lib/b.so\"\n";
+    let old: Config = facet_kdl::from_str(kdl).unwrap();
+    let old_spans = SpanMap::build(kdl).unwrap();
+    let offset = kdl.find("b.so").unwrap();
+    let new_text = kdl.replace("b.so", "c.so");
+    let edit = TextEdit {
+        offset,
+        old_len: 4,
+        new_len: 4,
+    };
+    assert_eq!(old_spans.dirty_nodes(&edit), [2]);
+    let (new, _) = facet_kdl::reparse(&old, &old_spans, &new_text, edit).unwrap();
+    let paths: Vec<&str> = new.plugins.iter().map(|plugin| plugin.path.as_str()).collect();
+    assert_eq!(paths, ["/usr/lib/a.so", "/usr/lib/c.so"]);
+    assert_eq!(new.server.port, 8080);
+}
+
+#[test]
+fn inserting_a_new_node_is_picked_up() {
+    let old: Config = facet_kdl::from_str(KDL).unwrap();
+    let old_spans = SpanMap::build(KDL).unwrap();
+    let inserted = "plugin \"/usr/lib/b.so\"\n";
+    let new_text = format!("{KDL}{inserted}");
+    let edit = TextEdit {
+        offset: KDL.len(),
+        old_len: 0,
+        new_len: inserted.len(),
+    };
+    // The insert touches no existing node, but the node list changed, so the
+    // fast path must not fire.
+    assert!(old_spans.dirty_nodes(&edit).is_empty());
+    let (new, _) = facet_kdl::reparse(&old, &old_spans, &new_text, edit).unwrap();
+    assert_eq!(new.plugins.len(), 2);
+}
+
+#[test]
+fn edits_that_break_the_document_report_parse_errors() {
+    let old: Config = facet_kdl::from_str(KDL).unwrap();
+    let old_spans = SpanMap::build(KDL).unwrap();
+    let new_text = format!("{KDL}broken {{");
+    let edit = TextEdit {
+        offset: KDL.len(),
+        old_len: 0,
+        new_len: 8,
+    };
+    let error = facet_kdl::reparse(&old, &old_spans, &new_text, edit).unwrap_err();
+    assert!(matches!(error.kind, facet_kdl::KdlErrorKind::Parse(_)));
+}
+
+const NESTED: &str = "server port=8080 {\n    tls cert=\"/etc/cert.pem\"\n}\nplugin \"/usr/lib/a.so\"\n";
+
+#[test]
+fn locate_maps_offsets_to_node_paths() {
+    let offset = NESTED.find("8080").unwrap();
+    let path = facet_kdl::locate(NESTED, offset).unwrap();
+    assert_eq!(path.nodes, ["server"]);
+    assert_eq!(path.property.as_deref(), Some("port"));
+
+    let offset = NESTED.find("cert=").unwrap();
+    let path = facet_kdl::locate(NESTED, offset).unwrap();
+    assert_eq!(path.nodes, ["server", "tls"]);
+    assert_eq!(path.property.as_deref(), Some("cert"));
+
+    let offset = NESTED.find("plugin").unwrap();
+    let path = facet_kdl::locate(NESTED, offset).unwrap();
+    assert_eq!(path.nodes, ["plugin"]);
+    assert_eq!(path.property, None);
+}
+
+#[test]
+fn locate_returns_none_outside_nodes_and_on_broken_text() {
+    assert_eq!(facet_kdl::locate(NESTED, NESTED.len() + 10), None);
+    assert_eq!(facet_kdl::locate("broken {", 0), None);
+}
@@ -0,0 +1,167 @@
+#![cfg(all(feature = "ser", feature = "de"))]
+
+use facet::Facet;
+
+#[derive(Debug, Facet, PartialEq)]
+struct Config {
+    #[facet(child)]
+    server: Server,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct Server {
+    #[facet(property)]
+    port: u16,
+}
+
+fn temp_file(name: &str, bytes: &[u8]) -> std::path::PathBuf {
+    let path = std::env::temp_dir().join(format!("facet-kdl-test-{}-{name}", std::process::id()));
+    std::fs::write(&path, bytes).unwrap();
+    path
+}
+
+#[test]
+fn from_path_reads_plain_utf8() {
+    let path = temp_file("plain.kdl", b"server port=8080\n");
+    let config: Config = facet_kdl::from_path(&path).unwrap();
+    assert_eq!(config.server.port, 8080);
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn from_path_strips_utf8_bom() {
+    let mut bytes = vec![0xEF, 0xBB, 0xBF];
+    bytes.extend_from_slice(b"server port=1\n");
+    let path = temp_file("bom.kdl", &bytes);
+    let config: Config = facet_kdl::from_path(&path).unwrap();
+    assert_eq!(config.server.port, 1);
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn from_path_decodes_utf16_le() {
+    let text = "server port=2\n";
+    let mut bytes = vec![0xFF, 0xFE];
+    for unit in text.encode_utf16() {
+        bytes.extend_from_slice(&unit.to_le_bytes());
+    }
+    let path = temp_file("utf16.kdl", &bytes);
+    let config: Config = facet_kdl::from_path(&path).unwrap();
+    assert_eq!(config.server.port, 2);
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn from_path_errors_carry_the_filename() {
+    let path = temp_file("broken.kdl", b"server port=");
+    let error = facet_kdl::from_path::<Config>(&path).unwrap_err();
+    assert!(error.source_code.name().contains("broken.kdl"));
+    std::fs::remove_file(&path).ok();
+}
+
+#[test]
+fn to_path_writes_then_reads_back() {
+    let path = std::env::temp_dir().join(format!(
+        "facet-kdl-test-{}-roundtrip.kdl",
+        std::process::id()
+    ));
+    let config = Config {
+        server: Server { port: 4000 },
+    };
+    facet_kdl::to_path(&path, &config).unwrap();
+    let read: Config = facet_kdl::from_path(&path).unwrap();
+    assert_eq!(read, config);
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn to_path_leaves_no_temp_file_behind() {
+    let dir = std::env::temp_dir().join(format!("facet-kdl-test-{}-atomic", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("config.kdl");
+    let config = Config {
+        server: Server { port: 1 },
+    };
+    facet_kdl::to_path(&path, &config).unwrap();
+    let entries: Vec<_> = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    assert_eq!(entries, vec![std::ffi::OsString::from("config.kdl")]);
+    std::fs::remove_dir_all(dir).ok();
+}
+
+#[cfg(unix)]
+#[test]
+fn to_path_preserves_existing_permissions() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let path = temp_file("perms.kdl", b"server port=1\n");
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600)).unwrap();
+    let config = Config {
+        server: Server { port: 2 },
+    };
+    facet_kdl::to_path(&path, &config).unwrap();
+    let mode = std::fs::metadata(&path).unwrap().permissions().mode() & 0o777;
+    assert_eq!(mode, 0o600);
+    std::fs::remove_file(path).ok();
+}
+
+#[test]
+fn from_path_missing_file_is_io_error() {
+    let error =
+        facet_kdl::from_path::<Config>("/definitely/not/here/config.kdl").unwrap_err();
+    assert!(matches!(error.kind, facet_kdl::KdlErrorKind::Io(_)));
+}
+
+#[test]
+fn from_slice_reads_plain_utf8() {
+    let config: Config = facet_kdl::from_slice(b"server port=8080\n").unwrap();
+    assert_eq!(config.server.port, 8080);
+}
+
+#[test]
+fn from_slice_strips_utf8_bom() {
+    let mut bytes = vec![0xEF, 0xBB, 0xBF];
+    bytes.extend_from_slice(b"server port=1\n");
+    let config: Config = facet_kdl::from_slice(&bytes).unwrap();
+    assert_eq!(config.server.port, 1);
+}
+
+#[test]
+fn from_slice_decodes_utf16_be() {
+    let text = "server port=3\n";
+    let mut bytes = vec![0xFE, 0xFF];
+    for unit in text.encode_utf16() {
+        bytes.extend_from_slice(&unit.to_be_bytes());
+    }
+    let config: Config = facet_kdl::from_slice(&bytes).unwrap();
+    assert_eq!(config.server.port, 3);
+}
+
+#[test]
+fn from_slice_rejects_invalid_utf8() {
+    let error = facet_kdl::from_slice::<Config>(b"server port=1 \xFF\n").unwrap_err();
+    assert!(matches!(error.kind, facet_kdl::KdlErrorKind::Encoding(_)));
+}
+
+#[test]
+fn from_slice_lossy_parses_around_replacement_characters() {
+    // The invalid byte lands in a comment, so the replacement character is
+    // harmless and the rest of the document parses normally.
+    let config: Config =
+        facet_kdl::from_slice_lossy(b"// \xFF\nserver port=5\n").unwrap();
+    assert_eq!(config.server.port, 5);
+}
+
+#[test]
+fn from_slice_lossy_still_surfaces_parse_errors() {
+    // The replacement character sits inside a bare value; KDL accepts it as
+    // an identifier string, so the error points at the bad value instead of
+    // reporting a byte offset with no context.
+    let error = facet_kdl::from_slice_lossy::<Config>(b"server port=\xFF\n").unwrap_err();
+    assert!(matches!(
+        error.kind,
+        facet_kdl::KdlErrorKind::InvalidValueForShape { .. }
+    ));
+}
@@ -0,0 +1,268 @@
+#![cfg(all(feature = "ser", feature = "de"))]
+
+use facet::Facet;
+use facet_kdl::{DeserializeOptions, Naming, SerializeOptions};
+
+#[derive(Debug, Facet, PartialEq)]
+struct Config {
+    #[facet(child)]
+    server: Server,
+    #[facet(children)]
+    plugins: Vec<Plugin>,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct Server {
+    #[facet(property)]
+    max_connections: u32,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct Plugin {
+    #[facet(argument)]
+    path: String,
+}
+
+fn sample() -> Config {
+    Config {
+        server: Server {
+            max_connections: 10,
+        },
+        plugins: vec![Plugin {
+            path: "/usr/lib/a.so".to_string(),
+        }],
+    }
+}
+
+/// `max_connections` -> `MAX-CONNECTIONS`, `Plugin` -> `PLUGIN`.
+fn screaming_kebab(name: &str) -> String {
+    name.to_uppercase().replace('_', "-")
+}
+
+fn de_options() -> DeserializeOptions {
+    DeserializeOptions {
+        naming: Naming::new(screaming_kebab),
+        ..DeserializeOptions::default()
+    }
+}
+
+fn ser_options() -> SerializeOptions {
+    SerializeOptions {
+        naming: Naming::new(screaming_kebab),
+        ..SerializeOptions::default()
+    }
+}
+
+#[test]
+fn custom_convention_matches_nodes_and_properties() {
+    let kdl = "SERVER MAX-CONNECTIONS=10\nPLUGIN \"/usr/lib/a.so\"";
+    let config: Config = facet_kdl::from_str_with_options(kdl, &de_options()).unwrap();
+    assert_eq!(config, sample());
+}
+
+#[test]
+fn custom_convention_rejects_default_names() {
+    let kdl = "server max_connections=10";
+    let error = facet_kdl::from_str_with_options::<Config>(kdl, &de_options()).unwrap_err();
+    let facet_kdl::KdlErrorKind::NoMatchingNode { expected, .. } = error.kind else {
+        panic!("expected NoMatchingNode, got {:?}", error.kind);
+    };
+    assert!(expected.contains(&"SERVER".to_string()));
+    assert!(expected.contains(&"PLUGIN".to_string()));
+}
+
+#[test]
+fn diagnostics_render_converted_property_names() {
+    let kdl = "SERVER MAX-CONECTIONS=10";
+    let error = facet_kdl::from_str_with_options::<Config>(kdl, &de_options()).unwrap_err();
+    let facet_kdl::KdlErrorKind::NoMatchingProperty { expected, .. } = error.kind else {
+        panic!("expected NoMatchingProperty, got {:?}", error.kind);
+    };
+    assert_eq!(expected.len(), 1);
+    assert_eq!(expected[0].name, "MAX-CONNECTIONS");
+}
+
+#[test]
+fn custom_convention_serializes_both_directions() {
+    let kdl = facet_kdl::to_string_with_options(&sample(), &ser_options()).unwrap();
+    assert_eq!(kdl, "SERVER MAX-CONNECTIONS=10\nPLUGIN \"/usr/lib/a.so\"\n");
+    let back: Config = facet_kdl::from_str_with_options(&kdl, &de_options()).unwrap();
+    assert_eq!(back, sample());
+}
+
+#[test]
+fn default_naming_is_unchanged() {
+    let kdl = facet_kdl::to_string(&sample()).unwrap();
+    assert_eq!(kdl, "server max_connections=10\nplugin \"/usr/lib/a.so\"\n");
+    let back: Config = facet_kdl::from_str(&kdl).unwrap();
+    assert_eq!(back, sample());
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct ServicesDoc {
+    #[facet(children)]
+    services: Vec<Service>,
+}
+
+// The derive folds the rename into the reflected variant name, so both
+// directions and the error candidates see `fast-cgi`, never `FastCgi`.
+#[derive(Debug, Facet, PartialEq)]
+#[repr(u8)]
+#[allow(dead_code)] // `Plain` is only ever built through reflection
+enum Service {
+    #[facet(rename = "fast-cgi")]
+    FastCgi {
+        #[facet(property)]
+        port: u16,
+    },
+    Plain {},
+}
+
+#[test]
+fn renamed_variants_match_and_emit_their_renamed_form() {
+    let doc: ServicesDoc = facet_kdl::from_str("fast-cgi port=9000").unwrap();
+    assert_eq!(doc.services, [Service::FastCgi { port: 9000 }]);
+    assert_eq!(
+        facet_kdl::to_string(&doc).unwrap(),
+        "fast-cgi port=9000\n"
+    );
+}
+
+#[test]
+fn renamed_variants_appear_in_error_candidates() {
+    let error = facet_kdl::from_str::<ServicesDoc>("bogus").unwrap_err();
+    let message = error.to_string();
+    assert!(message.contains("fast-cgi"), "unexpected message: {message}");
+    assert!(!message.contains("FastCgi"), "unexpected message: {message}");
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct PoolDoc {
+    #[facet(child, rename = "connection-pool")]
+    pool: Pool,
+}
+
+// `rename_all` is folded into the reflected field names by the derive, the
+// same way per-field `rename` is, so matching, emission and error
+// candidates all see the kebab-case spellings with no work in this crate.
+#[derive(Debug, Facet, PartialEq)]
+#[facet(rename_all = "kebab-case")]
+struct Pool {
+    #[facet(property)]
+    max_connections: u32,
+    #[facet(property)]
+    idle_timeout_secs: Option<u32>,
+}
+
+#[test]
+fn rename_all_maps_fields_to_kebab_case_both_directions() {
+    let kdl = "connection-pool max-connections=32 idle-timeout-secs=60\n";
+    let doc: PoolDoc = facet_kdl::from_str(kdl).unwrap();
+    assert_eq!(
+        doc.pool,
+        Pool {
+            max_connections: 32,
+            idle_timeout_secs: Some(60),
+        }
+    );
+    assert_eq!(facet_kdl::to_string(&doc).unwrap(), kdl);
+}
+
+#[test]
+fn rename_all_rejects_the_rust_spellings() {
+    let kdl = "connection-pool max_connections=32";
+    let error = facet_kdl::from_str::<PoolDoc>(kdl).unwrap_err();
+    let facet_kdl::KdlErrorKind::NoMatchingProperty { expected, .. } = error.kind else {
+        panic!("expected NoMatchingProperty, got {:?}", error.kind);
+    };
+    assert!(expected
+        .iter()
+        .any(|property| property.name == "max-connections"));
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct SourcesDoc {
+    #[facet(children)]
+    sources: Vec<Source>,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+#[repr(u8)]
+#[allow(dead_code)] // `FileSource` is only ever built through reflection
+enum Source {
+    HttpSource {
+        #[facet(property)]
+        url: String,
+    },
+    FileSource {
+        #[facet(property)]
+        path: String,
+    },
+}
+
+fn kebab_de() -> DeserializeOptions {
+    DeserializeOptions {
+        naming: Naming::kebab_case(),
+        ..DeserializeOptions::default()
+    }
+}
+
+#[test]
+fn kebab_case_convention_matches_enum_variant_nodes() {
+    let kdl = "http-source url=\"https://example.com\"\nfile-source path=\"/var/log\"\n";
+    let doc: SourcesDoc = facet_kdl::from_str_with_options(kdl, &kebab_de()).unwrap();
+    assert_eq!(
+        doc.sources,
+        [
+            Source::HttpSource {
+                url: "https://example.com".to_string()
+            },
+            Source::FileSource {
+                path: "/var/log".to_string()
+            },
+        ]
+    );
+    let ser = SerializeOptions {
+        naming: Naming::kebab_case(),
+        ..SerializeOptions::default()
+    };
+    assert_eq!(
+        facet_kdl::to_string_with_options(&doc, &ser).unwrap(),
+        kdl
+    );
+}
+
+#[test]
+fn kebab_case_convention_lists_converted_candidates() {
+    let error =
+        facet_kdl::from_str_with_options::<SourcesDoc>("HttpSource url=\"u\"", &kebab_de())
+            .unwrap_err();
+    let facet_kdl::KdlErrorKind::NoMatchingNode { expected, .. } = error.kind else {
+        panic!("expected NoMatchingNode, got {:?}", error.kind);
+    };
+    assert!(expected.contains(&"http-source".to_string()));
+}
+
+#[test]
+fn kebab_case_folds_acronym_runs() {
+    // `HTTPSource` and `HttpSource` would collide under the conversion;
+    // exercised through a field name to pin the folding rule itself.
+    #[derive(Debug, Facet, PartialEq)]
+    struct AcronymDoc {
+        #[facet(child)]
+        server: AcronymServer,
+    }
+
+    #[derive(Debug, Facet, PartialEq)]
+    struct AcronymServer {
+        #[facet(property)]
+        enable_tls: bool,
+        #[facet(property)]
+        http2: bool,
+    }
+
+    let kdl = "server enable-tls=#true http2=#false";
+    let doc: AcronymDoc = facet_kdl::from_str_with_options(kdl, &kebab_de()).unwrap();
+    assert!(doc.server.enable_tls);
+    assert!(!doc.server.http2);
+}
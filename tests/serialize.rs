@@ -0,0 +1,1205 @@
+#![cfg(all(feature = "ser", feature = "de"))]
+
+use facet::Facet;
+
+#[derive(Clone, Debug, Facet, PartialEq)]
+struct Config {
+    #[facet(child)]
+    server: Server,
+    #[facet(children)]
+    plugins: Vec<Plugin>,
+}
+
+#[derive(Clone, Debug, Facet, PartialEq)]
+struct Server {
+    #[facet(argument)]
+    name: String,
+    #[facet(property)]
+    port: u16,
+    #[facet(property)]
+    verbose: Option<bool>,
+}
+
+#[derive(Clone, Debug, Facet, PartialEq)]
+struct Plugin {
+    #[facet(argument)]
+    path: String,
+}
+
+fn sample() -> Config {
+    Config {
+        server: Server {
+            name: "main".to_string(),
+            port: 8080,
+            verbose: None,
+        },
+        plugins: vec![
+            Plugin {
+                path: "/usr/lib/a.so".to_string(),
+            },
+            Plugin {
+                path: "/usr/lib/b.so".to_string(),
+            },
+        ],
+    }
+}
+
+#[test]
+fn basic_document() {
+    let kdl = facet_kdl::to_string(&sample()).unwrap();
+    assert_eq!(
+        kdl,
+        "server \"main\" port=8080\nplugin \"/usr/lib/a.so\"\nplugin \"/usr/lib/b.so\"\n"
+    );
+}
+
+#[test]
+fn none_property_is_omitted() {
+    let kdl = facet_kdl::to_string(&sample()).unwrap();
+    assert!(!kdl.contains("verbose"));
+}
+
+#[test]
+fn round_trip() {
+    let config = sample();
+    let kdl = facet_kdl::to_string(&config).unwrap();
+    let reparsed: Config = facet_kdl::from_str(&kdl).unwrap();
+    assert_eq!(reparsed, config);
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct Nested {
+    #[facet(child)]
+    outer: Outer,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct Outer {
+    #[facet(property)]
+    id: u32,
+    #[facet(child)]
+    inner: Inner,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct Inner {
+    #[facet(property)]
+    value: String,
+}
+
+#[test]
+fn nested_children_blocks() {
+    let nested = Nested {
+        outer: Outer {
+            id: 1,
+            inner: Inner {
+                value: "deep".to_string(),
+            },
+        },
+    };
+    let kdl = facet_kdl::to_string(&nested).unwrap();
+    assert_eq!(kdl, "outer id=1 {\n    inner value=\"deep\"\n}\n");
+    let reparsed: Nested = facet_kdl::from_str(&kdl).unwrap();
+    assert_eq!(reparsed, nested);
+}
+
+#[test]
+fn compact_output_is_single_line() {
+    let nested = Nested {
+        outer: Outer {
+            id: 1,
+            inner: Inner {
+                value: "deep".to_string(),
+            },
+        },
+    };
+    let kdl = facet_kdl::to_string_compact(&nested).unwrap();
+    assert_eq!(kdl, "outer id=1 { inner value=\"deep\"; };");
+    assert!(!kdl.contains('\n'));
+    // The regular deserializer accepts the compact form unchanged.
+    let reparsed: Nested = facet_kdl::from_str(&kdl).unwrap();
+    assert_eq!(reparsed, nested);
+}
+
+#[test]
+fn formatted_output_is_canonical() {
+    let kdl = facet_kdl::to_string_formatted(&sample(), facet_kdl::FormatConfig::default()).unwrap();
+    let reparsed: Config = facet_kdl::from_str(&kdl).unwrap();
+    assert_eq!(reparsed, sample());
+    // Autoformatted output is newline-terminated and free of stray blank lines.
+    assert!(kdl.ends_with('\n'));
+    assert!(!kdl.contains("\n\n"));
+}
+
+#[test]
+fn strings_are_escaped() {
+    #[derive(Debug, Facet, PartialEq)]
+    struct Doc {
+        #[facet(child)]
+        node: Node,
+    }
+
+    #[derive(Debug, Facet, PartialEq)]
+    struct Node {
+        #[facet(property)]
+        text: String,
+    }
+
+    let doc = Doc {
+        node: Node {
+            text: "line\n\"quoted\"".to_string(),
+        },
+    };
+    let kdl = facet_kdl::to_string(&doc).unwrap();
+    assert_eq!(kdl, "node text=\"line\\n\\\"quoted\\\"\"\n");
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct SpannedConfig {
+    #[facet(child)]
+    server: facet_kdl::Spanned<SpannedServer>,
+    #[facet(children)]
+    plugins: Vec<facet_kdl::Spanned<Plugin>>,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct SpannedServer {
+    #[facet(property)]
+    port: facet_kdl::Spanned<u16>,
+}
+
+fn spanned<T>(value: T) -> facet_kdl::Spanned<T> {
+    facet_kdl::Spanned {
+        value,
+        span: facet_kdl::Span { offset: 0, len: 0 },
+    }
+}
+
+#[test]
+fn spanned_wrappers_serialize_as_their_inner_value() {
+    let config = SpannedConfig {
+        server: spanned(SpannedServer {
+            port: spanned(8080),
+        }),
+        plugins: vec![spanned(Plugin {
+            path: "/usr/lib/a.so".to_string(),
+        })],
+    };
+    let kdl = facet_kdl::to_string(&config).unwrap();
+    assert_eq!(kdl, "server port=8080\nplugin \"/usr/lib/a.so\"\n");
+    let formatted = facet_kdl::to_string_formatted(&config, Default::default()).unwrap();
+    assert!(formatted.contains("server port=8080"));
+    assert!(formatted.contains("plugin \"/usr/lib/a.so\""));
+    assert!(!formatted.contains("spanned"));
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct TreeDoc {
+    #[facet(child)]
+    root: TreeNode,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct TreeNode {
+    #[facet(property)]
+    name: String,
+    #[facet(child)]
+    next: Option<Box<TreeNode>>,
+}
+
+fn chain(names: &[&str]) -> Option<Box<TreeNode>> {
+    let (first, rest) = names.split_first()?;
+    Some(Box::new(TreeNode {
+        name: first.to_string(),
+        next: chain(rest),
+    }))
+}
+
+#[test]
+fn option_box_children_serialize_three_levels_deep() {
+    let doc = TreeDoc {
+        root: *chain(&["a", "b", "c"]).unwrap(),
+    };
+    let kdl = facet_kdl::to_string(&doc).unwrap();
+    // The leaf's `next` field is `None`, so it gets no children block.
+    assert_eq!(
+        kdl,
+        "root name=\"a\" {\n    next name=\"b\" {\n        next name=\"c\"\n    }\n}\n"
+    );
+}
+
+#[test]
+fn empty_children_blocks_can_still_be_emitted() {
+    let doc = TreeDoc {
+        root: *chain(&["a"]).unwrap(),
+    };
+    assert_eq!(facet_kdl::to_string(&doc).unwrap(), "root name=\"a\"\n");
+    let options = facet_kdl::SerializeOptions {
+        empty_children: facet_kdl::EmptyChildrenPolicy::Emit,
+        ..Default::default()
+    };
+    let kdl = facet_kdl::to_string_with_options(&doc, &options).unwrap();
+    assert_eq!(kdl, "root name=\"a\" {\n}\n");
+    // Either spelling deserializes to the same value.
+    let back: TreeDoc = facet_kdl::from_str(&kdl).unwrap();
+    assert_eq!(back, doc);
+}
+
+#[test]
+fn formatted_output_omits_empty_children_blocks() {
+    let doc = TreeDoc {
+        root: *chain(&["a"]).unwrap(),
+    };
+    let kdl = facet_kdl::to_string_formatted(&doc, Default::default()).unwrap();
+    assert!(!kdl.contains('{'));
+}
+
+#[test]
+fn option_box_children_round_trip() {
+    let doc = TreeDoc {
+        root: *chain(&["a", "b", "c"]).unwrap(),
+    };
+    let kdl = facet_kdl::to_string(&doc).unwrap();
+    let back: TreeDoc = facet_kdl::from_str(&kdl).unwrap();
+    assert_eq!(back, doc);
+}
+
+#[test]
+fn keyword_floats_round_trip() {
+    #[derive(Debug, Facet, PartialEq)]
+    struct Doc {
+        #[facet(child)]
+        stats: Stats,
+    }
+
+    #[derive(Debug, Facet, PartialEq)]
+    struct Stats {
+        #[facet(property)]
+        ratio: f64,
+    }
+
+    let doc = Doc {
+        stats: Stats {
+            ratio: f64::INFINITY,
+        },
+    };
+    let kdl = facet_kdl::to_string(&doc).unwrap();
+    assert_eq!(kdl, "stats ratio=#inf\n");
+    let back: Doc = facet_kdl::from_str(&kdl).unwrap();
+    assert_eq!(back, doc);
+}
+
+#[test]
+fn unit_and_phantom_data_fields_are_skipped() {
+    #[derive(Debug, Facet, PartialEq)]
+    struct Doc {
+        #[facet(child)]
+        server: TypedServer,
+        _reserved: (),
+    }
+
+    #[derive(Debug, Facet, PartialEq)]
+    struct TypedServer {
+        #[facet(property)]
+        port: u16,
+        _state: core::marker::PhantomData<u8>,
+    }
+
+    let doc = Doc {
+        server: TypedServer {
+            port: 8080,
+            _state: core::marker::PhantomData,
+        },
+        _reserved: (),
+    };
+    let kdl = facet_kdl::to_string(&doc).unwrap();
+    assert_eq!(kdl, "server port=8080\n");
+    let formatted = facet_kdl::to_string_formatted(&doc, Default::default()).unwrap();
+    assert!(!formatted.contains("_state"));
+    let back: Doc = facet_kdl::from_str(&kdl).unwrap();
+    assert_eq!(back, doc);
+}
+
+#[test]
+fn map_enum_values_serialize_with_variant_annotations() {
+    #[derive(Debug, Facet, PartialEq)]
+    struct Doc {
+        #[facet(children)]
+        services: std::collections::BTreeMap<String, Service>,
+    }
+
+    #[derive(Debug, Facet, PartialEq)]
+    #[repr(u8)]
+    enum Service {
+        Web {
+            #[facet(property)]
+            port: u16,
+        },
+        Worker {
+            #[facet(property)]
+            queue: String,
+        },
+    }
+
+    let doc = Doc {
+        services: [
+            ("frontend".to_string(), Service::Web { port: 80 }),
+            (
+                "mailer".to_string(),
+                Service::Worker {
+                    queue: "outbound".to_string(),
+                },
+            ),
+        ]
+        .into(),
+    };
+    let kdl = facet_kdl::to_string(&doc).unwrap();
+    assert_eq!(kdl, "(Web)frontend port=80\n(Worker)mailer queue=\"outbound\"\n");
+    let formatted = facet_kdl::to_string_formatted(&doc, Default::default()).unwrap();
+    assert!(formatted.contains("(Web)frontend"));
+    let back: Doc = facet_kdl::from_str(&kdl).unwrap();
+    assert_eq!(back, doc);
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct PermsDoc {
+    #[facet(child)]
+    perms: Perms,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct Perms {
+    #[facet(property, kdl(radix = 8))]
+    mode: u32,
+    #[facet(property, kdl(radix = 16), kdl(width = 4))]
+    mask: u16,
+}
+
+#[test]
+fn radix_attributes_format_integer_literals() {
+    let doc = PermsDoc {
+        perms: Perms {
+            mode: 0o755,
+            mask: 0x00FF,
+        },
+    };
+    let kdl = facet_kdl::to_string(&doc).unwrap();
+    assert_eq!(kdl, "perms mode=0o755 mask=0x00FF\n");
+}
+
+#[test]
+fn radix_formatted_output_round_trips() {
+    let doc = PermsDoc {
+        perms: Perms {
+            mode: 0o644,
+            mask: 0xBEEF,
+        },
+    };
+    let kdl = facet_kdl::to_string(&doc).unwrap();
+    let back: PermsDoc = facet_kdl::from_str(&kdl).unwrap();
+    assert_eq!(back, doc);
+    let formatted = facet_kdl::to_string_formatted(&doc, Default::default()).unwrap();
+    assert!(formatted.contains("0o644"), "unexpected output: {formatted}");
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct HostDoc {
+    #[facet(child)]
+    service: EmbeddingService,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct EmbeddingService {
+    #[facet(argument)]
+    name: String,
+    #[facet(kdl(document))]
+    body: Fragment,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct Fragment {
+    #[facet(child)]
+    limits: Limits,
+    #[facet(children)]
+    mounts: Vec<Mount>,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct Limits {
+    #[facet(property)]
+    cpu: u32,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct Mount {
+    #[facet(argument)]
+    path: String,
+}
+
+fn embedding_sample() -> HostDoc {
+    HostDoc {
+        service: EmbeddingService {
+            name: "api".to_string(),
+            body: Fragment {
+                limits: Limits { cpu: 2 },
+                mounts: vec![
+                    Mount {
+                        path: "/data".to_string(),
+                    },
+                    Mount {
+                        path: "/logs".to_string(),
+                    },
+                ],
+            },
+        },
+    }
+}
+
+#[test]
+fn document_fields_serialize_as_the_children_block() {
+    let kdl = facet_kdl::to_string(&embedding_sample()).unwrap();
+    assert_eq!(
+        kdl,
+        "service \"api\" {\n    limits cpu=2\n    mount \"/data\"\n    mount \"/logs\"\n}\n"
+    );
+}
+
+#[test]
+fn document_fields_round_trip() {
+    let doc = embedding_sample();
+    let kdl = facet_kdl::to_string(&doc).unwrap();
+    let back: HostDoc = facet_kdl::from_str(&kdl).unwrap();
+    assert_eq!(back, doc);
+    let formatted = facet_kdl::to_string_formatted(&doc, Default::default()).unwrap();
+    let back: HostDoc = facet_kdl::from_str(&formatted).unwrap();
+    assert_eq!(back, doc);
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct ClusterDoc {
+    #[facet(children)]
+    entries: Vec<ClusterEntry>,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+#[repr(u8)]
+enum ClusterEntry {
+    Hosts(Vec<String>),
+    Ports(Vec<u16>),
+}
+
+#[test]
+fn list_payload_variants_serialize_as_argument_runs() {
+    let doc = ClusterDoc {
+        entries: vec![
+            ClusterEntry::Hosts(vec!["alpha".to_string(), "beta".to_string()]),
+            ClusterEntry::Ports(vec![80, 443]),
+        ],
+    };
+    let kdl = facet_kdl::to_string(&doc).unwrap();
+    assert_eq!(kdl, "Hosts \"alpha\" \"beta\"\nPorts 80 443\n");
+    let back: ClusterDoc = facet_kdl::from_str(&kdl).unwrap();
+    assert_eq!(back, doc);
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct GlyphDoc {
+    #[facet(child)]
+    glyph: Glyph,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct Glyph {
+    #[facet(argument)]
+    symbol: char,
+    #[facet(property)]
+    name: std::borrow::Cow<'static, str>,
+}
+
+#[test]
+fn char_and_cow_scalars_serialize_as_strings() {
+    let doc = GlyphDoc {
+        glyph: Glyph {
+            symbol: '@',
+            name: std::borrow::Cow::Borrowed("at"),
+        },
+    };
+    let kdl = facet_kdl::to_string(&doc).unwrap();
+    assert_eq!(kdl, "glyph \"@\" name=\"at\"\n");
+    // Both serializers go through the same scalar probing.
+    let formatted = facet_kdl::to_string_formatted(&doc, Default::default()).unwrap();
+    assert!(formatted.contains("\"@\""), "unexpected output: {formatted}");
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct BannerDoc {
+    #[facet(child)]
+    banner: Banner,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct Banner {
+    #[facet(argument)]
+    text: &'static str,
+}
+
+#[test]
+fn static_str_scalars_serialize_as_strings() {
+    let doc = BannerDoc {
+        banner: Banner { text: "welcome" },
+    };
+    let kdl = facet_kdl::to_string(&doc).unwrap();
+    assert_eq!(kdl, "banner \"welcome\"\n");
+}
+
+#[test]
+fn char_scalars_round_trip() {
+    let doc = GlyphDoc {
+        glyph: Glyph {
+            symbol: '✓',
+            name: std::borrow::Cow::Borrowed("check"),
+        },
+    };
+    let kdl = facet_kdl::to_string(&doc).unwrap();
+    let back: GlyphDoc = facet_kdl::from_str(&kdl).unwrap();
+    assert_eq!(back, doc);
+}
+
+#[derive(Debug, Facet, PartialEq, Eq, PartialOrd, Ord)]
+struct Crew {
+    #[facet(argument)]
+    name: String,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct RosterDoc {
+    #[facet(children)]
+    crew: Vec<Crew>,
+    #[facet(children)]
+    roles: std::collections::BTreeMap<String, Crew>,
+    #[facet(children)]
+    badges: std::collections::BTreeSet<Crew>,
+    #[facet(children)]
+    backup: Option<Vec<Crew>>,
+}
+
+#[test]
+fn empty_children_containers_serialize_to_nothing() {
+    let doc = RosterDoc {
+        crew: Vec::new(),
+        roles: std::collections::BTreeMap::new(),
+        badges: std::collections::BTreeSet::new(),
+        backup: None,
+    };
+    assert_eq!(facet_kdl::to_string(&doc).unwrap(), "");
+    let formatted = facet_kdl::to_string_formatted(&doc, Default::default()).unwrap();
+    assert_eq!(formatted.trim(), "");
+}
+
+#[test]
+fn optional_children_containers_serialize_their_contents() {
+    let doc = RosterDoc {
+        crew: vec![Crew {
+            name: "ana".to_string(),
+        }],
+        roles: std::collections::BTreeMap::from([(
+            "lead".to_string(),
+            Crew {
+                name: "bo".to_string(),
+            },
+        )]),
+        badges: std::collections::BTreeSet::from([Crew {
+            name: "cid".to_string(),
+        }]),
+        backup: Some(vec![Crew {
+            name: "zoe".to_string(),
+        }]),
+    };
+    let expected = "crew \"ana\"\nlead \"bo\"\ncrew \"cid\"\ncrew \"zoe\"\n";
+    assert_eq!(facet_kdl::to_string(&doc).unwrap(), expected);
+}
+
+#[derive(Debug, Facet)]
+struct WrappedRoster(RosterDoc);
+
+#[test]
+fn newtype_documents_serialize_via_the_inner_type() {
+    let inner = RosterDoc {
+        crew: vec![Crew {
+            name: "ana".to_string(),
+        }],
+        roles: std::collections::BTreeMap::new(),
+        badges: std::collections::BTreeSet::new(),
+        backup: None,
+    };
+    let wrapped = facet_kdl::to_string(&WrappedRoster(inner)).unwrap();
+    assert_eq!(wrapped, "crew \"ana\"\n");
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct InlineDoc {
+    #[facet(child)]
+    endpoint: Endpoint,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct Endpoint {
+    #[facet(property)]
+    host: String,
+    #[facet(child, kdl(inline))]
+    limits: InlineLimits,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct InlineLimits {
+    #[facet(property)]
+    cpu: u32,
+    #[facet(property)]
+    memory: u32,
+}
+
+#[test]
+fn inline_children_serialize_as_parent_properties() {
+    let doc = InlineDoc {
+        endpoint: Endpoint {
+            host: "api".to_string(),
+            limits: InlineLimits { cpu: 2, memory: 512 },
+        },
+    };
+    let expected = "endpoint host=\"api\" cpu=2 memory=512\n";
+    assert_eq!(facet_kdl::to_string(&doc).unwrap(), expected);
+    let formatted = facet_kdl::to_string_formatted(&doc, Default::default()).unwrap();
+    assert_eq!(formatted, expected);
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct MixedTopLevel {
+    #[facet(property)]
+    port: u16,
+    #[facet(child)]
+    meta: MixedMeta,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct MixedMeta {
+    #[facet(property)]
+    name: String,
+}
+
+#[test]
+fn top_level_entry_fields_error_identically_in_both_directions() {
+    let value = MixedTopLevel {
+        port: 1,
+        meta: MixedMeta {
+            name: "x".to_string(),
+        },
+    };
+    let ser = facet_kdl::to_string(&value).unwrap_err();
+    let formatted = facet_kdl::to_string_formatted(&value, Default::default()).unwrap_err();
+    let de = facet_kdl::from_str::<MixedTopLevel>("meta name=\"x\"").unwrap_err();
+    for error in [&ser, &formatted, &de] {
+        match &error.kind {
+            facet_kdl::KdlErrorKind::IllegalAttributesOnTopLevelShape { offenders, .. } => {
+                assert_eq!(offenders, &vec![("port", "property")]);
+            }
+            other => panic!("unexpected error kind: {other:?}"),
+        }
+        assert!(
+            error.to_string().contains("`port` is `property`"),
+            "{error}"
+        );
+    }
+}
+
+#[derive(Debug, Facet, PartialEq)]
+#[facet(transparent)]
+struct TaggedConfig(Config);
+
+#[derive(Debug, Facet, PartialEq)]
+struct HandleDoc {
+    #[facet(child)]
+    server: ServerHandle,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+#[facet(transparent)]
+struct ServerHandle(Server);
+
+#[test]
+fn transparent_document_serializes_as_its_inner_type() {
+    let tagged = TaggedConfig(sample());
+    assert_eq!(
+        facet_kdl::to_string(&tagged).unwrap(),
+        facet_kdl::to_string(&sample()).unwrap()
+    );
+}
+
+#[test]
+fn transparent_child_field_serializes_as_its_inner_type() {
+    let doc = HandleDoc {
+        server: ServerHandle(Server {
+            name: "main".to_string(),
+            port: 8080,
+            verbose: None,
+        }),
+    };
+    assert_eq!(
+        facet_kdl::to_string(&doc).unwrap(),
+        "server \"main\" port=8080\n"
+    );
+    assert_eq!(
+        facet_kdl::to_string_formatted(&doc, facet_kdl::FormatConfig::default())
+            .unwrap()
+            .trim(),
+        "server \"main\" port=8080"
+    );
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct TasksDoc {
+    #[facet(children)]
+    tasks: Vec<Task>,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+#[repr(u8)]
+enum Task {
+    Build {
+        #[facet(property)]
+        target: String,
+    },
+    Clean,
+}
+
+#[test]
+fn variant_property_makes_enum_nodes_self_describing() {
+    let doc = TasksDoc {
+        tasks: vec![
+            Task::Build {
+                target: "all".to_string(),
+            },
+            Task::Clean,
+        ],
+    };
+    let options = facet_kdl::SerializeOptions {
+        variant_property: Some("kind".to_string()),
+        ..Default::default()
+    };
+    let kdl = facet_kdl::to_string_with_options(&doc, &options).unwrap();
+    assert_eq!(kdl, "Build kind=\"Build\" target=\"all\"\nClean kind=\"Clean\"\n");
+
+    let de_options = facet_kdl::DeserializeOptions {
+        variant_property: Some("kind".to_string()),
+        ..Default::default()
+    };
+    let back: TasksDoc = facet_kdl::from_str_with_options(&kdl, &de_options).unwrap();
+    assert_eq!(back, doc);
+}
+
+#[test]
+fn variant_property_mismatch_is_an_error() {
+    let de_options = facet_kdl::DeserializeOptions {
+        variant_property: Some("kind".to_string()),
+        ..Default::default()
+    };
+    let error = facet_kdl::from_str_with_options::<TasksDoc>(
+        "Build kind=\"Clean\" target=\"all\"",
+        &de_options,
+    )
+    .unwrap_err();
+    match error.kind {
+        facet_kdl::KdlErrorKind::VariantPropertyMismatch {
+            key,
+            expected,
+            found,
+        } => {
+            assert_eq!(key, "kind");
+            assert_eq!(expected, "Build");
+            assert_eq!(found, "Clean");
+        }
+        other => panic!("expected a variant property mismatch, got {other:?}"),
+    }
+    assert!(error.span.is_some());
+}
+
+#[test]
+fn next_line_braces_open_the_block_on_their_own_line() {
+    let nested = Nested {
+        outer: Outer {
+            id: 1,
+            inner: Inner {
+                value: "deep".to_string(),
+            },
+        },
+    };
+    let options = facet_kdl::SerializeOptions {
+        brace_style: facet_kdl::BraceStyle::NextLine,
+        ..Default::default()
+    };
+    let kdl = facet_kdl::to_string_with_options(&nested, &options).unwrap();
+    assert_eq!(kdl, "outer id=1 \\\n{\n    inner value=\"deep\"\n}\n");
+    let reparsed: Nested = facet_kdl::from_str(&kdl).unwrap();
+    assert_eq!(reparsed, nested);
+}
+
+#[test]
+fn semicolon_separators_terminate_every_node() {
+    let nested = Nested {
+        outer: Outer {
+            id: 1,
+            inner: Inner {
+                value: "deep".to_string(),
+            },
+        },
+    };
+    let options = facet_kdl::SerializeOptions {
+        node_separator: facet_kdl::NodeSeparator::Semicolon,
+        ..Default::default()
+    };
+    let kdl = facet_kdl::to_string_with_options(&nested, &options).unwrap();
+    assert_eq!(kdl, "outer id=1 {\n    inner value=\"deep\";\n};\n");
+    let reparsed: Nested = facet_kdl::from_str(&kdl).unwrap();
+    assert_eq!(reparsed, nested);
+}
+
+#[test]
+fn custom_indent_string_applies_per_depth_level() {
+    let nested = Nested {
+        outer: Outer {
+            id: 1,
+            inner: Inner {
+                value: "deep".to_string(),
+            },
+        },
+    };
+    let options = facet_kdl::SerializeOptions {
+        indent: "\t".to_string(),
+        ..Default::default()
+    };
+    let kdl = facet_kdl::to_string_with_options(&nested, &options).unwrap();
+    assert_eq!(kdl, "outer id=1 {\n\tinner value=\"deep\"\n}\n");
+    let reparsed: Nested = facet_kdl::from_str(&kdl).unwrap();
+    assert_eq!(reparsed, nested);
+}
+
+#[test]
+fn v1_dialect_writes_bare_keyword_literals() {
+    let mut config = sample();
+    config.server.verbose = Some(true);
+    let options = facet_kdl::SerializeOptions {
+        dialect: facet_kdl::KdlVersion::V1,
+        ..Default::default()
+    };
+    let kdl = facet_kdl::to_string_with_options(&config, &options).unwrap();
+    assert!(kdl.contains("verbose=true"), "unexpected output: {kdl}");
+    assert!(!kdl.contains("#true"), "unexpected output: {kdl}");
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct BudgetDoc {
+    #[facet(child)]
+    budget: Budget,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct Budget {
+    #[facet(property, kdl(node_or_property))]
+    timeout: u64,
+    #[facet(property, kdl(node_or_property = node))]
+    retries: u32,
+}
+
+#[test]
+fn node_or_property_writes_the_configured_canonical_form() {
+    let doc = BudgetDoc {
+        budget: Budget {
+            timeout: 30,
+            retries: 3,
+        },
+    };
+    let kdl = facet_kdl::to_string(&doc).unwrap();
+    assert_eq!(kdl, "budget timeout=30 {\n    retries 3\n}\n");
+    let reparsed: BudgetDoc = facet_kdl::from_str(&kdl).unwrap();
+    assert_eq!(reparsed, doc);
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct WrappedDoc {
+    #[facet(child)]
+    server: WrappedServer,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct WrappedServer {
+    #[facet(property)]
+    port: Option<facet_kdl::Spanned<u16>>,
+    #[facet(property)]
+    host: Box<facet_kdl::Spanned<String>>,
+}
+
+#[test]
+fn nested_wrapper_properties_serialize_as_their_inner_scalar() {
+    let doc = WrappedDoc {
+        server: WrappedServer {
+            port: Some(facet_kdl::Spanned {
+                value: 8080,
+                span: facet_kdl::Span { offset: 0, len: 0 },
+            }),
+            host: Box::new(facet_kdl::Spanned {
+                value: "localhost".to_string(),
+                span: facet_kdl::Span { offset: 0, len: 0 },
+            }),
+        },
+    };
+    let kdl = facet_kdl::to_string(&doc).unwrap();
+    assert_eq!(kdl, "server port=8080 host=\"localhost\"\n");
+}
+
+#[test]
+fn none_wrapper_property_is_omitted() {
+    let doc = WrappedDoc {
+        server: WrappedServer {
+            port: None,
+            host: Box::new(facet_kdl::Spanned {
+                value: "localhost".to_string(),
+                span: facet_kdl::Span { offset: 0, len: 0 },
+            }),
+        },
+    };
+    let kdl = facet_kdl::to_string(&doc).unwrap();
+    assert_eq!(kdl, "server host=\"localhost\"\n");
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct PassthroughDoc {
+    #[facet(child, kdl(verbatim))]
+    snippet: String,
+}
+
+#[test]
+fn verbatim_child_emits_its_text_as_children() {
+    let doc = PassthroughDoc {
+        snippet: "route \"/api\" upstream=\"backend\"\nroute \"/\"\n".to_string(),
+    };
+    let kdl = facet_kdl::to_string(&doc).unwrap();
+    assert_eq!(
+        kdl,
+        "snippet {\n    route \"/api\" upstream=\"backend\"\n    route \"/\"\n}\n"
+    );
+}
+
+#[test]
+fn verbatim_child_rejects_text_that_does_not_parse() {
+    let doc = PassthroughDoc {
+        snippet: "route \"unclosed".to_string(),
+    };
+    let error = facet_kdl::to_string(&doc).unwrap_err();
+    assert!(
+        error.to_string().contains("verbatim KDL does not parse"),
+        "unexpected message: {error}"
+    );
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct TelemetryDoc {
+    #[facet(child)]
+    sample: Sample,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+struct Sample {
+    #[facet(property)]
+    level: Level,
+}
+
+#[derive(Debug, Facet, PartialEq)]
+#[repr(u8)]
+#[allow(dead_code)] // `High` is only ever built through reflection
+enum Level {
+    Low = 1,
+    Medium = 5,
+    High = 9,
+}
+
+#[test]
+fn unit_only_enum_values_serialize_as_their_name() {
+    let doc = TelemetryDoc {
+        sample: Sample {
+            level: Level::Medium,
+        },
+    };
+    assert_eq!(
+        facet_kdl::to_string(&doc).unwrap(),
+        "sample level=\"Medium\"\n"
+    );
+}
+
+#[test]
+fn unit_only_enum_values_serialize_as_their_discriminant_on_request() {
+    let doc = TelemetryDoc {
+        sample: Sample {
+            level: Level::Medium,
+        },
+    };
+    let options = facet_kdl::SerializeOptions {
+        unit_variant_format: facet_kdl::UnitVariantFormat::Discriminant,
+        ..Default::default()
+    };
+    assert_eq!(
+        facet_kdl::to_string_with_options(&doc, &options).unwrap(),
+        "sample level=5\n"
+    );
+}
+
+#[test]
+fn to_document_exposes_the_structured_form() {
+    let document = facet_kdl::to_document(&sample()).unwrap();
+    let server = document.get("server").unwrap();
+    assert_eq!(
+        server.entries()[0].value(),
+        &facet_kdl::kdl::KdlValue::String("main".to_string())
+    );
+    assert_eq!(document.nodes().len(), 3);
+}
+
+#[test]
+fn to_document_supports_post_processing_before_rendering() {
+    let mut document = facet_kdl::to_document(&sample()).unwrap();
+    // The whole point of the structured form: edit with kdl-rs APIs, then
+    // render — here a comment a pure-string pipeline couldn't attach.
+    document.get_mut("server").unwrap().set_format(facet_kdl::kdl::KdlNodeFormat {
+        leading: "// managed by deploy tooling\n".into(),
+        ..Default::default()
+    });
+    document.autoformat();
+    let text = document.to_string();
+    assert!(
+        text.contains("// managed by deploy tooling"),
+        "unexpected output: {text}"
+    );
+    let reread: Config = facet_kdl::from_str(&text).unwrap();
+    assert_eq!(reread, sample());
+}
+
+#[test]
+fn update_document_rewrites_only_changed_values() {
+    let original = "\
+// deployment config, hand-edited
+server \"main\" port=8080  // the main listener
+plugin \"/usr/lib/a.so\"
+plugin \"/usr/lib/b.so\"
+";
+    let mut document = facet_kdl::parse(original).unwrap();
+    let mut config = sample();
+    config.server.port = 9090;
+    facet_kdl::update_document(&mut document, &config).unwrap();
+    let text = document.to_string();
+    assert!(
+        text.contains("// deployment config, hand-edited"),
+        "comment lost: {text}"
+    );
+    assert!(
+        text.contains("port=9090  // the main listener"),
+        "value not rewritten in place: {text}"
+    );
+    let reread: Config = facet_kdl::from_str(&text).unwrap();
+    assert_eq!(reread, config);
+}
+
+#[test]
+fn update_document_adds_and_trims_children_elements() {
+    let mut document = facet_kdl::parse("server \"main\" port=8080\nplugin \"/usr/lib/a.so\"\nplugin \"/usr/lib/b.so\"\n").unwrap();
+    let mut config = sample();
+    config.plugins.truncate(1);
+    facet_kdl::update_document(&mut document, &config).unwrap();
+    let trimmed: Config = facet_kdl::from_str(&document.to_string()).unwrap();
+    assert_eq!(trimmed.plugins.len(), 1);
+
+    config.plugins.push(Plugin {
+        path: "/usr/lib/c.so".to_string(),
+    });
+    facet_kdl::update_document(&mut document, &config).unwrap();
+    let grown: Config = facet_kdl::from_str(&document.to_string()).unwrap();
+    assert_eq!(grown, config);
+}
+
+#[test]
+fn update_document_leaves_unknown_nodes_alone() {
+    // `extra` is not part of `Config`; the merge must not delete it.
+    let mut document = facet_kdl::parse("server \"main\" port=8080\nextra \"keep me\"\n").unwrap();
+    let mut config = sample();
+    config.plugins.clear();
+    facet_kdl::update_document(&mut document, &config).unwrap();
+    assert!(document.to_string().contains("extra \"keep me\""));
+}
+
+#[test]
+fn grouped_flatten_serializes_prefixed_properties() {
+    #[derive(Debug, Facet, PartialEq)]
+    struct Doc {
+        #[facet(child)]
+        server: GroupedServer,
+    }
+
+    #[derive(Debug, Facet, PartialEq)]
+    struct GroupedServer {
+        #[facet(property)]
+        port: u16,
+        #[facet(flatten, kdl(group = "tls"))]
+        tls: Tls,
+    }
+
+    #[derive(Debug, Facet, PartialEq)]
+    struct Tls {
+        #[facet(property)]
+        cert: String,
+    }
+
+    let doc = Doc {
+        server: GroupedServer {
+            port: 443,
+            tls: Tls {
+                cert: "/etc/cert".to_string(),
+            },
+        },
+    };
+    let kdl = facet_kdl::to_string(&doc).unwrap();
+    assert_eq!(kdl, "server port=443 tls-cert=\"/etc/cert\"\n");
+    let back: Doc = facet_kdl::from_str(&kdl).unwrap();
+    assert_eq!(back, doc);
+}
+
+#[test]
+fn wrapper_fields_serialize_their_idioms() {
+    #[derive(Debug, Facet, PartialEq)]
+    struct Doc {
+        #[facet(child)]
+        run: Command,
+    }
+
+    #[derive(Debug, Facet, PartialEq)]
+    struct Command {
+        name: facet_kdl::NodeName,
+        args: facet_kdl::Args<String>,
+        env: facet_kdl::Props,
+    }
+
+    let doc = Doc {
+        run: Command {
+            // The `NodeName` value names the node, not the field.
+            name: "deploy".into(),
+            args: facet_kdl::Args(vec!["cargo".to_string(), "publish".to_string()]),
+            env: facet_kdl::Props(
+                [
+                    ("RUST_LOG".to_string(), "info".to_string()),
+                    ("CI".to_string(), "1".to_string()),
+                ]
+                .into_iter()
+                .collect(),
+            ),
+        },
+    };
+    let kdl = facet_kdl::to_string(&doc).unwrap();
+    // Props entries come out sorted by key for deterministic output.
+    assert_eq!(kdl, "deploy \"cargo\" \"publish\" CI=\"1\" RUST_LOG=\"info\"\n");
+}
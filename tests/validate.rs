@@ -0,0 +1,277 @@
+use facet::Facet;
+
+#[derive(Debug, Facet)]
+struct GoodDoc {
+    #[facet(child)]
+    node: GoodNode,
+}
+
+#[derive(Debug, Facet)]
+struct GoodNode {
+    #[facet(argument)]
+    name: String,
+    #[facet(property)]
+    port: u16,
+}
+
+#[test]
+fn valid_types_produce_no_issues() {
+    assert!(facet_kdl::validate_attributes::<GoodDoc>().is_empty());
+}
+
+#[derive(Debug, Facet)]
+struct ConflictingRoles {
+    #[facet(argument, property)]
+    name: String,
+}
+
+#[test]
+fn conflicting_roles_are_reported() {
+    let issues = facet_kdl::validate_attributes::<ConflictingRoles>();
+    assert_eq!(issues.len(), 1);
+    assert_eq!(issues[0].field, "name");
+    assert!(issues[0].message.contains("argument and property"));
+}
+
+#[derive(Debug, Facet)]
+struct ScalarChildren {
+    #[facet(children)]
+    member: u32,
+}
+
+#[test]
+fn non_container_children_field_is_reported() {
+    let issues = facet_kdl::validate_attributes::<ScalarChildren>();
+    assert_eq!(issues.len(), 1);
+    assert!(issues[0].message.contains("container"));
+    assert!(issues[0].message.contains("child"));
+}
+
+#[derive(Debug, Facet)]
+struct ScalarArguments {
+    #[facet(arguments)]
+    names: String,
+}
+
+#[test]
+fn non_list_arguments_field_is_reported() {
+    let issues = facet_kdl::validate_attributes::<ScalarArguments>();
+    assert_eq!(issues.len(), 1);
+    assert!(issues[0].message.contains("argument"));
+}
+
+#[derive(Debug, Facet)]
+struct DupServer {
+    #[facet(property)]
+    port: u16,
+    #[facet(flatten)]
+    connection: ConnectionSettings,
+}
+
+#[derive(Debug, Facet)]
+struct ConnectionSettings {
+    #[facet(property)]
+    port: u16,
+    #[facet(property)]
+    timeout: u32,
+}
+
+#[test]
+fn duplicate_properties_across_flatten_are_reported() {
+    let issues = facet_kdl::validate_attributes::<DupServer>();
+    assert_eq!(issues.len(), 1);
+    assert_eq!(issues[0].field, "port");
+    let message = &issues[0].message;
+    assert!(message.contains("DupServer.port"), "{message}");
+    assert!(
+        message.contains("ConnectionSettings.port"),
+        "{message}"
+    );
+    assert!(message.contains("flattened via `connection`"), "{message}");
+}
+
+#[test]
+fn check_passes_for_valid_types() {
+    facet_kdl::check::<GoodDoc>().unwrap();
+}
+
+#[test]
+fn check_reports_every_issue_on_one_line_each() {
+    let report = facet_kdl::check::<DupServer>().unwrap_err();
+    assert!(report.contains("port"), "{report}");
+    assert!(report.contains("defined twice"), "{report}");
+}
+
+#[derive(Debug, Facet)]
+struct NodeOrPropertyChild {
+    #[facet(child, kdl(node_or_property))]
+    timeout: Timeout,
+}
+
+#[derive(Debug, Facet)]
+struct Timeout {
+    #[facet(argument)]
+    seconds: u64,
+}
+
+#[test]
+fn node_or_property_outside_the_property_role_is_reported() {
+    let issues = facet_kdl::validate_attributes::<NodeOrPropertyChild>();
+    assert_eq!(issues.len(), 1);
+    assert!(issues[0].message.contains("requires the `property` role"));
+}
+
+#[derive(Debug, Facet)]
+struct NodeOrPropertyBadForm {
+    #[facet(property, kdl(node_or_property = entry))]
+    timeout: u64,
+}
+
+#[test]
+fn node_or_property_with_unknown_canonical_form_is_reported() {
+    let issues = facet_kdl::validate_attributes::<NodeOrPropertyBadForm>();
+    assert_eq!(issues.len(), 1);
+    assert!(issues[0].message.contains("use `node` or `property`"));
+}
+
+#[derive(Debug, Facet)]
+struct ScalarElements {
+    #[facet(children)]
+    ports: Vec<u16>,
+}
+
+#[test]
+fn scalar_children_element_type_is_reported() {
+    let issues = facet_kdl::validate_attributes::<ScalarElements>();
+    assert_eq!(issues.len(), 1);
+    assert!(issues[0].message.contains("u16"));
+    assert!(issues[0].message.contains("structs or enums"));
+}
+
+#[derive(Debug, Facet)]
+struct VerbatimOffRole {
+    #[facet(property, kdl(verbatim))]
+    #[allow(dead_code)]
+    snippet: String,
+}
+
+#[derive(Debug, Facet)]
+struct VerbatimOffType {
+    #[facet(child, kdl(verbatim))]
+    #[allow(dead_code)]
+    snippet: u32,
+}
+
+#[test]
+fn verbatim_requires_a_child_role_string() {
+    let issues = facet_kdl::validate_attributes::<VerbatimOffRole>();
+    assert_eq!(issues.len(), 1);
+    assert!(issues[0].message.contains("requires the `child` role"));
+
+    let issues = facet_kdl::validate_attributes::<VerbatimOffType>();
+    assert_eq!(issues.len(), 1);
+    assert!(issues[0].message.contains("requires a `String`"));
+}
+
+#[derive(Debug, Facet)]
+struct DeadFieldDoc {
+    #[facet(child)]
+    #[allow(dead_code)]
+    server: DeadFieldServer,
+}
+
+#[derive(Debug, Facet)]
+struct DeadFieldServer {
+    #[facet(property)]
+    #[allow(dead_code)]
+    port: u16,
+    #[allow(dead_code)]
+    retries: u32,
+}
+
+#[test]
+fn lint_flags_fields_nothing_claims() {
+    let lints = facet_kdl::lint::<DeadFieldDoc>();
+    assert_eq!(lints.len(), 1);
+    assert_eq!(lints[0].field, "retries");
+    assert!(lints[0].message.contains("can never be populated"));
+}
+
+#[derive(Debug, Facet)]
+struct CollidingRenameDoc {
+    #[facet(child)]
+    #[allow(dead_code)]
+    server: CollidingRenameServer,
+}
+
+#[derive(Debug, Facet)]
+struct CollidingRenameServer {
+    #[facet(property, rename = "port")]
+    #[allow(dead_code)]
+    listen: u16,
+    #[facet(property)]
+    #[allow(dead_code)]
+    port: u16,
+}
+
+#[test]
+fn lint_flags_renames_colliding_with_a_sibling() {
+    let lints = facet_kdl::lint::<CollidingRenameDoc>();
+    assert_eq!(lints.len(), 1);
+    assert_eq!(lints[0].field, "port");
+    assert!(lints[0].message.contains("more than once"));
+}
+
+#[derive(Debug, Facet)]
+struct OptionElementsDoc {
+    #[facet(children)]
+    #[allow(dead_code)]
+    plugins: Vec<Option<PluginEntry>>,
+}
+
+#[derive(Debug, Facet)]
+struct PluginEntry {
+    #[facet(argument)]
+    #[allow(dead_code)]
+    path: String,
+}
+
+#[test]
+fn lint_flags_children_of_option_elements() {
+    let lints = facet_kdl::lint::<OptionElementsDoc>();
+    assert_eq!(lints.len(), 1);
+    assert_eq!(lints[0].field, "plugins");
+    assert!(lints[0].message.contains("always fills `Some`"));
+}
+
+#[test]
+fn lint_accepts_a_clean_document_type() {
+    assert!(facet_kdl::lint::<GoodDoc>().is_empty());
+}
+
+#[derive(Debug, Facet)]
+struct ScalarChild {
+    #[facet(child)]
+    port: u16,
+}
+
+#[test]
+fn scalar_child_field_is_reported() {
+    let issues = facet_kdl::validate_attributes::<ScalarChild>();
+    assert_eq!(issues.len(), 1);
+    assert_eq!(issues[0].field, "port");
+    assert!(issues[0].message.contains("scalar"));
+    assert!(issues[0].message.contains("property"));
+}
+
+#[derive(Debug, Facet)]
+struct BoxedChild {
+    #[facet(child)]
+    // A struct reached through `Option` and `Box` layers is still fine.
+    inner: Option<Box<GoodNode>>,
+}
+
+#[test]
+fn wrapped_struct_child_is_not_reported() {
+    assert!(facet_kdl::validate_attributes::<BoxedChild>().is_empty());
+}